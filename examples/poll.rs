@@ -38,7 +38,10 @@ pub fn main() {
 
   loop {
     thread::sleep(Duration::from_millis(1000));
-    let _r = client.poll_updates().unwrap();
+    let events = client.poll_updates().unwrap();
+    for event in events {
+      println!("Event: {}", event);
+    }
     print_updates(&client);
   }
 }
@@ -0,0 +1,641 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! A local JSON API over a polling client, so other services can read
+//! devices and send commands without each logging into the gateway.
+//!
+//! `ApiServer` wraps a logged-in `RazberryClient`. Call `poll` on a timer,
+//! and mount the handler from `get_handler` on a hyper server:
+//!
+//!   GET  /devices                 Every device, by ID.
+//!   GET  /devices/{id}            One device, with its current readings.
+//!   GET  /devices/{id}/commands   The device's command classes.
+//!   POST /devices/{id}/commands   Run a method, eg. {"instance": 0,
+//!                                 "commandClass": "SwitchBinary",
+//!                                 "method": "Set", "args": [255]}.
+//!   GET  /events                  A Server-Sent Events stream of changes.
+//!
+//! Each event is sent as JSON, with an "id" that clients may resume from
+//! with the `Last-Event-ID` header. User codes are never sent. The
+//! `razberry_api` binary runs a server.
+
+use client::RazberryClient;
+use command_class::user_code::is_in_code;
+use command_class::user_code::remove_codes;
+use command_classes::CommandClasses;
+use device::Device;
+use error::RazberryError;
+use event::Event;
+use hyper::header::CacheControl;
+use hyper::header::CacheDirective;
+use hyper::header::ContentType;
+use hyper::server::Handler;
+use hyper::server::Request;
+use hyper::server::Response;
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+use network_state::NetworkState;
+use readings::get_string;
+use readings::readings;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::io::Read;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The data changes streamed as events.
+const CHANGE_PATTERN : &'static str = "devices.*.instances.*.commandClasses.*.data.**";
+
+/// Events kept for clients resuming with `Last-Event-ID`.
+const EVENT_CAPACITY : usize = 1000;
+
+/// How often an idle event stream sends a comment, to notice closed
+/// connections.
+const KEEPALIVE_SECS : u64 = 15;
+
+/**
+ * Wraps a client, and publishes the events from each poll.
+ */
+pub struct ApiServer {
+  client: Arc<Mutex<RazberryClient>>,
+  events: Arc<EventLog>,
+}
+
+/**
+ * Serves the API from a hyper server. Use a thread per open event stream,
+ * eg. with `Server::handle_threads`.
+ */
+pub struct ApiHandler {
+  client: Arc<Mutex<RazberryClient>>,
+  events: Arc<EventLog>,
+}
+
+/**
+ * Recent events, numbered from 1, and a signal for new ones.
+ */
+struct EventLog {
+  log: Mutex<EventBuffer>,
+  added: Condvar,
+}
+
+struct EventBuffer {
+  next_id: u64,
+  events: VecDeque<(u64, String)>,
+}
+
+impl ApiServer {
+  /// Wrap a logged-in client, and subscribe it to the changes to stream.
+  pub fn new(mut client: RazberryClient) -> Result<ApiServer, RazberryError> {
+    client.subscribe(CHANGE_PATTERN)?;
+
+    Ok(ApiServer {
+      client: Arc::new(Mutex::new(client)),
+      events: Arc::new(EventLog {
+        log: Mutex::new(EventBuffer { next_id: 1, events: VecDeque::new() }),
+        added: Condvar::new(),
+      }),
+    })
+  }
+
+  /// The wrapped client, eg. to log in again when the session expires.
+  pub fn get_client(&self) -> Arc<Mutex<RazberryClient>> {
+    self.client.clone()
+  }
+
+  /// Poll the gateway, and send the events to open event streams.
+  pub fn poll(&self) -> Result<Vec<Event>, RazberryError> {
+    let events = self.client.lock()
+        .map_err(|_| RazberryError::ClientError)?
+        .poll_updates()?;
+
+    self.events.publish(&events);
+    Ok(events)
+  }
+
+  /// A handler for a hyper server.
+  pub fn get_handler(&self) -> ApiHandler {
+    ApiHandler {
+      client: self.client.clone(),
+      events: self.events.clone(),
+    }
+  }
+}
+
+impl ApiHandler {
+  /// Answer a JSON request, returning the status and body.
+  pub fn respond(&self, method: &str, path: &str, body: &str) -> (u16, Json) {
+    let segments = path.trim_matches('/').split("/").collect::<Vec<&str>>();
+
+    let client = match self.client.lock() {
+      Ok(client) => client,
+      Err(_) => return error(500, "Internal error"),
+    };
+
+    let state = match client.get_state() {
+      Some(state) => state,
+      None => return error(503, "Devices are not loaded"),
+    };
+
+    if segments[0] != "devices" || segments.len() > 3
+        || (segments.len() == 3 && segments[2] != "commands") {
+      return error(404, "Not found");
+    }
+
+    let device = match segments.get(1) {
+      None => None,
+      Some(device_id) => match state.get_device(device_id) {
+        None => return error(404, "No such device"),
+        Some(device) => Some(device),
+      },
+    };
+
+    match (method, device, segments.len()) {
+      ("GET", None, _) => {
+        let mut devices = state.get_devices();
+        devices.sort_by_key(|d| (d.id.parse::<u32>().unwrap_or(u32::max_value()), d.id.clone()));
+        (200, Json::Array(devices.into_iter().map(|d| device_json(state, d)).collect()))
+      },
+      ("GET", Some(device), 2) => (200, device_detail_json(state, device)),
+      ("GET", Some(device), _) => (200, commands_json(state, device)),
+      ("POST", Some(device), 3) => {
+        let command = match command(state, device, body) {
+          Err(_) => return error(400, "Expected {\"instance\", \"commandClass\", \
+            \"method\", \"args\"}, with number, boolean or alphanumeric string args"),
+          Ok(command) => command,
+        };
+
+        match client.run_command(&command) {
+          Ok(result) => {
+            let mut object = BTreeMap::new();
+            object.insert("command".to_string(), Json::String(command));
+            object.insert("result".to_string(), result);
+            (200, Json::Object(object))
+          },
+          Err(RazberryError::BadCredentials) => error(503, "Not logged in"),
+          Err(_) => error(502, "The gateway rejected the command"),
+        }
+      },
+      _ => error(405, "Method not allowed"),
+    }
+  }
+
+  /// Stream events after the given ID, or only new events, until the
+  /// connection closes.
+  fn stream_events(&self, last_event_id: Option<u64>, mut response: Response) {
+    response.headers_mut().set(ContentType("text/event-stream".parse().unwrap()));
+    response.headers_mut().set(CacheControl(vec![CacheDirective::NoCache]));
+
+    let mut stream = match response.start() {
+      Ok(stream) => stream,
+      Err(_) => return,
+    };
+
+    let mut after = match last_event_id {
+      Some(id) => self.events.get_resume_id(id),
+      None => self.events.get_last_id(),
+    };
+
+    loop {
+      let events = self.events.wait_after(after, Duration::from_secs(KEEPALIVE_SECS));
+
+      let mut text = String::new();
+      if events.is_empty() {
+        text.push_str(": keepalive\n\n");
+      }
+
+      for (id, data) in events {
+        text.push_str(&format_event(id, &data));
+        after = id;
+      }
+
+      if stream.write_all(text.as_bytes()).and_then(|_| stream.flush()).is_err() {
+        return;
+      }
+    }
+  }
+}
+
+impl Handler for ApiHandler {
+  fn handle(&self, mut request: Request, mut response: Response) {
+    let path = match request.uri {
+      RequestUri::AbsolutePath(ref path) => path.split("?").next().unwrap_or("").to_string(),
+      _ => String::new(),
+    };
+
+    if path == "/events" {
+      let last_event_id = request.headers.get_raw("Last-Event-ID")
+          .and_then(|lines| lines.first())
+          .and_then(|line| String::from_utf8_lossy(line).trim().parse().ok());
+
+      self.stream_events(last_event_id, response);
+      return;
+    }
+
+    let mut body = String::new();
+    let _ = request.read_to_string(&mut body);
+
+    let (status, json) = self.respond(&request.method.to_string(), &path, &body);
+
+    *response.status_mut() = StatusCode::from_u16(status);
+    response.headers_mut().set(ContentType::json());
+    let _ = response.send(json.pretty().to_string().as_bytes());
+  }
+}
+
+impl EventLog {
+  fn publish(&self, events: &[Event]) {
+    if events.is_empty() {
+      return;
+    }
+
+    if let Ok(mut log) = self.log.lock() {
+      for json in events.iter().filter_map(event_json) {
+        let id = log.next_id;
+        log.next_id += 1;
+        log.events.push_back((id, json.to_string()));
+
+        if log.events.len() > EVENT_CAPACITY {
+          log.events.pop_front();
+        }
+      }
+    }
+
+    self.added.notify_all();
+  }
+
+  fn get_last_id(&self) -> u64 {
+    self.log.lock().map(|log| log.next_id - 1).unwrap_or(0)
+  }
+
+  /// Where to resume a client that last saw the given ID. IDs start again
+  /// from 1 when the server restarts, so an ID beyond the log was seen
+  /// before then, and every kept event is resent.
+  fn get_resume_id(&self, last_event_id: u64) -> u64 {
+    let log = match self.log.lock() {
+      Ok(log) => log,
+      Err(_) => return last_event_id,
+    };
+
+    if last_event_id < log.next_id {
+      return last_event_id;
+    }
+
+    log.events.front().map(|&(id, _)| id - 1).unwrap_or(log.next_id - 1)
+  }
+
+  /// Get the events after an ID, waiting up to the timeout for one.
+  fn wait_after(&self, after: u64, timeout: Duration) -> Vec<(u64, String)> {
+    let mut log = match self.log.lock() {
+      Ok(log) => log,
+      Err(_) => return Vec::new(),
+    };
+
+    if log.next_id - 1 <= after {
+      log = match self.added.wait_timeout(log, timeout) {
+        Ok((log, _)) => log,
+        Err(_) => return Vec::new(),
+      };
+    }
+
+    log.events.iter()
+        .filter(|&&(id, _)| id > after)
+        .cloned()
+        .collect()
+  }
+}
+
+/// Encode an event to stream, without any user codes it carries. Changes to
+/// a code itself are not streamed at all.
+fn event_json(event: &Event) -> Option<Json> {
+  let mut json = event.to_json();
+
+  if let Event::DataChanged { ref change } = *event {
+    let path = change.path.split(".").collect::<Vec<&str>>();
+    if is_in_code(&path) {
+      return None;
+    }
+
+    if let Some(value) = json.as_object_mut().and_then(|o| o.get_mut("value")) {
+      remove_codes(value, &path);
+    }
+  }
+
+  Some(json)
+}
+
+/// Format a Server-Sent Event.
+fn format_event(id: u64, data: &str) -> String {
+  format!("id: {}\ndata: {}\n\n", id, data)
+}
+
+fn error(status: u16, message: &str) -> (u16, Json) {
+  let mut object = BTreeMap::new();
+  object.insert("error".to_string(), Json::String(message.to_string()));
+  (status, Json::Object(object))
+}
+
+fn class_name(command_class: CommandClasses) -> String {
+  command_class.get_name()
+      .map(|name| name.to_string())
+      .unwrap_or(format!("0x{:02X}", command_class.to_byte()))
+}
+
+fn device_json(state: &NetworkState, device: &Device) -> Json {
+  let failed = state.get_data()
+      .find_dotted(&format!("devices.{}.data.isFailed", device.id))
+      .and_then(|f| f.get_value().as_bool())
+      .unwrap_or(false);
+
+  let mut object = BTreeMap::new();
+  object.insert("id".to_string(), Json::String(device.id.to_string()));
+  object.insert("name".to_string(), Json::String(device.name.to_string()));
+  object.insert("lastContacted".to_string(), Json::I64(device.last_contacted.timestamp()));
+  object.insert("failed".to_string(), Json::Boolean(failed));
+  object.insert("commandClasses".to_string(), Json::Array(
+    device.supported_command_classes.iter()
+        .map(|c| Json::String(class_name(*c)))
+        .collect()));
+  Json::Object(object)
+}
+
+/// A device with its current readings.
+fn device_detail_json(state: &NetworkState, device: &Device) -> Json {
+  let values = readings(state).into_iter()
+      .filter(|r| r.device_id == device.id)
+      .map(|reading| {
+        let mut object = BTreeMap::new();
+        object.insert("instance".to_string(), Json::String(reading.instance.to_string()));
+        object.insert("commandClass".to_string(),
+          Json::String(class_name(reading.command_class)));
+        object.insert("sensor".to_string(), Json::String(reading.sensor.to_string()));
+        object.insert("value".to_string(), reading.value.get_value().to_json());
+
+        if let Some(update_time) = reading.value.get_update_time() {
+          object.insert("updateTime".to_string(), Json::I64(update_time.timestamp()));
+        }
+        if let Some(sensor_type) = get_string(&reading, "sensorTypeString") {
+          object.insert("type".to_string(), Json::String(sensor_type.to_string()));
+        }
+        if let Some(scale) = get_string(&reading, "scaleString") {
+          object.insert("scale".to_string(), Json::String(scale.to_string()));
+        }
+
+        Json::Object(object)
+      })
+      .collect();
+
+  let mut json = device_json(state, device);
+  if let Json::Object(ref mut object) = json {
+    object.insert("readings".to_string(), Json::Array(values));
+  }
+  json
+}
+
+/// The command classes of each of a device's instances.
+fn commands_json(state: &NetworkState, device: &Device) -> Json {
+  let mut commands = Vec::new();
+
+  let instances = state.get_data()
+      .find_dotted(&format!("devices.{}.instances", device.id))
+      .map(|i| i.get_children().iter().collect::<Vec<_>>())
+      .unwrap_or(Vec::new());
+
+  for (instance, holder) in instances {
+    let classes = holder.get_child("commandClasses")
+        .map(|c| c.get_children().keys().filter_map(|k| k.parse::<u8>().ok()).collect())
+        .unwrap_or(Vec::new());
+
+    for id in classes {
+      let mut object = BTreeMap::new();
+      object.insert("instance".to_string(), Json::String(instance.to_string()));
+      object.insert("commandClass".to_string(),
+        Json::String(class_name(CommandClasses::from_byte(id))));
+      object.insert("id".to_string(), Json::U64(id as u64));
+      commands.push(Json::Object(object));
+    }
+  }
+
+  Json::Array(commands)
+}
+
+/// Build the '/ZWaveAPI/Run' expression for a command request. The method
+/// and arguments are checked by `RazberryClient::format_method`.
+fn command(state: &NetworkState, device: &Device, body: &str)
+    -> Result<String, RazberryError> {
+  let json = Json::from_str(body)?;
+
+  let instance = match json.find("instance") {
+    None => 0,
+    Some(&Json::String(ref instance)) => instance.parse::<u64>()
+        .map_err(|_| RazberryError::InvalidArgument)?,
+    Some(instance) => instance.as_u64()
+        .ok_or(RazberryError::InvalidArgument)?,
+  };
+
+  let command_class = match json.find("commandClass") {
+    Some(&Json::String(ref name)) => CommandClasses::all().into_iter()
+        .find(|c| c.get_name() == Some(name.as_str()))
+        .ok_or(RazberryError::InvalidArgument)?,
+    Some(id) => id.as_u64()
+        .filter(|id| *id <= 255)
+        .map(|id| CommandClasses::from_byte(id as u8))
+        .ok_or(RazberryError::InvalidArgument)?,
+    None => return Err(RazberryError::InvalidArgument),
+  };
+
+  let name = json.find("method").and_then(|m| m.as_string())
+      .ok_or(RazberryError::InvalidArgument)?;
+
+  let method = match json.find("args") {
+    None => RazberryClient::format_method(name, &[])?,
+    Some(&Json::Array(ref args)) => RazberryClient::format_method(name, args)?,
+    Some(_) => return Err(RazberryError::InvalidArgument),
+  };
+
+  let path = format!("devices.{}.instances.{}.commandClasses.{}", device.id, instance,
+    command_class.to_byte());
+
+  if state.get_data().find_dotted(&path).is_none() {
+    return Err(RazberryError::InvalidArgument);
+  }
+
+  Ok(format!("devices[{}].instances[{}].commandClasses[{}].{}", device.id, instance,
+    command_class.to_byte(), method))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::TimeZone;
+  use chrono::UTC;
+  use data_holder::DataHolder;
+  use mock::MockGateway;
+  use mock::test_client;
+  use simulator::Change;
+  use simulator::Simulator;
+  use simulator::VirtualClass;
+  use subscription::DataChange;
+  use subscription::SubscriptionId;
+
+  fn server() -> (Arc<Mutex<MockGateway>>, ApiServer) {
+    let mut simulator = Simulator::new(1500000000);
+    simulator.add_node("2", "Hall motion", &[
+      VirtualClass::SensorBinary,
+      VirtualClass::Battery,
+    ]).unwrap();
+    simulator.add_node("3", "Lamp", &[VirtualClass::SwitchBinary]).unwrap();
+
+    let gateway = Arc::new(Mutex::new(MockGateway::from_simulator(simulator)));
+    let client = test_client(&gateway);
+
+    (gateway, ApiServer::new(client).unwrap())
+  }
+
+  #[test]
+  fn test_devices() {
+    let (_, server) = server();
+    let handler = server.get_handler();
+
+    let (status, devices) = handler.respond("GET", "/devices", "");
+    assert_eq!(200, status);
+    let ids = devices.as_array().unwrap().iter()
+        .map(|d| d.find("id").unwrap().as_string().unwrap())
+        .collect::<Vec<&str>>();
+    assert_eq!(vec!["2", "3"], ids);
+
+    let (status, device) = handler.respond("GET", "/devices/2", "");
+    assert_eq!(200, status);
+    assert_eq!(Some("Hall motion"), device.find("name").and_then(|n| n.as_string()));
+    assert_eq!(Some(false), device.find("failed").and_then(|f| f.as_boolean()));
+
+    let readings = device.find("readings").unwrap().as_array().unwrap();
+    assert_eq!(2, readings.len());
+    assert_eq!(Some("SensorBinary"),
+      readings[0].find("commandClass").and_then(|c| c.as_string()));
+    assert_eq!(Some("General purpose"), readings[0].find("type").and_then(|t| t.as_string()));
+    assert_eq!(Some(&Json::Boolean(false)), readings[0].find("value"));
+    assert_eq!(Some(&Json::I64(100)), readings[1].find("value"));
+
+    let (status, commands) = handler.respond("GET", "/devices/3/commands", "");
+    assert_eq!(200, status);
+    assert!(commands.as_array().unwrap().iter()
+        .any(|c| c.find("commandClass").and_then(|c| c.as_string()) == Some("SwitchBinary")));
+
+    assert_eq!(404, handler.respond("GET", "/devices/9", "").0);
+    assert_eq!(404, handler.respond("GET", "/devices/2/other", "").0);
+    assert_eq!(405, handler.respond("POST", "/devices", "").0);
+  }
+
+  #[test]
+  fn test_command_and_events() {
+    let (gateway, server) = server();
+    let handler = server.get_handler();
+
+    let (status, result) = handler.respond("POST", "/devices/3/commands",
+      r#"{"instance": 0, "commandClass": "SwitchBinary", "method": "Set", "args": [255]}"#);
+    assert_eq!(200, status);
+    assert_eq!(Some("devices[3].instances[0].commandClasses[37].Set(255)"),
+      result.find("command").and_then(|c| c.as_string()));
+
+    let after = server.events.get_last_id();
+    gateway.lock().unwrap().advance(1).unwrap();
+    server.poll().unwrap();
+
+    let events = server.events.wait_after(after, Duration::from_millis(0));
+    assert_eq!(1, events.len());
+    assert!(events[0].1.contains("\"path\":\"devices.3.instances.0.commandClasses.37.data.level\""));
+    assert!(format_event(events[0].0, &events[0].1).starts_with("id: 1\ndata: {"));
+
+    let (_, device) = handler.respond("GET", "/devices/3", "");
+    let readings = device.find("readings").unwrap().as_array().unwrap();
+    assert_eq!(Some(&Json::Boolean(true)), readings[0].find("value"));
+
+    gateway.lock().unwrap().get_simulator_mut().schedule(5, "2", Change::Battery(40));
+    gateway.lock().unwrap().advance(10).unwrap();
+    server.poll().unwrap();
+    assert_eq!(1, server.events.wait_after(1, Duration::from_millis(0)).len());
+  }
+
+  #[test]
+  fn test_resume_after_restart() {
+    let (gateway, server) = server();
+    assert_eq!(0, server.events.get_resume_id(7));
+
+    for level in &[40, 30, 20] {
+      gateway.lock().unwrap().get_simulator_mut().schedule(1, "2", Change::Battery(*level));
+      gateway.lock().unwrap().advance(5).unwrap();
+      server.poll().unwrap();
+    }
+    assert_eq!(3, server.events.get_last_id());
+
+    assert_eq!(2, server.events.get_resume_id(2));
+    assert_eq!(3, server.events.get_resume_id(3));
+
+    // An ID from before a restart.
+    let after = server.events.get_resume_id(500);
+    assert_eq!(0, after);
+    assert_eq!(3, server.events.wait_after(after, Duration::from_millis(0)).len());
+  }
+
+  #[test]
+  fn test_events_omit_user_codes() {
+    let data = Json::from_str(r#"{
+      "value": null, "type": "empty", "updateTime": 1500000000,
+      "1": {
+        "value": null, "type": "empty", "updateTime": 1500000000,
+        "status": {"value": 1, "type": "int", "updateTime": 1500000000},
+        "code": {"value": "1234", "type": "string", "updateTime": 1500000000}
+      }
+    }"#).unwrap();
+
+    let change = |path: &str, data: &Json| Event::DataChanged {
+      change: DataChange {
+        subscription: SubscriptionId(0),
+        path: path.to_string(),
+        captures: Vec::new(),
+        data: DataHolder::from_json(data).unwrap(),
+        timestamp: UTC.timestamp(1500000000, 0),
+      },
+    };
+
+    let path = "devices.5.instances.0.commandClasses.99.data";
+    let json = event_json(&change(path, &data)).unwrap();
+    let value = json.find("value").unwrap();
+    assert!(value.find_path(&["1", "status"]).is_some());
+    assert!(value.find_path(&["1", "code"]).is_none());
+    assert!(!json.to_string().contains("1234"));
+
+    let code = data.find_path(&["1", "code"]).unwrap();
+    assert_eq!(None, event_json(&change(&format!("{}.1.code", path), code)));
+
+    let (_, server) = server();
+    server.events.publish(&[change(path, &data), change(&format!("{}.1.code", path), code)]);
+    let events = server.events.wait_after(0, Duration::from_millis(0));
+    assert_eq!(1, events.len());
+    assert!(!events[0].1.contains("1234"));
+  }
+
+  #[test]
+  fn test_command_validation() {
+    let (_, server) = server();
+    let handler = server.get_handler();
+
+    for body in &[
+      "not json",
+      r#"{"commandClass": "SwitchBinary", "method": "Set(1);Evil", "args": []}"#,
+      r#"{"commandClass": "SwitchBinary", "method": "Set", "args": ["a\"); Evil(\""]}"#,
+      r#"{"commandClass": "SwitchBinary", "method": "Set", "args": [[1]]}"#,
+      r#"{"commandClass": "Unknown", "method": "Set"}"#,
+      r#"{"commandClass": "SensorBinary", "method": "Get"}"#,
+      r#"{"instance": 4, "commandClass": 37, "method": "Get"}"#,
+    ] {
+      assert_eq!(400, handler.respond("POST", "/devices/3/commands", body).0, "{}", body);
+    }
+
+    assert_eq!(200, handler.respond("POST", "/devices/3/commands",
+      r#"{"instance": "0", "commandClass": 37, "method": "Get"}"#).0);
+  }
+}
@@ -0,0 +1,43 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Serve a mock Z-Way gateway for integration testing.
+//!
+//!   mock_gateway [address] [data.json] [username] [password]
+//!
+//! Defaults to 127.0.0.1:8083, sample_data/data.json and admin / admin.
+
+extern crate razberry;
+extern crate rustc_serialize;
+
+use razberry::mock::MockGateway;
+use razberry::mock::MockServer;
+use rustc_serialize::json::Json;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+pub fn main() {
+  let args : Vec<String> = env::args().collect();
+
+  let address = args.get(1).map(|a| a.as_str()).unwrap_or("127.0.0.1:8083");
+  let seed = args.get(2).map(|a| a.as_str()).unwrap_or("sample_data/data.json");
+  let username = args.get(3).map(|a| a.as_str()).unwrap_or("admin");
+  let password = args.get(4).map(|a| a.as_str()).unwrap_or("admin");
+
+  let mut contents = String::new();
+  File::open(seed)
+      .and_then(|mut f| f.read_to_string(&mut contents))
+      .expect("Could not read the seed data");
+
+  let json = Json::from_str(&contents).expect("Seed data is not JSON");
+
+  let mut gateway = MockGateway::new(&json).expect("Seed data is not Z-Way data");
+  gateway.set_credentials(username, password);
+
+  let server = MockServer::start(address, Arc::new(Mutex::new(gateway)))
+      .expect("Could not start the server");
+
+  println!("Mock gateway listening on {}", server.get_address());
+}
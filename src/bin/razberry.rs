@@ -0,0 +1,441 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Use a Razberry gateway from the command line.
+//!
+//!   razberry [--host HOST[:PORT]] [--json] [--include-codes] COMMAND [ARGS]
+//!
+//! Commands:
+//!
+//!   login USERNAME PASSWORD   Log in and store the session token.
+//!   devices                   List the devices.
+//!   show DEVICE               Show a device's command classes.
+//!   watch [DEVICE]            Stream change events until interrupted.
+//!   get DEVICE                Get a switch or dimmer level.
+//!   set DEVICE on|off|LEVEL   Turn a switch on or off, or move a dimmer.
+//!   run EXPRESSION            Run a command or query on the gateway.
+//!   dump                      Print the full gateway data as JSON.
+//!
+//! `login` stores the host and session token in the file named by
+//! `RAZBERRY_SESSION`, or `~/.razberry-session`; the other commands reuse
+//! them. `--json` prints JSON in place of tables. The data printed by
+//! `show --json` and `dump` leaves out user codes (PINs) unless
+//! `--include-codes` is given.
+
+extern crate razberry;
+extern crate rustc_serialize;
+
+use razberry::CommandClasses;
+use razberry::DataValue;
+use razberry::Device;
+use razberry::RazberryClient;
+use razberry::RazberryError;
+use razberry::command_class::user_code::remove_codes;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::fs;
+use std::io::Read;
+use std::io::Write;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+
+const USAGE : &'static str = "\
+Usage: razberry [--host HOST[:PORT]] [--json] [--include-codes] COMMAND [ARGS]
+
+Commands:
+  login USERNAME PASSWORD   Log in and store the session token.
+  devices                   List the devices.
+  show DEVICE               Show a device's command classes.
+  watch [DEVICE]            Stream change events until interrupted.
+  get DEVICE                Get a switch or dimmer level.
+  set DEVICE on|off|LEVEL   Turn a switch on or off, or move a dimmer.
+  run EXPRESSION            Run a command or query on the gateway.
+  dump                      Print the full gateway data as JSON.
+
+User codes are left out of printed data unless --include-codes is given.";
+
+/// Options shared by every command.
+struct Options {
+  host: Option<String>,
+  json: bool,
+  include_codes: bool,
+}
+
+/// The stored login.
+struct Session {
+  host: String,
+  token: Option<String>,
+}
+
+pub fn main() {
+  let mut options = Options { host: None, json: false, include_codes: false };
+  let mut args = Vec::new();
+
+  let mut iter = env::args().skip(1);
+  while let Some(arg) = iter.next() {
+    match arg.as_str() {
+      "--json" => options.json = true,
+      "--include-codes" => options.include_codes = true,
+      "--host" => options.host = iter.next(),
+      "-h" | "--help" => exit_with_usage(0),
+      _ => args.push(arg),
+    }
+  }
+
+  let command = args.get(0).map(|a| a.as_str()).unwrap_or("");
+  let arg = |i: usize| args[i].as_str();
+
+  let result = match (command, args.len()) {
+    ("login", 3) => login(&options, arg(1), arg(2)),
+    ("devices", 1) => devices(&options),
+    ("show", 2) => show(&options, arg(1)),
+    ("watch", 1) => watch(&options, None),
+    ("watch", 2) => watch(&options, Some(arg(1))),
+    ("get", 2) => get(&options, arg(1)),
+    ("set", 3) => set(&options, arg(1), arg(2)),
+    ("run", 2) => run(&options, arg(1)),
+    ("dump", 1) => dump(&options),
+    _ => exit_with_usage(2),
+  };
+
+  if let Err(error) = result {
+    let _ = writeln!(std::io::stderr(), "razberry: {}", describe(&error));
+    process::exit(1);
+  }
+}
+
+fn exit_with_usage(code: i32) -> ! {
+  let _ = writeln!(std::io::stderr(), "{}", USAGE);
+  process::exit(code);
+}
+
+fn describe(error: &RazberryError) -> String {
+  match *error {
+    RazberryError::BadCredentials => {
+      "The gateway refused the session. Run 'razberry login' again.".to_string()
+    },
+    RazberryError::InvalidArgument => "Invalid argument.".to_string(),
+    ref other => format!("{:?}", other),
+  }
+}
+
+fn login(options: &Options, username: &str, password: &str)
+    -> Result<(), RazberryError> {
+  let host = options.host.clone()
+      .or_else(|| read_session().map(|s| s.host))
+      .unwrap_or("localhost".to_string());
+
+  let mut client = RazberryClient::for_address(&host)?;
+  client.login(username, password)?;
+
+  write_session(&Session {
+    host: host.clone(),
+    token: client.get_session_token(),
+  })?;
+
+  if !options.json {
+    println!("Logged in to {}.", host);
+  }
+
+  Ok(())
+}
+
+fn devices(options: &Options) -> Result<(), RazberryError> {
+  let client = loaded_client(options)?;
+
+  let mut devices = client.get_devices();
+  devices.sort_by_key(|d| d.id.parse::<u32>().unwrap_or(u32::max_value()));
+
+  if options.json {
+    let devices = devices.iter().map(|d| device_json(d)).collect();
+    println!("{}", Json::Array(devices).pretty());
+    return Ok(());
+  }
+
+  let rows = devices.iter().map(|d| {
+    vec![
+      d.id.to_string(),
+      d.name.to_string(),
+      d.last_contacted.format("%Y-%m-%d %H:%M:%S").to_string(),
+      class_names(d).join(", "),
+    ]
+  }).collect();
+
+  print_table(&["ID", "NAME", "LAST CONTACTED", "CLASSES"], rows);
+  Ok(())
+}
+
+fn show(options: &Options, device_id: &str) -> Result<(), RazberryError> {
+  let client = loaded_client(options)?;
+  let device = find_device(&client, device_id)?;
+
+  if options.json {
+    let mut data = client.get_state()
+        .and_then(|s| s.get_data().find_dotted(&format!("devices.{}", device_id)))
+        .map(|h| h.to_json())
+        .unwrap_or(Json::Null);
+
+    if !options.include_codes {
+      remove_codes(&mut data, &["devices", device_id]);
+    }
+
+    println!("{}", data.pretty());
+    return Ok(());
+  }
+
+  println!("{} ({})", device.name, device.id);
+  println!("  Last contacted: {}", device.last_contacted);
+
+  let mut classes = device.supported_command_classes.clone();
+  classes.sort_by_key(|cc| cc.to_byte());
+
+  for command_class in classes {
+    let version = device.command_class_versions.get(&command_class)
+        .map(|v| format!(" v{}", v))
+        .unwrap_or(String::new());
+
+    match device.command_classes.get(&command_class) {
+      Some(state) => println!("  {}{}: {}", class_name(&command_class), version, state),
+      None => println!("  {}{}", class_name(&command_class), version),
+    }
+  }
+
+  Ok(())
+}
+
+fn watch(options: &Options, device_id: Option<&str>) -> Result<(), RazberryError> {
+  let mut client = loaded_client(options)?;
+
+  let pattern = format!("devices.{}.instances.*.commandClasses.*.data.**",
+    device_id.unwrap_or("*"));
+  client.subscribe(&pattern)?;
+
+  loop {
+    thread::sleep(Duration::from_secs(1));
+
+    for event in client.poll_updates()? {
+      if options.json {
+        println!("{}", event.to_json());
+      } else {
+        println!("{}", event);
+      }
+    }
+  }
+}
+
+fn get(options: &Options, device_id: &str) -> Result<(), RazberryError> {
+  let client = loaded_client(options)?;
+  let device = find_device(&client, device_id)?;
+
+  let command_class = switch_class(device).ok_or(RazberryError::InvalidArgument)?;
+  let expression = format!("devices[{}].instances[0].commandClasses[{}].data.level",
+    device_id, command_class.to_byte());
+
+  let level = client.query(&expression)?.ok_or(RazberryError::BadResponse)?;
+
+  if options.json {
+    let mut object = BTreeMap::new();
+    object.insert("device".to_string(), Json::String(device_id.to_string()));
+    object.insert("level".to_string(), level.get_value().to_json());
+    println!("{}", Json::Object(object));
+    return Ok(());
+  }
+
+  match *level.get_value() {
+    DataValue::Bool(true) => println!("on"),
+    DataValue::Bool(false) => println!("off"),
+    ref other => println!("{}", other),
+  }
+
+  Ok(())
+}
+
+fn set(options: &Options, device_id: &str, level: &str) -> Result<(), RazberryError> {
+  let client = loaded_client(options)?;
+  let device = find_device(&client, device_id)?;
+
+  let command_class = switch_class(device).ok_or(RazberryError::InvalidArgument)?;
+
+  let level = match level {
+    "on" => 99,
+    "off" => 0,
+    other => other.parse::<u8>().map_err(|_| RazberryError::InvalidArgument)?,
+  };
+
+  match command_class {
+    CommandClasses::SwitchMultilevel => client.set_dimmer(device_id, 0, level),
+    _ => client.set_switch(device_id, 0, level > 0),
+  }
+}
+
+fn run(options: &Options, expression: &str) -> Result<(), RazberryError> {
+  let client = stored_client(options)?;
+  let result = client.run_command(expression)?;
+  println!("{}", result.pretty());
+  Ok(())
+}
+
+fn dump(options: &Options) -> Result<(), RazberryError> {
+  let client = loaded_client(options)?;
+
+  let mut data = client.get_state()
+      .map(|s| s.get_data().to_json())
+      .unwrap_or(Json::Null);
+
+  if let (Some(state), Some(object)) = (client.get_state(), data.as_object_mut()) {
+    object.insert("updateTime".to_string(),
+      Json::I64(state.get_update_time().timestamp()));
+  }
+
+  if !options.include_codes {
+    remove_codes(&mut data, &[]);
+  }
+
+  println!("{}", data.pretty());
+  Ok(())
+}
+
+/// Prefer the dimmer, which reports a level, over the switch.
+fn switch_class(device: &Device) -> Option<CommandClasses> {
+  [CommandClasses::SwitchMultilevel, CommandClasses::SwitchBinary].iter()
+      .find(|cc| device.supported_command_classes.contains(cc))
+      .map(|cc| *cc)
+}
+
+fn find_device<'a>(client: &'a RazberryClient, device_id: &str)
+    -> Result<&'a Device, RazberryError> {
+  client.get_state()
+      .and_then(|s| s.get_device(device_id))
+      .ok_or(RazberryError::InvalidArgument)
+}
+
+fn class_name(command_class: &CommandClasses) -> String {
+  command_class.get_name()
+      .map(|name| name.to_string())
+      .unwrap_or(format!("0x{:02X}", command_class.to_byte()))
+}
+
+fn class_names(device: &Device) -> Vec<String> {
+  device.supported_command_classes.iter().map(class_name).collect()
+}
+
+fn device_json(device: &Device) -> Json {
+  let mut object = BTreeMap::new();
+  object.insert("id".to_string(), Json::String(device.id.to_string()));
+  object.insert("name".to_string(), Json::String(device.name.to_string()));
+  object.insert("lastContacted".to_string(),
+    Json::I64(device.last_contacted.timestamp()));
+  object.insert("commandClasses".to_string(),
+    Json::Array(class_names(device).into_iter().map(Json::String).collect()));
+  Json::Object(object)
+}
+
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+  let mut widths = headers.iter().map(|h| h.chars().count()).collect::<Vec<usize>>();
+
+  for row in &rows {
+    for (i, cell) in row.iter().enumerate() {
+      widths[i] = widths[i].max(cell.chars().count());
+    }
+  }
+
+  let headers = headers.iter().map(|h| h.to_string()).collect::<Vec<String>>();
+
+  for row in Some(headers).into_iter().chain(rows) {
+    let line = row.iter().enumerate()
+        .map(|(i, cell)| {
+          let padding = widths[i] - cell.chars().count();
+          format!("{}{}", cell, " ".repeat(padding))
+        })
+        .collect::<Vec<String>>()
+        .join("  ");
+    println!("{}", line.trim_right());
+  }
+}
+
+
+/// A client for the stored session, or `--host` without one.
+fn stored_client(options: &Options) -> Result<RazberryClient, RazberryError> {
+  let session = read_session();
+
+  let host = options.host.clone()
+      .or_else(|| session.as_ref().map(|s| s.host.clone()))
+      .unwrap_or("localhost".to_string());
+
+  let mut client = RazberryClient::for_address(&host)?;
+  client.set_session_token(session.and_then(|s| s.token));
+  Ok(client)
+}
+
+fn loaded_client(options: &Options) -> Result<RazberryClient, RazberryError> {
+  let mut client = stored_client(options)?;
+  client.load_devices()?;
+  Ok(client)
+}
+
+fn session_path() -> PathBuf {
+  match env::var_os("RAZBERRY_SESSION") {
+    Some(path) => PathBuf::from(path),
+    None => {
+      let home = env::var_os("HOME").map(PathBuf::from).unwrap_or(PathBuf::from("."));
+      home.join(".razberry-session")
+    },
+  }
+}
+
+fn read_session() -> Option<Session> {
+  let mut contents = String::new();
+  File::open(session_path())
+      .and_then(|mut f| f.read_to_string(&mut contents))
+      .ok()?;
+
+  let json = Json::from_str(&contents).ok()?;
+
+  Some(Session {
+    host: json.find("host").and_then(|h| h.as_string())?.to_string(),
+    token: json.find("token").and_then(|t| t.as_string()).map(|t| t.to_string()),
+  })
+}
+
+fn write_session(session: &Session) -> Result<(), RazberryError> {
+  let mut object = BTreeMap::new();
+  object.insert("host".to_string(), Json::String(session.host.to_string()));
+  if let Some(ref token) = session.token {
+    object.insert("token".to_string(), Json::String(token.to_string()));
+  }
+
+  create_private(&session_path())
+      .and_then(|mut f| writeln!(f, "{}", Json::Object(object)))
+      .map_err(|_| RazberryError::ClientError)
+}
+
+/// Create or truncate a file readable only by the user, since the session
+/// file holds a live session token. An existing file's permissions are
+/// tightened too.
+#[cfg(unix)]
+fn create_private(path: &Path) -> io::Result<File> {
+  use std::os::unix::fs::OpenOptionsExt;
+  use std::os::unix::fs::PermissionsExt;
+
+  let file = OpenOptions::new()
+      .write(true)
+      .create(true)
+      .truncate(true)
+      .mode(0o600)
+      .open(path)?;
+
+  file.set_permissions(fs::Permissions::from_mode(0o600))?;
+  Ok(file)
+}
+
+#[cfg(not(unix))]
+fn create_private(path: &Path) -> io::Result<File> {
+  File::create(path)
+}
@@ -0,0 +1,89 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Serve a local JSON API and event stream for a Razberry gateway.
+//!
+//!   razberry_api HOST[:PORT] USERNAME PASSWORD [LISTEN]
+//!
+//! Listens on 127.0.0.1:8090 by default, and polls once a second. The API
+//! has no authentication of its own, so only listen on trusted networks.
+//! Try it with curl:
+//!
+//!   curl http://127.0.0.1:8090/devices
+//!   curl -N http://127.0.0.1:8090/events
+//!   curl -d '{"commandClass": "SwitchBinary", "method": "Set", "args": [255]}' \
+//!     http://127.0.0.1:8090/devices/7/commands
+
+extern crate hyper;
+extern crate razberry;
+
+use hyper::server::Server;
+use razberry::RazberryClient;
+use razberry::RazberryError;
+use razberry::api::ApiServer;
+use std::env;
+use std::io::Write;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_LISTEN : &'static str = "127.0.0.1:8090";
+const POLL_INTERVAL_MS : u64 = 1000;
+const RETRY_INTERVAL_MS : u64 = 10000;
+
+/// Request threads, each of which an open event stream holds.
+const THREADS : usize = 16;
+
+pub fn main() {
+  let args : Vec<String> = env::args().collect();
+
+  if args.len() < 4 || args.len() > 5 {
+    let _ = writeln!(std::io::stderr(),
+      "Usage: razberry_api HOST[:PORT] USERNAME PASSWORD [LISTEN]");
+    process::exit(2);
+  }
+
+  let mut client = RazberryClient::for_address(&args[1]).expect("Invalid host");
+  let listen = args.get(4).map(|a| a.as_str()).unwrap_or(DEFAULT_LISTEN);
+
+  while let Err(error) = connect(&mut client, &args[2], &args[3]) {
+    let _ = writeln!(std::io::stderr(), "Could not load devices: {:?}", error);
+    thread::sleep(Duration::from_millis(RETRY_INTERVAL_MS));
+  }
+
+  let api = ApiServer::new(client).expect("Could not subscribe to changes");
+
+  let mut server = Server::http(listen).expect("Could not listen");
+  server.keep_alive(None);
+  let listening = server.handle_threads(api.get_handler(), THREADS)
+      .expect("Could not start the server");
+
+  println!("Serving the API on http://{}/devices", listening.socket);
+
+  loop {
+    match api.poll() {
+      Ok(_) => {},
+      Err(RazberryError::BadCredentials) => {
+        // Session expired.
+        let client = api.get_client();
+        let mut client = client.lock().expect("Client lock poisoned");
+        if let Err(error) = connect(&mut client, &args[2], &args[3]) {
+          let _ = writeln!(std::io::stderr(), "Could not load devices: {:?}", error);
+          drop(client);
+          thread::sleep(Duration::from_millis(RETRY_INTERVAL_MS));
+        }
+      },
+      Err(error) => {
+        let _ = writeln!(std::io::stderr(), "Poll failed: {:?}", error);
+      },
+    }
+
+    thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+  }
+}
+
+fn connect(client: &mut RazberryClient, username: &str, password: &str)
+    -> Result<(), RazberryError> {
+  client.login(username, password)?;
+  client.load_devices()
+}
+
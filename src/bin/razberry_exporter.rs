@@ -0,0 +1,81 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Poll a Razberry gateway and serve Prometheus metrics at '/metrics'.
+//!
+//!   razberry_exporter HOST[:PORT] USERNAME PASSWORD [LISTEN]
+//!
+//! Listens on 0.0.0.0:9479 by default, and polls once a second.
+
+extern crate hyper;
+extern crate razberry;
+
+use hyper::server::Server;
+use razberry::RazberryClient;
+use razberry::RazberryError;
+use razberry::metrics::Metrics;
+use razberry::metrics::MetricsHandler;
+use std::env;
+use std::io::Write;
+use std::process;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_LISTEN : &'static str = "0.0.0.0:9479";
+const POLL_INTERVAL_MS : u64 = 1000;
+const RETRY_INTERVAL_MS : u64 = 10000;
+
+pub fn main() {
+  let args : Vec<String> = env::args().collect();
+
+  if args.len() < 4 || args.len() > 5 {
+    let _ = writeln!(std::io::stderr(),
+      "Usage: razberry_exporter HOST[:PORT] USERNAME PASSWORD [LISTEN]");
+    process::exit(2);
+  }
+
+  let mut client = RazberryClient::for_address(&args[1]).expect("Invalid host");
+  let listen = args.get(4).map(|a| a.as_str()).unwrap_or(DEFAULT_LISTEN);
+
+  let metrics = Arc::new(Mutex::new(Metrics::new()));
+
+  let server = Server::http(listen).expect("Could not listen");
+  let listening = server.handle(MetricsHandler::new(metrics.clone()))
+      .expect("Could not start the server");
+
+  println!("Serving metrics on http://{}/metrics", listening.socket);
+
+  loop {
+    if let Err(error) = connect(&mut client, &args[2], &args[3]) {
+      let _ = writeln!(std::io::stderr(), "Could not load devices: {:?}", error);
+      thread::sleep(Duration::from_millis(RETRY_INTERVAL_MS));
+      continue;
+    }
+
+    loop {
+      let result = client.poll_updates();
+
+      if let Ok(mut metrics) = metrics.lock() {
+        metrics.update(&client);
+      }
+
+      match result {
+        Ok(_) => {},
+        Err(RazberryError::BadCredentials) => break, // Session expired.
+        Err(error) => {
+          let _ = writeln!(std::io::stderr(), "Poll failed: {:?}", error);
+        },
+      }
+
+      thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+    }
+  }
+}
+
+fn connect(client: &mut RazberryClient, username: &str, password: &str)
+    -> Result<(), RazberryError> {
+  client.login(username, password)?;
+  client.load_devices()
+}
+
@@ -0,0 +1,101 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Poll a Razberry gateway and write readings as InfluxDB line protocol.
+//!
+//!   razberry_influx HOST[:PORT] USERNAME PASSWORD [OUTPUT]
+//!
+//! OUTPUT is "-" for stdout (the default), an InfluxDB write URL such as
+//! "http://localhost:8086/write?db=home", or a file to append to. HTTPS is
+//! not supported; put a local proxy in front of a TLS endpoint. Every
+//! current reading is written on start, then each reading the gateway
+//! updates, once a second. Points carry the gateway's timestamps, so
+//! rewriting them after a restart does not duplicate them.
+
+extern crate razberry;
+
+use razberry::RazberryClient;
+use razberry::RazberryError;
+use razberry::influx::HttpWriter;
+use razberry::influx::InfluxExporter;
+use razberry::influx::LineWriter;
+use razberry::influx::PointWriter;
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::io;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL_MS : u64 = 1000;
+const RETRY_INTERVAL_MS : u64 = 10000;
+
+pub fn main() {
+  let args : Vec<String> = env::args().collect();
+
+  if args.len() < 4 || args.len() > 5 {
+    let _ = writeln!(io::stderr(),
+      "Usage: razberry_influx HOST[:PORT] USERNAME PASSWORD [OUTPUT]");
+    process::exit(2);
+  }
+
+  let mut client = RazberryClient::for_address(&args[1]).expect("Invalid host");
+  let output = args.get(4).map(|a| a.as_str()).unwrap_or("-");
+
+  if output.starts_with("https://") {
+    let _ = writeln!(io::stderr(),
+      "HTTPS output is not supported; use an http:// URL, eg. through a local proxy.");
+    process::exit(2);
+  }
+
+  let mut writer : Box<PointWriter> = if output == "-" {
+    Box::new(LineWriter::new(io::stdout()))
+  } else if output.starts_with("http://") {
+    Box::new(HttpWriter::new(output))
+  } else {
+    let file = OpenOptions::new().create(true).append(true).open(output)
+        .expect("Could not open the output file");
+    Box::new(LineWriter::new(file))
+  };
+
+  let mut exporter = InfluxExporter::new();
+
+  loop {
+    if let Err(error) = run(&mut client, &mut exporter, &mut *writer, &args[2], &args[3]) {
+      let _ = writeln!(io::stderr(), "Export stopped: {:?}", error);
+    }
+
+    thread::sleep(Duration::from_millis(RETRY_INTERVAL_MS));
+  }
+}
+
+/// Log in and export until the session expires.
+fn run(client: &mut RazberryClient, exporter: &mut InfluxExporter,
+       writer: &mut PointWriter, username: &str, password: &str)
+    -> Result<(), RazberryError> {
+  client.login(username, password)?;
+  client.load_devices()?;
+
+  loop {
+    if let Some(state) = client.get_state() {
+      let points = exporter.updates(state);
+
+      if let Err(error) = writer.write_points(&points) {
+        // Write every current reading again next time.
+        let _ = writeln!(io::stderr(), "Write failed: {:?}", error);
+        exporter.reset();
+      }
+    }
+
+    thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+
+    match client.poll_updates() {
+      Ok(_) => {},
+      Err(RazberryError::BadCredentials) => return Err(RazberryError::BadCredentials),
+      Err(error) => {
+        let _ = writeln!(io::stderr(), "Poll failed: {:?}", error);
+      },
+    }
+  }
+}
+
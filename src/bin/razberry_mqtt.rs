@@ -0,0 +1,129 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Bridge a Razberry gateway to an MQTT broker.
+//!
+//!   razberry_mqtt HOST[:PORT] USERNAME PASSWORD [BROKER] [PREFIX]
+//!
+//! Publishes device values beneath PREFIX ("razberry") on BROKER
+//! (127.0.0.1:1883), and runs the commands sent to ".../set" topics. Set
+//! `MQTT_USERNAME` and `MQTT_PASSWORD` if the broker requires a login.
+//!
+//! Devices are announced to Home Assistant beneath the discovery prefix in
+//! `MQTT_DISCOVERY_PREFIX` ("homeassistant"); set it empty to turn discovery
+//! off. Try it with mosquitto:
+//!
+//!   mosquitto_sub -v -t 'razberry/#'
+//!   mosquitto_pub -t razberry/7/0/SwitchBinary/level/set -m on
+
+extern crate razberry;
+
+use razberry::RazberryClient;
+use razberry::RazberryError;
+use razberry::mqtt::Message;
+use razberry::mqtt::MqttBridge;
+use razberry::mqtt::MqttClient;
+use razberry::mqtt::MqttOptions;
+use std::env;
+use std::io::Write;
+use std::process;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+const DEFAULT_BROKER : &'static str = "127.0.0.1:1883";
+const DEFAULT_PREFIX : &'static str = "razberry";
+const DEFAULT_DISCOVERY_PREFIX : &'static str = "homeassistant";
+const POLL_INTERVAL_MS : u64 = 1000;
+const RETRY_INTERVAL_MS : u64 = 10000;
+
+pub fn main() {
+  let args : Vec<String> = env::args().collect();
+
+  if args.len() < 4 || args.len() > 6 {
+    let _ = writeln!(std::io::stderr(),
+      "Usage: razberry_mqtt HOST[:PORT] USERNAME PASSWORD [BROKER] [PREFIX]");
+    process::exit(2);
+  }
+
+  let mut client = RazberryClient::for_address(&args[1]).expect("Invalid host");
+  let broker = args.get(4).map(|a| a.as_str()).unwrap_or(DEFAULT_BROKER);
+  let prefix = args.get(5).map(|a| a.as_str()).unwrap_or(DEFAULT_PREFIX);
+
+  let mut bridge = MqttBridge::new(prefix);
+
+  let discovery_prefix = env::var("MQTT_DISCOVERY_PREFIX")
+      .unwrap_or(DEFAULT_DISCOVERY_PREFIX.to_string());
+  if !discovery_prefix.is_empty() {
+    bridge.set_discovery_prefix(Some(&discovery_prefix));
+  }
+
+  let mut options = MqttOptions::new(&format!("razberry-{}", process::id()));
+  options.username = env::var("MQTT_USERNAME").ok();
+  options.password = env::var("MQTT_PASSWORD").ok();
+  options.will = Some(Message::new(&bridge.get_availability_topic(), "offline", true));
+
+  loop {
+    if let Err(error) = run(&mut client, &mut bridge, broker, &options, &args[2], &args[3]) {
+      let _ = writeln!(std::io::stderr(), "Bridge stopped: {:?}", error);
+    }
+
+    thread::sleep(Duration::from_millis(RETRY_INTERVAL_MS));
+  }
+}
+
+/// Connect to both ends and relay until either fails.
+fn run(client: &mut RazberryClient, bridge: &mut MqttBridge, broker: &str,
+       options: &MqttOptions, username: &str, password: &str)
+    -> Result<(), RazberryError> {
+  client.login(username, password)?;
+  client.load_devices()?;
+
+  let mut mqtt = MqttClient::connect(broker, options)?;
+
+  for filter in bridge.get_command_filters() {
+    mqtt.subscribe(&filter)?;
+  }
+
+  mqtt.publish(&Message::new(&bridge.get_availability_topic(), "online", true))?;
+  bridge.reset();
+
+  loop {
+    if let Some(state) = client.get_state() {
+      for message in bridge.updates(state) {
+        mqtt.publish(&message)?;
+      }
+    }
+
+    // Run commands as they arrive until the next poll is due.
+    let deadline = Instant::now() + Duration::from_millis(POLL_INTERVAL_MS);
+
+    loop {
+      let now = Instant::now();
+      if now >= deadline {
+        break;
+      }
+
+      let message = match mqtt.recv_timeout(deadline - now)? {
+        None => break,
+        Some(message) => message,
+      };
+
+      let result = bridge.command(&message.topic, &message.payload)
+          .and_then(|command| client.run_command(&command));
+
+      if let Err(error) = result {
+        let _ = writeln!(std::io::stderr(), "Command on {} failed: {:?}",
+          message.topic, error);
+      }
+    }
+
+    match client.poll_updates() {
+      Ok(_) => {},
+      Err(RazberryError::BadCredentials) => return Err(RazberryError::BadCredentials),
+      Err(error) => {
+        let _ = writeln!(std::io::stderr(), "Poll failed: {:?}", error);
+      },
+    }
+  }
+}
+
@@ -0,0 +1,110 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Run automation rules against a Razberry gateway.
+//!
+//!   razberry_rules [--dry-run] HOST[:PORT] USERNAME PASSWORD RULES
+//!
+//! RULES is a TOML file, or YAML or JSON if it ends in ".yaml", ".yml" or
+//! ".json". Rules are evaluated after each poll, once a second. Actions are
+//! printed as they are taken, and notifications are printed for the caller
+//! to deliver. With `--dry-run`, no commands are sent.
+
+extern crate razberry;
+extern crate rustc_serialize;
+
+use razberry::RazberryClient;
+use razberry::RazberryError;
+use razberry::rules::RuleEngine;
+use rustc_serialize::json::Json;
+use std::env;
+use std::fs::File;
+use std::io::Read;
+use std::io::Write;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL_MS : u64 = 1000;
+const RETRY_INTERVAL_MS : u64 = 10000;
+
+pub fn main() {
+  let mut args : Vec<String> = env::args().skip(1).collect();
+
+  let dry_run = args.first().map(|a| a == "--dry-run").unwrap_or(false);
+  if dry_run {
+    args.remove(0);
+  }
+
+  if args.len() != 4 {
+    let _ = writeln!(std::io::stderr(),
+      "Usage: razberry_rules [--dry-run] HOST[:PORT] USERNAME PASSWORD RULES");
+    process::exit(2);
+  }
+
+  let mut engine = match load_rules(&args[3]) {
+    Ok(engine) => engine,
+    Err(error) => {
+      let _ = writeln!(std::io::stderr(), "Could not load {}: {:?}", args[3], error);
+      process::exit(1);
+    },
+  };
+  engine.set_dry_run(dry_run);
+
+  println!("Loaded {} rules{}", engine.get_rules().len(),
+    if dry_run { " (dry run)" } else { "" });
+
+  let mut client = RazberryClient::for_address(&args[0]).expect("Invalid host");
+
+  loop {
+    if let Err(error) = run(&mut client, &mut engine, &args[1], &args[2]) {
+      let _ = writeln!(std::io::stderr(), "Rules stopped: {:?}", error);
+    }
+
+    thread::sleep(Duration::from_millis(RETRY_INTERVAL_MS));
+  }
+}
+
+/// Log in and evaluate rules until the session expires.
+fn run(client: &mut RazberryClient, engine: &mut RuleEngine, username: &str,
+       password: &str) -> Result<(), RazberryError> {
+  client.login(username, password)?;
+  client.load_devices()?;
+
+  loop {
+    for (activation, result) in engine.run(client) {
+      match result {
+        Ok(_) => println!("{} [{}] {}", activation.time, activation.rule, activation.action),
+        Err(error) => {
+          let _ = writeln!(std::io::stderr(), "{} [{}] {} failed: {:?}",
+            activation.time, activation.rule, activation.action, error);
+        },
+      }
+    }
+
+    thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+
+    match client.poll_updates() {
+      Ok(_) => {},
+      Err(RazberryError::BadCredentials) => return Err(RazberryError::BadCredentials),
+      Err(error) => {
+        let _ = writeln!(std::io::stderr(), "Poll failed: {:?}", error);
+      },
+    }
+  }
+}
+
+fn load_rules(filename: &str) -> Result<RuleEngine, RazberryError> {
+  let mut text = String::new();
+  File::open(filename)
+      .and_then(|mut file| file.read_to_string(&mut text))
+      .map_err(|_| RazberryError::BadRule)?;
+
+  if filename.ends_with(".json") {
+    RuleEngine::from_json(&Json::from_str(&text)?)
+  } else if filename.ends_with(".yaml") || filename.ends_with(".yml") {
+    RuleEngine::from_yaml(&text)
+  } else {
+    RuleEngine::from_toml(&text)
+  }
+}
+
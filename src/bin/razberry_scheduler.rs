@@ -0,0 +1,135 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Run scheduled commands against a Razberry gateway.
+//!
+//!   razberry_scheduler HOST[:PORT] USERNAME PASSWORD SCHEDULE [STATE]
+//!
+//! SCHEDULE is a TOML file, or JSON if it ends in ".json". Next runs are
+//! saved to STATE (SCHEDULE with ".state.json" appended), so runs missed
+//! while stopped are skipped or caught up on restart, by each job's policy.
+
+extern crate razberry;
+extern crate rustc_serialize;
+
+use razberry::RazberryClient;
+use razberry::RazberryError;
+use razberry::scheduler::Scheduler;
+use rustc_serialize::json::Json;
+use std::env;
+use std::fs::File;
+use std::fs;
+use std::io::Read;
+use std::io::Write;
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL_MS : u64 = 1000;
+const RETRY_INTERVAL_MS : u64 = 10000;
+
+pub fn main() {
+  let args : Vec<String> = env::args().collect();
+
+  if args.len() < 5 || args.len() > 6 {
+    let _ = writeln!(std::io::stderr(),
+      "Usage: razberry_scheduler HOST[:PORT] USERNAME PASSWORD SCHEDULE [STATE]");
+    process::exit(2);
+  }
+
+  let mut scheduler = match load_schedule(&args[4]) {
+    Ok(scheduler) => scheduler,
+    Err(error) => {
+      let _ = writeln!(std::io::stderr(), "Could not load {}: {:?}", args[4], error);
+      process::exit(1);
+    },
+  };
+
+  let state_filename = args.get(5).cloned()
+      .unwrap_or(format!("{}.state.json", args[4]));
+
+  if let Ok(text) = read_file(&state_filename) {
+    let result = Json::from_str(&text).map_err(RazberryError::from)
+        .and_then(|json| scheduler.restore(&json));
+
+    if let Err(error) = result {
+      let _ = writeln!(std::io::stderr(), "Ignoring {}: {:?}", state_filename, error);
+    }
+  }
+
+  println!("Loaded {} jobs", scheduler.get_jobs().len());
+
+  let mut client = RazberryClient::for_address(&args[1]).expect("Invalid host");
+
+  loop {
+    if let Err(error) = run(&mut client, &mut scheduler, &state_filename, &args[2], &args[3]) {
+      let _ = writeln!(std::io::stderr(), "Scheduler stopped: {:?}", error);
+    }
+
+    thread::sleep(Duration::from_millis(RETRY_INTERVAL_MS));
+  }
+}
+
+/// Log in and run jobs until the session expires.
+fn run(client: &mut RazberryClient, scheduler: &mut Scheduler, state_filename: &str,
+       username: &str, password: &str) -> Result<(), RazberryError> {
+  client.login(username, password)?;
+  client.load_devices()?;
+
+  let mut saved = String::new();
+
+  loop {
+    for (run, result) in scheduler.run(client) {
+      match result {
+        Ok(_) => println!("{} [{}] {}", run.time, run.job, run.action),
+        Err(error) => {
+          let _ = writeln!(std::io::stderr(), "{} [{}] {} failed: {:?}",
+            run.time, run.job, run.action, error);
+        },
+      }
+    }
+
+    let state = scheduler.to_json().to_string();
+    if state != saved {
+      match save_state(state_filename, &state) {
+        Ok(_) => saved = state,
+        Err(error) => {
+          let _ = writeln!(std::io::stderr(), "Could not save {}: {}", state_filename, error);
+        },
+      }
+    }
+
+    thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+
+    match client.poll_updates() {
+      Ok(_) => {},
+      Err(RazberryError::BadCredentials) => return Err(RazberryError::BadCredentials),
+      Err(error) => {
+        let _ = writeln!(std::io::stderr(), "Poll failed: {:?}", error);
+      },
+    }
+  }
+}
+
+fn load_schedule(filename: &str) -> Result<Scheduler, RazberryError> {
+  let text = read_file(filename).map_err(|_| RazberryError::BadSchedule)?;
+
+  if filename.ends_with(".json") {
+    Scheduler::from_json(&Json::from_str(&text)?)
+  } else {
+    Scheduler::from_toml(&text)
+  }
+}
+
+fn read_file(filename: &str) -> std::io::Result<String> {
+  let mut text = String::new();
+  File::open(filename)?.read_to_string(&mut text)?;
+  Ok(text)
+}
+
+/// Write through a temporary file, so a crash never leaves half a file.
+fn save_state(filename: &str, state: &str) -> std::io::Result<()> {
+  let temporary = format!("{}.tmp", filename);
+  File::create(&temporary)?.write_all(state.as_bytes())?;
+  fs::rename(&temporary, filename)
+}
+
@@ -1,7 +1,10 @@
 // Copyright (c) 2016-2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
 
+#[allow(deprecated)]
 pub use response::DataResponse;
+#[allow(deprecated)]
 pub use response::GatewayState;
+#[allow(deprecated)]
 pub use response::PartialGatewayState;
 pub use response::ResponseError;
 pub use response::Timestamp;
@@ -11,26 +14,35 @@ pub use url::Url;
 use chrono::NaiveDateTime;
 use chrono::UTC;
 use chrono::datetime::DateTime;
+use command_class::door_lock::DoorLockMode;
+use command_classes::CommandClasses;
+use command_class::thermostat_mode::ThermostatModeType;
+use command_class::thermostat_setpoint::SetpointType;
+use command_class::user_code::UserIdStatus;
+use data_holder::DataHolder;
+use data_holder::DataValue;
 use device::Device;
-use device_update::DeviceUpdate;
 use error::RazberryError;
-use hyper::client::Client;
-use hyper::header::ContentType;
-use hyper::header::Cookie;
-use hyper::header::SetCookie;
-use hyper::mime::Attr;
-use hyper::mime::Mime;
-use hyper::mime::SubLevel;
-use hyper::mime::TopLevel;
-use hyper::mime::Value;
-use hyper::status::StatusCode;
+use event::Event;
+use event::ResyncEvent;
+use event::ResyncReason;
+use network_state::NetworkState;
+use poll_stats::PollStats;
 use rustc_serialize::json::Json;
 use rustc_serialize::json;
-use std::collections::HashMap;
-use std::io::Read;
+use std::time::Instant;
+use subscription::SubscriptionId;
+use subscription::Subscriptions;
+use tracked_value::TrackedValue;
+use transport::HttpRequest;
+use transport::HttpResponse;
+use transport::HyperTransport;
+use transport::Transport;
 
 const DEFAULT_PORT : u32 = 8083u32;
 const SESSION_COOKIE_NAME : &'static str = "ZWAYSession";
+const DEFAULT_MAX_POLL_INTERVAL_SECS : u64 = 600u64;
+const DEFAULT_TIMEOUT_SECS : u64 = 30u64;
 
 /**
  * Razberry Z-Wave gateway client.
@@ -43,12 +55,25 @@ pub struct RazberryClient {
   /// Razberry gateway session token for making authenticated requests.
   session_token: Option<String>,
 
-  /// HTTP Client.
-  client: Client,
+  /// Sends requests to the gateway; HTTP unless replaced.
+  transport: Box<Transport + Send>,
 
-  /// Z-wave devices that have been loaded.
-  /// This is a map of device ID to device.
-  devices: HashMap<String, Device>,
+  /// The devices and raw Z-Way data tree, as loaded from '/ZWaveAPI/Data'
+  /// and kept current with each poll. None until `load_devices`.
+  state: Option<NetworkState>,
+
+  /// Data path patterns whose changes are reported by `poll_updates`.
+  subscriptions: Subscriptions,
+
+  /// Longest pause between polls after which a delta is no longer trusted
+  /// and the state is reloaded in full instead.
+  max_poll_interval: Option<Duration>,
+
+  /// Local time of the last successful load or poll.
+  last_poll: Option<Instant>,
+
+  /// Counters for `poll_updates`.
+  poll_stats: PollStats,
 
   /// The last time Z-wave device updates were successfully polled.
   /// Timestamp is that of the Razberry endpoint (not the program's CPU time).
@@ -77,6 +102,27 @@ impl RazberryClient {
     RazberryClient::new(hostname, DEFAULT_PORT)
   }
 
+  /**
+   * Construct a client from an address of the form "HOST[:PORT]", using the
+   * default port if none is given. Read and write timeouts are set, so a
+   * stalled gateway fails the request instead of hanging the caller.
+   */
+  pub fn for_address(address: &str) -> Result<RazberryClient, RazberryError> {
+    let mut parts = address.splitn(2, ":");
+    let hostname = parts.next().unwrap_or("localhost");
+    let port = match parts.next() {
+      None => DEFAULT_PORT,
+      Some(port) => port.parse().map_err(|_| RazberryError::InvalidArgument)?,
+    };
+
+    let mut client = RazberryClient::new(hostname, port)
+        .map_err(|_| RazberryError::InvalidArgument)?;
+
+    client.set_read_timeout(Some(Duration::from_secs(DEFAULT_TIMEOUT_SECS)));
+    client.set_write_timeout(Some(Duration::from_secs(DEFAULT_TIMEOUT_SECS)));
+    Ok(client)
+  }
+
   /**
    * Construct a client from hostname and port.
    */
@@ -85,13 +131,25 @@ impl RazberryClient {
       RazberryClient {
         base_url: url,
         session_token: None,
-        client: Client::new(),
-        devices: HashMap::new(),
+        transport: Box::new(HyperTransport::new()),
+        state: None,
+        subscriptions: Subscriptions::default(),
+        max_poll_interval: Some(Duration::from_secs(DEFAULT_MAX_POLL_INTERVAL_SECS)),
+        last_poll: None,
+        poll_stats: PollStats::default(),
         last_update: None,
       }
     })
   }
 
+  /**
+   * Set the longest pause between polls after which `poll_updates` reloads
+   * the state in full rather than trusting a delta. None disables the check.
+   */
+  pub fn set_max_poll_interval(&mut self, interval: Option<Duration>) {
+    self.max_poll_interval = interval;
+  }
+
   /**
    * Set the session for the cookie manually.
    */
@@ -111,14 +169,23 @@ impl RazberryClient {
    * Set HTTP client read timeout.
    */
   pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
-    self.client.set_read_timeout(timeout)
+    self.transport.set_read_timeout(timeout)
   }
 
   /**
    * Set HTTP client write timeout.
    */
   pub fn set_write_timeout(&mut self, timeout: Option<Duration>) {
-    self.client.set_write_timeout(timeout)
+    self.transport.set_write_timeout(timeout)
+  }
+
+  /**
+   * Replace the transport requests are sent over, eg. with a
+   * `RecordingTransport` to capture a session or a `ReplayTransport` to
+   * reproduce one.
+   */
+  pub fn set_transport(&mut self, transport: Box<Transport + Send>) {
+    self.transport = transport;
   }
 
   /**
@@ -136,24 +203,19 @@ impl RazberryClient {
 
     let url = try!(self.login_url());
 
-    let result = try!(self.client.post(url)
-        .body(&login_request)
-        .header(ContentType(Mime(TopLevel::Application, SubLevel::Json,
-                                     vec![(Attr::Charset, Value::Utf8)])))
-        .send()
-        .map_err(|_| RazberryError::ClientError));
-
-    match result.status {
-      StatusCode::Ok => {}, // Continue
-      StatusCode::Unauthorized => { return Err(RazberryError::BadCredentials); },
-      _ => { return Err(RazberryError::BadRequest); },
-    }
+    let result = self.transport.send(&HttpRequest {
+      method: "POST".to_string(),
+      url: url.to_string(),
+      headers: vec![
+        ("Content-Type".to_string(), "application/json; charset=utf-8".to_string()),
+      ],
+      body: Some(login_request),
+    })?;
 
-    // Get the session cookie from the response.
-    // TODO: Cleanup once 'as_slice' becomes stable.
-    let cookies = result.headers.get::<SetCookie>().unwrap().clone();
+    Self::check_status(&result)?;
 
-    for cookie in cookies.iter() {
+    // Get the session cookie from the response.
+    for cookie in result.get_headers("Set-Cookie") {
       match Self::parse_cookie_value(cookie) {
         None => continue,
         Some((name, value)) => {
@@ -183,105 +245,145 @@ impl RazberryClient {
     None
   }
 
-  // TODO: Test.
   /// Query the initial data payload for devices (the bare /Data endpoint).
   pub fn load_devices(&mut self) -> Result<(), RazberryError> {
     let url = self.data_url(None)?;
+    let body = self.get_authenticated(url)?;
+    let json = Json::from_str(&body)?;
 
-    let session_token = self.session_token.as_ref()
-        .ok_or(RazberryError::ClientError)?;
+    let state = NetworkState::from_json(&json)?;
 
-    let mut result = self.client.get(url)
-        .header(Cookie(vec![
-          format!("{}={}", SESSION_COOKIE_NAME, session_token)
-        ]))
-        .send()
-        .map_err(|_| RazberryError::ClientError)?;
+    self.last_update = Some(state.get_update_time());
+    self.last_poll = Some(Instant::now());
+    self.state = Some(state); // TODO: Interior mutability.
 
-    match result.status {
-      StatusCode::Ok => {}, // Continue
-      StatusCode::Unauthorized => {
-        return Err(RazberryError::BadCredentials);
-      },
-      _ => { return Err(RazberryError::BadRequest); },
-    }
+    Ok(())
+  }
 
-    let mut body = String::new();
+  /// Poll the /Data/{time} endpoint for updates. Returns the discrete events,
+  /// such as scene activations and subscribed data changes, observed since
+  /// the last poll.
+  ///
+  /// If the delta cannot be trusted to hold every update, because polling
+  /// paused for too long, the gateway clock went backwards, or the response
+  /// looks wrong, the state is reloaded with `load_devices` instead and a
+  /// single `Resynced` event is returned.
+  pub fn poll_updates(&mut self) -> Result<Vec<Event>, RazberryError> {
+    let start = Instant::now();
+    let result = self.poll();
+    self.poll_stats.record(start.elapsed(), &result);
+    result
+  }
 
-    result.read_to_string(&mut body)
-        .map_err(|_| RazberryError::ServerError)?;
+  /// Get counters describing how polling has gone, eg. for monitoring.
+  pub fn get_poll_stats(&self) -> &PollStats {
+    &self.poll_stats
+  }
 
-    let json = Json::from_str(&body)?;
+  fn poll(&mut self) -> Result<Vec<Event>, RazberryError> {
+    // Can't poll for updates unless we've loaded devices first.
+    let dt = self.last_update.ok_or(RazberryError::ClientError)?;
+    let timestamp = dt.timestamp();
 
-    let devices_json = json.find("devices")
-        .and_then(|d| d.as_object())
-        .ok_or(RazberryError::BadResponse)?;
+    let paused = match (self.last_poll, self.max_poll_interval) {
+      (Some(last_poll), Some(interval)) => last_poll.elapsed() > interval,
+      _ => false,
+    };
 
-    let mut devices = HashMap::new();
+    if paused {
+      return self.resync(ResyncReason::Gap);
+    }
+
+    let url = self.data_url(Some(timestamp))?;
+    let body = self.get_authenticated(url)?;
+    let json = Json::from_str(&body)?;
 
-    for (device_id, device_json) in devices_json {
-      let device = Device::initialize_from_json(device_id, &device_json)?;
-      devices.insert(device_id.to_string(), device);
+    let reason = match self.state {
+      None => return Err(RazberryError::ClientError),
+      Some(ref state) => state.check_delta(&json, dt),
+    };
+
+    if let Some(reason) = reason {
+      return self.resync(reason);
     }
 
     let update_time = Self::parse_update_time(&json)?;
 
+    // Updates from the second of the last poll are delivered again.
+    let applied = match self.state {
+      None => return Err(RazberryError::ClientError),
+      Some(ref mut state) => state.dedupe_delta(&json).and_then(|json| {
+        let events = state.apply_delta(&json, dt)?;
+        Ok((json, events))
+      }),
+    };
+
+    // A delta that cannot be applied left the state unchanged, but cannot be
+    // trusted either.
+    let (json, mut events) = match applied {
+      Err(RazberryError::BadResponse) => {
+        return self.resync(ResyncReason::SuspiciousResponse);
+      },
+      other => other?,
+    };
+
+    for change in self.subscriptions.collect_changes(&json, update_time)? {
+      events.push(Event::DataChanged { change: change });
+    }
+
     self.last_update = Some(update_time);
-    self.devices = devices; // TODO: Interior mutability.
+    self.last_poll = Some(Instant::now());
 
-    Ok(())
+    events.sort_by_key(|e| e.get_timestamp());
+    Ok(events)
   }
 
-  // TODO: Test.
-  /// Poll the /Data/{time} endpoint for updates.
-  pub fn poll_updates(&mut self) -> Result<(), RazberryError> {
-    // Can't poll for updates unless we've loaded devices first.
-    let dt = self.last_update.ok_or(RazberryError::ClientError)?;
-    let timestamp = dt.timestamp();
+  /// Save the loaded state, including `last_update`, as a versioned JSON
+  /// snapshot for `restore`.
+  pub fn snapshot(&self) -> Result<String, RazberryError> {
+    let state = self.state.as_ref().ok_or(RazberryError::ClientError)?;
+    Ok(state.to_snapshot(UTC::now()).to_string())
+  }
 
-    let url = self.data_url(Some(timestamp))?;
+  /// Resume from a snapshot written by `snapshot`, so that the next
+  /// `poll_updates` continues from the saved timestamp rather than paying
+  /// for a full load. If the snapshot is older than the maximum poll
+  /// interval, the state is reloaded with `load_devices` instead and a
+  /// single `Resynced` event is returned.
+  pub fn restore(&mut self, snapshot: &str) -> Result<Vec<Event>, RazberryError> {
+    let json = Json::from_str(snapshot).map_err(|_| RazberryError::BadSnapshot)?;
+    let (state, saved_at) = NetworkState::from_snapshot(&json)?;
 
-    let session_token = self.session_token.as_ref()
-        .ok_or(RazberryError::ClientError)?;
+    let age = UTC::now().timestamp() - saved_at.timestamp();
 
-    let mut result = self.client.get(url)
-        .header(Cookie(vec![
-          format!("{}={}", SESSION_COOKIE_NAME, session_token)
-        ]))
-        .send()
-        .map_err(|_| RazberryError::ClientError)?;
+    let stale = match self.max_poll_interval {
+      Some(interval) => age < 0 || age as u64 > interval.as_secs(),
+      None => false,
+    };
 
-    match result.status {
-      StatusCode::Ok => {}, // Continue
-      StatusCode::Unauthorized => {
-        return Err(RazberryError::BadCredentials);
-      },
-      _ => return Err(RazberryError::BadRequest),
+    if stale {
+      return self.resync(ResyncReason::Gap);
     }
 
-    let mut body = String::new();
+    self.last_update = Some(state.get_update_time());
+    self.last_poll = Some(Instant::now());
+    self.state = Some(state);
 
-    result.read_to_string(&mut body)
-        .map_err(|_| RazberryError::ServerError)?;
+    Ok(Vec::new())
+  }
 
-    let json = Json::from_str(&body)?;
+  /// Reload the state in full and report why.
+  fn resync(&mut self, reason: ResyncReason) -> Result<Vec<Event>, RazberryError> {
+    self.load_devices()?;
 
-    // TODO: DEVICE UPDATES!
-    let updates = DeviceUpdate::parse_updates(&json)?;
+    let timestamp = self.last_update.ok_or(RazberryError::ClientError)?;
 
-    for (device_id, updates) in updates {
-      match self.devices.get_mut(&device_id) {
-        None => continue, // Perhaps a new device was added. We must ignore.
-        Some(ref mut device) => {
-          let _r = device.process_updates(updates)?;
-        },
-      }
-    }
-
-    let update_time = Self::parse_update_time(&json)?;
-
-    self.last_update = Some(update_time);
-    Ok(())
+    Ok(vec![Event::Resynced {
+      resync: ResyncEvent {
+        reason: reason,
+        timestamp: timestamp,
+      },
+    }])
   }
 
   // TODO: API is a WIP. Prefer interior mutability.
@@ -290,9 +392,210 @@ impl RazberryClient {
   // atomic guarantees.
   /// Get devices that have been loaded by the client.
   pub fn get_devices(&self) -> Vec<&Device> {
-    self.devices.values()
-        .map(|d| d)
-        .collect()
+    match self.state {
+      None => Vec::new(),
+      Some(ref state) => state.get_devices(),
+    }
+  }
+
+  /// Get the devices and raw data tree loaded by the client, if any.
+  pub fn get_state(&self) -> Option<&NetworkState> {
+    self.state.as_ref()
+  }
+
+  /// Subscribe to changes of raw data paths matching a pattern, eg.
+  /// "devices.*.data.lastReceived" or
+  /// "devices.4.instances.*.commandClasses.49.**". `*` matches one path
+  /// component and `**` any number. Matching changes, including those to
+  /// controller keys, are returned by `poll_updates` as `DataChanged` events.
+  pub fn subscribe(&mut self, pattern: &str)
+      -> Result<SubscriptionId, RazberryError> {
+    self.subscriptions.subscribe(pattern)
+  }
+
+  /// Remove a subscription. Returns whether it was registered.
+  pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+    self.subscriptions.unsubscribe(id)
+  }
+
+  /// Look up any value in the loaded data tree using Z-Way's expression
+  /// syntax, eg. "devices[4].instances[0].commandClasses[49].data[1].val".
+  /// This reaches command classes that have no typed model. Returns `None`
+  /// if nothing lives at the path; the tree is empty until `load_devices`.
+  pub fn query(&self, expression: &str)
+      -> Result<Option<TrackedValue<DataValue>>, RazberryError> {
+    let empty = DataHolder::empty();
+    let data = self.state.as_ref().map(|s| s.get_data()).unwrap_or(&empty);
+    let holder = data.query(expression)?;
+    Ok(holder.map(|h| TrackedValue::from_holder(h, |v| v.clone())))
+  }
+
+  /// Run a command or query on the gateway through the '/ZWaveAPI/Run'
+  /// endpoint, eg. "devices[4].instances[0].commandClasses[50].Reset()".
+  /// Returns the JSON result of evaluating the expression.
+  pub fn run_command(&self, command: &str) -> Result<Json, RazberryError> {
+    let url = self.run_url(command)?;
+    let body = self.get_authenticated(url)?;
+
+    if body.trim().is_empty() {
+      return Ok(Json::Null);
+    }
+
+    Ok(Json::from_str(&body)?)
+  }
+
+  /// Reset the accumulated readings of a meter (command class 0x32).
+  pub fn reset_meter(&self, device_id: &str, instance: u8)
+      -> Result<(), RazberryError> {
+    self.run_command_class_method(device_id, instance,
+        CommandClasses::Meter, "Reset()")
+  }
+
+  /// Turn a switch (command class 0x25) on or off.
+  pub fn set_switch(&self, device_id: &str, instance: u8, on: bool)
+      -> Result<(), RazberryError> {
+    let method = format!("Set({})", if on { 255 } else { 0 });
+    self.run_command_class_method(device_id, instance,
+        CommandClasses::SwitchBinary, &method)
+  }
+
+  /// Move a dimmer (command class 0x26) to a level from 0 (off) to 99.
+  pub fn set_dimmer(&self, device_id: &str, instance: u8, level: u8)
+      -> Result<(), RazberryError> {
+    if level > 99 {
+      return Err(RazberryError::InvalidArgument);
+    }
+
+    let method = format!("Set({})", level);
+    self.run_command_class_method(device_id, instance,
+        CommandClasses::SwitchMultilevel, &method)
+  }
+
+  /// Change the mode of a thermostat (command class 0x40).
+  pub fn set_thermostat_mode(&self, device_id: &str, instance: u8,
+                             mode: ThermostatModeType)
+      -> Result<(), RazberryError> {
+    let method = format!("Set({})", mode.to_byte());
+    self.run_command_class_method(device_id, instance,
+        CommandClasses::ThermostatMode, &method)
+  }
+
+  /// Change a thermostat setpoint (command class 0x43). The value is in the
+  /// scale the thermostat reports for that setpoint type, and must be finite.
+  pub fn set_thermostat_setpoint(&self, device_id: &str, instance: u8,
+                                 setpoint_type: SetpointType, value: f64)
+      -> Result<(), RazberryError> {
+    if !value.is_finite() {
+      return Err(RazberryError::InvalidArgument);
+    }
+
+    let method = format!("Set({},{})", setpoint_type.to_byte(), value);
+    self.run_command_class_method(device_id, instance,
+        CommandClasses::ThermostatSetpoint, &method)
+  }
+
+  /// Secure a door lock (command class 0x62).
+  pub fn lock_door(&self, device_id: &str, instance: u8)
+      -> Result<(), RazberryError> {
+    let method = format!("Set({})", DoorLockMode::Secured.to_byte());
+    self.run_command_class_method(device_id, instance,
+        CommandClasses::DoorLock, &method)
+  }
+
+  /// Unsecure a door lock (command class 0x62).
+  pub fn unlock_door(&self, device_id: &str, instance: u8)
+      -> Result<(), RazberryError> {
+    let method = format!("Set({})", DoorLockMode::Unsecured.to_byte());
+    self.run_command_class_method(device_id, instance,
+        CommandClasses::DoorLock, &method)
+  }
+
+  /// Store a PIN code in a user code slot (command class 0x63). Codes must be
+  /// four to ten digits.
+  pub fn set_user_code(&self, device_id: &str, instance: u8, user_id: u16,
+                       code: &str) -> Result<(), RazberryError> {
+    let valid = code.len() >= 4 && code.len() <= 10
+        && code.chars().all(|c| c.is_digit(10));
+
+    if !valid {
+      return Err(RazberryError::InvalidArgument);
+    }
+
+    let method = format!("Set({},\"{}\",{})", user_id, code,
+        UserIdStatus::Occupied.to_byte());
+    self.run_command_class_method(device_id, instance,
+        CommandClasses::UserCode, &method)
+  }
+
+  /// Erase the code in a user code slot (command class 0x63).
+  pub fn clear_user_code(&self, device_id: &str, instance: u8, user_id: u16)
+      -> Result<(), RazberryError> {
+    let method = format!("Set({},\"\",{})", user_id,
+        UserIdStatus::Available.to_byte());
+    self.run_command_class_method(device_id, instance,
+        CommandClasses::UserCode, &method)
+  }
+
+  /// Call a method on a device's command class instance. The device ID must
+  /// be a node number, so it cannot carry other script into the command.
+  fn run_command_class_method(&self, device_id: &str, instance: u8,
+                              command_class: CommandClasses, method: &str)
+      -> Result<(), RazberryError> {
+    let device_id = device_id.parse::<u8>()
+        .map_err(|_| RazberryError::InvalidArgument)?;
+
+    let command = format!("devices[{}].instances[{}].commandClasses[{}].{}",
+        device_id, instance, command_class.to_byte(), method);
+    self.run_command(&command).map(|_| ())
+  }
+
+  /// Format a method call for a '/ZWaveAPI/Run' expression, eg. "Set(255)".
+  /// The name must be letters, and each argument a finite number, a boolean
+  /// or a string of letters, digits and spaces, so that callers passing on
+  /// untrusted requests cannot smuggle in other script.
+  pub(crate) fn format_method(name: &str, args: &[Json]) -> Result<String, RazberryError> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphabetic()) {
+      return Err(RazberryError::InvalidArgument);
+    }
+
+    let args = args.iter().map(|arg| match *arg {
+      Json::I64(_) | Json::U64(_) | Json::Boolean(_) => Ok(arg.to_string()),
+      Json::F64(f) if f.is_finite() => Ok(arg.to_string()),
+      Json::String(ref s) if s.chars().all(|c| c.is_ascii_alphanumeric() || c == ' ') => {
+        Ok(arg.to_string())
+      },
+      _ => Err(RazberryError::InvalidArgument),
+    }).collect::<Result<Vec<String>, RazberryError>>()?;
+
+    Ok(format!("{}({})", name, args.join(",")))
+  }
+
+  /// Perform a GET request with the session cookie and return the body.
+  fn get_authenticated(&self, url: Url) -> Result<String, RazberryError> {
+    let session_token = self.session_token.as_ref()
+        .ok_or(RazberryError::ClientError)?;
+
+    let result = self.transport.send(&HttpRequest {
+      method: "GET".to_string(),
+      url: url.to_string(),
+      headers: vec![
+        ("Cookie".to_string(), format!("{}={}", SESSION_COOKIE_NAME, session_token)),
+      ],
+      body: None,
+    })?;
+
+    Self::check_status(&result)?;
+
+    Ok(result.body)
+  }
+
+  /// Map an unsuccessful response status to an error.
+  fn check_status(response: &HttpResponse) -> Result<(), RazberryError> {
+    match response.status {
+      200 => Ok(()),
+      401 => Err(RazberryError::BadCredentials),
+      _ => Err(RazberryError::BadRequest),
+    }
   }
 
   // TODO: Unit test this. Make sure Chrono::DateTime.timestamp() equals the original.
@@ -310,30 +613,14 @@ impl RazberryClient {
 
   /**
    * Get a full data dump of the state of the Razberry gateway and all
-   * of its associated devices.
+   * of its associated devices. Superseded by `load_devices` and
+   * `get_state`.
    */
   #[deprecated]
+  #[allow(deprecated)]
   pub fn fetch_gateway_state(&self) -> Result<GatewayState, RazberryError> {
-    let url = try!(self.data_url(None));
-    let session_token = self.session_token.as_ref()
-        .ok_or(RazberryError::ClientError)?;
-
-    let mut result = try!(self.client.get(url)
-        .header(Cookie(vec![
-          format!("{}={}", SESSION_COOKIE_NAME, session_token)
-        ]))
-        .send()
-        .map_err(|_| RazberryError::ClientError));
-
-    match result.status {
-      StatusCode::Ok => {}, // Continue
-      StatusCode::Unauthorized => { return Err(RazberryError::BadCredentials); },
-      _ => { return Err(RazberryError::BadRequest); },
-    }
-
-    let mut body = String::new();
-    try!(result.read_to_string(&mut body)
-         .map_err(|_| RazberryError::ServerError));
+    let url = self.data_url(None)?;
+    let body = self.get_authenticated(url)?;
 
     GatewayState::build(&body).map_err(|_| RazberryError::ClientError)
   }
@@ -341,39 +628,24 @@ impl RazberryClient {
   /**
    * Get an updated view of the state of the Razberry gateway. This
    * fetches any state changes since the last fetch or update and
-   * patches the delta into the 'GatewayState' object.
+   * patches the delta into the 'GatewayState' object. Superseded by
+   * `poll_updates`.
    */
   #[deprecated]
+  #[allow(deprecated)]
   pub fn update_gateway_state(&self, gateway_state: &mut GatewayState) ->
       Result<(), RazberryError> {
     let timestamp = gateway_state.get_end_timestamp();
-    let url = try!(self.data_url(Some(timestamp)));
-
-    let session_token = self.session_token.as_ref()
-        .ok_or(RazberryError::ClientError)?;
-
-    let mut result = try!(self.client.get(url)
-        .header(Cookie(vec![
-          format!("{}={}", SESSION_COOKIE_NAME, session_token)
-        ]))
-        .send()
-        .map_err(|_| RazberryError::ClientError));
-
-    match result.status {
-      StatusCode::Ok => {}, // Continue
-      StatusCode::Unauthorized => { return Err(RazberryError::BadCredentials); },
-      _ => { return Err(RazberryError::BadRequest); },
-    }
-
-    let mut body = String::new();
-    try!(result.read_to_string(&mut body)
-         .map_err(|_| RazberryError::ServerError));
+    let url = self.data_url(Some(timestamp))?;
+    let body = self.get_authenticated(url)?;
 
-    let partial_state = try!(PartialGatewayState::build(&body, timestamp)
-        .map_err(|_| RazberryError::ClientError));
+    let partial_state = PartialGatewayState::build(&body, timestamp)
+        .map_err(|_| RazberryError::ClientError)?;
 
-    // TODO: Rethink errors.
-    gateway_state.merge(&partial_state).map_err(|_| RazberryError::ClientError)
+    gateway_state.merge(&partial_state).map_err(|e| match e {
+      ResponseError::PossibleMissingEvents => RazberryError::PossibleMissingEvents,
+      _ => RazberryError::ClientError,
+    })
   }
 
   /// Generate a data URL.
@@ -386,6 +658,12 @@ impl RazberryClient {
         .map_err(|_| RazberryError::ClientError)
   }
 
+  /// Generate a command URL.
+  fn run_url(&self, command: &str) -> Result<Url, RazberryError> {
+    self.base_url.join(&format!("/ZWaveAPI/Run/{}", command))
+        .map_err(|_| RazberryError::ClientError)
+  }
+
   /// Generate login URL.
   fn login_url(&self) -> Result<Url, RazberryError> {
     self.base_url.join("/ZAutomation/api/v1/login")
@@ -398,6 +676,7 @@ impl RazberryClient {
    * of its associated devices.
    */
   #[deprecated]
+  #[allow(deprecated)]
   pub fn get_data(&self) -> Result<DataResponse, RazberryError> {
     self.fetch_data(None)
   }
@@ -409,6 +688,7 @@ impl RazberryClient {
    * timestamp.
    */
   #[deprecated]
+  #[allow(deprecated)]
   pub fn get_data_after(&self, timestamp: i64)
       -> Result<DataResponse, RazberryError> {
     self.fetch_data(Some(timestamp))
@@ -420,6 +700,7 @@ impl RazberryClient {
    * Calls the data endpoint with an invalid timestamp.
    */
   #[deprecated]
+  #[allow(deprecated)]
   pub fn get_server_timestamp(&self) -> Result<DataResponse, RazberryError> {
     self.fetch_data(Some(20000000000))
   }
@@ -427,28 +708,11 @@ impl RazberryClient {
   /// XXX: DEPRECATED.
   /// Do lookup at the data endpoint.
   #[deprecated]
+  #[allow(deprecated)]
   pub fn fetch_data(&self, timestamp: Option<i64>)
       -> Result<DataResponse, RazberryError> {
-    let url = try!(self.data_url(timestamp));
-    let session_token = self.session_token.as_ref()
-        .ok_or(RazberryError::ClientError)?;
-
-    let mut result = try!(self.client.get(url)
-        .header(Cookie(vec![
-          format!("{}={}", SESSION_COOKIE_NAME, session_token)
-        ]))
-        .send()
-        .map_err(|_| RazberryError::ClientError));
-
-    match result.status {
-      StatusCode::Ok => {}, // Continue
-      StatusCode::Unauthorized => { return Err(RazberryError::BadCredentials); },
-      _ => { return Err(RazberryError::BadRequest); },
-    }
-
-    let mut body = String::new();
-    try!(result.read_to_string(&mut body)
-         .map_err(|_| RazberryError::ServerError));
+    let url = self.data_url(timestamp)?;
+    let body = self.get_authenticated(url)?;
 
     DataResponse::from_str(&body).map_err(|_| RazberryError::ClientError)
   }
@@ -470,6 +734,19 @@ mod tests {
     assert!(RazberryClient::new("localhost", 1234u32).is_ok())
   }
 
+  #[test]
+  fn client_with_address() {
+    let client = RazberryClient::for_address("gateway.local").unwrap();
+    assert_eq!(Some(8083), client.base_url.port());
+
+    let client = RazberryClient::for_address("127.0.0.1:1234").unwrap();
+    assert_eq!(Some("127.0.0.1"), client.base_url.host_str());
+    assert_eq!(Some(1234), client.base_url.port());
+
+    assert!(RazberryClient::for_address("localhost:port").is_err());
+    assert!(RazberryClient::for_address("localhost:99999").is_err());
+  }
+
   #[test]
   fn test_good_cookie_parsing() {
     let cookie = "ZWAYSession=foo-bar-baz; Path=/; HttpOnly";
@@ -492,6 +769,154 @@ mod tests {
     assert!(parsed.is_none());
   }
 
+  #[test]
+  fn test_run_url() {
+    let client = RazberryClient::for_hostname("localhost").unwrap();
+    let url = client.run_url("devices[4].instances[0].commandClasses[50].Reset()")
+        .unwrap();
+    assert_eq!("/ZWaveAPI/Run/devices[4].instances[0].commandClasses[50].Reset()",
+      url.path());
+  }
+
+  #[test]
+  fn test_set_user_code_validation() {
+    let client = RazberryClient::for_hostname("localhost").unwrap();
+
+    // Rejected before any request is made.
+    for code in &["123", "12345678901", "12a4", "1234\\\")"] {
+      match client.set_user_code("5", 0, 1, code) {
+        Err(RazberryError::InvalidArgument) => {},
+        _ => panic!("Code should have been rejected: {}", code),
+      }
+    }
+  }
+
+  #[test]
+  fn test_device_id_validation() {
+    let client = RazberryClient::for_hostname("localhost").unwrap();
+
+    // Rejected before any request is made.
+    for device_id in &["", "x", "256", "-1", " 2",
+        "2].instances[0].commandClasses[99].Set(1,\"1234\",1);devices[2"] {
+      match client.set_switch(device_id, 0, true) {
+        Err(RazberryError::InvalidArgument) => {},
+        _ => panic!("Device should have been rejected: {}", device_id),
+      }
+    }
+  }
+
+  #[test]
+  fn test_format_method() {
+    let args = [Json::U64(1), Json::String("1234".to_string()), Json::Boolean(true),
+      Json::F64(21.5), Json::I64(-1)];
+    assert_eq!("Set(1,\"1234\",true,21.5,-1)",
+      RazberryClient::format_method("Set", &args).unwrap());
+    assert_eq!("Get()", RazberryClient::format_method("Get", &[]).unwrap());
+
+    for name in &["", "Set(1);Evil", "x.Set"] {
+      assert!(RazberryClient::format_method(name, &[]).is_err(), "{}", name);
+    }
+
+    for arg in &[
+      Json::F64(::std::f64::NAN),
+      Json::F64(::std::f64::INFINITY),
+      Json::String("a\"); Evil(\"".to_string()),
+      Json::Array(vec![Json::U64(1)]),
+      Json::Null,
+    ] {
+      assert!(RazberryClient::format_method("Set", &[arg.clone()]).is_err(), "{:?}", arg);
+    }
+  }
+
+  #[test]
+  fn test_set_dimmer_validation() {
+    let client = RazberryClient::for_hostname("localhost").unwrap();
+
+    match client.set_dimmer("5", 0, 100) {
+      Err(RazberryError::InvalidArgument) => {},
+      _ => panic!("Level should have been rejected"),
+    }
+  }
+
+  #[test]
+  fn test_set_thermostat_setpoint_validation() {
+    let client = RazberryClient::for_hostname("localhost").unwrap();
+
+    for value in &[::std::f64::NAN, ::std::f64::INFINITY, ::std::f64::NEG_INFINITY] {
+      match client.set_thermostat_setpoint("5", 0, SetpointType::Heating, *value) {
+        Err(RazberryError::InvalidArgument) => {},
+        _ => panic!("Setpoint should have been rejected: {}", value),
+      }
+    }
+  }
+
+  #[test]
+  fn test_restore() {
+    let json = Json::from_str(include_str!("../sample_data/data.json")).unwrap();
+    let state = NetworkState::from_json(&json).unwrap();
+    let snapshot = state.to_snapshot(UTC::now()).to_string();
+
+    let mut client = RazberryClient::for_hostname("localhost").unwrap();
+    assert!(client.snapshot().is_err());
+
+    assert!(client.restore(&snapshot).unwrap().is_empty());
+    assert_eq!(Some(state.get_update_time()), client.last_update);
+    assert_eq!(state.get_devices().len(), client.get_devices().len());
+    assert!(client.snapshot().is_ok());
+
+    assert!(client.restore("{}").is_err());
+  }
+
+  #[test]
+  fn test_replayed_session() {
+    use transport::Exchange;
+    use transport::ReplayTransport;
+
+    fn exchange(method: &str, path: &str, headers: Vec<(String, String)>,
+                body: &str) -> Exchange {
+      Exchange {
+        request: HttpRequest {
+          method: method.to_string(),
+          url: format!("http://localhost:8083{}", path),
+          headers: Vec::new(),
+          body: None,
+        },
+        response: Some(HttpResponse {
+          status: 200,
+          headers: headers,
+          body: body.to_string(),
+        }),
+        timestamp: UTC::now(),
+        elapsed_ms: 0,
+      }
+    }
+
+    let replay = ReplayTransport::new(vec![
+      exchange("POST", "/ZAutomation/api/v1/login",
+        vec![("Set-Cookie".to_string(), "ZWAYSession=abc; Path=/".to_string())],
+        "{}"),
+      exchange("GET", "/ZWaveAPI/Data", Vec::new(),
+        include_str!("../sample_data/data.json")),
+      exchange("GET", "/ZWaveAPI/Data/1456036521", Vec::new(),
+        include_str!("../sample_data/data-device-updates.json")),
+    ]);
+
+    let mut client = RazberryClient::for_hostname("localhost").unwrap();
+    client.set_transport(Box::new(replay));
+
+    client.login("admin", "password").unwrap();
+    assert_eq!(Some("abc".to_string()), client.get_session_token());
+
+    client.load_devices().unwrap();
+    assert!(!client.get_devices().is_empty());
+
+    client.poll_updates().unwrap();
+    assert_eq!(1456036634, client.last_update.unwrap().timestamp());
+
+    // The recording is exhausted.
+    assert!(client.poll_updates().is_err());
+  }
+
   #[test]
   fn test_parse_timestamp() {
     fn make_datetime(ts: i64) -> DateTime<UTC> {
@@ -0,0 +1,265 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use data_holder::DataHolder;
+use device_update::DeviceUpdate;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::fmt;
+
+/**
+ * How a scene controller button was actuated.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum KeyAttribute {
+  PressedOnce,
+  Released,
+  HeldDown,
+  PressedTwice,
+  PressedThreeTimes,
+  PressedFourTimes,
+  PressedFiveTimes,
+}
+
+impl KeyAttribute {
+  /// Convert a key attribute identifier into a key attribute.
+  pub fn from_byte(key_attribute: u8) -> Option<KeyAttribute> {
+    let key_attribute = match key_attribute {
+      0x00 => KeyAttribute::PressedOnce,
+      0x01 => KeyAttribute::Released,
+      0x02 => KeyAttribute::HeldDown,
+      0x03 => KeyAttribute::PressedTwice,
+      0x04 => KeyAttribute::PressedThreeTimes,
+      0x05 => KeyAttribute::PressedFourTimes,
+      0x06 => KeyAttribute::PressedFiveTimes,
+      _ => return None,
+    };
+    Some(key_attribute)
+  }
+}
+
+/**
+ * Command class 0x5B (91). Button presses on wall remotes and scene
+ * controllers. These are momentary events rather than device state, so the
+ * command class keeps track of which press it has already reported.
+ *
+ * Z-Way timestamps are whole seconds, so a press is told apart from a
+ * re-delivery by its scene, key attribute and "updateTime" together. Two
+ * identical presses within the same second are reported once, and only the
+ * last press between two polls is seen at all.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct CentralScene {
+  scene: Option<u8>,
+  key_attribute: Option<KeyAttribute>,
+  updated: Option<DateTime<UTC>>,
+
+  /// The "updateTime" of the last press handed out by `take_activation`.
+  reported: Option<DateTime<UTC>>,
+
+  /// The scene and key attribute of that press.
+  reported_press: (Option<u8>, Option<KeyAttribute>),
+}
+
+impl CentralScene {
+  /// Construct a CentralScene command class. The press present at
+  /// initialization is treated as already reported.
+  pub fn initialize_from_json(json: &Json)
+      -> Result<CentralScene, RazberryError> {
+    let holder = DataHolder::from_json(json)?;
+    let data = holder.get_child("data")
+        .ok_or(RazberryError::BadResponse)?;
+
+    let mut central_scene = CentralScene::default();
+
+    for (key, value) in data.get_children() {
+      central_scene.apply(key, value);
+    }
+
+    central_scene.reported = central_scene.updated;
+    central_scene.reported_press = (central_scene.scene, central_scene.key_attribute);
+    Ok(central_scene)
+  }
+
+  /// Get the last scene number activated.
+  pub fn get_scene(&self) -> Option<u8> {
+    self.scene
+  }
+
+  /// Get how the last scene was activated.
+  pub fn get_key_attribute(&self) -> Option<KeyAttribute> {
+    self.key_attribute
+  }
+
+  /// Get when the last scene was activated.
+  pub fn get_updated(&self) -> Option<DateTime<UTC>> {
+    self.updated
+  }
+
+  /// Return the latest press if it has not been returned before.
+  pub(crate) fn take_activation(&mut self)
+      -> Option<(u8, Option<KeyAttribute>, DateTime<UTC>)> {
+    let press = (self.scene, self.key_attribute);
+
+    let is_new = self.updated > self.reported
+        || (self.updated == self.reported && press != self.reported_press);
+
+    if !is_new {
+      return None; // Nothing new, or a re-delivery of a reported press.
+    }
+
+    self.reported = self.updated;
+    self.reported_press = press;
+
+    match (self.scene, self.get_updated()) {
+      (Some(scene), Some(updated)) => {
+        Some((scene, self.key_attribute, updated))
+      },
+      _ => None,
+    }
+  }
+
+  /// Process the updates from the client.
+  /// Should not be publicly used.
+  pub fn process_update(&mut self, update: &DeviceUpdate)
+      -> Result<(), RazberryError> {
+    if update.path.get(4) != Some(&"data") {
+      return Ok(()); // Irrelevant update.
+    }
+
+    match update.path.get(5) {
+      Some(key) => self.apply(key, &update.data),
+      None => {
+        // The entire data subtree was replaced.
+        for (key, value) in update.data.get_children() {
+          self.apply(key, value);
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  /// Apply a data holder directly beneath "data".
+  fn apply(&mut self, key: &str, holder: &DataHolder) {
+    let value = holder.get_value().as_int().map(|v| v as u8);
+    let updated = holder.get_update_time();
+
+    match key {
+      "currentScene" => self.scene = value,
+      "keyAttribute" => {
+        self.key_attribute = value.and_then(|k| KeyAttribute::from_byte(k));
+      },
+      _ => return, // Not tracked.
+    }
+
+    if updated > self.updated {
+      self.updated = updated;
+    }
+  }
+}
+
+impl fmt::Display for CentralScene {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "CentralScene(scene: {:?}, key_attribute: {:?})",
+      self.scene, self.key_attribute)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn update<'a>(key: &'a str, json: &Json) -> DeviceUpdate<'a> {
+    DeviceUpdate {
+      path: vec!["instances", "0", "commandClasses", "91", "data", key],
+      data: DataHolder::from_json(json).unwrap(),
+    }
+  }
+
+  #[test]
+  fn test_initial_press_is_not_reported() {
+    let json = Json::from_str(r#"
+      {
+        "name": "CentralScene",
+        "data": {
+          "currentScene": { "value": 1, "type": "int", "updateTime": 1492409902 },
+          "keyAttribute": { "value": 0, "type": "int", "updateTime": 1492409902 }
+        }
+      }
+    "#).unwrap();
+
+    let mut cc = CentralScene::initialize_from_json(&json).unwrap();
+
+    assert_eq!(Some(1), cc.get_scene());
+    assert!(cc.take_activation().is_none());
+  }
+
+  #[test]
+  fn test_press_is_reported_once() {
+    let mut cc = CentralScene::default();
+
+    let scene = Json::from_str(
+      r#"{ "value": 2, "type": "int", "updateTime": 1492410000 }"#).unwrap();
+    let attribute = Json::from_str(
+      r#"{ "value": 2, "type": "int", "updateTime": 1492410000 }"#).unwrap();
+
+    cc.process_update(&update("currentScene", &scene)).unwrap();
+    cc.process_update(&update("keyAttribute", &attribute)).unwrap();
+
+    let (scene_number, key_attribute, timestamp) = cc.take_activation().unwrap();
+    assert_eq!(2, scene_number);
+    assert_eq!(Some(KeyAttribute::HeldDown), key_attribute);
+    assert_eq!(1492410000, timestamp.timestamp());
+
+    // The same updates delivered again by the next poll.
+    cc.process_update(&update("currentScene", &scene)).unwrap();
+    cc.process_update(&update("keyAttribute", &attribute)).unwrap();
+
+    assert!(cc.take_activation().is_none());
+  }
+
+  #[test]
+  fn test_repeated_press_is_reported() {
+    let mut cc = CentralScene::default();
+
+    let first = Json::from_str(
+      r#"{ "value": 1, "type": "int", "updateTime": 1492410000 }"#).unwrap();
+    let second = Json::from_str(
+      r#"{ "value": 1, "type": "int", "updateTime": 1492410005 }"#).unwrap();
+
+    cc.process_update(&update("currentScene", &first)).unwrap();
+    assert!(cc.take_activation().is_some());
+
+    cc.process_update(&update("currentScene", &second)).unwrap();
+    assert!(cc.take_activation().is_some());
+  }
+
+  #[test]
+  fn test_presses_within_a_second_are_reported() {
+    let mut cc = CentralScene::default();
+
+    let press = |scene: u8, key_attribute: u8| {
+      (Json::from_str(&format!(
+          r#"{{ "value": {}, "type": "int", "updateTime": 1492410000 }}"#, scene)).unwrap(),
+       Json::from_str(&format!(
+          r#"{{ "value": {}, "type": "int", "updateTime": 1492410000 }}"#, key_attribute)).unwrap())
+    };
+
+    for &(scene, key_attribute) in &[(1, 0), (1, 1), (2, 0)] {
+      let (scene_json, attribute_json) = press(scene, key_attribute);
+      cc.process_update(&update("currentScene", &scene_json)).unwrap();
+      cc.process_update(&update("keyAttribute", &attribute_json)).unwrap();
+
+      let activation = cc.take_activation().unwrap();
+      assert_eq!((scene, KeyAttribute::from_byte(key_attribute)), (activation.0, activation.1));
+    }
+
+    // A re-delivery of the last press.
+    let (scene_json, attribute_json) = press(2, 0);
+    cc.process_update(&update("currentScene", &scene_json)).unwrap();
+    cc.process_update(&update("keyAttribute", &attribute_json)).unwrap();
+    assert!(cc.take_activation().is_none());
+  }
+}
@@ -0,0 +1,270 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use data_holder::DataHolder;
+use device_update::DeviceUpdate;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::fmt;
+use tracked_value::Freshness;
+use tracked_value::TrackedValue;
+
+/**
+ * The lock modes of a door lock.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DoorLockMode {
+  Unsecured,
+  UnsecuredWithTimeout,
+  InsideUnsecured,
+  InsideUnsecuredWithTimeout,
+  OutsideUnsecured,
+  OutsideUnsecuredWithTimeout,
+  Unknown,
+  Secured,
+}
+
+impl DoorLockMode {
+  /// Convert a lock mode identifier into a lock mode.
+  pub fn from_byte(mode: u8) -> Option<DoorLockMode> {
+    let mode = match mode {
+      0x00 => DoorLockMode::Unsecured,
+      0x01 => DoorLockMode::UnsecuredWithTimeout,
+      0x10 => DoorLockMode::InsideUnsecured,
+      0x11 => DoorLockMode::InsideUnsecuredWithTimeout,
+      0x20 => DoorLockMode::OutsideUnsecured,
+      0x21 => DoorLockMode::OutsideUnsecuredWithTimeout,
+      0xFE => DoorLockMode::Unknown,
+      0xFF => DoorLockMode::Secured,
+      _ => return None,
+    };
+    Some(mode)
+  }
+
+  /// Convert a lock mode into its identifier.
+  pub fn to_byte(&self) -> u8 {
+    match *self {
+      DoorLockMode::Unsecured => 0x00,
+      DoorLockMode::UnsecuredWithTimeout => 0x01,
+      DoorLockMode::InsideUnsecured => 0x10,
+      DoorLockMode::InsideUnsecuredWithTimeout => 0x11,
+      DoorLockMode::OutsideUnsecured => 0x20,
+      DoorLockMode::OutsideUnsecuredWithTimeout => 0x21,
+      DoorLockMode::Unknown => 0xFE,
+      DoorLockMode::Secured => 0xFF,
+    }
+  }
+}
+
+/**
+ * The physical state of the door, bolt, and latch, as decoded from the
+ * "condition" bitmask.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct DoorCondition {
+  pub door_closed: bool,
+  pub bolt_locked: bool,
+  pub latch_closed: bool,
+}
+
+impl DoorCondition {
+  /// Decode the condition bitmask.
+  pub fn from_byte(condition: u8) -> DoorCondition {
+    DoorCondition {
+      door_closed: condition & 0x01 != 0,
+      bolt_locked: condition & 0x02 == 0, // The bolt bit is set when unlocked.
+      latch_closed: condition & 0x04 != 0,
+    }
+  }
+}
+
+/**
+ * Command class 0x62 (98). The state and configuration of a door lock.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct DoorLock {
+  mode: TrackedValue<Option<DoorLockMode>>,
+  condition: Option<u8>,
+  lock_minutes: Option<u8>,
+  lock_seconds: Option<u8>,
+  timeout_minutes: Option<u8>,
+  timeout_seconds: Option<u8>,
+}
+
+impl DoorLock {
+  /// Construct a DoorLock command class.
+  pub fn initialize_from_json(json: &Json) -> Result<DoorLock, RazberryError> {
+    let holder = DataHolder::from_json(json)?;
+    let data = holder.get_child("data")
+        .ok_or(RazberryError::BadResponse)?;
+
+    let mut door_lock = DoorLock::default();
+
+    for (key, value) in data.get_children() {
+      door_lock.apply(key, value);
+    }
+
+    Ok(door_lock)
+  }
+
+  /// Get the current lock mode.
+  pub fn get_mode(&self) -> Option<DoorLockMode> {
+    *self.mode.get_value()
+  }
+
+  /// Whether the lock is fully secured.
+  pub fn is_locked(&self) -> Option<bool> {
+    match *self.mode.get_value() {
+      None | Some(DoorLockMode::Unknown) => None,
+      Some(mode) => Some(mode == DoorLockMode::Secured),
+    }
+  }
+
+  /// Get when the lock mode was last updated.
+  pub fn get_mode_updated(&self) -> Option<DateTime<UTC>> {
+    self.mode.get_update_time()
+  }
+
+  /// Get whether the lock mode can be trusted as current.
+  pub fn get_freshness(&self) -> Freshness {
+    self.mode.get_freshness()
+  }
+
+  /// Whether the lock mode answers the most recent refresh request.
+  pub fn is_valid(&self) -> bool {
+    self.mode.is_valid()
+  }
+
+  /// Get the door, bolt, and latch conditions.
+  pub fn get_condition(&self) -> Option<DoorCondition> {
+    self.condition.map(|c| DoorCondition::from_byte(c))
+  }
+
+  /// Get the time remaining, in seconds, before the lock automatically
+  /// re-secures itself. Only reported in the "with timeout" modes.
+  pub fn get_remaining_timeout(&self) -> Option<u32> {
+    to_seconds(self.lock_minutes, self.lock_seconds)
+  }
+
+  /// Get the configured automatic re-lock timeout, in seconds.
+  pub fn get_configured_timeout(&self) -> Option<u32> {
+    to_seconds(self.timeout_minutes, self.timeout_seconds)
+  }
+
+  /// Process the updates from the client.
+  /// Should not be publicly used.
+  pub fn process_update(&mut self, update: &DeviceUpdate)
+      -> Result<(), RazberryError> {
+    if update.path.get(4) != Some(&"data") {
+      return Ok(()); // Irrelevant update.
+    }
+
+    match update.path.get(5) {
+      Some(key) => self.apply(key, &update.data),
+      None => {
+        // The entire data subtree was replaced.
+        for (key, value) in update.data.get_children() {
+          self.apply(key, value);
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  /// Apply a data holder directly beneath "data".
+  fn apply(&mut self, key: &str, holder: &DataHolder) {
+    let value = holder.get_value().as_int().map(|v| v as u8);
+    match key {
+      "mode" => {
+        self.mode = TrackedValue::from_holder(holder, |_| {
+          value.and_then(|m| DoorLockMode::from_byte(m))
+        });
+      },
+      "condition" => self.condition = value,
+      "lockMinutes" => self.lock_minutes = value,
+      "lockSeconds" => self.lock_seconds = value,
+      "timeoutMinutes" => self.timeout_minutes = value,
+      "timeoutSeconds" => self.timeout_seconds = value,
+      _ => {}, // Not tracked.
+    }
+  }
+}
+
+fn to_seconds(minutes: Option<u8>, seconds: Option<u8>) -> Option<u32> {
+  match (minutes, seconds) {
+    (None, None) => None,
+    // 0xFE in both fields means "no timeout".
+    (Some(0xFE), Some(0xFE)) => None,
+    (m, s) => Some(m.unwrap_or(0) as u32 * 60 + s.unwrap_or(0) as u32),
+  }
+}
+
+impl fmt::Display for DoorLock {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "DoorLock(mode: {:?}, condition: {:?})",
+      self.get_mode(), self.get_condition())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_initialize_from_json() {
+    let json = Json::from_str(r#"
+      {
+        "name": "DoorLock",
+        "data": {
+          "mode": { "value": 255, "type": "int", "updateTime": 1492409902 },
+          "condition": { "value": 5, "type": "int" },
+          "lockMinutes": { "value": 254, "type": "int" },
+          "lockSeconds": { "value": 254, "type": "int" },
+          "timeoutMinutes": { "value": 1, "type": "int" },
+          "timeoutSeconds": { "value": 30, "type": "int" }
+        }
+      }
+    "#).unwrap();
+
+    let cc = DoorLock::initialize_from_json(&json).unwrap();
+
+    assert_eq!(Some(DoorLockMode::Secured), cc.get_mode());
+    assert_eq!(Some(true), cc.is_locked());
+    assert_eq!(Some(DoorCondition {
+      door_closed: true,
+      bolt_locked: true,
+      latch_closed: true,
+    }), cc.get_condition());
+    assert_eq!(None, cc.get_remaining_timeout());
+    assert_eq!(Some(90), cc.get_configured_timeout());
+  }
+
+  #[test]
+  fn test_process_update() {
+    let mut cc = DoorLock::default();
+    assert_eq!(None, cc.is_locked());
+
+    let json = Json::from_str(r#"
+      { "value": 0, "type": "int", "updateTime": 1492410000 }
+    "#).unwrap();
+
+    let update = DeviceUpdate {
+      path: vec!["instances", "0", "commandClasses", "98", "data", "mode"],
+      data: DataHolder::from_json(&json).unwrap(),
+    };
+
+    cc.process_update(&update).unwrap();
+
+    assert_eq!(Some(false), cc.is_locked());
+  }
+
+  #[test]
+  fn test_condition() {
+    let condition = DoorCondition::from_byte(0x02);
+    assert!(!condition.door_closed);
+    assert!(!condition.bolt_locked);
+    assert!(!condition.latch_closed);
+  }
+}
@@ -0,0 +1,211 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use data_holder::{DataHolder, DataValue};
+use device_update::DeviceUpdate;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::fmt;
+
+/**
+ * Command class 0x72 (114). Reports who made the device and what it is.
+ * Values stay empty until Z-Way has finished interviewing the device.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct ManufacturerSpecific {
+  vendor_id: Option<u16>,
+  vendor: Option<String>,
+  product_type: Option<u16>,
+  product_id: Option<u16>,
+  serial_number: Option<Vec<u8>>,
+}
+
+impl ManufacturerSpecific {
+  /// Construct a ManufacturerSpecific command class.
+  pub fn initialize_from_json(json: &Json)
+      -> Result<ManufacturerSpecific, RazberryError> {
+    let holder = DataHolder::from_json(json)?;
+    let data = holder.get_child("data")
+        .ok_or(RazberryError::BadResponse)?;
+
+    let mut manufacturer_specific = ManufacturerSpecific::default();
+
+    for (key, value) in data.get_children() {
+      manufacturer_specific.apply(key, value);
+    }
+
+    Ok(manufacturer_specific)
+  }
+
+  /// Get the Z-Wave Alliance manufacturer identifier.
+  pub fn get_vendor_id(&self) -> Option<u16> {
+    self.vendor_id
+  }
+
+  /// Get the manufacturer name, as resolved by Z-Way.
+  pub fn get_vendor(&self) -> Option<&str> {
+    self.vendor.as_ref().map(|s| s.as_str())
+  }
+
+  /// Get the manufacturer-assigned product type.
+  pub fn get_product_type(&self) -> Option<u16> {
+    self.product_type
+  }
+
+  /// Get the manufacturer-assigned product identifier.
+  pub fn get_product_id(&self) -> Option<u16> {
+    self.product_id
+  }
+
+  /// Get the device serial number (or device ID) bytes, if reported.
+  pub fn get_serial_number(&self) -> Option<&[u8]> {
+    self.serial_number.as_ref().map(|s| s.as_slice())
+  }
+
+  /// Process the updates from the client.
+  /// Should not be publicly used.
+  pub fn process_update(&mut self, update: &DeviceUpdate)
+      -> Result<(), RazberryError> {
+    if update.path.get(4) != Some(&"data") {
+      return Ok(()); // Irrelevant update.
+    }
+
+    match update.path.get(5) {
+      Some(key) => self.apply(key, &update.data),
+      None => {
+        // The entire data subtree was replaced.
+        for (key, value) in update.data.get_children() {
+          self.apply(key, value);
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  /// Apply a single data holder, eg. "vendorId", to the model.
+  fn apply(&mut self, key: &str, holder: &DataHolder) {
+    let value = holder.get_value();
+    match key {
+      "vendorId" => self.vendor_id = read_u16(value),
+      "vendor" => self.vendor = value.as_str().map(|s| s.to_string()),
+      "productType" => self.product_type = read_u16(value),
+      "productId" => self.product_id = read_u16(value),
+      "serialNumber" | "deviceId" => {
+        let serial = value.as_binary().map(|b| b.to_vec());
+        if serial.is_some() {
+          self.serial_number = serial;
+        }
+      },
+      _ => {}, // Not tracked.
+    }
+  }
+}
+
+fn read_u16(value: &DataValue) -> Option<u16> {
+  value.as_int().map(|v| v as u16)
+}
+
+impl fmt::Display for ManufacturerSpecific {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "ManufacturerSpecific(vendor: {}, vendor_id: {:?}, \
+        product_type: {:?}, product_id: {:?})",
+      self.vendor.as_ref().map(|s| s.as_str()).unwrap_or("unknown"),
+      self.vendor_id, self.product_type, self.product_id)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_initialize_from_json() {
+    // A subset of the JSON for an Aeotec multisensor.
+    let json = r#"
+      {
+        "name": "ManufacturerSpecific",
+        "data": {
+          "value": null,
+          "type": "empty",
+          "vendorId": {
+            "value": 134,
+            "type": "int",
+            "invalidateTime": 1455606536,
+            "updateTime": 1455606537
+          },
+          "vendor": {
+            "value": "Aeon Labs",
+            "type": "string",
+            "invalidateTime": 1455606536,
+            "updateTime": 1455606537
+          },
+          "productId": {
+            "value": 74,
+            "type": "int",
+            "invalidateTime": 1455606536,
+            "updateTime": 1455606537
+          },
+          "productType": {
+            "value": 258,
+            "type": "int",
+            "invalidateTime": 1455606536,
+            "updateTime": 1455606537
+          },
+          "serialNumber": {
+            "value": [0, 0, 1, 2],
+            "type": "binary",
+            "invalidateTime": 1455606536,
+            "updateTime": 1455606538
+          },
+          "invalidateTime": 1455606536,
+          "updateTime": 1455606417
+        }
+      }
+    "#;
+
+    let json = Json::from_str(json).unwrap();
+    let cc = ManufacturerSpecific::initialize_from_json(&json).unwrap();
+
+    assert_eq!(Some(134), cc.get_vendor_id());
+    assert_eq!(Some("Aeon Labs"), cc.get_vendor());
+    assert_eq!(Some(258), cc.get_product_type());
+    assert_eq!(Some(74), cc.get_product_id());
+    assert_eq!(Some(&[0u8, 0, 1, 2][..]), cc.get_serial_number());
+  }
+
+  #[test]
+  fn test_initialize_before_interview() {
+    let json = r#"
+      {
+        "data": {
+          "vendorId": { "value": null, "type": "empty" }
+        }
+      }
+    "#;
+
+    let json = Json::from_str(json).unwrap();
+    let cc = ManufacturerSpecific::initialize_from_json(&json).unwrap();
+
+    assert_eq!(None, cc.get_vendor_id());
+    assert_eq!(None, cc.get_serial_number());
+  }
+
+  #[test]
+  fn test_process_update() {
+    let mut cc = ManufacturerSpecific::default();
+
+    let json = Json::from_str(r#"
+      { "value": 515, "type": "int", "updateTime": 1491289442 }
+    "#).unwrap();
+
+    let update = DeviceUpdate {
+      path: vec!["instances", "0", "commandClasses", "114", "data",
+        "productType"],
+      data: DataHolder::from_json(&json).unwrap(),
+    };
+
+    cc.process_update(&update).unwrap();
+
+    assert_eq!(Some(515), cc.get_product_type());
+  }
+}
@@ -0,0 +1,429 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use data_holder::DataHolder;
+use device_update::DeviceUpdate;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use std::fmt;
+use tracked_value::Freshness;
+use tracked_value::TrackedValue;
+
+/**
+ * The kind of quantity a meter measures.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MeterType {
+  Electric,
+  Gas,
+  Water,
+}
+
+impl MeterType {
+  /// Convert the meter type reported by Z-Way ("sensorType").
+  pub fn from_byte(meter_type: u8) -> Option<MeterType> {
+    let meter_type = match meter_type {
+      0x01 => MeterType::Electric,
+      0x02 => MeterType::Gas,
+      0x03 => MeterType::Water,
+      _ => return None,
+    };
+    Some(meter_type)
+  }
+}
+
+/**
+ * The unit of a meter reading. The meaning of a scale identifier depends
+ * on the meter type.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MeterScale {
+  KilowattHours,
+  KilovoltAmpereHours,
+  Watts,
+  Pulses,
+  Volts,
+  Amperes,
+  PowerFactor,
+  CubicMeters,
+  CubicFeet,
+  UsGallons,
+}
+
+impl MeterScale {
+  /// Convert a scale identifier for the given meter type.
+  pub fn from_byte(meter_type: MeterType, scale: u8) -> Option<MeterScale> {
+    let scale = match (meter_type, scale) {
+      (MeterType::Electric, 0) => MeterScale::KilowattHours,
+      (MeterType::Electric, 1) => MeterScale::KilovoltAmpereHours,
+      (MeterType::Electric, 2) => MeterScale::Watts,
+      (MeterType::Electric, 3) => MeterScale::Pulses,
+      (MeterType::Electric, 4) => MeterScale::Volts,
+      (MeterType::Electric, 5) => MeterScale::Amperes,
+      (MeterType::Electric, 6) => MeterScale::PowerFactor,
+      (MeterType::Gas, 0) => MeterScale::CubicMeters,
+      (MeterType::Gas, 1) => MeterScale::CubicFeet,
+      (MeterType::Gas, 3) => MeterScale::Pulses,
+      (MeterType::Water, 0) => MeterScale::CubicMeters,
+      (MeterType::Water, 1) => MeterScale::CubicFeet,
+      (MeterType::Water, 2) => MeterScale::UsGallons,
+      (MeterType::Water, 3) => MeterScale::Pulses,
+      _ => return None,
+    };
+    Some(scale)
+  }
+
+  /// Get the unit symbol for the scale.
+  pub fn get_unit(&self) -> &'static str {
+    match *self {
+      MeterScale::KilowattHours => "kWh",
+      MeterScale::KilovoltAmpereHours => "kVAh",
+      MeterScale::Watts => "W",
+      MeterScale::Pulses => "pulses",
+      MeterScale::Volts => "V",
+      MeterScale::Amperes => "A",
+      MeterScale::PowerFactor => "PF",
+      MeterScale::CubicMeters => "m³",
+      MeterScale::CubicFeet => "ft³",
+      MeterScale::UsGallons => "gal",
+    }
+  }
+}
+
+/**
+ * A single reading from a meter, in one scale.
+ */
+#[derive(Clone, Debug)]
+pub struct MeterReading {
+  meter_type: Option<MeterType>,
+  scale: Option<MeterScale>,
+  value: TrackedValue<Option<f64>>,
+  previous_value: Option<f64>,
+  delta_time: Option<u32>,
+}
+
+impl MeterReading {
+  /// Construct a reading from a scale data holder, eg. "data.2".
+  fn from_holder(holder: &DataHolder) -> MeterReading {
+    let mut reading = MeterReading {
+      meter_type: None,
+      scale: None,
+      value: TrackedValue::default(),
+      previous_value: None,
+      delta_time: None,
+    };
+
+    // The type must be known before the scale can be interpreted.
+    if let Some(meter_type) = holder.get_child("sensorType") {
+      reading.apply("sensorType", meter_type);
+    }
+    for (key, value) in holder.get_children() {
+      reading.apply(key, value);
+    }
+
+    reading
+  }
+
+  /// Get the type of meter.
+  pub fn get_meter_type(&self) -> Option<MeterType> {
+    self.meter_type
+  }
+
+  /// Get the unit of the reading.
+  pub fn get_scale(&self) -> Option<MeterScale> {
+    self.scale
+  }
+
+  /// Get the current reading.
+  pub fn get_value(&self) -> Option<f64> {
+    *self.value.get_value()
+  }
+
+  /// Get the previous reading, if the meter reports it.
+  pub fn get_previous_value(&self) -> Option<f64> {
+    self.previous_value
+  }
+
+  /// Get the number of seconds between the previous and current reading, if
+  /// the meter reports it.
+  pub fn get_delta_time(&self) -> Option<u32> {
+    self.delta_time
+  }
+
+  /// Get when the reading was last updated.
+  pub fn get_updated(&self) -> Option<DateTime<UTC>> {
+    self.value.get_update_time()
+  }
+
+  /// Get whether the reading can be trusted as current.
+  pub fn get_freshness(&self) -> Freshness {
+    self.value.get_freshness()
+  }
+
+  /// Whether the reading answers the most recent refresh request.
+  pub fn is_valid(&self) -> bool {
+    self.value.is_valid()
+  }
+
+  /// Apply a single data holder, eg. "val", to the reading.
+  fn apply(&mut self, key: &str, holder: &DataHolder) {
+    let value = holder.get_value();
+    match key {
+      "sensorType" => {
+        self.meter_type = value.as_int()
+            .and_then(|t| MeterType::from_byte(t as u8));
+      },
+      "scale" => {
+        let scale = value.as_int();
+        self.scale = match (self.meter_type, scale) {
+          (Some(meter_type), Some(scale)) => {
+            MeterScale::from_byte(meter_type, scale as u8)
+          },
+          _ => None,
+        };
+      },
+      "val" => {
+        self.value = TrackedValue::from_holder(holder, |v| v.as_float());
+      },
+      "previous" => self.previous_value = value.as_float(),
+      "delta" => self.delta_time = value.as_int().map(|d| d as u32),
+      _ => {}, // Not tracked.
+    }
+  }
+}
+
+/**
+ * Command class 0x32 (50). Energy, gas, and water meters.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Meter {
+  /// Readings keyed by their Z-Way scale identifier.
+  readings: BTreeMap<u8, MeterReading>,
+  resettable: Option<bool>,
+}
+
+impl Meter {
+  /// Construct a Meter command class.
+  pub fn initialize_from_json(json: &Json) -> Result<Meter, RazberryError> {
+    let holder = DataHolder::from_json(json)?;
+    let data = holder.get_child("data")
+        .ok_or(RazberryError::BadResponse)?;
+
+    let mut meter = Meter::default();
+
+    for (key, value) in data.get_children() {
+      meter.apply(key, value);
+    }
+
+    Ok(meter)
+  }
+
+  /// Get all of the readings.
+  pub fn get_readings(&self) -> Vec<&MeterReading> {
+    self.readings.values().collect()
+  }
+
+  /// Get the reading in a particular unit.
+  pub fn get_reading(&self, scale: MeterScale) -> Option<&MeterReading> {
+    self.readings.values().find(|r| r.scale == Some(scale))
+  }
+
+  /// Whether the meter supports the Reset() command.
+  pub fn is_resettable(&self) -> Option<bool> {
+    self.resettable
+  }
+
+  /// Process the updates from the client.
+  /// Should not be publicly used.
+  pub fn process_update(&mut self, update: &DeviceUpdate)
+      -> Result<(), RazberryError> {
+    if update.path.get(4) != Some(&"data") {
+      return Ok(()); // Irrelevant update.
+    }
+
+    match (update.path.get(5), update.path.get(6)) {
+      (None, _) => {
+        // The entire data subtree was replaced.
+        for (key, value) in update.data.get_children() {
+          self.apply(key, value);
+        }
+      },
+      (Some(key), None) => self.apply(key, &update.data),
+      (Some(scale), Some(field)) => {
+        // A single field of a reading, eg. "data.2.val".
+        let scale = match scale.parse::<u8>() {
+          Err(_) => return Ok(()),
+          Ok(scale) => scale,
+        };
+        match self.readings.get_mut(&scale) {
+          None => {}, // Not loaded at initialization.
+          Some(reading) => reading.apply(field, &update.data),
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  /// Apply a data holder directly beneath "data".
+  fn apply(&mut self, key: &str, holder: &DataHolder) {
+    if key == "resettable" {
+      self.resettable = holder.get_value().as_bool();
+      return;
+    }
+
+    if let Ok(scale) = key.parse::<u8>() {
+      self.readings.insert(scale, MeterReading::from_holder(holder));
+    }
+  }
+}
+
+impl fmt::Display for MeterReading {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let value = self.get_value().map(|v| v.to_string())
+        .unwrap_or("unknown".to_string());
+    let unit = self.scale.map(|s| s.get_unit()).unwrap_or("");
+    write!(f, "{} {}", value, unit)
+  }
+}
+
+impl fmt::Display for Meter {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let readings = self.readings.values()
+        .map(|r| r.to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
+    write!(f, "Meter({})", readings)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // A subset of the JSON for a smart plug reporting kWh and W.
+  const METER_JSON : &'static str = r#"
+    {
+      "name": "Meter",
+      "data": {
+        "value": null,
+        "type": "empty",
+        "resettable": {
+          "value": true,
+          "type": "bool",
+          "invalidateTime": 1491741863,
+          "updateTime": 1491741864
+        },
+        "0": {
+          "value": null,
+          "type": "empty",
+          "sensorType": { "value": 1, "type": "int" },
+          "sensorTypeString": { "value": "Electric", "type": "string" },
+          "val": {
+            "value": 12.75,
+            "type": "float",
+            "invalidateTime": 1491741863,
+            "updateTime": 1492409902
+          },
+          "scale": { "value": 0, "type": "int" },
+          "scaleString": { "value": "kWh", "type": "string" },
+          "ratetype": { "value": 1, "type": "int" },
+          "delta": { "value": 300, "type": "int" },
+          "previous": { "value": 12.5, "type": "float" }
+        },
+        "2": {
+          "value": null,
+          "type": "empty",
+          "sensorType": { "value": 1, "type": "int" },
+          "val": {
+            "value": 41.2,
+            "type": "float",
+            "invalidateTime": 1491741863,
+            "updateTime": 1492409902
+          },
+          "scale": { "value": 2, "type": "int" },
+          "scaleString": { "value": "W", "type": "string" }
+        }
+      }
+    }
+  "#;
+
+  #[test]
+  fn test_initialize_from_json() {
+    let json = Json::from_str(METER_JSON).unwrap();
+    let meter = Meter::initialize_from_json(&json).unwrap();
+
+    assert_eq!(2, meter.get_readings().len());
+    assert_eq!(Some(true), meter.is_resettable());
+
+    let energy = meter.get_reading(MeterScale::KilowattHours).unwrap();
+    assert_eq!(Some(MeterType::Electric), energy.get_meter_type());
+    assert_eq!(Some(12.75), energy.get_value());
+    assert_eq!(Some(12.5), energy.get_previous_value());
+    assert_eq!(Some(300), energy.get_delta_time());
+    assert_eq!(1492409902, energy.get_updated().unwrap().timestamp());
+
+    let power = meter.get_reading(MeterScale::Watts).unwrap();
+    assert_eq!(Some(41.2), power.get_value());
+    assert_eq!(None, power.get_previous_value());
+    assert_eq!("41.2 W", power.to_string());
+  }
+
+  #[test]
+  fn test_scales_depend_on_type() {
+    assert_eq!(Some(MeterScale::Watts),
+      MeterScale::from_byte(MeterType::Electric, 2));
+    assert_eq!(Some(MeterScale::UsGallons),
+      MeterScale::from_byte(MeterType::Water, 2));
+    assert_eq!(None, MeterScale::from_byte(MeterType::Gas, 2));
+  }
+
+  #[test]
+  fn test_process_value_update() {
+    let json = Json::from_str(METER_JSON).unwrap();
+    let mut meter = Meter::initialize_from_json(&json).unwrap();
+
+    let json = Json::from_str(r#"
+      { "value": 97.0, "type": "float", "updateTime": 1492410000 }
+    "#).unwrap();
+
+    let update = DeviceUpdate {
+      path: vec!["instances", "0", "commandClasses", "50", "data", "2", "val"],
+      data: DataHolder::from_json(&json).unwrap(),
+    };
+
+    meter.process_update(&update).unwrap();
+
+    let power = meter.get_reading(MeterScale::Watts).unwrap();
+    assert_eq!(Some(97.0), power.get_value());
+    assert_eq!(1492410000, power.get_updated().unwrap().timestamp());
+  }
+
+  #[test]
+  fn test_process_reading_update() {
+    let mut meter = Meter::default();
+
+    let json = Json::from_str(r#"
+      {
+        "value": null,
+        "type": "empty",
+        "sensorType": { "value": 3, "type": "int" },
+        "val": { "value": 1.5, "type": "float", "updateTime": 1492410000 },
+        "scale": { "value": 0, "type": "int" }
+      }
+    "#).unwrap();
+
+    let update = DeviceUpdate {
+      path: vec!["instances", "0", "commandClasses", "50", "data", "0"],
+      data: DataHolder::from_json(&json).unwrap(),
+    };
+
+    meter.process_update(&update).unwrap();
+
+    let water = meter.get_reading(MeterScale::CubicMeters).unwrap();
+    assert_eq!(Some(MeterType::Water), water.get_meter_type());
+    assert_eq!(Some(1.5), water.get_value());
+  }
+}
@@ -1,10 +1,35 @@
 // Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
 
+pub mod central_scene;
+pub mod door_lock;
+pub mod manufacturer_specific;
+pub mod meter;
+pub mod scene_activation;
 pub mod sensor_binary;
 pub mod sensor_multilevel;
+pub mod thermostat_fan_mode;
+pub mod thermostat_mode;
+pub mod thermostat_operating_state;
+pub mod thermostat_setpoint;
+pub mod user_code;
+pub mod version;
 
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use command_class::central_scene::CentralScene;
+use command_class::central_scene::KeyAttribute;
+use command_class::door_lock::DoorLock;
+use command_class::manufacturer_specific::ManufacturerSpecific;
+use command_class::meter::Meter;
+use command_class::scene_activation::SceneActivation;
 use command_class::sensor_binary::SensorBinary;
 use command_class::sensor_multilevel::SensorMultilevel;
+use command_class::thermostat_fan_mode::ThermostatFanMode;
+use command_class::thermostat_mode::ThermostatMode;
+use command_class::thermostat_operating_state::ThermostatOperatingState;
+use command_class::thermostat_setpoint::ThermostatSetpoint;
+use command_class::user_code::UserCode;
+use command_class::version::Version;
 use command_classes::CommandClasses;
 use device_update::DeviceUpdate;
 use error::RazberryError;
@@ -14,10 +39,21 @@ use std::fmt;
 /**
  * Polymorphic struct that can contain any command class instance.
  */
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum CommandClass {
+  CentralScene { inner: CentralScene },
+  DoorLock { inner: DoorLock },
+  ManufacturerSpecific { inner: ManufacturerSpecific },
+  Meter { inner: Meter },
+  SceneActivation { inner: SceneActivation },
   SensorBinary { inner: SensorBinary },
   SensorMultilevel { inner: SensorMultilevel },
+  ThermostatFanMode { inner: ThermostatFanMode },
+  ThermostatMode { inner: ThermostatMode },
+  ThermostatOperatingState { inner: ThermostatOperatingState },
+  ThermostatSetpoint { inner: ThermostatSetpoint },
+  UserCode { inner: UserCode },
+  Version { inner: Version },
   Unsupported, // FIXME: This bucket is a poor concession since I'm in a hurry
 }
 
@@ -28,10 +64,54 @@ impl CommandClass {
       -> Result<CommandClass, RazberryError> {
 
     let result = match command_class {
+      CommandClasses::ManufacturerSpecific => {
+        let inner = ManufacturerSpecific::initialize_from_json(json)?;
+        CommandClass::ManufacturerSpecific { inner: inner }
+      },
+      CommandClasses::Meter => {
+        let inner = Meter::initialize_from_json(json)?;
+        CommandClass::Meter { inner: inner }
+      },
       CommandClasses::SensorBinary => {
         let sensor = SensorBinary::initialize_from_json(json)?;
         CommandClass::SensorBinary { inner:  sensor }
       },
+      CommandClasses::Version => {
+        let inner = Version::initialize_from_json(json)?;
+        CommandClass::Version { inner: inner }
+      },
+      CommandClasses::ThermostatMode => {
+        let inner = ThermostatMode::initialize_from_json(json)?;
+        CommandClass::ThermostatMode { inner: inner }
+      },
+      CommandClasses::ThermostatOperatingState => {
+        let inner = ThermostatOperatingState::initialize_from_json(json)?;
+        CommandClass::ThermostatOperatingState { inner: inner }
+      },
+      CommandClasses::ThermostatSetpoint => {
+        let inner = ThermostatSetpoint::initialize_from_json(json)?;
+        CommandClass::ThermostatSetpoint { inner: inner }
+      },
+      CommandClasses::ThermostatFanMode => {
+        let inner = ThermostatFanMode::initialize_from_json(json)?;
+        CommandClass::ThermostatFanMode { inner: inner }
+      },
+      CommandClasses::DoorLock => {
+        let inner = DoorLock::initialize_from_json(json)?;
+        CommandClass::DoorLock { inner: inner }
+      },
+      CommandClasses::UserCode => {
+        let inner = UserCode::initialize_from_json(json)?;
+        CommandClass::UserCode { inner: inner }
+      },
+      CommandClasses::CentralScene => {
+        let inner = CentralScene::initialize_from_json(json)?;
+        CommandClass::CentralScene { inner: inner }
+      },
+      CommandClasses::SceneActivation => {
+        let inner = SceneActivation::initialize_from_json(json)?;
+        CommandClass::SceneActivation { inner: inner }
+      },
       _ => CommandClass::Unsupported,
     };
 
@@ -44,18 +124,79 @@ impl CommandClass {
   pub fn process_update(&mut self, update: &DeviceUpdate)
       -> Result<(), RazberryError> {
     match self {
+      &mut CommandClass::ManufacturerSpecific { ref mut inner } => {
+        inner.process_update(update)
+      },
+      &mut CommandClass::Meter { ref mut inner } => {
+        inner.process_update(update)
+      },
       &mut CommandClass::SensorBinary { ref mut inner } => {
         inner.process_update(update)
       },
+      &mut CommandClass::Version { ref mut inner } => {
+        inner.process_update(update)
+      },
+      &mut CommandClass::ThermostatMode { ref mut inner } => {
+        inner.process_update(update)
+      },
+      &mut CommandClass::ThermostatOperatingState { ref mut inner } => {
+        inner.process_update(update)
+      },
+      &mut CommandClass::ThermostatSetpoint { ref mut inner } => {
+        inner.process_update(update)
+      },
+      &mut CommandClass::ThermostatFanMode { ref mut inner } => {
+        inner.process_update(update)
+      },
+      &mut CommandClass::DoorLock { ref mut inner } => {
+        inner.process_update(update)
+      },
+      &mut CommandClass::UserCode { ref mut inner } => {
+        inner.process_update(update)
+      },
+      &mut CommandClass::CentralScene { ref mut inner } => {
+        inner.process_update(update)
+      },
+      &mut CommandClass::SceneActivation { ref mut inner } => {
+        inner.process_update(update)
+      },
       _ => Ok(()), // Unsupported
     }
   }
 }
 
+impl CommandClass {
+  /// Return a scene activation that has not been handed out before, if this
+  /// command class reports momentary scene events.
+  pub(crate) fn take_scene_activation(&mut self)
+      -> Option<(u8, Option<KeyAttribute>, DateTime<UTC>)> {
+    match self {
+      &mut CommandClass::CentralScene { ref mut inner } => {
+        inner.take_activation()
+      },
+      &mut CommandClass::SceneActivation { ref mut inner } => {
+        inner.take_activation().map(|(scene, updated)| (scene, None, updated))
+      },
+      _ => None,
+    }
+  }
+}
+
 impl fmt::Display for CommandClass {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
+      &CommandClass::ManufacturerSpecific { ref inner } => inner.fmt(f),
+      &CommandClass::Meter { ref inner } => inner.fmt(f),
       &CommandClass::SensorBinary { ref inner } => inner.fmt(f),
+      &CommandClass::Version { ref inner } => inner.fmt(f),
+      &CommandClass::ThermostatMode { ref inner } => inner.fmt(f),
+      &CommandClass::ThermostatOperatingState { ref inner } => inner.fmt(f),
+      &CommandClass::ThermostatSetpoint { ref inner } => inner.fmt(f),
+      &CommandClass::ThermostatFanMode { ref inner } => inner.fmt(f),
+      &CommandClass::DoorLock { ref inner } => inner.fmt(f),
+      &CommandClass::UserCode { ref inner } => inner.fmt(f),
+      &CommandClass::CentralScene { ref inner } => inner.fmt(f),
+      &CommandClass::SceneActivation { ref inner } => inner.fmt(f),
       _ => write!(f, "CommandClass (no fmt::Display impl)"),
     }
   }
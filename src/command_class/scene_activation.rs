@@ -0,0 +1,153 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use data_holder::DataHolder;
+use device_update::DeviceUpdate;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::fmt;
+
+/**
+ * Command class 0x2B (43). Scene activations sent by older scene
+ * controllers. Like CentralScene, activations are momentary events and each
+ * is handed out only once, told apart by scene and "updateTime". The same
+ * scene activated twice within a second is reported once.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct SceneActivation {
+  scene: Option<u8>,
+  updated: Option<DateTime<UTC>>,
+
+  /// The "updateTime" of the last activation handed out by `take_activation`.
+  reported: Option<DateTime<UTC>>,
+
+  /// The scene of that activation.
+  reported_scene: Option<u8>,
+}
+
+impl SceneActivation {
+  /// Construct a SceneActivation command class. The activation present at
+  /// initialization is treated as already reported.
+  pub fn initialize_from_json(json: &Json)
+      -> Result<SceneActivation, RazberryError> {
+    let holder = DataHolder::from_json(json)?;
+    let mut scene_activation = SceneActivation::default();
+
+    if let Some(scene) = holder.find(&["data", "currentScene"]) {
+      scene_activation.apply_scene(scene);
+    }
+
+    scene_activation.reported = scene_activation.updated;
+    scene_activation.reported_scene = scene_activation.scene;
+    Ok(scene_activation)
+  }
+
+  /// Get the last scene number activated.
+  pub fn get_scene(&self) -> Option<u8> {
+    self.scene
+  }
+
+  /// Get when the last scene was activated.
+  pub fn get_updated(&self) -> Option<DateTime<UTC>> {
+    self.updated
+  }
+
+  /// Return the latest activation if it has not been returned before.
+  pub(crate) fn take_activation(&mut self) -> Option<(u8, DateTime<UTC>)> {
+    let is_new = self.updated > self.reported
+        || (self.updated == self.reported && self.scene != self.reported_scene);
+
+    if !is_new {
+      return None; // Nothing new, or a re-delivery of a reported activation.
+    }
+
+    self.reported = self.updated;
+    self.reported_scene = self.scene;
+
+    match (self.scene, self.get_updated()) {
+      (Some(scene), Some(updated)) => Some((scene, updated)),
+      _ => None,
+    }
+  }
+
+  /// Process the updates from the client.
+  /// Should not be publicly used.
+  pub fn process_update(&mut self, update: &DeviceUpdate)
+      -> Result<(), RazberryError> {
+    if update.path.get(4) != Some(&"data") {
+      return Ok(()); // Irrelevant update.
+    }
+
+    match update.path.get(5) {
+      Some(&"currentScene") => self.apply_scene(&update.data),
+      Some(_) => {}, // Not tracked.
+      None => {
+        if let Some(scene) = update.data.get_child("currentScene") {
+          self.apply_scene(scene);
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  fn apply_scene(&mut self, holder: &DataHolder) {
+    self.scene = holder.get_value().as_int().map(|v| v as u8);
+    self.updated = holder.get_update_time();
+  }
+}
+
+impl fmt::Display for SceneActivation {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "SceneActivation(scene: {:?})", self.scene)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_activation_is_reported_once() {
+    let json = Json::from_str(r#"
+      {
+        "name": "SceneActivation",
+        "data": {
+          "currentScene": { "value": 3, "type": "int", "updateTime": 1492409902 }
+        }
+      }
+    "#).unwrap();
+
+    let mut cc = SceneActivation::initialize_from_json(&json).unwrap();
+    assert!(cc.take_activation().is_none());
+
+    let json = Json::from_str(
+      r#"{ "value": 4, "type": "int", "updateTime": 1492410000 }"#).unwrap();
+
+    let update = DeviceUpdate {
+      path: vec!["instances", "0", "commandClasses", "43", "data",
+        "currentScene"],
+      data: DataHolder::from_json(&json).unwrap(),
+    };
+
+    cc.process_update(&update).unwrap();
+    assert_eq!(Some(4), cc.take_activation().map(|a| a.0));
+
+    cc.process_update(&update).unwrap();
+    assert!(cc.take_activation().is_none());
+
+    // Another scene within the same second.
+    let json = Json::from_str(
+      r#"{ "value": 5, "type": "int", "updateTime": 1492410000 }"#).unwrap();
+
+    let update = DeviceUpdate {
+      path: vec!["instances", "0", "commandClasses", "43", "data",
+        "currentScene"],
+      data: DataHolder::from_json(&json).unwrap(),
+    };
+
+    cc.process_update(&update).unwrap();
+    assert_eq!(Some(5), cc.take_activation().map(|a| a.0));
+  }
+}
@@ -1,21 +1,22 @@
 // Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
 
-use chrono::NaiveDateTime;
 use chrono::UTC;
 use chrono::datetime::DateTime;
+use data_holder::DataHolder;
 use device_update::DeviceUpdate;
 use error::RazberryError;
 use rustc_serialize::json::Json;
 use std::fmt;
+use tracked_value::Freshness;
+use tracked_value::TrackedValue;
 
 /**
  * Represents a sensor with a binary state.
  */
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SensorBinary {
-  // TODO: Keep more data, such as sensor update time, previous value, etc.
-  level: bool,
-  level_updated: DateTime<UTC>,
+  // TODO: Keep more data, such as previous value, etc.
+  level: TrackedValue<bool>,
 }
 
 impl SensorBinary {
@@ -28,20 +29,12 @@ impl SensorBinary {
     // predefined indices, given that Aeotec seems to use consistent "IDs".
     // FIXME: Also, I'm not sure where Rust's json parser is getting "data" in
     // the path from. It doesn't even look like it's a key at this level!
-    let level = json.find_path(&["data", "1", "level", "value"])
-        .and_then(|j| j.as_boolean())
+    let holder = DataHolder::from_json(json)?;
+    let level = holder.find(&["data", "1", "level"])
         .ok_or(RazberryError::BadResponse)?;
 
-    let timestamp = json.find_path(&["data", "1", "level", "updateTime"])
-        .and_then(|j| j.as_i64())
-        .ok_or(RazberryError::BadResponse)?;
-
-    let dt = NaiveDateTime::from_timestamp(timestamp, 0);
-    let utc = DateTime::from_utc(dt, UTC);
-
     let sensor = SensorBinary {
-      level: level,
-      level_updated: utc,
+      level: SensorBinary::read_level(level)?,
     };
 
     Ok(sensor)
@@ -49,7 +42,22 @@ impl SensorBinary {
 
   /// Get the sensor's state.
   pub fn get_level(&self) -> bool {
-    self.level
+    *self.level.get_value()
+  }
+
+  /// Get when the sensor's state was last updated.
+  pub fn get_level_updated(&self) -> Option<DateTime<UTC>> {
+    self.level.get_update_time()
+  }
+
+  /// Get whether the sensor's state can be trusted as current.
+  pub fn get_freshness(&self) -> Freshness {
+    self.level.get_freshness()
+  }
+
+  /// Whether the sensor's state answers the most recent refresh request.
+  pub fn is_valid(&self) -> bool {
+    self.level.is_valid()
   }
 
   /// Process the updates from the client.
@@ -60,27 +68,30 @@ impl SensorBinary {
       return Ok(()); // Irrelevant update.
     }
 
-    let level = update.data.find_path(&["level", "value"])
-        .and_then(|j| j.as_boolean())
-        .ok_or(RazberryError::BadResponse)?;
-
-    let timestamp = update.data.find_path(&["level", "updateTime"])
-        .and_then(|j| j.as_i64())
+    let level = update.data.get_child("level")
         .ok_or(RazberryError::BadResponse)?;
 
-    let dt = NaiveDateTime::from_timestamp(timestamp, 0);
-
-    self.level = level;
-    self.level_updated = DateTime::from_utc(dt, UTC);
+    self.level = SensorBinary::read_level(level)?;
 
     Ok(())
   }
+
+  fn read_level(holder: &DataHolder) -> Result<TrackedValue<bool>, RazberryError> {
+    let level = holder.get_value().as_bool()
+        .ok_or(RazberryError::BadResponse)?;
+    if holder.get_update_time().is_none() {
+      return Err(RazberryError::BadResponse);
+    }
+    Ok(TrackedValue::from_holder(holder, |_| level))
+  }
 }
 
 impl fmt::Display for SensorBinary {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    write!(f, "SensorBinary(level: {}, updated:{})",
-      self.level, self.level_updated)
+    let updated = self.level.get_update_time()
+        .map(|t| t.to_string())
+        .unwrap_or("unknown".to_string());
+    write!(f, "SensorBinary(level: {}, updated:{})", self.get_level(), updated)
   }
 }
 
@@ -125,14 +136,39 @@ mod tests {
     let json = Json::from_str(json).unwrap();
     let sensor = SensorBinary::initialize_from_json(&json).unwrap();
 
-    assert_eq!(false, sensor.get_level())
+    assert_eq!(false, sensor.get_level());
+    assert!(sensor.is_valid());
+  }
+
+  #[test]
+  fn test_invalidated_level() {
+    // The gateway asked a sleeping sensor for its state; it has not answered.
+    let json = Json::from_str(r#"
+      {
+        "data": {
+          "1": {
+            "level": {
+              "value": true,
+              "type": "bool",
+              "invalidateTime": 1491289500,
+              "updateTime": 1491289442
+            }
+          }
+        }
+      }
+    "#).unwrap();
+
+    let sensor = SensorBinary::initialize_from_json(&json).unwrap();
+
+    assert_eq!(true, sensor.get_level());
+    assert_eq!(Freshness::Invalid, sensor.get_freshness());
+    assert!(!sensor.is_valid());
   }
 
   #[test]
   fn test_process_update() {
     let mut sensor = SensorBinary {
-      level: true,
-      level_updated: UTC::now(),
+      level: TrackedValue::new(true, Some(UTC::now()), None),
     };
 
     assert_eq!(true, sensor.get_level());
@@ -162,7 +198,7 @@ mod tests {
 
     let update = DeviceUpdate {
       path: vec!["instances", "0", "commandClasses", "48", "data", "1"],
-      data: &json,
+      data: DataHolder::from_json(&json).unwrap(),
     };
 
     sensor.process_update(&update);
@@ -1,5 +1,5 @@
 // Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SensorMultilevel {
 }
@@ -0,0 +1,187 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use data_holder::DataHolder;
+use device_update::DeviceUpdate;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::fmt;
+use tracked_value::Freshness;
+use tracked_value::TrackedValue;
+
+/**
+ * The modes a thermostat fan may be placed in.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum FanModeType {
+  AutoLow,
+  OnLow,
+  AutoHigh,
+  OnHigh,
+  AutoMedium,
+  OnMedium,
+  Circulation,
+  HumidityCirculation,
+  LeftRight,
+  UpDown,
+  Quiet,
+}
+
+impl FanModeType {
+  /// Convert a fan mode identifier into a fan mode.
+  pub fn from_byte(mode: u8) -> Option<FanModeType> {
+    let mode = match mode {
+      0x00 => FanModeType::AutoLow,
+      0x01 => FanModeType::OnLow,
+      0x02 => FanModeType::AutoHigh,
+      0x03 => FanModeType::OnHigh,
+      0x04 => FanModeType::AutoMedium,
+      0x05 => FanModeType::OnMedium,
+      0x06 => FanModeType::Circulation,
+      0x07 => FanModeType::HumidityCirculation,
+      0x08 => FanModeType::LeftRight,
+      0x09 => FanModeType::UpDown,
+      0x0A => FanModeType::Quiet,
+      _ => return None,
+    };
+    Some(mode)
+  }
+}
+
+/**
+ * Command class 0x44 (68). The current and supported fan modes.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct ThermostatFanMode {
+  mode: TrackedValue<Option<FanModeType>>,
+  off: Option<bool>,
+  supported_modes: Vec<FanModeType>,
+}
+
+impl ThermostatFanMode {
+  /// Construct a ThermostatFanMode command class.
+  pub fn initialize_from_json(json: &Json)
+      -> Result<ThermostatFanMode, RazberryError> {
+    let holder = DataHolder::from_json(json)?;
+    let data = holder.get_child("data")
+        .ok_or(RazberryError::BadResponse)?;
+
+    let mut fan_mode = ThermostatFanMode::default();
+
+    for (key, value) in data.get_children() {
+      fan_mode.apply(key, value);
+    }
+
+    Ok(fan_mode)
+  }
+
+  /// Get the current fan mode.
+  pub fn get_mode(&self) -> Option<FanModeType> {
+    *self.mode.get_value()
+  }
+
+  /// Get when the fan mode was last updated.
+  pub fn get_mode_updated(&self) -> Option<DateTime<UTC>> {
+    self.mode.get_update_time()
+  }
+
+  /// Get whether the fan mode can be trusted as current.
+  pub fn get_freshness(&self) -> Freshness {
+    self.mode.get_freshness()
+  }
+
+  /// Whether the fan mode answers the most recent refresh request.
+  pub fn is_valid(&self) -> bool {
+    self.mode.is_valid()
+  }
+
+  /// Whether the fan is switched off (Thermostat Fan Mode v2+).
+  pub fn is_off(&self) -> Option<bool> {
+    self.off
+  }
+
+  /// Get the fan modes the thermostat supports.
+  pub fn get_supported_modes(&self) -> &[FanModeType] {
+    &self.supported_modes
+  }
+
+  /// Process the updates from the client.
+  /// Should not be publicly used.
+  pub fn process_update(&mut self, update: &DeviceUpdate)
+      -> Result<(), RazberryError> {
+    if update.path.get(4) != Some(&"data") {
+      return Ok(()); // Irrelevant update.
+    }
+
+    match update.path.get(5) {
+      Some(key) => self.apply(key, &update.data),
+      None => {
+        // The entire data subtree was replaced.
+        for (key, value) in update.data.get_children() {
+          self.apply(key, value);
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  /// Apply a data holder directly beneath "data".
+  fn apply(&mut self, key: &str, holder: &DataHolder) {
+    match key {
+      "mode" => {
+        self.mode = TrackedValue::from_holder(holder, |v| {
+          v.as_int().and_then(|m| FanModeType::from_byte(m as u8))
+        });
+      },
+      "off" => self.off = holder.get_value().as_bool(),
+      _ => {
+        // Supported modes are reported as children keyed by identifier.
+        let mode = key.parse::<u8>()
+            .ok()
+            .and_then(|m| FanModeType::from_byte(m));
+
+        if let Some(mode) = mode {
+          if !self.supported_modes.contains(&mode) {
+            self.supported_modes.push(mode);
+            self.supported_modes.sort();
+          }
+        }
+      },
+    }
+  }
+}
+
+impl fmt::Display for ThermostatFanMode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "ThermostatFanMode(mode: {:?}, off: {:?})", self.get_mode(), self.off)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_initialize_from_json() {
+    let json = Json::from_str(r#"
+      {
+        "name": "ThermostatFanMode",
+        "data": {
+          "mode": { "value": 1, "type": "int", "updateTime": 1492409902 },
+          "off": { "value": false, "type": "bool" },
+          "0": { "modeName": { "value": "Auto Low", "type": "string" } },
+          "1": { "modeName": { "value": "On Low", "type": "string" } }
+        }
+      }
+    "#).unwrap();
+
+    let cc = ThermostatFanMode::initialize_from_json(&json).unwrap();
+
+    assert_eq!(Some(FanModeType::OnLow), cc.get_mode());
+    assert_eq!(Some(false), cc.is_off());
+    assert_eq!(&[FanModeType::AutoLow, FanModeType::OnLow],
+      cc.get_supported_modes());
+  }
+}
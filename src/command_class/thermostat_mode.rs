@@ -0,0 +1,244 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use data_holder::DataHolder;
+use device_update::DeviceUpdate;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::fmt;
+use tracked_value::Freshness;
+use tracked_value::TrackedValue;
+
+/**
+ * The modes a thermostat may be placed in.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ThermostatModeType {
+  Off,
+  Heat,
+  Cool,
+  Auto,
+  AuxiliaryHeat,
+  Resume,
+  FanOnly,
+  Furnace,
+  DryAir,
+  MoistAir,
+  AutoChangeover,
+  EnergySaveHeat,
+  EnergySaveCool,
+  Away,
+  FullPower,
+  ManufacturerSpecific,
+}
+
+impl ThermostatModeType {
+  /// Convert a mode identifier into a mode.
+  pub fn from_byte(mode: u8) -> Option<ThermostatModeType> {
+    let mode = match mode {
+      0x00 => ThermostatModeType::Off,
+      0x01 => ThermostatModeType::Heat,
+      0x02 => ThermostatModeType::Cool,
+      0x03 => ThermostatModeType::Auto,
+      0x04 => ThermostatModeType::AuxiliaryHeat,
+      0x05 => ThermostatModeType::Resume,
+      0x06 => ThermostatModeType::FanOnly,
+      0x07 => ThermostatModeType::Furnace,
+      0x08 => ThermostatModeType::DryAir,
+      0x09 => ThermostatModeType::MoistAir,
+      0x0A => ThermostatModeType::AutoChangeover,
+      0x0B => ThermostatModeType::EnergySaveHeat,
+      0x0C => ThermostatModeType::EnergySaveCool,
+      0x0D => ThermostatModeType::Away,
+      0x0F => ThermostatModeType::FullPower,
+      0x1F => ThermostatModeType::ManufacturerSpecific,
+      _ => return None,
+    };
+    Some(mode)
+  }
+
+  /// Convert a mode into its identifier.
+  pub fn to_byte(&self) -> u8 {
+    match *self {
+      ThermostatModeType::Off => 0x00,
+      ThermostatModeType::Heat => 0x01,
+      ThermostatModeType::Cool => 0x02,
+      ThermostatModeType::Auto => 0x03,
+      ThermostatModeType::AuxiliaryHeat => 0x04,
+      ThermostatModeType::Resume => 0x05,
+      ThermostatModeType::FanOnly => 0x06,
+      ThermostatModeType::Furnace => 0x07,
+      ThermostatModeType::DryAir => 0x08,
+      ThermostatModeType::MoistAir => 0x09,
+      ThermostatModeType::AutoChangeover => 0x0A,
+      ThermostatModeType::EnergySaveHeat => 0x0B,
+      ThermostatModeType::EnergySaveCool => 0x0C,
+      ThermostatModeType::Away => 0x0D,
+      ThermostatModeType::FullPower => 0x0F,
+      ThermostatModeType::ManufacturerSpecific => 0x1F,
+    }
+  }
+}
+
+/**
+ * Command class 0x40 (64). The current and supported thermostat modes.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct ThermostatMode {
+  mode: TrackedValue<Option<ThermostatModeType>>,
+  supported_modes: Vec<ThermostatModeType>,
+}
+
+impl ThermostatMode {
+  /// Construct a ThermostatMode command class.
+  pub fn initialize_from_json(json: &Json)
+      -> Result<ThermostatMode, RazberryError> {
+    let holder = DataHolder::from_json(json)?;
+    let data = holder.get_child("data")
+        .ok_or(RazberryError::BadResponse)?;
+
+    let mut thermostat_mode = ThermostatMode::default();
+
+    for (key, value) in data.get_children() {
+      thermostat_mode.apply(key, value);
+    }
+
+    Ok(thermostat_mode)
+  }
+
+  /// Get the current mode.
+  pub fn get_mode(&self) -> Option<ThermostatModeType> {
+    *self.mode.get_value()
+  }
+
+  /// Get when the mode was last updated.
+  pub fn get_mode_updated(&self) -> Option<DateTime<UTC>> {
+    self.mode.get_update_time()
+  }
+
+  /// Get whether the mode can be trusted as current.
+  pub fn get_freshness(&self) -> Freshness {
+    self.mode.get_freshness()
+  }
+
+  /// Whether the mode answers the most recent refresh request.
+  pub fn is_valid(&self) -> bool {
+    self.mode.is_valid()
+  }
+
+  /// Get the modes the thermostat supports.
+  pub fn get_supported_modes(&self) -> &[ThermostatModeType] {
+    &self.supported_modes
+  }
+
+  /// Process the updates from the client.
+  /// Should not be publicly used.
+  pub fn process_update(&mut self, update: &DeviceUpdate)
+      -> Result<(), RazberryError> {
+    if update.path.get(4) != Some(&"data") {
+      return Ok(()); // Irrelevant update.
+    }
+
+    match update.path.get(5) {
+      Some(key) => self.apply(key, &update.data),
+      None => {
+        // The entire data subtree was replaced.
+        for (key, value) in update.data.get_children() {
+          self.apply(key, value);
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  /// Apply a data holder directly beneath "data".
+  fn apply(&mut self, key: &str, holder: &DataHolder) {
+    if key == "mode" {
+      self.mode = TrackedValue::from_holder(holder, |v| {
+        v.as_int().and_then(|m| ThermostatModeType::from_byte(m as u8))
+      });
+      return;
+    }
+
+    // Supported modes are reported as children keyed by mode identifier.
+    let mode = key.parse::<u8>()
+        .ok()
+        .and_then(|m| ThermostatModeType::from_byte(m));
+
+    if let Some(mode) = mode {
+      if !self.supported_modes.contains(&mode) {
+        self.supported_modes.push(mode);
+        self.supported_modes.sort();
+      }
+    }
+  }
+}
+
+impl fmt::Display for ThermostatMode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "ThermostatMode(mode: {:?})", self.get_mode())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_initialize_from_json() {
+    let json = Json::from_str(r#"
+      {
+        "name": "ThermostatMode",
+        "data": {
+          "value": null,
+          "type": "empty",
+          "mode": {
+            "value": 1,
+            "type": "int",
+            "invalidateTime": 1491741863,
+            "updateTime": 1492409902
+          },
+          "0": { "modeName": { "value": "Off", "type": "string" } },
+          "1": { "modeName": { "value": "Heat", "type": "string" } },
+          "11": { "modeName": { "value": "Energy Save Heat", "type": "string" } }
+        }
+      }
+    "#).unwrap();
+
+    let cc = ThermostatMode::initialize_from_json(&json).unwrap();
+
+    assert_eq!(Some(ThermostatModeType::Heat), cc.get_mode());
+    assert_eq!(1492409902, cc.get_mode_updated().unwrap().timestamp());
+    assert_eq!(&[ThermostatModeType::Off, ThermostatModeType::Heat,
+      ThermostatModeType::EnergySaveHeat], cc.get_supported_modes());
+  }
+
+  #[test]
+  fn test_process_update() {
+    let mut cc = ThermostatMode::default();
+
+    let json = Json::from_str(r#"
+      { "value": 0, "type": "int", "updateTime": 1492410000 }
+    "#).unwrap();
+
+    let update = DeviceUpdate {
+      path: vec!["instances", "0", "commandClasses", "64", "data", "mode"],
+      data: DataHolder::from_json(&json).unwrap(),
+    };
+
+    cc.process_update(&update).unwrap();
+
+    assert_eq!(Some(ThermostatModeType::Off), cc.get_mode());
+  }
+
+  #[test]
+  fn test_mode_round_trip() {
+    for byte in 0..0x20u8 {
+      if let Some(mode) = ThermostatModeType::from_byte(byte) {
+        assert_eq!(byte, mode.to_byte());
+      }
+    }
+  }
+}
@@ -0,0 +1,163 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use data_holder::DataHolder;
+use device_update::DeviceUpdate;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::fmt;
+use tracked_value::Freshness;
+use tracked_value::TrackedValue;
+
+/**
+ * What the HVAC equipment is currently doing.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum OperatingState {
+  Idle,
+  Heating,
+  Cooling,
+  FanOnly,
+  PendingHeat,
+  PendingCool,
+  VentEconomizer,
+  AuxiliaryHeating,
+  SecondStageHeating,
+  SecondStageCooling,
+  SecondStageAuxiliaryHeating,
+  ThirdStageAuxiliaryHeating,
+}
+
+impl OperatingState {
+  /// Convert an operating state identifier into an operating state.
+  pub fn from_byte(state: u8) -> Option<OperatingState> {
+    let state = match state {
+      0x00 => OperatingState::Idle,
+      0x01 => OperatingState::Heating,
+      0x02 => OperatingState::Cooling,
+      0x03 => OperatingState::FanOnly,
+      0x04 => OperatingState::PendingHeat,
+      0x05 => OperatingState::PendingCool,
+      0x06 => OperatingState::VentEconomizer,
+      0x07 => OperatingState::AuxiliaryHeating,
+      0x08 => OperatingState::SecondStageHeating,
+      0x09 => OperatingState::SecondStageCooling,
+      0x0A => OperatingState::SecondStageAuxiliaryHeating,
+      0x0B => OperatingState::ThirdStageAuxiliaryHeating,
+      _ => return None,
+    };
+    Some(state)
+  }
+}
+
+/**
+ * Command class 0x42 (66). Reports the thermostat's operating state.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct ThermostatOperatingState {
+  state: TrackedValue<Option<OperatingState>>,
+}
+
+impl ThermostatOperatingState {
+  /// Construct a ThermostatOperatingState command class.
+  pub fn initialize_from_json(json: &Json)
+      -> Result<ThermostatOperatingState, RazberryError> {
+    let holder = DataHolder::from_json(json)?;
+    let mut operating_state = ThermostatOperatingState::default();
+
+    let state = holder.find(&["data", "state"])
+        .ok_or(RazberryError::BadResponse)?;
+
+    operating_state.apply_state(state);
+    Ok(operating_state)
+  }
+
+  /// Get the current operating state.
+  pub fn get_state(&self) -> Option<OperatingState> {
+    *self.state.get_value()
+  }
+
+  /// Get when the operating state was last updated.
+  pub fn get_state_updated(&self) -> Option<DateTime<UTC>> {
+    self.state.get_update_time()
+  }
+
+  /// Get whether the operating state can be trusted as current.
+  pub fn get_freshness(&self) -> Freshness {
+    self.state.get_freshness()
+  }
+
+  /// Whether the operating state answers the most recent refresh request.
+  pub fn is_valid(&self) -> bool {
+    self.state.is_valid()
+  }
+
+  /// Process the updates from the client.
+  /// Should not be publicly used.
+  pub fn process_update(&mut self, update: &DeviceUpdate)
+      -> Result<(), RazberryError> {
+    if update.path.get(4) != Some(&"data") {
+      return Ok(()); // Irrelevant update.
+    }
+
+    match update.path.get(5) {
+      Some(&"state") => self.apply_state(&update.data),
+      Some(_) => {}, // Not tracked.
+      None => {
+        if let Some(state) = update.data.get_child("state") {
+          self.apply_state(state);
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  fn apply_state(&mut self, holder: &DataHolder) {
+    self.state = TrackedValue::from_holder(holder, |v| {
+      v.as_int().and_then(|s| OperatingState::from_byte(s as u8))
+    });
+  }
+}
+
+impl fmt::Display for ThermostatOperatingState {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "ThermostatOperatingState(state: {:?})", self.get_state())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_initialize_and_update() {
+    let json = Json::from_str(r#"
+      {
+        "name": "ThermostatOperatingState",
+        "data": {
+          "state": { "value": 1, "type": "int", "updateTime": 1492409902 }
+        }
+      }
+    "#).unwrap();
+
+    let mut cc = ThermostatOperatingState::initialize_from_json(&json).unwrap();
+
+    assert_eq!(Some(OperatingState::Heating), cc.get_state());
+
+    let json = Json::from_str(r#"
+      { "value": 0, "type": "int", "updateTime": 1492410000 }
+    "#).unwrap();
+
+    let update = DeviceUpdate {
+      path: vec!["instances", "0", "commandClasses", "66", "data", "state"],
+      data: DataHolder::from_json(&json).unwrap(),
+    };
+
+    cc.process_update(&update).unwrap();
+
+    assert_eq!(Some(OperatingState::Idle), cc.get_state());
+    assert_eq!(1492410000, cc.get_state_updated().unwrap().timestamp());
+  }
+}
@@ -0,0 +1,320 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use data_holder::DataHolder;
+use device_update::DeviceUpdate;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use std::fmt;
+use tracked_value::Freshness;
+use tracked_value::TrackedValue;
+
+/**
+ * The kinds of setpoints a thermostat may keep.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum SetpointType {
+  Heating,
+  Cooling,
+  Furnace,
+  DryAir,
+  MoistAir,
+  AutoChangeover,
+  EnergySaveHeating,
+  EnergySaveCooling,
+  AwayHeating,
+  AwayCooling,
+  FullPower,
+}
+
+impl SetpointType {
+  /// Convert a setpoint type identifier into a setpoint type.
+  pub fn from_byte(setpoint_type: u8) -> Option<SetpointType> {
+    let setpoint_type = match setpoint_type {
+      0x01 => SetpointType::Heating,
+      0x02 => SetpointType::Cooling,
+      0x07 => SetpointType::Furnace,
+      0x08 => SetpointType::DryAir,
+      0x09 => SetpointType::MoistAir,
+      0x0A => SetpointType::AutoChangeover,
+      0x0B => SetpointType::EnergySaveHeating,
+      0x0C => SetpointType::EnergySaveCooling,
+      0x0D => SetpointType::AwayHeating,
+      0x0E => SetpointType::AwayCooling,
+      0x0F => SetpointType::FullPower,
+      _ => return None,
+    };
+    Some(setpoint_type)
+  }
+
+  /// Convert a setpoint type into its identifier.
+  pub fn to_byte(&self) -> u8 {
+    match *self {
+      SetpointType::Heating => 0x01,
+      SetpointType::Cooling => 0x02,
+      SetpointType::Furnace => 0x07,
+      SetpointType::DryAir => 0x08,
+      SetpointType::MoistAir => 0x09,
+      SetpointType::AutoChangeover => 0x0A,
+      SetpointType::EnergySaveHeating => 0x0B,
+      SetpointType::EnergySaveCooling => 0x0C,
+      SetpointType::AwayHeating => 0x0D,
+      SetpointType::AwayCooling => 0x0E,
+      SetpointType::FullPower => 0x0F,
+    }
+  }
+}
+
+/**
+ * The scale of a temperature setpoint.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TemperatureScale {
+  Celsius,
+  Fahrenheit,
+}
+
+impl TemperatureScale {
+  /// Convert a scale identifier into a scale.
+  pub fn from_byte(scale: u8) -> Option<TemperatureScale> {
+    match scale {
+      0x00 => Some(TemperatureScale::Celsius),
+      0x01 => Some(TemperatureScale::Fahrenheit),
+      _ => None,
+    }
+  }
+
+  /// Get the unit symbol for the scale.
+  pub fn get_unit(&self) -> &'static str {
+    match *self {
+      TemperatureScale::Celsius => "°C",
+      TemperatureScale::Fahrenheit => "°F",
+    }
+  }
+}
+
+/**
+ * The target temperature for one setpoint type.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Setpoint {
+  value: TrackedValue<Option<f64>>,
+  scale: Option<TemperatureScale>,
+}
+
+impl Setpoint {
+  fn from_holder(holder: &DataHolder) -> Setpoint {
+    let mut setpoint = Setpoint::default();
+    for (key, value) in holder.get_children() {
+      setpoint.apply(key, value);
+    }
+    setpoint
+  }
+
+  /// Get the target temperature.
+  pub fn get_value(&self) -> Option<f64> {
+    *self.value.get_value()
+  }
+
+  /// Get the scale of the target temperature.
+  pub fn get_scale(&self) -> Option<TemperatureScale> {
+    self.scale
+  }
+
+  /// Get when the setpoint was last updated.
+  pub fn get_updated(&self) -> Option<DateTime<UTC>> {
+    self.value.get_update_time()
+  }
+
+  /// Get whether the setpoint can be trusted as current.
+  pub fn get_freshness(&self) -> Freshness {
+    self.value.get_freshness()
+  }
+
+  /// Whether the setpoint answers the most recent refresh request.
+  pub fn is_valid(&self) -> bool {
+    self.value.is_valid()
+  }
+
+  fn apply(&mut self, key: &str, holder: &DataHolder) {
+    let value = holder.get_value();
+    match key {
+      "setVal" | "val" => {
+        self.value = TrackedValue::from_holder(holder, |v| v.as_float());
+      },
+      "scale" => {
+        self.scale = value.as_int()
+            .and_then(|s| TemperatureScale::from_byte(s as u8));
+      },
+      _ => {}, // Not tracked.
+    }
+  }
+}
+
+/**
+ * Command class 0x43 (67). Target temperatures, keyed by setpoint type.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct ThermostatSetpoint {
+  setpoints: BTreeMap<SetpointType, Setpoint>,
+}
+
+impl ThermostatSetpoint {
+  /// Construct a ThermostatSetpoint command class.
+  pub fn initialize_from_json(json: &Json)
+      -> Result<ThermostatSetpoint, RazberryError> {
+    let holder = DataHolder::from_json(json)?;
+    let data = holder.get_child("data")
+        .ok_or(RazberryError::BadResponse)?;
+
+    let mut thermostat_setpoint = ThermostatSetpoint::default();
+
+    for (key, value) in data.get_children() {
+      thermostat_setpoint.apply(key, value);
+    }
+
+    Ok(thermostat_setpoint)
+  }
+
+  /// Get the setpoint of a given type.
+  pub fn get_setpoint(&self, setpoint_type: SetpointType) -> Option<&Setpoint> {
+    self.setpoints.get(&setpoint_type)
+  }
+
+  /// Get the setpoint types the thermostat supports.
+  pub fn get_setpoint_types(&self) -> Vec<SetpointType> {
+    self.setpoints.keys().map(|t| *t).collect()
+  }
+
+  /// Process the updates from the client.
+  /// Should not be publicly used.
+  pub fn process_update(&mut self, update: &DeviceUpdate)
+      -> Result<(), RazberryError> {
+    if update.path.get(4) != Some(&"data") {
+      return Ok(()); // Irrelevant update.
+    }
+
+    match (update.path.get(5), update.path.get(6)) {
+      (None, _) => {
+        // The entire data subtree was replaced.
+        for (key, value) in update.data.get_children() {
+          self.apply(key, value);
+        }
+      },
+      (Some(key), None) => self.apply(key, &update.data),
+      (Some(key), Some(field)) => {
+        // A single field of a setpoint, eg. "data.1.setVal".
+        let setpoint_type = key.parse::<u8>()
+            .ok()
+            .and_then(|t| SetpointType::from_byte(t));
+        if let Some(setpoint_type) = setpoint_type {
+          self.setpoints.entry(setpoint_type)
+              .or_insert_with(|| Setpoint::default())
+              .apply(field, &update.data);
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  /// Apply a data holder directly beneath "data".
+  fn apply(&mut self, key: &str, holder: &DataHolder) {
+    let setpoint_type = key.parse::<u8>()
+        .ok()
+        .and_then(|t| SetpointType::from_byte(t));
+
+    if let Some(setpoint_type) = setpoint_type {
+      self.setpoints.insert(setpoint_type, Setpoint::from_holder(holder));
+    }
+  }
+}
+
+impl fmt::Display for ThermostatSetpoint {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let setpoints = self.setpoints.iter()
+        .map(|(t, s)| {
+          let value = s.get_value().map(|v| v.to_string())
+              .unwrap_or("unknown".to_string());
+          let unit = s.scale.map(|s| s.get_unit()).unwrap_or("");
+          format!("{:?}: {}{}", t, value, unit)
+        })
+        .collect::<Vec<String>>()
+        .join(", ");
+    write!(f, "ThermostatSetpoint({})", setpoints)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const SETPOINT_JSON : &'static str = r#"
+    {
+      "name": "ThermostatSetpoint",
+      "data": {
+        "value": null,
+        "type": "empty",
+        "1": {
+          "value": null,
+          "type": "empty",
+          "modeName": { "value": "Heating", "type": "string" },
+          "setVal": {
+            "value": 21.5,
+            "type": "float",
+            "invalidateTime": 1491741863,
+            "updateTime": 1492409902
+          },
+          "scale": { "value": 0, "type": "int" },
+          "scaleString": { "value": "°C", "type": "string" }
+        },
+        "11": {
+          "value": null,
+          "type": "empty",
+          "setVal": { "value": 17, "type": "float", "updateTime": 1492409902 },
+          "scale": { "value": 0, "type": "int" }
+        }
+      }
+    }
+  "#;
+
+  #[test]
+  fn test_initialize_from_json() {
+    let json = Json::from_str(SETPOINT_JSON).unwrap();
+    let cc = ThermostatSetpoint::initialize_from_json(&json).unwrap();
+
+    assert_eq!(vec![SetpointType::Heating, SetpointType::EnergySaveHeating],
+      cc.get_setpoint_types());
+
+    let heating = cc.get_setpoint(SetpointType::Heating).unwrap();
+    assert_eq!(Some(21.5), heating.get_value());
+    assert_eq!(Some(TemperatureScale::Celsius), heating.get_scale());
+
+    assert!(cc.get_setpoint(SetpointType::Cooling).is_none());
+  }
+
+  #[test]
+  fn test_process_update() {
+    let json = Json::from_str(SETPOINT_JSON).unwrap();
+    let mut cc = ThermostatSetpoint::initialize_from_json(&json).unwrap();
+
+    let json = Json::from_str(r#"
+      { "value": 19.0, "type": "float", "updateTime": 1492410000 }
+    "#).unwrap();
+
+    let update = DeviceUpdate {
+      path: vec!["instances", "0", "commandClasses", "67", "data", "1",
+        "setVal"],
+      data: DataHolder::from_json(&json).unwrap(),
+    };
+
+    cc.process_update(&update).unwrap();
+
+    let heating = cc.get_setpoint(SetpointType::Heating).unwrap();
+    assert_eq!(Some(19.0), heating.get_value());
+    assert_eq!(Some(TemperatureScale::Celsius), heating.get_scale());
+    assert_eq!(1492410000, heating.get_updated().unwrap().timestamp());
+  }
+}
@@ -0,0 +1,337 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use data_holder::{DataHolder, DataValue};
+use device_update::DeviceUpdate;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/**
+ * The status of a user code slot.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum UserIdStatus {
+  /// The slot is empty.
+  Available,
+  /// The slot holds a code that will be accepted.
+  Occupied,
+  /// The slot holds a code that is disabled.
+  Reserved,
+  /// The lock did not report a status.
+  NotAvailable,
+}
+
+impl UserIdStatus {
+  /// Convert a status identifier into a status.
+  pub fn from_byte(status: u8) -> Option<UserIdStatus> {
+    let status = match status {
+      0x00 => UserIdStatus::Available,
+      0x01 => UserIdStatus::Occupied,
+      0x02 => UserIdStatus::Reserved,
+      0xFE => UserIdStatus::NotAvailable,
+      _ => return None,
+    };
+    Some(status)
+  }
+
+  /// Convert a status into its identifier.
+  pub fn to_byte(&self) -> u8 {
+    match *self {
+      UserIdStatus::Available => 0x00,
+      UserIdStatus::Occupied => 0x01,
+      UserIdStatus::Reserved => 0x02,
+      UserIdStatus::NotAvailable => 0xFE,
+    }
+  }
+}
+
+/// Whether a path in the data tree, eg.
+/// "devices.5.instances.0.commandClasses.99.data.1.code", is where a slot
+/// stores its code. Such holders are kept out of snapshots and event streams.
+pub fn is_code_path(path: &[&str]) -> bool {
+  path.len() == 9 && path[0] == "devices" && path[2] == "instances"
+      && path[4] == "commandClasses" && path[5] == "99" && path[6] == "data"
+      && path[8] == "code"
+}
+
+/// Whether a path is where a slot stores its code, or beneath it.
+pub fn is_in_code(path: &[&str]) -> bool {
+  path.len() >= 9 && is_code_path(&path[..9])
+}
+
+/// Remove every stored code from a data tree, or a subtree of it, eg. the
+/// data of a single event. `path` is where `json` sits in the tree.
+pub fn remove_codes(json: &mut Json, path: &[&str]) {
+  let mut path = path.iter().map(|s| s.to_string()).collect();
+  remove_codes_below(json, &mut path);
+}
+
+fn remove_codes_below(json: &mut Json, path: &mut Vec<String>) {
+  let object = match json.as_object_mut() {
+    None => return,
+    Some(o) => o,
+  };
+
+  let is_code_parent = {
+    let mut code_path = path.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+    code_path.push("code");
+    is_code_path(&code_path)
+  };
+
+  if is_code_parent {
+    object.remove("code");
+  }
+
+  for (key, child) in object.iter_mut() {
+    path.push(key.to_string());
+    remove_codes_below(child, path);
+    path.pop();
+  }
+}
+
+/**
+ * A single user code slot. The code itself is never included in `Debug` or
+ * `Display` output.
+ */
+#[derive(Clone, Default)]
+pub struct UserCodeSlot {
+  user_id: u16,
+  status: Option<UserIdStatus>,
+  code: Option<String>,
+}
+
+impl UserCodeSlot {
+  fn new(user_id: u16) -> UserCodeSlot {
+    UserCodeSlot {
+      user_id: user_id,
+      status: None,
+      code: None,
+    }
+  }
+
+  fn from_holder(user_id: u16, holder: &DataHolder) -> UserCodeSlot {
+    let mut slot = UserCodeSlot::new(user_id);
+    for (key, value) in holder.get_children() {
+      slot.apply(key, value);
+    }
+    slot
+  }
+
+  /// Get the slot number.
+  pub fn get_user_id(&self) -> u16 {
+    self.user_id
+  }
+
+  /// Get the slot status.
+  pub fn get_status(&self) -> Option<UserIdStatus> {
+    self.status
+  }
+
+  /// Get the code stored in the slot. Handle with care.
+  pub fn get_code(&self) -> Option<&str> {
+    self.code.as_ref().map(|s| s.as_str())
+  }
+
+  fn apply(&mut self, key: &str, holder: &DataHolder) {
+    let value = holder.get_value();
+    match key {
+      "status" => {
+        self.status = value.as_int()
+            .and_then(|s| UserIdStatus::from_byte(s as u8));
+      },
+      "code" => {
+        // Z-Way reports codes either as a string or as ASCII bytes.
+        self.code = match *value {
+          DataValue::String(ref code) => Some(code.to_string()),
+          DataValue::Binary(ref bytes) => String::from_utf8(bytes.clone()).ok(),
+          _ => None,
+        };
+      },
+      _ => {}, // Not tracked.
+    }
+  }
+}
+
+impl fmt::Debug for UserCodeSlot {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let code = self.code.as_ref().map(|_| "<redacted>");
+    write!(f, "UserCodeSlot {{ user_id: {:?}, status: {:?}, code: {:?} }}",
+      self.user_id, self.status, code)
+  }
+}
+
+impl fmt::Display for UserCodeSlot {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let code = if self.code.is_some() { "<redacted>" } else { "none" };
+    write!(f, "UserCodeSlot({}, status: {:?}, code: {})",
+      self.user_id, self.status, code)
+  }
+}
+
+/**
+ * Command class 0x63 (99). The PIN code slots of a lock or keypad.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct UserCode {
+  max_users: Option<u16>,
+  slots: BTreeMap<u16, UserCodeSlot>,
+}
+
+impl UserCode {
+  /// Construct a UserCode command class.
+  pub fn initialize_from_json(json: &Json) -> Result<UserCode, RazberryError> {
+    let holder = DataHolder::from_json(json)?;
+    let data = holder.get_child("data")
+        .ok_or(RazberryError::BadResponse)?;
+
+    let mut user_code = UserCode::default();
+
+    for (key, value) in data.get_children() {
+      user_code.apply(key, value);
+    }
+
+    Ok(user_code)
+  }
+
+  /// Get the number of user code slots the device supports.
+  pub fn get_max_users(&self) -> Option<u16> {
+    self.max_users
+  }
+
+  /// Get all known slots, ordered by slot number.
+  pub fn get_slots(&self) -> Vec<&UserCodeSlot> {
+    self.slots.values().collect()
+  }
+
+  /// Get a single slot.
+  pub fn get_slot(&self, user_id: u16) -> Option<&UserCodeSlot> {
+    self.slots.get(&user_id)
+  }
+
+  /// Process the updates from the client.
+  /// Should not be publicly used.
+  pub fn process_update(&mut self, update: &DeviceUpdate)
+      -> Result<(), RazberryError> {
+    if update.path.get(4) != Some(&"data") {
+      return Ok(()); // Irrelevant update.
+    }
+
+    match (update.path.get(5), update.path.get(6)) {
+      (None, _) => {
+        // The entire data subtree was replaced.
+        for (key, value) in update.data.get_children() {
+          self.apply(key, value);
+        }
+      },
+      (Some(key), None) => self.apply(key, &update.data),
+      (Some(key), Some(field)) => {
+        // A single field of a slot, eg. "data.3.status".
+        if let Ok(user_id) = key.parse::<u16>() {
+          self.slots.entry(user_id)
+              .or_insert_with(|| UserCodeSlot::new(user_id))
+              .apply(field, &update.data);
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  /// Apply a data holder directly beneath "data".
+  fn apply(&mut self, key: &str, holder: &DataHolder) {
+    if key == "maxUsers" {
+      self.max_users = holder.get_value().as_int().map(|v| v as u16);
+      return;
+    }
+
+    if let Ok(user_id) = key.parse::<u16>() {
+      self.slots.insert(user_id, UserCodeSlot::from_holder(user_id, holder));
+    }
+  }
+}
+
+impl fmt::Display for UserCode {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let occupied = self.slots.values()
+        .filter(|s| s.status == Some(UserIdStatus::Occupied))
+        .count();
+    write!(f, "UserCode(slots: {}, occupied: {})", self.slots.len(), occupied)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const USER_CODE_JSON : &'static str = r#"
+    {
+      "name": "UserCode",
+      "data": {
+        "maxUsers": { "value": 30, "type": "int" },
+        "1": {
+          "value": null,
+          "type": "empty",
+          "code": { "value": [49, 50, 51, 52], "type": "binary" },
+          "status": { "value": 1, "type": "int" }
+        },
+        "2": {
+          "value": null,
+          "type": "empty",
+          "code": { "value": "", "type": "string" },
+          "status": { "value": 0, "type": "int" }
+        }
+      }
+    }
+  "#;
+
+  #[test]
+  fn test_initialize_from_json() {
+    let json = Json::from_str(USER_CODE_JSON).unwrap();
+    let cc = UserCode::initialize_from_json(&json).unwrap();
+
+    assert_eq!(Some(30), cc.get_max_users());
+    assert_eq!(2, cc.get_slots().len());
+
+    let slot = cc.get_slot(1).unwrap();
+    assert_eq!(Some(UserIdStatus::Occupied), slot.get_status());
+    assert_eq!(Some("1234"), slot.get_code());
+
+    let slot = cc.get_slot(2).unwrap();
+    assert_eq!(Some(UserIdStatus::Available), slot.get_status());
+  }
+
+  #[test]
+  fn test_codes_are_redacted() {
+    let json = Json::from_str(USER_CODE_JSON).unwrap();
+    let cc = UserCode::initialize_from_json(&json).unwrap();
+    let slot = cc.get_slot(1).unwrap();
+
+    assert!(!format!("{:?}", slot).contains("1234"));
+    assert!(!format!("{}", slot).contains("1234"));
+    assert!(!format!("{:?}", cc).contains("1234"));
+    assert!(format!("{:?}", slot).contains("<redacted>"));
+  }
+
+  #[test]
+  fn test_process_update() {
+    let json = Json::from_str(USER_CODE_JSON).unwrap();
+    let mut cc = UserCode::initialize_from_json(&json).unwrap();
+
+    let json = Json::from_str(r#"
+      { "value": 2, "type": "int", "updateTime": 1492410000 }
+    "#).unwrap();
+
+    let update = DeviceUpdate {
+      path: vec!["instances", "0", "commandClasses", "99", "data", "1",
+        "status"],
+      data: DataHolder::from_json(&json).unwrap(),
+    };
+
+    cc.process_update(&update).unwrap();
+
+    let slot = cc.get_slot(1).unwrap();
+    assert_eq!(Some(UserIdStatus::Reserved), slot.get_status());
+    assert_eq!(Some("1234"), slot.get_code());
+  }
+}
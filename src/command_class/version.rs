@@ -0,0 +1,236 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use data_holder::{DataHolder, DataValue};
+use device_update::DeviceUpdate;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::fmt;
+
+/**
+ * The Z-Wave library a device's firmware was built against.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LibraryType {
+  StaticController,
+  Controller,
+  EnhancedSlave,
+  Slave,
+  Installer,
+  RoutingSlave,
+  BridgeController,
+  DeviceUnderTest,
+  AvRemote,
+  AvDevice,
+}
+
+impl LibraryType {
+  /// Convert the library type reported by the device ("ZWLib").
+  pub fn from_byte(library_type: u8) -> Option<LibraryType> {
+    let library_type = match library_type {
+      0x01 => LibraryType::StaticController,
+      0x02 => LibraryType::Controller,
+      0x03 => LibraryType::EnhancedSlave,
+      0x04 => LibraryType::Slave,
+      0x05 => LibraryType::Installer,
+      0x06 => LibraryType::RoutingSlave,
+      0x07 => LibraryType::BridgeController,
+      0x08 => LibraryType::DeviceUnderTest,
+      0x0A => LibraryType::AvRemote,
+      0x0B => LibraryType::AvDevice,
+      _ => return None,
+    };
+    Some(library_type)
+  }
+}
+
+/**
+ * A "major.minor" firmware or protocol version.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct FirmwareVersion {
+  pub major: u8,
+  pub minor: u8,
+}
+
+impl fmt::Display for FirmwareVersion {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}.{:02}", self.major, self.minor)
+  }
+}
+
+/**
+ * Command class 0x86 (134). Reports the Z-Wave library, protocol, and
+ * application firmware versions of a device.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Version {
+  library_type: Option<u8>,
+  protocol_major: Option<u8>,
+  protocol_minor: Option<u8>,
+  application_major: Option<u8>,
+  application_minor: Option<u8>,
+  hardware_version: Option<u8>,
+  sdk: Option<String>,
+}
+
+impl Version {
+  /// Construct a Version command class.
+  pub fn initialize_from_json(json: &Json) -> Result<Version, RazberryError> {
+    let holder = DataHolder::from_json(json)?;
+    let data = holder.get_child("data")
+        .ok_or(RazberryError::BadResponse)?;
+
+    let mut version = Version::default();
+
+    for (key, value) in data.get_children() {
+      version.apply(key, value);
+    }
+
+    Ok(version)
+  }
+
+  /// Get the Z-Wave library type, if known.
+  pub fn get_library_type(&self) -> Option<LibraryType> {
+    self.library_type.and_then(|t| LibraryType::from_byte(t))
+  }
+
+  /// Get the Z-Wave protocol version.
+  pub fn get_protocol_version(&self) -> Option<FirmwareVersion> {
+    make_version(self.protocol_major, self.protocol_minor)
+  }
+
+  /// Get the application (manufacturer firmware) version.
+  pub fn get_application_version(&self) -> Option<FirmwareVersion> {
+    make_version(self.application_major, self.application_minor)
+  }
+
+  /// Get the hardware version (Version command class v2+).
+  pub fn get_hardware_version(&self) -> Option<u8> {
+    self.hardware_version
+  }
+
+  /// Get the SDK version string, as resolved by Z-Way.
+  pub fn get_sdk(&self) -> Option<&str> {
+    self.sdk.as_ref().map(|s| s.as_str())
+  }
+
+  /// Process the updates from the client.
+  /// Should not be publicly used.
+  pub fn process_update(&mut self, update: &DeviceUpdate)
+      -> Result<(), RazberryError> {
+    if update.path.get(4) != Some(&"data") {
+      return Ok(()); // Irrelevant update.
+    }
+
+    match update.path.get(5) {
+      Some(key) => self.apply(key, &update.data),
+      None => {
+        // The entire data subtree was replaced.
+        for (key, value) in update.data.get_children() {
+          self.apply(key, value);
+        }
+      },
+    }
+
+    Ok(())
+  }
+
+  /// Apply a single data holder, eg. "ZWLib", to the model.
+  fn apply(&mut self, key: &str, holder: &DataHolder) {
+    let value = holder.get_value();
+    match key {
+      "ZWLib" => self.library_type = read_u8(value),
+      "ZWProtocolMajor" => self.protocol_major = read_u8(value),
+      "ZWProtocolMinor" => self.protocol_minor = read_u8(value),
+      "applicationMajor" => self.application_major = read_u8(value),
+      "applicationMinor" => self.application_minor = read_u8(value),
+      "hardwareVersion" => self.hardware_version = read_u8(value),
+      "SDK" => self.sdk = value.as_str().map(|s| s.to_string()),
+      _ => {}, // Not tracked.
+    }
+  }
+}
+
+fn read_u8(value: &DataValue) -> Option<u8> {
+  value.as_int().map(|v| v as u8)
+}
+
+fn make_version(major: Option<u8>, minor: Option<u8>)
+    -> Option<FirmwareVersion> {
+  match (major, minor) {
+    (Some(major), Some(minor)) => Some(FirmwareVersion {
+      major: major,
+      minor: minor,
+    }),
+    _ => None,
+  }
+}
+
+impl fmt::Display for Version {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let protocol = self.get_protocol_version()
+        .map(|v| v.to_string())
+        .unwrap_or("unknown".to_string());
+    let application = self.get_application_version()
+        .map(|v| v.to_string())
+        .unwrap_or("unknown".to_string());
+    write!(f, "Version(library: {:?}, protocol: {}, application: {})",
+      self.get_library_type(), protocol, application)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_initialize_from_json() {
+    // A subset of the JSON for an Aeotec multisensor.
+    let json = r#"
+      {
+        "name": "Version",
+        "data": {
+          "value": null,
+          "type": "empty",
+          "ZWLib": { "value": 3, "type": "int" },
+          "ZWProtocolMajor": { "value": 3, "type": "int" },
+          "ZWProtocolMinor": { "value": 92, "type": "int" },
+          "SDK": { "value": "6.51.01", "type": "string" },
+          "applicationMajor": { "value": 1, "type": "int" },
+          "applicationMinor": { "value": 2, "type": "int" },
+          "hardwareVersion": { "value": 74, "type": "int" }
+        }
+      }
+    "#;
+
+    let json = Json::from_str(json).unwrap();
+    let version = Version::initialize_from_json(&json).unwrap();
+
+    assert_eq!(Some(LibraryType::EnhancedSlave), version.get_library_type());
+    assert_eq!(Some(FirmwareVersion { major: 3, minor: 92 }),
+      version.get_protocol_version());
+    assert_eq!(Some(FirmwareVersion { major: 1, minor: 2 }),
+      version.get_application_version());
+    assert_eq!(Some(74), version.get_hardware_version());
+    assert_eq!(Some("6.51.01"), version.get_sdk());
+  }
+
+  #[test]
+  fn test_partial_version() {
+    let json = r#"
+      { "data": { "applicationMajor": { "value": 1, "type": "int" } } }
+    "#;
+
+    let json = Json::from_str(json).unwrap();
+    let version = Version::initialize_from_json(&json).unwrap();
+
+    assert_eq!(None, version.get_application_version());
+    assert_eq!(None, version.get_library_type());
+  }
+
+  #[test]
+  fn test_firmware_version_display() {
+    let version = FirmwareVersion { major: 1, minor: 2 };
+    assert_eq!("1.02", version.to_string());
+  }
+}
@@ -1,102 +1,448 @@
 // Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
 
+use error::RazberryError;
 use std::fmt;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::mem;
+use std::str::FromStr;
 
 /**
  * The different ZWave command classes supported by various devices.
+ * Identifiers missing from the registry are kept as `Unknown` so that no
+ * command class reported by the gateway is lost. An `Unknown` holding a
+ * registered identifier is equal to, and hashes the same as, the named
+ * command class; `from_byte` never builds one.
  */
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq)]
 pub enum CommandClasses {
   Alarm,
   AlarmSensor,
   AlarmSilence,
+  AntiTheft,
+  ApplicationCapability,
+  ApplicationStatus,
   Association,
+  AssociationCommandConfiguration,
+  AssociationGroupInformation,
+  AvContentDirectoryMd,
+  AvContentSearchMd,
+  AvRendererStatus,
+  AvTaggingMd,
+  BarrierOperator,
   Basic,
+  BasicTariffInfo,
+  BasicWindowCovering,
   Battery,
+  CentralScene,
+  ChimneyFan,
+  ClimateControlSchedule,
   Clock,
   Configuration,
+  ControllerReplication,
+  Crc16,
+  DcpConfig,
+  DcpMonitor,
+  DeviceResetLocally,
+  Dmx,
+  DoorLock,
+  DoorLockLogging,
+  EnergyProduction,
+  EntryControl,
   FirmwareUpdate,
+  GeographicLocation,
+  GroupingName,
+  Hail,
+  HrvControl,
+  HrvStatus,
+  HumidityControlMode,
+  HumidityControlOperatingState,
+  HumidityControlSetpoint,
+  InclusionController,
+  Indicator,
+  IpAssociation,
+  IpConfiguration,
+  IrrigationControl,
+  Language,
+  Lock,
+  Mailbox,
+  ManufacturerProprietary,
   ManufacturerSpecific,
+  Mark,
+  Meter,
+  MeterPulse,
+  MeterTableConfig,
+  MeterTableMonitor,
+  MeterTablePush,
+  MtpWindowCovering,
   MultiChannel,
   MultiChannelAssociation,
+  MultiCmd,
+  NetworkManagementBasic,
+  NetworkManagementInclusion,
+  NetworkManagementInstallationMaintenance,
+  NetworkManagementPrimary,
+  NetworkManagementProxy,
   NoOperation,
   NodeNaming,
+  NodeProvisioning,
+  NonInteroperable,
   PowerLevel,
+  Prepayment,
+  PrepaymentEncapsulation,
+  Proprietary,
+  Protection,
+  RateTableConfig,
+  RateTableMonitor,
+  RemoteAssociation,
+  RemoteAssociationActivate,
+  SceneActivation,
+  SceneActuatorConf,
+  SceneControllerConf,
+  Schedule,
+  ScheduleEntryLock,
+  ScreenAttributes,
+  ScreenMd,
+  Security,
+  Security2,
+  SecurityPanelMode,
+  SecurityPanelZone,
+  SecurityPanelZoneSensor,
   SensorBinary,
   SensorConfiguration,
   SensorMultilevel,
+  SimpleAvControl,
+  Supervision,
+  SwitchAll,
   SwitchBinary,
+  SwitchColor,
   SwitchMultilevel,
+  SwitchToggleBinary,
+  SwitchToggleMultilevel,
+  TariffConfig,
+  TariffTableMonitor,
+  ThermostatFanMode,
+  ThermostatFanState,
+  ThermostatHeating,
+  ThermostatMode,
+  ThermostatOperatingState,
+  ThermostatSetback,
+  ThermostatSetpoint,
+  Time,
+  TimeParameters,
+  TransportService,
+  UserCode,
   Version,
   Wakeup,
+  WindowCovering,
+  ZWavePlusInfo,
+  Zip,
+  Zip6LowPan,
+  ZipGateway,
+  ZipNaming,
+  ZipNd,
+  ZipPortal,
+  Unknown(u8),
 }
 
+/// The command class registry: (command class, identifier, Z-Way name,
+/// obsolete or deprecated by the Z-Wave Alliance).
+const REGISTRY : &'static [(CommandClasses, u8, &'static str, bool)] = &[
+  (CommandClasses::NoOperation, 0x00, "NoOperation", false),
+  (CommandClasses::Basic, 0x20, "Basic", false),
+  (CommandClasses::ControllerReplication, 0x21, "ControllerReplication", false),
+  (CommandClasses::ApplicationStatus, 0x22, "ApplicationStatus", false),
+  (CommandClasses::Zip, 0x23, "ZIP", false),
+  (CommandClasses::SecurityPanelMode, 0x24, "SecurityPanelMode", true),
+  (CommandClasses::SwitchBinary, 0x25, "SwitchBinary", false),
+  (CommandClasses::SwitchMultilevel, 0x26, "SwitchMultilevel", false),
+  (CommandClasses::SwitchAll, 0x27, "SwitchAll", true),
+  (CommandClasses::SwitchToggleBinary, 0x28, "SwitchToggleBinary", true),
+  (CommandClasses::SwitchToggleMultilevel, 0x29, "SwitchToggleMultilevel", true),
+  (CommandClasses::ChimneyFan, 0x2A, "ChimneyFan", true),
+  (CommandClasses::SceneActivation, 0x2B, "SceneActivation", false),
+  (CommandClasses::SceneActuatorConf, 0x2C, "SceneActuatorConf", false),
+  (CommandClasses::SceneControllerConf, 0x2D, "SceneControllerConf", false),
+  (CommandClasses::SecurityPanelZone, 0x2E, "SecurityPanelZone", true),
+  (CommandClasses::SecurityPanelZoneSensor, 0x2F, "SecurityPanelZoneSensor", true),
+  (CommandClasses::SensorBinary, 0x30, "SensorBinary", false),
+  (CommandClasses::SensorMultilevel, 0x31, "SensorMultilevel", false),
+  (CommandClasses::Meter, 0x32, "Meter", false),
+  (CommandClasses::SwitchColor, 0x33, "SwitchColor", false),
+  (CommandClasses::NetworkManagementInclusion, 0x34, "NetworkManagementInclusion", false),
+  (CommandClasses::MeterPulse, 0x35, "MeterPulse", true),
+  (CommandClasses::BasicTariffInfo, 0x36, "BasicTariffInfo", false),
+  (CommandClasses::HrvStatus, 0x37, "HrvStatus", false),
+  (CommandClasses::ThermostatHeating, 0x38, "ThermostatHeating", true),
+  (CommandClasses::HrvControl, 0x39, "HrvControl", false),
+  (CommandClasses::DcpConfig, 0x3A, "DcpConfig", false),
+  (CommandClasses::DcpMonitor, 0x3B, "DcpMonitor", false),
+  (CommandClasses::MeterTableConfig, 0x3C, "MeterTableConfig", false),
+  (CommandClasses::MeterTableMonitor, 0x3D, "MeterTableMonitor", false),
+  (CommandClasses::MeterTablePush, 0x3E, "MeterTablePush", false),
+  (CommandClasses::Prepayment, 0x3F, "Prepayment", false),
+  (CommandClasses::ThermostatMode, 0x40, "ThermostatMode", false),
+  (CommandClasses::PrepaymentEncapsulation, 0x41, "PrepaymentEncapsulation", false),
+  (CommandClasses::ThermostatOperatingState, 0x42, "ThermostatOperatingState", false),
+  (CommandClasses::ThermostatSetpoint, 0x43, "ThermostatSetpoint", false),
+  (CommandClasses::ThermostatFanMode, 0x44, "ThermostatFanMode", false),
+  (CommandClasses::ThermostatFanState, 0x45, "ThermostatFanState", false),
+  (CommandClasses::ClimateControlSchedule, 0x46, "ClimateControlSchedule", true),
+  (CommandClasses::ThermostatSetback, 0x47, "ThermostatSetback", false),
+  (CommandClasses::RateTableConfig, 0x48, "RateTableConfig", false),
+  (CommandClasses::RateTableMonitor, 0x49, "RateTableMonitor", false),
+  (CommandClasses::TariffConfig, 0x4A, "TariffConfig", false),
+  (CommandClasses::TariffTableMonitor, 0x4B, "TariffTableMonitor", false),
+  (CommandClasses::DoorLockLogging, 0x4C, "DoorLockLogging", false),
+  (CommandClasses::NetworkManagementBasic, 0x4D, "NetworkManagementBasic", false),
+  (CommandClasses::ScheduleEntryLock, 0x4E, "ScheduleEntryLock", true),
+  (CommandClasses::Zip6LowPan, 0x4F, "ZIP6LoWPAN", false),
+  (CommandClasses::BasicWindowCovering, 0x50, "BasicWindowCovering", true),
+  (CommandClasses::MtpWindowCovering, 0x51, "MTPWindowCovering", true),
+  (CommandClasses::NetworkManagementProxy, 0x52, "NetworkManagementProxy", false),
+  (CommandClasses::Schedule, 0x53, "Schedule", false),
+  (CommandClasses::NetworkManagementPrimary, 0x54, "NetworkManagementPrimary", false),
+  (CommandClasses::TransportService, 0x55, "TransportService", false),
+  (CommandClasses::Crc16, 0x56, "CRC16", false),
+  (CommandClasses::ApplicationCapability, 0x57, "ApplicationCapability", false),
+  (CommandClasses::ZipNd, 0x58, "ZIPND", false),
+  (CommandClasses::AssociationGroupInformation, 0x59, "AssociationGroupInformation", false),
+  (CommandClasses::DeviceResetLocally, 0x5A, "DeviceResetLocally", false),
+  (CommandClasses::CentralScene, 0x5B, "CentralScene", false),
+  (CommandClasses::IpAssociation, 0x5C, "IPAssociation", false),
+  (CommandClasses::AntiTheft, 0x5D, "AntiTheft", false),
+  (CommandClasses::ZWavePlusInfo, 0x5E, "ZWavePlusInfo", false),
+  (CommandClasses::ZipGateway, 0x5F, "ZIPGateway", false),
+  (CommandClasses::MultiChannel, 0x60, "MultiChannel", false),
+  (CommandClasses::ZipPortal, 0x61, "ZIPPortal", false),
+  (CommandClasses::DoorLock, 0x62, "DoorLock", false),
+  (CommandClasses::UserCode, 0x63, "UserCode", false),
+  (CommandClasses::HumidityControlSetpoint, 0x64, "HumidityControlSetpoint", false),
+  (CommandClasses::Dmx, 0x65, "DMX", false),
+  (CommandClasses::BarrierOperator, 0x66, "BarrierOperator", false),
+  (CommandClasses::NetworkManagementInstallationMaintenance, 0x67, "NetworkManagementInstallationMaintenance", false),
+  (CommandClasses::ZipNaming, 0x68, "ZIPNaming", false),
+  (CommandClasses::Mailbox, 0x69, "Mailbox", false),
+  (CommandClasses::WindowCovering, 0x6A, "WindowCovering", false),
+  (CommandClasses::IrrigationControl, 0x6B, "IrrigationControl", false),
+  (CommandClasses::Supervision, 0x6C, "Supervision", false),
+  (CommandClasses::HumidityControlMode, 0x6D, "HumidityControlMode", false),
+  (CommandClasses::HumidityControlOperatingState, 0x6E, "HumidityControlOperatingState", false),
+  (CommandClasses::EntryControl, 0x6F, "EntryControl", false),
+  (CommandClasses::Configuration, 0x70, "Configuration", false),
+  (CommandClasses::Alarm, 0x71, "Alarm", false),
+  (CommandClasses::ManufacturerSpecific, 0x72, "ManufacturerSpecific", false),
+  (CommandClasses::PowerLevel, 0x73, "PowerLevel", false),
+  (CommandClasses::InclusionController, 0x74, "InclusionController", false),
+  (CommandClasses::Protection, 0x75, "Protection", false),
+  (CommandClasses::Lock, 0x76, "Lock", true),
+  (CommandClasses::NodeNaming, 0x77, "NodeNaming", false),
+  (CommandClasses::NodeProvisioning, 0x78, "NodeProvisioning", false),
+  (CommandClasses::FirmwareUpdate, 0x7A, "FirmwareUpdate", false),
+  (CommandClasses::GroupingName, 0x7B, "GroupingName", true),
+  (CommandClasses::RemoteAssociationActivate, 0x7C, "RemoteAssociationActivate", true),
+  (CommandClasses::RemoteAssociation, 0x7D, "RemoteAssociation", true),
+  (CommandClasses::Battery, 0x80, "Battery", false),
+  (CommandClasses::Clock, 0x81, "Clock", false),
+  (CommandClasses::Hail, 0x82, "Hail", true),
+  (CommandClasses::Wakeup, 0x84, "Wakeup", false),
+  (CommandClasses::Association, 0x85, "Association", false),
+  (CommandClasses::Version, 0x86, "Version", false),
+  (CommandClasses::Indicator, 0x87, "Indicator", false),
+  (CommandClasses::Proprietary, 0x88, "Proprietary", true),
+  (CommandClasses::Language, 0x89, "Language", false),
+  (CommandClasses::Time, 0x8A, "Time", false),
+  (CommandClasses::TimeParameters, 0x8B, "TimeParameters", false),
+  (CommandClasses::GeographicLocation, 0x8C, "GeographicLocation", false),
+  (CommandClasses::MultiChannelAssociation, 0x8E, "MultiChannelAssociation", false),
+  (CommandClasses::MultiCmd, 0x8F, "MultiCmd", false),
+  (CommandClasses::EnergyProduction, 0x90, "EnergyProduction", false),
+  (CommandClasses::ManufacturerProprietary, 0x91, "ManufacturerProprietary", false),
+  (CommandClasses::ScreenMd, 0x92, "ScreenMD", false),
+  (CommandClasses::ScreenAttributes, 0x93, "ScreenAttributes", false),
+  (CommandClasses::SimpleAvControl, 0x94, "SimpleAVControl", false),
+  (CommandClasses::AvContentDirectoryMd, 0x95, "AVContentDirectoryMD", false),
+  (CommandClasses::AvRendererStatus, 0x96, "AVRendererStatus", false),
+  (CommandClasses::AvContentSearchMd, 0x97, "AVContentSearchMD", false),
+  (CommandClasses::Security, 0x98, "Security", false),
+  (CommandClasses::AvTaggingMd, 0x99, "AVTaggingMD", false),
+  (CommandClasses::IpConfiguration, 0x9A, "IPConfiguration", true),
+  (CommandClasses::AssociationCommandConfiguration, 0x9B, "AssociationCommandConfiguration", false),
+  (CommandClasses::AlarmSensor, 0x9C, "AlarmSensor", true),
+  (CommandClasses::AlarmSilence, 0x9D, "AlarmSilence", false),
+  (CommandClasses::SensorConfiguration, 0x9E, "SensorConfiguration", true),
+  (CommandClasses::Security2, 0x9F, "SecurityS2", false),
+  (CommandClasses::Mark, 0xEF, "Mark", false),
+  (CommandClasses::NonInteroperable, 0xF0, "NonInteroperable", false),
+];
+
 impl CommandClasses {
   /// Convert a command class identifier into a command class.
-  pub fn from_byte(command_class_id: u8) -> Option<CommandClasses> {
-    let command_class = match command_class_id {
-      0x00 => CommandClasses::NoOperation,
-      0x20 => CommandClasses::Basic,
-      0x25 => CommandClasses::SwitchBinary,
-      0x26 => CommandClasses::SwitchMultilevel,
-      0x30 => CommandClasses::SensorBinary,
-      0x31 => CommandClasses::SensorMultilevel,
-      0x60 => CommandClasses::MultiChannel,
-      0x7A => CommandClasses::FirmwareUpdate,
-      0x70 => CommandClasses::Configuration,
-      0x71 => CommandClasses::Alarm,
-      0x72 => CommandClasses::ManufacturerSpecific,
-      0x73 => CommandClasses::PowerLevel,
-      0x77 => CommandClasses::NodeNaming,
-      0x80 => CommandClasses::Battery,
-      0x81 => CommandClasses::Clock,
-      0x84 => CommandClasses::Wakeup,
-      0x85 => CommandClasses::Association,
-      0x86 => CommandClasses::Version,
-      0x8E => CommandClasses::MultiChannelAssociation,
-      0x9C => CommandClasses::AlarmSensor,
-      0x9D => CommandClasses::AlarmSilence,
-      0x9E => CommandClasses::SensorConfiguration,
-      _ => return None,
-    };
-    Some(command_class)
-  }
-
-  // TODO(MERGE-BLOCKER): TEST.
-  /// Convert a command class string identifier into a command class.
-  pub fn from_str(command_class_id: &str) -> Option<CommandClasses> {
-    command_class_id.parse::<u8>()
-        .ok() // Discard parse errors.
-        .and_then(|cc| Self::from_byte(cc))
+  pub fn from_byte(command_class_id: u8) -> CommandClasses {
+    REGISTRY.iter()
+        .find(|entry| entry.1 == command_class_id)
+        .map(|entry| entry.0)
+        .unwrap_or(CommandClasses::Unknown(command_class_id))
+  }
+
+  /// Convert a command class into its identifier.
+  pub fn to_byte(&self) -> u8 {
+    match *self {
+      CommandClasses::Unknown(command_class_id) => command_class_id,
+      _ => self.registry_entry().map(|entry| entry.1).unwrap_or(0),
+    }
+  }
+
+  /// Get the canonical Z-Way name, eg. "SensorBinary". Unknown command
+  /// classes have no name.
+  pub fn get_name(&self) -> Option<&'static str> {
+    self.registry_entry().map(|entry| entry.2)
+  }
+
+  /// Whether the Z-Wave Alliance has obsoleted or deprecated the command
+  /// class.
+  pub fn is_obsolete(&self) -> bool {
+    self.registry_entry().map(|entry| entry.3).unwrap_or(false)
+  }
+
+  /// Whether the command class is in the registry.
+  pub fn is_known(&self) -> bool {
+    self.registry_entry().is_some()
+  }
+
+  /// Get every command class in the registry, ordered by identifier.
+  pub fn all() -> Vec<CommandClasses> {
+    REGISTRY.iter().map(|entry| entry.0).collect()
+  }
+
+  fn registry_entry(&self)
+      -> Option<&'static (CommandClasses, u8, &'static str, bool)> {
+    match *self {
+      CommandClasses::Unknown(command_class_id) => {
+        // Normalize in case an identifier in the registry was wrapped.
+        REGISTRY.iter().find(|entry| entry.1 == command_class_id)
+      },
+      _ => REGISTRY.iter()
+          .find(|entry| mem::discriminant(&entry.0) == mem::discriminant(self)),
+    }
+  }
+}
+
+impl PartialEq for CommandClasses {
+  fn eq(&self, other: &CommandClasses) -> bool {
+    match (*self, *other) {
+      (CommandClasses::Unknown(_), _) | (_, CommandClasses::Unknown(_)) =>
+        self.to_byte() == other.to_byte(),
+      _ => mem::discriminant(self) == mem::discriminant(other),
+    }
+  }
+}
+
+impl Hash for CommandClasses {
+  fn hash<H: Hasher>(&self, state: &mut H) {
+    self.to_byte().hash(state);
+  }
+}
+
+impl FromStr for CommandClasses {
+  type Err = RazberryError;
+
+  /// Parse either a decimal identifier, as used for keys in the Z-Way
+  /// payloads (eg. "48"), or a Z-Way name (eg. "SensorBinary").
+  fn from_str(command_class: &str) -> Result<CommandClasses, RazberryError> {
+    if let Ok(command_class_id) = command_class.parse::<u8>() {
+      return Ok(CommandClasses::from_byte(command_class_id));
+    }
+
+    REGISTRY.iter()
+        .find(|entry| entry.2.eq_ignore_ascii_case(command_class))
+        .map(|entry| entry.0)
+        .ok_or(RazberryError::InvalidArgument)
   }
 }
 
 impl fmt::Display for CommandClasses {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-    let s = match *self {
-      CommandClasses::Alarm => "Alarm",
-      CommandClasses::AlarmSensor => "AlarmSensor",
-      CommandClasses::AlarmSilence => "AlarmSilence",
-      CommandClasses::Association => "Association",
-      CommandClasses::Basic => "Basic",
-      CommandClasses::Battery => "Battery",
-      CommandClasses::Clock => "Clock",
-      CommandClasses::Configuration => "Configuration",
-      CommandClasses::FirmwareUpdate => "FirmwareUpdate",
-      CommandClasses::ManufacturerSpecific => "ManufacturerSpecific",
-      CommandClasses::MultiChannel => "MultiChannel",
-      CommandClasses::MultiChannelAssociation => "MultiChannelAssociation",
-      CommandClasses::NoOperation => "NoOperation",
-      CommandClasses::NodeNaming => "NodeNaming",
-      CommandClasses::PowerLevel => "PowerLevel",
-      CommandClasses::SensorBinary => "SensorBinary",
-      CommandClasses::SensorConfiguration => "SensorConfiguration",
-      CommandClasses::SensorMultilevel => "SensorMultilevel",
-      CommandClasses::SwitchBinary => "SwitchBinary",
-      CommandClasses::SwitchMultilevel => "SwitchMultilevel",
-      CommandClasses::Version => "Version",
-      CommandClasses::Wakeup => "Wakeup",
-    };
-    write!(f, "<CommandClasses::{}>", s)
+    match self.get_name() {
+      Some(name) => write!(f, "<CommandClasses::{}>", name),
+      None => write!(f, "<CommandClasses::Unknown(0x{:02X})>", self.to_byte()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_from_byte() {
+    assert_eq!(CommandClasses::SensorBinary, CommandClasses::from_byte(0x30));
+    assert_eq!(CommandClasses::Security, CommandClasses::from_byte(0x98));
+    assert_eq!(CommandClasses::Unknown(0xFE), CommandClasses::from_byte(0xFE));
+  }
+
+  #[test]
+  fn test_registry_round_trip() {
+    for command_class in CommandClasses::all() {
+      assert!(command_class.is_known());
+      assert_eq!(command_class,
+        CommandClasses::from_byte(command_class.to_byte()));
+    }
+
+    for command_class_id in 0..256u16 {
+      let command_class_id = command_class_id as u8;
+      assert_eq!(command_class_id,
+        CommandClasses::from_byte(command_class_id).to_byte());
+    }
+  }
+
+  #[test]
+  fn test_registry_has_no_duplicates() {
+    let all = CommandClasses::all();
+    for (i, a) in all.iter().enumerate() {
+      for b in all.iter().skip(i + 1) {
+        assert!(a.to_byte() != b.to_byte());
+        assert!(a.get_name() != b.get_name());
+      }
+    }
+  }
+
+  #[test]
+  fn test_from_str() {
+    assert_eq!(CommandClasses::SensorBinary,
+      "48".parse::<CommandClasses>().unwrap());
+    assert_eq!(CommandClasses::SensorBinary,
+      "SensorBinary".parse::<CommandClasses>().unwrap());
+    assert_eq!(CommandClasses::Crc16, "crc16".parse::<CommandClasses>().unwrap());
+    assert_eq!(CommandClasses::Unknown(250),
+      "250".parse::<CommandClasses>().unwrap());
+
+    assert!("256".parse::<CommandClasses>().is_err());
+    assert!("NotACommandClass".parse::<CommandClasses>().is_err());
+    assert!("".parse::<CommandClasses>().is_err());
+  }
+
+  #[test]
+  fn test_names_and_flags() {
+    assert_eq!(Some("ZWavePlusInfo"), CommandClasses::ZWavePlusInfo.get_name());
+    assert_eq!(None, CommandClasses::Unknown(0xFE).get_name());
+    assert!(CommandClasses::ClimateControlSchedule.is_obsolete());
+    assert!(!CommandClasses::SensorBinary.is_obsolete());
+    assert_eq!("<CommandClasses::Unknown(0xFE)>",
+      CommandClasses::Unknown(0xFE).to_string());
+  }
+
+  #[test]
+  fn test_wrapped_identifier_is_normalized() {
+    use std::collections::HashSet;
+
+    assert_eq!(Some("SensorBinary"), CommandClasses::Unknown(0x30).get_name());
+    assert_eq!(CommandClasses::SensorBinary, CommandClasses::Unknown(0x30));
+    assert!(CommandClasses::SensorBinary != CommandClasses::Unknown(0x31));
+    assert!(CommandClasses::Unknown(0xFE) != CommandClasses::Unknown(0xFD));
+
+    let mut set = HashSet::new();
+    set.insert(CommandClasses::SensorBinary);
+    assert!(set.contains(&CommandClasses::Unknown(0x30)));
   }
 }
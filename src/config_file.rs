@@ -0,0 +1,90 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Helpers for the TOML, YAML and JSON files the rule engine and scheduler
+//! load. TOML and YAML are converted to JSON, so every format shares one set
+//! of keys.
+
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use toml;
+use yaml_rust::Yaml;
+use yaml_rust::YamlLoader;
+
+/// Parse TOML as a JSON object, or None if it is invalid.
+pub fn parse_toml(text: &str) -> Option<Json> {
+  toml::Parser::new(text).parse().map(|table| toml_to_json(toml::Value::Table(table)))
+}
+
+/// Parse the first YAML document as JSON, or None if it is invalid. Keys
+/// must be strings or numbers.
+pub fn parse_yaml(text: &str) -> Option<Json> {
+  YamlLoader::load_from_str(text).ok()
+      .and_then(|documents| documents.into_iter().next())
+      .and_then(yaml_to_json)
+}
+
+/// Parse "HH:MM" as minutes after midnight.
+pub fn parse_time_of_day(time: &str) -> Option<u32> {
+  let mut parts = time.splitn(2, ":");
+  let hours = parts.next().and_then(|h| h.parse::<u32>().ok());
+  let minutes = parts.next().and_then(|m| m.parse::<u32>().ok());
+
+  match (hours, minutes) {
+    (Some(h), Some(m)) if h < 24 && m < 60 => Some(h * 60 + m),
+    _ => None,
+  }
+}
+
+/// Parse "+HH:MM" or "-HH:MM" as seconds.
+pub fn parse_utc_offset(offset: &str) -> Option<i64> {
+  let sign = match offset.chars().next() {
+    Some('+') => 1,
+    Some('-') => -1,
+    _ => return None,
+  };
+
+  parse_time_of_day(&offset[1..]).map(|minutes| sign * minutes as i64 * 60)
+}
+
+fn toml_to_json(value: toml::Value) -> Json {
+  match value {
+    toml::Value::String(s) | toml::Value::Datetime(s) => Json::String(s),
+    toml::Value::Integer(n) => Json::I64(n),
+    toml::Value::Float(n) => Json::F64(n),
+    toml::Value::Boolean(b) => Json::Boolean(b),
+    toml::Value::Array(items) => Json::Array(items.into_iter().map(toml_to_json).collect()),
+    toml::Value::Table(table) => {
+      Json::Object(table.into_iter()
+          .map(|(k, v)| (k, toml_to_json(v)))
+          .collect::<BTreeMap<String, Json>>())
+    },
+  }
+}
+
+fn yaml_to_json(value: Yaml) -> Option<Json> {
+  let json = match value {
+    Yaml::Real(s) => Json::F64(s.parse().ok()?),
+    Yaml::Integer(n) => Json::I64(n),
+    Yaml::String(s) => Json::String(s),
+    Yaml::Boolean(b) => Json::Boolean(b),
+    Yaml::Null => Json::Null,
+    Yaml::Array(items) => {
+      Json::Array(items.into_iter().map(yaml_to_json).collect::<Option<Vec<Json>>>()?)
+    },
+    Yaml::Hash(hash) => {
+      let mut object = BTreeMap::new();
+      for (key, value) in hash {
+        let key = match key {
+          Yaml::String(s) | Yaml::Real(s) => s,
+          Yaml::Integer(n) => n.to_string(),
+          _ => return None,
+        };
+        object.insert(key, yaml_to_json(value)?);
+      }
+      Json::Object(object)
+    },
+    Yaml::Alias(_) | Yaml::BadValue => return None,
+  };
+
+  Some(json)
+}
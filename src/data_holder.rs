@@ -0,0 +1,728 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::NaiveDateTime;
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+use std::fmt;
+use std::str::Chars;
+use tracked_value::Freshness;
+
+/**
+ * A decoded Z-Way data holder value. Z-Way tags every value with a "type",
+ * which determines the variant.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum DataValue {
+  /// "empty": The holder has no value (eg. a structural node).
+  Empty,
+  /// "bool"
+  Bool(bool),
+  /// "int"
+  Int(i64),
+  /// "float"
+  Float(f64),
+  /// "string"
+  String(String),
+  /// "binary": A byte array.
+  Binary(Vec<u8>),
+  /// "int[]"
+  IntArray(Vec<i64>),
+  /// "float[]"
+  FloatArray(Vec<f64>),
+  /// "string[]"
+  StringArray(Vec<String>),
+}
+
+impl DataValue {
+  /// Decode a value given its Z-Way type. If the type is missing or not
+  /// recognized, it is inferred from the JSON.
+  pub fn from_json(value: &Json, value_type: Option<&str>) -> DataValue {
+    let decoded = match value_type {
+      Some("empty") => Some(DataValue::Empty),
+      Some("bool") => value.as_boolean().map(|b| DataValue::Bool(b)),
+      Some("int") => value.as_i64().map(|i| DataValue::Int(i)),
+      // Z-Way reports whole-number floats without a decimal point.
+      Some("float") => value.as_f64().map(|f| DataValue::Float(f)),
+      Some("string") => {
+        value.as_string().map(|s| DataValue::String(s.to_string()))
+      },
+      Some("binary") => {
+        decode_array(value, |j| j.as_u64().map(|b| b as u8))
+            .map(|a| DataValue::Binary(a))
+      },
+      Some("int[]") => {
+        decode_array(value, |j| j.as_i64()).map(|a| DataValue::IntArray(a))
+      },
+      Some("float[]") => {
+        decode_array(value, |j| j.as_f64()).map(|a| DataValue::FloatArray(a))
+      },
+      Some("string[]") => {
+        decode_array(value, |j| j.as_string().map(|s| s.to_string()))
+            .map(|a| DataValue::StringArray(a))
+      },
+      _ => None,
+    };
+
+    decoded.unwrap_or_else(|| DataValue::infer(value))
+  }
+
+  /// Decode a value without type information.
+  fn infer(value: &Json) -> DataValue {
+    match *value {
+      Json::Boolean(b) => DataValue::Bool(b),
+      Json::I64(i) => DataValue::Int(i),
+      Json::U64(u) => DataValue::Int(u as i64),
+      Json::F64(f) => DataValue::Float(f),
+      Json::String(ref s) => DataValue::String(s.to_string()),
+      Json::Array(_) => {
+        decode_array(value, |j| j.as_i64())
+            .map(|a| DataValue::IntArray(a))
+            .or_else(|| decode_array(value, |j| j.as_f64())
+                .map(|a| DataValue::FloatArray(a)))
+            .or_else(|| decode_array(value, |j| j.as_string()
+                .map(|s| s.to_string()))
+                .map(|a| DataValue::StringArray(a)))
+            .unwrap_or(DataValue::Empty)
+      },
+      Json::Null | Json::Object(_) => DataValue::Empty,
+    }
+  }
+
+  /// Encode the value as Z-Way would. Paired with `get_type`.
+  pub fn to_json(&self) -> Json {
+    match *self {
+      DataValue::Empty => Json::Null,
+      DataValue::Bool(b) => Json::Boolean(b),
+      DataValue::Int(i) => Json::I64(i),
+      DataValue::Float(f) => Json::F64(f),
+      DataValue::String(ref s) => Json::String(s.to_string()),
+      DataValue::Binary(ref a) => {
+        Json::Array(a.iter().map(|b| Json::U64(*b as u64)).collect())
+      },
+      DataValue::IntArray(ref a) => {
+        Json::Array(a.iter().map(|i| Json::I64(*i)).collect())
+      },
+      DataValue::FloatArray(ref a) => {
+        Json::Array(a.iter().map(|f| Json::F64(*f)).collect())
+      },
+      DataValue::StringArray(ref a) => {
+        Json::Array(a.iter().map(|s| Json::String(s.to_string())).collect())
+      },
+    }
+  }
+
+  /// Whether the holder has no value.
+  pub fn is_empty(&self) -> bool {
+    *self == DataValue::Empty
+  }
+
+  /// Get a boolean value.
+  pub fn as_bool(&self) -> Option<bool> {
+    match *self {
+      DataValue::Bool(b) => Some(b),
+      _ => None,
+    }
+  }
+
+  /// Get an integer value. Floats with no fractional part also qualify,
+  /// since Z-Way does not always distinguish the two.
+  pub fn as_int(&self) -> Option<i64> {
+    match *self {
+      DataValue::Int(i) => Some(i),
+      DataValue::Float(f) if f.fract() == 0.0 => Some(f as i64),
+      _ => None,
+    }
+  }
+
+  /// Get a numeric value as a float.
+  pub fn as_float(&self) -> Option<f64> {
+    match *self {
+      DataValue::Int(i) => Some(i as f64),
+      DataValue::Float(f) => Some(f),
+      _ => None,
+    }
+  }
+
+  /// Get a string value.
+  pub fn as_str(&self) -> Option<&str> {
+    match *self {
+      DataValue::String(ref s) => Some(s),
+      _ => None,
+    }
+  }
+
+  /// Get a byte array value.
+  pub fn as_binary(&self) -> Option<&[u8]> {
+    match *self {
+      DataValue::Binary(ref b) => Some(b),
+      _ => None,
+    }
+  }
+
+  /// Get an integer array value.
+  pub fn as_int_array(&self) -> Option<&[i64]> {
+    match *self {
+      DataValue::IntArray(ref a) => Some(a),
+      _ => None,
+    }
+  }
+
+  /// Get a float array value.
+  pub fn as_float_array(&self) -> Option<&[f64]> {
+    match *self {
+      DataValue::FloatArray(ref a) => Some(a),
+      _ => None,
+    }
+  }
+
+  /// Get a string array value.
+  pub fn as_string_array(&self) -> Option<&[String]> {
+    match *self {
+      DataValue::StringArray(ref a) => Some(a),
+      _ => None,
+    }
+  }
+
+  /// Get the Z-Way type name of the value.
+  pub fn get_type(&self) -> &'static str {
+    match *self {
+      DataValue::Empty => "empty",
+      DataValue::Bool(_) => "bool",
+      DataValue::Int(_) => "int",
+      DataValue::Float(_) => "float",
+      DataValue::String(_) => "string",
+      DataValue::Binary(_) => "binary",
+      DataValue::IntArray(_) => "int[]",
+      DataValue::FloatArray(_) => "float[]",
+      DataValue::StringArray(_) => "string[]",
+    }
+  }
+}
+
+fn decode_array<T, F>(value: &Json, decode: F) -> Option<Vec<T>>
+    where F: Fn(&Json) -> Option<T> {
+  value.as_array().and_then(|a| a.iter().map(|j| decode(j)).collect())
+}
+
+impl fmt::Display for DataValue {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      DataValue::Empty => write!(f, "null"),
+      DataValue::Bool(b) => write!(f, "{}", b),
+      DataValue::Int(i) => write!(f, "{}", i),
+      DataValue::Float(v) => write!(f, "{}", v),
+      DataValue::String(ref s) => write!(f, "{}", s),
+      DataValue::Binary(ref a) => write!(f, "{:?}", a),
+      DataValue::IntArray(ref a) => write!(f, "{:?}", a),
+      DataValue::FloatArray(ref a) => write!(f, "{:?}", a),
+      DataValue::StringArray(ref a) => write!(f, "{:?}", a),
+    }
+  }
+}
+
+/**
+ * A node in the Z-Way data tree. Every node has a value, the time the value
+ * was last updated, the time it was last invalidated (ie. a refresh was
+ * requested), and any number of named children.
+ *
+ * Structural nodes in the '/ZWaveAPI/Data' payload ("devices", "instances",
+ * "commandClasses") are modelled as holders with empty values, so one path
+ * syntax reaches everything.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataHolder {
+  value: DataValue,
+  update_time: Option<DateTime<UTC>>,
+  invalidate_time: Option<DateTime<UTC>>,
+  children: BTreeMap<String, DataHolder>,
+}
+
+impl DataHolder {
+  /// Construct an empty holder.
+  pub fn empty() -> DataHolder {
+    DataHolder {
+      value: DataValue::Empty,
+      update_time: None,
+      invalidate_time: None,
+      children: BTreeMap::new(),
+    }
+  }
+
+  /// Construct a holder (and its children) from a JSON node in a full
+  /// payload, or from the JSON under a single key of a delta payload.
+  pub fn from_json(json: &Json) -> Result<DataHolder, RazberryError> {
+    let object = json.as_object().ok_or(RazberryError::BadResponse)?;
+
+    let mut holder = DataHolder::empty();
+
+    let value_type = object.get("type").and_then(|t| t.as_string());
+
+    if let Some(value) = object.get("value") {
+      holder.value = DataValue::from_json(value, value_type);
+    }
+
+    holder.update_time = object.get("updateTime")
+        .and_then(|t| t.as_i64())
+        .map(|t| from_timestamp(t));
+
+    holder.invalidate_time = object.get("invalidateTime")
+        .and_then(|t| t.as_i64())
+        .map(|t| from_timestamp(t));
+
+    for (key, child) in object {
+      if child.is_object() {
+        holder.children.insert(key.to_string(), DataHolder::from_json(child)?);
+      }
+    }
+
+    Ok(holder)
+  }
+
+  /// Encode the holder and its children in the layout of the
+  /// '/ZWaveAPI/Data' payload. The inverse of `from_json`.
+  pub fn to_json(&self) -> Json {
+    let mut object = BTreeMap::new();
+
+    // Plain containers, eg. "devices", carry no value of their own.
+    if !self.value.is_empty() {
+      object.insert("value".to_string(), self.value.to_json());
+      object.insert("type".to_string(),
+        Json::String(self.value.get_type().to_string()));
+    }
+
+    if let Some(time) = self.invalidate_time {
+      object.insert("invalidateTime".to_string(), Json::I64(time.timestamp()));
+    }
+
+    if let Some(time) = self.update_time {
+      object.insert("updateTime".to_string(), Json::I64(time.timestamp()));
+    }
+
+    for (key, child) in self.children.iter() {
+      object.insert(key.to_string(), child.to_json());
+    }
+
+    Json::Object(object)
+  }
+
+  /// Construct a sparse tree from a '/ZWaveAPI/Data/{timestamp}' delta
+  /// payload, whose keys are dotted paths, eg. "devices.4.data.isAwake".
+  /// The tree can then be queried with the same paths as a full payload.
+  pub fn from_delta_json(json: &Json) -> Result<DataHolder, RazberryError> {
+    let mut root = DataHolder::empty();
+    root.apply_delta(json)?;
+    Ok(root)
+  }
+
+  /// Merge a '/ZWaveAPI/Data/{timestamp}' delta payload into a tree loaded
+  /// from the full payload. The root "updateTime" advances to that of the
+  /// delta.
+  pub fn apply_delta(&mut self, json: &Json) -> Result<(), RazberryError> {
+    let object = json.as_object().ok_or(RazberryError::BadResponse)?;
+
+    for (key, value) in object {
+      if !value.is_object() {
+        continue; // Eg. the top-level "updateTime".
+      }
+      let path = key.split(".").collect::<Vec<&str>>();
+      self.merge(&path, DataHolder::from_json(value)?);
+    }
+
+    let update_time = object.get("updateTime")
+        .and_then(|t| t.as_i64())
+        .map(|t| from_timestamp(t));
+
+    if update_time.is_some() {
+      self.update_time = update_time;
+    }
+
+    Ok(())
+  }
+
+  /// Get the value.
+  pub fn get_value(&self) -> &DataValue {
+    &self.value
+  }
+
+  /// Get when the value was last updated.
+  pub fn get_update_time(&self) -> Option<DateTime<UTC>> {
+    self.update_time
+  }
+
+  /// Get when the value was last invalidated.
+  pub fn get_invalidate_time(&self) -> Option<DateTime<UTC>> {
+    self.invalidate_time
+  }
+
+  /// Get whether the value can be trusted as current.
+  pub fn get_freshness(&self) -> Freshness {
+    Freshness::from_times(self.update_time, self.invalidate_time)
+  }
+
+  /// Whether the value answers the most recent refresh request.
+  pub fn is_valid(&self) -> bool {
+    self.get_freshness() == Freshness::Valid
+  }
+
+  /// Get the named children.
+  pub fn get_children(&self) -> &BTreeMap<String, DataHolder> {
+    &self.children
+  }
+
+  /// Get a single child.
+  pub fn get_child(&self, name: &str) -> Option<&DataHolder> {
+    self.children.get(name)
+  }
+
+  /// Find a descendant by path, eg. `&["data", "1", "level"]`.
+  pub fn find(&self, path: &[&str]) -> Option<&DataHolder> {
+    match path.split_first() {
+      None => Some(self),
+      Some((first, rest)) => self.children.get(*first)
+          .and_then(|child| child.find(rest)),
+    }
+  }
+
+  /// Find a descendant by dotted path, eg. "data.1.level".
+  pub fn find_dotted(&self, path: &str) -> Option<&DataHolder> {
+    if path.is_empty() {
+      return Some(self);
+    }
+    let path = path.split(".").collect::<Vec<&str>>();
+    self.find(&path)
+  }
+
+  /// Find the value of a descendant.
+  pub fn find_value(&self, path: &[&str]) -> Option<&DataValue> {
+    self.find(path).map(|holder| holder.get_value())
+  }
+
+  /// Find a descendant using Z-Way's own expression syntax, eg.
+  /// "devices[4].instances[0].commandClasses[49].data[1].val". Dotted
+  /// paths such as "devices.4.data.givenName" are accepted too. A trailing
+  /// ".value" is ignored, so expressions copied from the Z-Way console work
+  /// as is.
+  pub fn query(&self, expression: &str)
+      -> Result<Option<&DataHolder>, RazberryError> {
+    let mut path = parse_expression(expression)?;
+    if path.len() > 1 && path.last().map(|s| s.as_str()) == Some("value") {
+      path.pop();
+    }
+    let path = path.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+    Ok(self.find(&path))
+  }
+
+  /// Merge a holder into the tree at the given path, creating intermediate
+  /// nodes as necessary. The holder's value and timestamps replace those at
+  /// the path; children are merged recursively, and existing children that
+  /// the holder does not mention are kept.
+  pub fn merge(&mut self, path: &[&str], holder: DataHolder) {
+    match path.split_first() {
+      None => self.merge_node(holder),
+      Some((first, rest)) => {
+        self.children.entry(first.to_string())
+            .or_insert_with(|| DataHolder::empty())
+            .merge(rest, holder);
+      },
+    }
+  }
+
+  /// Whether merging the holder here would change nothing, ie. the tree
+  /// has already seen this update. Extra children of this node are ignored.
+  pub fn includes(&self, holder: &DataHolder) -> bool {
+    self.value == holder.value
+        && self.update_time == holder.update_time
+        && self.invalidate_time == holder.invalidate_time
+        && holder.children.iter().all(|(key, child)| {
+          self.children.get(key).map_or(false, |c| c.includes(child))
+        })
+  }
+
+  fn merge_node(&mut self, holder: DataHolder) {
+    self.value = holder.value;
+    self.update_time = holder.update_time;
+    self.invalidate_time = holder.invalidate_time;
+    for (key, child) in holder.children {
+      match self.children.entry(key) {
+        Entry::Occupied(mut existing) => existing.get_mut().merge_node(child),
+        Entry::Vacant(vacant) => { vacant.insert(child); },
+      }
+    }
+  }
+}
+
+/// Split a Z-Way expression into path components. Indices may be bare
+/// (`data[1]`) or quoted (`data["1"]`).
+fn parse_expression(expression: &str) -> Result<Vec<String>, RazberryError> {
+  let mut path = Vec::new();
+  let mut segment = String::new();
+  let mut after_index = false;
+  let mut chars = expression.trim().chars();
+
+  while let Some(c) = chars.next() {
+    match c {
+      '.' => {
+        if segment.is_empty() && !after_index {
+          return Err(RazberryError::InvalidArgument); // Eg. "a..b".
+        }
+        if !segment.is_empty() {
+          path.push(segment);
+          segment = String::new();
+        }
+        after_index = false;
+      },
+      '[' => {
+        if !segment.is_empty() {
+          path.push(segment);
+          segment = String::new();
+        } else if !after_index {
+          return Err(RazberryError::InvalidArgument); // Eg. "[1]" or "a.[1]".
+        }
+        path.push(parse_index(&mut chars)?);
+        after_index = true;
+      },
+      ']' => return Err(RazberryError::InvalidArgument),
+      _ => {
+        if after_index {
+          return Err(RazberryError::InvalidArgument); // Eg. "a[1]b".
+        }
+        segment.push(c);
+      },
+    }
+  }
+
+  if !segment.is_empty() {
+    path.push(segment);
+  } else if !after_index {
+    return Err(RazberryError::InvalidArgument); // Empty, or a trailing ".".
+  }
+
+  Ok(path)
+}
+
+/// Read an index up to and including the closing bracket.
+fn parse_index(chars: &mut Chars) -> Result<String, RazberryError> {
+  let mut index = String::new();
+  let mut quote = None;
+
+  while let Some(c) = chars.next() {
+    match (quote, c) {
+      (None, '"') | (None, '\'') if index.is_empty() => quote = Some(c),
+      (Some(q), _) if c == q => {
+        return match chars.next() {
+          Some(']') => Ok(index),
+          _ => Err(RazberryError::InvalidArgument),
+        };
+      },
+      (None, ']') => {
+        return match index.trim() {
+          "" => Err(RazberryError::InvalidArgument),
+          trimmed => Ok(trimmed.to_string()),
+        };
+      },
+      _ => index.push(c),
+    }
+  }
+
+  Err(RazberryError::InvalidArgument) // Unterminated.
+}
+
+/// Convert a Z-Way timestamp.
+fn from_timestamp(timestamp: i64) -> DateTime<UTC> {
+  DateTime::from_utc(NaiveDateTime::from_timestamp(timestamp, 0), UTC)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn decode(json: &str) -> DataValue {
+    DataHolder::from_json(&Json::from_str(json).unwrap())
+        .unwrap()
+        .get_value()
+        .clone()
+  }
+
+  #[test]
+  fn test_decode_types() {
+    assert_eq!(DataValue::Empty, decode(r#"{"value": null, "type": "empty"}"#));
+    assert_eq!(DataValue::Bool(true),
+      decode(r#"{"value": true, "type": "bool"}"#));
+    assert_eq!(DataValue::Int(-811605504),
+      decode(r#"{"value": -811605504, "type": "int"}"#));
+    assert_eq!(DataValue::Float(19.1),
+      decode(r#"{"value": 19.1, "type": "float"}"#));
+    assert_eq!(DataValue::Float(34.0),
+      decode(r#"{"value": 34, "type": "float"}"#));
+    assert_eq!(DataValue::String("Aeon Labs".to_string()),
+      decode(r#"{"value": "Aeon Labs", "type": "string"}"#));
+    assert_eq!(DataValue::Binary(vec![94, 134, 114]),
+      decode(r#"{"value": [94, 134, 114], "type": "binary"}"#));
+    assert_eq!(DataValue::IntArray(vec![2, 3, 4]),
+      decode(r#"{"value": [2, 3, 4], "type": "int[]"}"#));
+    assert_eq!(DataValue::FloatArray(vec![1.5, 2.0]),
+      decode(r#"{"value": [1.5, 2], "type": "float[]"}"#));
+    assert_eq!(DataValue::StringArray(vec!["a".to_string()]),
+      decode(r#"{"value": ["a"], "type": "string[]"}"#));
+  }
+
+  #[test]
+  fn test_decode_without_type() {
+    assert_eq!(DataValue::Int(5), decode(r#"{"value": 5}"#));
+    assert_eq!(DataValue::Bool(false), decode(r#"{"value": false}"#));
+    assert_eq!(DataValue::IntArray(vec![7]), decode(r#"{"value": [7]}"#));
+    assert_eq!(DataValue::Empty, decode(r#"{}"#));
+  }
+
+  #[test]
+  fn test_decode_mismatched_type() {
+    // Fall back to inference rather than dropping the value.
+    assert_eq!(DataValue::String("x".to_string()),
+      decode(r#"{"value": "x", "type": "int"}"#));
+  }
+
+  #[test]
+  fn test_value_conversions() {
+    assert_eq!(Some(34), DataValue::Float(34.0).as_int());
+    assert_eq!(None, DataValue::Float(19.1).as_int());
+    assert_eq!(Some(3.0), DataValue::Int(3).as_float());
+    assert_eq!(None, DataValue::Int(1).as_bool());
+  }
+
+  #[test]
+  fn test_full_payload() {
+    let json = Json::from_str(include_str!("../sample_data/data.json")).unwrap();
+    let root = DataHolder::from_json(&json).unwrap();
+
+    let level = root.find(&["devices", "4", "instances", "0",
+      "commandClasses", "49", "data", "1", "val"]).unwrap();
+
+    assert_eq!(Some(19.1), level.get_value().as_float());
+    assert_eq!(1456035988, level.get_update_time().unwrap().timestamp());
+    assert_eq!(1455606540, level.get_invalidate_time().unwrap().timestamp());
+
+    assert_eq!(Some("Multisensor"),
+      root.find_dotted("devices.4.data.givenName")
+          .and_then(|h| h.get_value().as_str()));
+
+    assert_eq!(1456036521, root.get_update_time().unwrap().timestamp());
+  }
+
+  #[test]
+  fn test_to_json_round_trip() {
+    let json = Json::from_str(include_str!("../sample_data/data.json")).unwrap();
+    let root = DataHolder::from_json(&json).unwrap();
+
+    assert_eq!(root, DataHolder::from_json(&root.to_json()).unwrap());
+  }
+
+  #[test]
+  fn test_query() {
+    let json = Json::from_str(include_str!("../sample_data/data.json")).unwrap();
+    let root = DataHolder::from_json(&json).unwrap();
+
+    let value = |expression| {
+      root.query(expression).unwrap().map(|h| h.get_value().clone())
+    };
+
+    let temperature = Some(DataValue::Float(19.1));
+    assert_eq!(temperature,
+      value("devices[4].instances[0].commandClasses[49].data[1].val"));
+    assert_eq!(temperature,
+      value("devices[4].instances[0].commandClasses[49].data[1].val.value"));
+    assert_eq!(temperature,
+      value(r#"devices["4"].instances[0].commandClasses[49].data['1'].val"#));
+    assert_eq!(temperature,
+      value("devices.4.instances.0.commandClasses.49.data.1.val"));
+
+    assert_eq!(None, value("devices[99].data.givenName"));
+  }
+
+  #[test]
+  fn test_parse_expression() {
+    assert_eq!(vec!["data", "1", "2", "level"],
+      parse_expression("data[1][2].level").unwrap());
+    assert_eq!(vec!["data", "a.b"], parse_expression(r#"data["a.b"]"#).unwrap());
+
+    for bad in &["", ".", "a..b", "a.", "[1]", "a.[1]", "a[1]b", "a[", "a[]",
+                 "a]", r#"a["1]"#] {
+      assert!(parse_expression(bad).is_err(), "accepted {:?}", bad);
+    }
+  }
+
+  #[test]
+  fn test_apply_delta() {
+    let json = Json::from_str(include_str!("../sample_data/data.json")).unwrap();
+    let mut root = DataHolder::from_json(&json).unwrap();
+
+    let delta = Json::from_str(r#"
+      {
+        "devices.4.instances.0.commandClasses.49.data.1.val": {
+          "value": 21.5,
+          "type": "float",
+          "invalidateTime": 1455606540,
+          "updateTime": 1456036600
+        },
+        "updateTime": 1456036605
+      }
+    "#).unwrap();
+
+    root.apply_delta(&delta).unwrap();
+
+    let val = root.find_dotted("devices.4.instances.0.commandClasses.49.data.1.val")
+        .unwrap();
+    assert_eq!(Some(21.5), val.get_value().as_float());
+    assert_eq!(1456036605, root.get_update_time().unwrap().timestamp());
+
+    // Untouched siblings and the rest of the tree survive.
+    assert!(root.find_dotted("devices.4.instances.0.commandClasses.49.data.1.scale")
+        .is_some());
+    assert!(root.find_dotted("devices.4.data.givenName").is_some());
+  }
+
+  #[test]
+  fn test_delta_payload() {
+    let json = Json::from_str(
+        include_str!("../sample_data/data-device-updates.json")).unwrap();
+    let root = DataHolder::from_delta_json(&json).unwrap();
+
+    assert_eq!(Some(false), root.find_dotted("devices.4.data.isAwake")
+        .and_then(|h| h.get_value().as_bool()));
+
+    // Children of a delta entry are reachable too.
+    assert_eq!(Some(false), root
+        .find_dotted("devices.4.instances.0.commandClasses.48.data.1.level")
+        .and_then(|h| h.get_value().as_bool()));
+
+    // Intermediate nodes are synthesized and empty.
+    assert!(root.find_dotted("devices.4").unwrap().get_value().is_empty());
+
+    assert_eq!(1456036634, root.get_update_time().unwrap().timestamp());
+  }
+
+  #[test]
+  fn test_merge_keeps_unmentioned_children() {
+    let mut root = DataHolder::from_json(&Json::from_str(r#"
+      {
+        "value": null,
+        "level": { "value": true, "type": "bool", "updateTime": 10 },
+        "sensorTypeString": { "value": "General purpose", "type": "string" }
+      }
+    "#).unwrap()).unwrap();
+
+    let update = DataHolder::from_json(&Json::from_str(r#"
+      { "value": false, "type": "bool", "updateTime": 20 }
+    "#).unwrap()).unwrap();
+
+    root.merge(&["level"], update);
+
+    let level = root.find(&["level"]).unwrap();
+    assert_eq!(Some(false), level.get_value().as_bool());
+    assert_eq!(20, level.get_update_time().unwrap().timestamp());
+    assert!(root.find(&["sensorTypeString"]).is_some());
+  }
+}
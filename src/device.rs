@@ -5,15 +5,22 @@ use chrono::UTC;
 use chrono::datetime::DateTime;
 use command_class::CommandClass;
 use command_classes::CommandClasses;
+use data_holder::DataHolder;
+use device_identity::DeviceIdentity;
 use device_update::DeviceUpdate;
 use error::RazberryError;
+use event::Event;
+use event::InvalidationEvent;
+use event::SceneEvent;
 use rustc_serialize::json::Json;
 use std::collections::HashMap;
 use std::fmt;
+use tracked_value::Freshness;
 
 /**
  * A Z-Wave Device.
  */
+#[derive(Clone, Debug)]
 pub struct Device {
   // TODO: Change all the visibilities, and hide behind locks (interior mut)
   /// The string (integer?) ID of the device in Z Way.
@@ -26,8 +33,20 @@ pub struct Device {
   /// This is the value of "data.lastReceived.updateTime"
   pub last_contacted: DateTime<UTC>,
 
+  /// The last time the Z Way controller asked the device for a refresh.
+  /// This is the value of "data.lastReceived.invalidateTime"
+  pub last_invalidated: Option<DateTime<UTC>>,
+
   /// Command classes associated with the device.
   pub command_classes: HashMap<CommandClasses, CommandClass>,
+
+  /// The version implemented for each command class, including those
+  /// without typed support. This is the value of "data.version".
+  pub command_class_versions: HashMap<CommandClasses, u8>,
+
+  /// Every command class the device supports, ordered by identifier,
+  /// whether or not this library has a typed model for it.
+  pub supported_command_classes: Vec<CommandClasses>,
 }
 
 impl Device {
@@ -39,6 +58,10 @@ impl Device {
       -> Result<Device, RazberryError> {
     let name = Device::get_string_property(json)?;
     let last_contacted = Device::get_last_contacted(json)?;
+    let last_invalidated = json
+        .find_path(&["data", "lastReceived", "invalidateTime"])
+        .and_then(|d| d.as_i64())
+        .map(|t| DateTime::from_utc(NaiveDateTime::from_timestamp(t, 0), UTC));
 
     // TODO: Multiple device instances.
     // Multiple instances are probably pretty rare, and muddy up the API a bit.
@@ -47,17 +70,36 @@ impl Device {
         .ok_or(RazberryError::BadResponse)?;
 
     let mut command_classes = HashMap::new();
+    let mut command_class_versions = HashMap::new();
+    let mut supported_command_classes = Vec::new();
 
     // TODO: Multiple command class instances.
     // Multiple instances here are probably more common, but I want to get a
     // simple working implementation first. The API is subject to change when
     // support is added.
     for (command_class_id, command_class_json) in cc_json {
-      let command_class = match CommandClasses::from_str(command_class_id) {
-        None => continue, // Unrecognized command class.
-        Some(cc) => cc,
+      let command_class = match command_class_id.parse::<CommandClasses>() {
+        Err(_) => continue, // Not a command class identifier.
+        Ok(cc) => cc,
       };
 
+      // Z-Way lists controlled-only command classes too.
+      let supported = command_class_json
+          .find_path(&["data", "supported", "value"])
+          .and_then(|v| v.as_boolean())
+          .unwrap_or(true);
+
+      if supported {
+        supported_command_classes.push(command_class);
+      }
+
+      let version = command_class_json.find_path(&["data", "version", "value"])
+          .and_then(|v| v.as_u64());
+
+      if let Some(version) = version {
+        command_class_versions.insert(command_class, version as u8);
+      }
+
       let cc_instance = CommandClass::initialize_from_json(command_class,
           command_class_json)?;
 
@@ -69,29 +111,44 @@ impl Device {
       command_classes.insert(command_class, cc_instance);
     }
 
+    supported_command_classes.sort_by_key(|cc| cc.to_byte());
+
     let device = Device {
       id: device_id.to_string(),
       name: name.to_string(),
       last_contacted: last_contacted,
+      last_invalidated: last_invalidated,
       command_classes: command_classes,
+      command_class_versions: command_class_versions,
+      supported_command_classes: supported_command_classes,
     };
     Ok(device)
   }
 
   /// Update the device from a JSON delta payload taken from the endpoint,
-  /// '/ZWaveAPI/Data/{timestamp}'.
-  pub fn process_updates(&mut self, updates: Vec<DeviceUpdate>)
-      -> Result<(), RazberryError> {
+  /// '/ZWaveAPI/Data/{timestamp}'. Returns any discrete events, such as
+  /// scene activations and invalidated values, that the updates contained.
+  ///
+  /// `previous` is the device's data tree before the updates are merged. A
+  /// value is reported as invalidated only if its "invalidateTime" is newer
+  /// than the tree's, so a stale value delivered again is not re-reported.
+  pub fn process_updates(&mut self, updates: Vec<DeviceUpdate>,
+                         previous: Option<&DataHolder>)
+      -> Result<Vec<Event>, RazberryError> {
+    let mut events = Vec::new();
+
     for update in updates {
+      let path = update.path.join(".");
+      let existing = previous.and_then(|p| p.find(&update.path));
+      self.collect_invalidations(&path, &update.data, existing, &mut events);
+
       match update.path.get(0) {
         Some(&"data") => {
           // Device meta updates.
           if update.path.get(1) == Some(&"lastReceived") {
-            let timestamp = update.data.find("updateTime")
-                .and_then(|d| d.as_i64())
+            self.last_contacted = update.data.get_update_time()
                 .ok_or(RazberryError::BadResponse)?;
-            let dt = NaiveDateTime::from_timestamp(timestamp, 0);
-            self.last_contacted = DateTime::from_utc(dt, UTC);
+            self.last_invalidated = update.data.get_invalidate_time();
           }
         },
         Some(&"instances") => {
@@ -103,7 +160,53 @@ impl Device {
         _ => continue, // Unknown update
       }
     }
-    Ok(())
+
+    for (command_class_id, command_class) in self.command_classes.iter_mut() {
+      if let Some((scene, key_attribute, timestamp)) =
+          command_class.take_scene_activation() {
+        events.push(Event::SceneActivated {
+          scene: SceneEvent {
+            device_id: self.id.clone(),
+            command_class: *command_class_id,
+            scene: scene,
+            key_attribute: key_attribute,
+            timestamp: timestamp,
+          },
+        });
+      }
+    }
+
+    Ok(events)
+  }
+
+  /// Report every value in the update that has become invalid since the
+  /// existing holder at the same path. Structural holders without a value of
+  /// their own are skipped.
+  fn collect_invalidations(&self, path: &str, holder: &DataHolder,
+                           existing: Option<&DataHolder>,
+                           events: &mut Vec<Event>) {
+    let previously_invalidated = existing.and_then(|e| e.get_invalidate_time());
+
+    if !holder.get_value().is_empty()
+        && holder.get_freshness() == Freshness::Invalid
+        && holder.get_invalidate_time() > previously_invalidated {
+      if let Some(invalidated) = holder.get_invalidate_time() {
+        events.push(Event::ValueInvalidated {
+          value: InvalidationEvent {
+            device_id: self.id.clone(),
+            path: path.to_string(),
+            last_updated: holder.get_update_time(),
+            timestamp: invalidated,
+          },
+        });
+      }
+    }
+
+    for (key, child) in holder.get_children() {
+      let child_path = format!("{}.{}", path, key);
+      let existing_child = existing.and_then(|e| e.get_child(key));
+      self.collect_invalidations(&child_path, child, existing_child, events);
+    }
   }
 
   fn process_command_class_update(&mut self, update: &DeviceUpdate)
@@ -111,17 +214,64 @@ impl Device {
     let command_class_id = update.path.get(3) // get the numeric value
         .ok_or(RazberryError::BadResponse)?;
 
-    let command_class_id = match CommandClasses::from_str(command_class_id) {
-      None => return Ok(()), // Unsupported command class
-      Some(cc) => cc,
+    let command_class_id = match command_class_id.parse::<CommandClasses>() {
+      Err(_) => return Ok(()), // Not a command class identifier.
+      Ok(cc) => cc,
     };
 
+    if update.path.get(4) == Some(&"data")
+        && update.path.get(5) == Some(&"version") {
+      let version = update.data.get_value().as_int();
+      if let Some(version) = version {
+        self.command_class_versions.insert(command_class_id, version as u8);
+      }
+    }
+
     match self.command_classes.get_mut(&command_class_id) {
       None => Ok(()), // Not loaded at initialization. Could indicate problem.
       Some(cc) => cc.process_update(update),
     }
   }
 
+  /// Get whether the device has answered the controller's most recent
+  /// request. Readings from a device that has not are likely out of date.
+  pub fn get_freshness(&self) -> Freshness {
+    Freshness::from_times(Some(self.last_contacted), self.last_invalidated)
+  }
+
+  /// Whether the device has answered the controller's most recent request.
+  pub fn is_valid(&self) -> bool {
+    self.get_freshness() == Freshness::Valid
+  }
+
+  /// Get the manufacturer, product, and firmware identity of the device.
+  pub fn get_identity(&self) -> DeviceIdentity {
+    let mut identity = DeviceIdentity::default();
+
+    let manufacturer_specific = self.command_classes
+        .get(&CommandClasses::ManufacturerSpecific);
+
+    if let Some(&CommandClass::ManufacturerSpecific { ref inner }) =
+        manufacturer_specific {
+      identity.manufacturer_id = inner.get_vendor_id();
+      identity.manufacturer = inner.get_vendor().map(|s| s.to_string());
+      identity.product_type = inner.get_product_type();
+      identity.product_id = inner.get_product_id();
+      identity.serial_number = inner.get_serial_number().map(|s| s.to_vec());
+    }
+
+    if let Some(&CommandClass::Version { ref inner }) =
+        self.command_classes.get(&CommandClasses::Version) {
+      identity.library_type = inner.get_library_type();
+      identity.protocol_version = inner.get_protocol_version();
+      identity.application_version = inner.get_application_version();
+      identity.hardware_version = inner.get_hardware_version();
+    }
+
+    identity.command_class_versions = self.command_class_versions.clone();
+    identity
+  }
+
   /// Get a string property on the device.
   fn get_string_property(json: &Json) -> Result<&str, RazberryError> {
     json.find_path(&["data", "givenName", "value"])
@@ -144,3 +294,92 @@ impl fmt::Display for Device {
     write!(f, "Device({}, {})", self.id, self.name)
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const FULL_JSON : &'static str = include_str!("../sample_data/data.json");
+
+  fn load_device(device_id: &str) -> Device {
+    let json = Json::from_str(FULL_JSON).unwrap();
+    let device_json = json.find_path(&["devices", device_id]).unwrap();
+    Device::initialize_from_json(device_id, device_json).unwrap()
+  }
+
+  #[test]
+  fn test_supported_command_classes() {
+    let device = load_device("4");
+
+    let supported = device.supported_command_classes.iter()
+        .map(|cc| cc.to_byte())
+        .collect::<Vec<u8>>();
+
+    assert_eq!(vec![0x20, 0x30, 0x31, 0x59, 0x5E, 0x70, 0x71, 0x72, 0x73,
+      0x7A, 0x80, 0x84, 0x85, 0x86, 0x98], supported);
+
+    assert!(device.supported_command_classes
+        .contains(&CommandClasses::ZWavePlusInfo));
+  }
+
+  #[test]
+  fn test_identity() {
+    let identity = load_device("4").get_identity();
+
+    assert_eq!(Some("Aeon Labs"), identity.manufacturer.as_ref()
+        .map(|s| s.as_str()));
+    assert_eq!("0086-0102-004A", identity.get_product_key().unwrap().to_string());
+    assert_eq!(Some(5), identity
+        .get_command_class_version(CommandClasses::SensorMultilevel));
+  }
+
+  #[test]
+  fn test_invalidated_value_is_reported() {
+    let mut device = load_device("4");
+
+    let json = Json::from_str(r#"
+      {
+        "value": 19.1,
+        "type": "float",
+        "invalidateTime": 1456036600,
+        "updateTime": 1456035988
+      }
+    "#).unwrap();
+
+    let update = DeviceUpdate {
+      path: vec!["instances", "0", "commandClasses", "49", "data", "1", "val"],
+      data: DataHolder::from_json(&json).unwrap(),
+    };
+
+    let events = device.process_updates(vec![update], None).unwrap();
+
+    match events.get(0) {
+      Some(&Event::ValueInvalidated { ref value }) => {
+        assert_eq!("4", value.device_id);
+        assert_eq!("instances.0.commandClasses.49.data.1.val", value.path);
+        assert_eq!(1456036600, value.timestamp.timestamp());
+      },
+      _ => panic!("Expected an invalidation event"),
+    }
+  }
+
+  #[test]
+  fn test_device_freshness() {
+    let mut device = load_device("4");
+    assert!(device.is_valid());
+
+    let json = Json::from_str(r#"
+      { "value": 0, "type": "int", "invalidateTime": 1456036700,
+        "updateTime": 1456036000 }
+    "#).unwrap();
+
+    let update = DeviceUpdate {
+      path: vec!["data", "lastReceived"],
+      data: DataHolder::from_json(&json).unwrap(),
+    };
+
+    device.process_updates(vec![update], None).unwrap();
+
+    assert_eq!(Freshness::Invalid, device.get_freshness());
+  }
+}
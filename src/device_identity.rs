@@ -0,0 +1,124 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use command_class::version::FirmwareVersion;
+use command_class::version::LibraryType;
+use command_classes::CommandClasses;
+use std::collections::HashMap;
+use std::fmt;
+
+/**
+ * Uniquely identifies a hardware product (but not an individual unit).
+ * Use this to key hardware-specific behavior instead of sniffing payloads.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ProductKey {
+  pub manufacturer_id: u16,
+  pub product_type: u16,
+  pub product_id: u16,
+}
+
+impl fmt::Display for ProductKey {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{:04X}-{:04X}-{:04X}",
+      self.manufacturer_id, self.product_type, self.product_id)
+  }
+}
+
+/**
+ * What a device is, as reported by the ManufacturerSpecific (0x72) and
+ * Version (0x86) command classes. Fields are empty if the device does not
+ * support the command class or Z-Way has not finished its interview.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct DeviceIdentity {
+  /// Z-Wave Alliance manufacturer identifier.
+  pub manufacturer_id: Option<u16>,
+
+  /// Manufacturer name, as resolved by Z-Way.
+  pub manufacturer: Option<String>,
+
+  /// Manufacturer-assigned product type.
+  pub product_type: Option<u16>,
+
+  /// Manufacturer-assigned product identifier.
+  pub product_id: Option<u16>,
+
+  /// Serial number (or device ID) bytes.
+  pub serial_number: Option<Vec<u8>>,
+
+  /// The Z-Wave library the firmware was built against.
+  pub library_type: Option<LibraryType>,
+
+  /// Z-Wave protocol version.
+  pub protocol_version: Option<FirmwareVersion>,
+
+  /// Application (manufacturer firmware) version.
+  pub application_version: Option<FirmwareVersion>,
+
+  /// Hardware revision.
+  pub hardware_version: Option<u8>,
+
+  /// The version implemented for each command class on the device.
+  pub command_class_versions: HashMap<CommandClasses, u8>,
+}
+
+impl DeviceIdentity {
+  /// Get the product key, if all three manufacturer fields are known.
+  pub fn get_product_key(&self) -> Option<ProductKey> {
+    match (self.manufacturer_id, self.product_type, self.product_id) {
+      (Some(manufacturer_id), Some(product_type), Some(product_id)) => {
+        Some(ProductKey {
+          manufacturer_id: manufacturer_id,
+          product_type: product_type,
+          product_id: product_id,
+        })
+      },
+      _ => None,
+    }
+  }
+
+  /// Get the version the device implements for a command class.
+  pub fn get_command_class_version(&self, command_class: CommandClasses)
+      -> Option<u8> {
+    self.command_class_versions.get(&command_class).map(|v| *v)
+  }
+}
+
+impl fmt::Display for DeviceIdentity {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    let product = self.get_product_key()
+        .map(|k| k.to_string())
+        .unwrap_or("unknown".to_string());
+    let firmware = self.application_version
+        .map(|v| v.to_string())
+        .unwrap_or("unknown".to_string());
+    write!(f, "DeviceIdentity({}, product: {}, firmware: {})",
+      self.manufacturer.as_ref().map(|s| s.as_str()).unwrap_or("unknown"),
+      product, firmware)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_product_key() {
+    let mut identity = DeviceIdentity::default();
+    assert!(identity.get_product_key().is_none());
+
+    identity.manufacturer_id = Some(0x86);
+    identity.product_type = Some(0x102);
+    assert!(identity.get_product_key().is_none());
+
+    identity.product_id = Some(0x4A);
+    let key = identity.get_product_key().unwrap();
+
+    assert_eq!(ProductKey {
+      manufacturer_id: 0x86,
+      product_type: 0x102,
+      product_id: 0x4A,
+    }, key);
+    assert_eq!("0086-0102-004A", key.to_string());
+  }
+}
@@ -1,5 +1,6 @@
 // Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
 
+use data_holder::DataHolder;
 use error::RazberryError;
 use rustc_serialize::json::Json;
 use std::collections::HashMap;
@@ -13,11 +14,11 @@ use std::collections::HashMap;
  * We parse out,
  *   device_id = "14"
  *   path = (instances, 0, commandClasses, 32, data, srcNodeId)
- *   data = JSON that lives under that key.
+ *   data = the data holder that lives under that key.
  */
 pub struct DeviceUpdate<'a> {
   pub path: Vec<&'a str>,
-  pub data: &'a Json,
+  pub data: DataHolder,
 }
 
 impl <'a> DeviceUpdate<'a> {
@@ -45,7 +46,7 @@ impl <'a> DeviceUpdate<'a> {
 
       let update = DeviceUpdate {
         path: path,
-        data: update_value,
+        data: DataHolder::from_json(update_value)?,
       };
 
       let device_updates = all_updates.entry(device_id.to_string())
@@ -133,6 +134,8 @@ mod tests {
     assert_eq!(vec!["instances", "0", "commandClasses", "48", "data", "1"],
       updates.get("9").unwrap().get(1).unwrap().path);
 
-    // TODO: Assert JSON payloads
+    let level = &updates.get("9").unwrap().get(1).unwrap().data;
+    assert_eq!(Some(true), level.find_value(&["level"]).unwrap().as_bool());
+    assert_eq!(1492409902, level.get_update_time().unwrap().timestamp());
   }
 }
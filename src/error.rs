@@ -18,6 +18,25 @@ pub enum RazberryError {
   /// Some kind of error from the Razberry gateway.
   ServerError,
 
+  /// A command argument was rejected before being sent to the gateway.
+  InvalidArgument,
+
+  /// A snapshot could not be read, or was written by an unsupported version.
+  BadSnapshot,
+
+  /// A delta payload started after the last one applied, so updates in
+  /// between may have been missed. The full state must be reloaded.
+  PossibleMissingEvents,
+
+  /// The MQTT broker refused the connection, or the connection failed.
+  BrokerError,
+
+  /// An automation rule could not be parsed, or has an invalid value.
+  BadRule,
+
+  /// A schedule could not be parsed, or has an invalid value.
+  BadSchedule,
+
   // Old:
   ClientError,
   BadRequest,
@@ -0,0 +1,184 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use command_class::central_scene::KeyAttribute;
+use command_classes::CommandClasses;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use std::fmt;
+use subscription::DataChange;
+
+/**
+ * A button press on a scene controller, reported through either the
+ * CentralScene (0x5B) or SceneActivation (0x2B) command class.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct SceneEvent {
+  /// The device that sent the scene.
+  pub device_id: String,
+
+  /// The command class that reported the scene.
+  pub command_class: CommandClasses,
+
+  /// The scene (typically the button) number.
+  pub scene: u8,
+
+  /// How the button was actuated. SceneActivation does not report this.
+  pub key_attribute: Option<KeyAttribute>,
+
+  /// Gateway time of the activation ("updateTime").
+  pub timestamp: DateTime<UTC>,
+}
+
+/**
+ * A value that the gateway asked a device to refresh, but that the device
+ * has not yet reported. Until it does, the last known value is stale.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct InvalidationEvent {
+  /// The device that owns the value.
+  pub device_id: String,
+
+  /// The dotted path of the value beneath the device, eg.
+  /// "instances.0.commandClasses.48.data.1.level".
+  pub path: String,
+
+  /// Gateway time of the last value update ("updateTime"), if any.
+  pub last_updated: Option<DateTime<UTC>>,
+
+  /// Gateway time of the invalidation ("invalidateTime").
+  pub timestamp: DateTime<UTC>,
+}
+
+/**
+ * Why the client discarded its state and reloaded it from the gateway.
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ResyncReason {
+  /// Updates may have been missed between polls: the delta was requested
+  /// from after the loaded state, or too long passed since the last poll.
+  Gap,
+
+  /// The gateway clock went backwards, as after a restart.
+  ClockRegression,
+
+  /// The delta payload was not shaped like one, eg. a full data dump or a
+  /// missing "updateTime".
+  SuspiciousResponse,
+
+  /// The delta referenced a device that was not loaded.
+  UnknownDevice { device_id: String },
+}
+
+impl fmt::Display for ResyncReason {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      ResyncReason::Gap => write!(f, "gap"),
+      ResyncReason::ClockRegression => write!(f, "clock regression"),
+      ResyncReason::SuspiciousResponse => write!(f, "suspicious response"),
+      ResyncReason::UnknownDevice { ref device_id } => {
+        write!(f, "unknown device {}", device_id)
+      },
+    }
+  }
+}
+
+/**
+ * A full reload of the gateway state in place of a delta poll. Changes made
+ * in between are not reported as events; compare against the new state.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResyncEvent {
+  /// Why the state was reloaded.
+  pub reason: ResyncReason,
+
+  /// Gateway time of the reloaded state ("updateTime").
+  pub timestamp: DateTime<UTC>,
+}
+
+/**
+ * Discrete events observed while polling the gateway for updates.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+  /// A scene was activated.
+  SceneActivated { scene: SceneEvent },
+
+  /// A value became stale.
+  ValueInvalidated { value: InvalidationEvent },
+
+  /// A data path matching a subscription changed.
+  DataChanged { change: DataChange },
+
+  /// The state was reloaded in full.
+  Resynced { resync: ResyncEvent },
+}
+
+impl Event {
+  /// Gateway time at which the event occurred.
+  pub fn get_timestamp(&self) -> DateTime<UTC> {
+    match *self {
+      Event::SceneActivated { ref scene } => scene.timestamp,
+      Event::ValueInvalidated { ref value } => value.timestamp,
+      Event::DataChanged { ref change } => change.timestamp,
+      Event::Resynced { ref resync } => resync.timestamp,
+    }
+  }
+
+  /// Encode as a JSON object with an "event" kind, eg. "dataChanged", and a
+  /// "timestamp" in seconds.
+  pub fn to_json(&self) -> Json {
+    let mut object = BTreeMap::new();
+    object.insert("timestamp".to_string(), Json::I64(self.get_timestamp().timestamp()));
+
+    let kind = match *self {
+      Event::SceneActivated { ref scene } => {
+        object.insert("device".to_string(), Json::String(scene.device_id.to_string()));
+        object.insert("scene".to_string(), Json::U64(scene.scene as u64));
+        "sceneActivated"
+      },
+      Event::ValueInvalidated { ref value } => {
+        object.insert("device".to_string(), Json::String(value.device_id.to_string()));
+        object.insert("path".to_string(), Json::String(value.path.to_string()));
+        "valueInvalidated"
+      },
+      Event::DataChanged { ref change } => {
+        object.insert("path".to_string(), Json::String(change.path.to_string()));
+        object.insert("value".to_string(), change.data.to_json());
+        "dataChanged"
+      },
+      Event::Resynced { ref resync } => {
+        object.insert("reason".to_string(), Json::String(resync.reason.to_string()));
+        "resynced"
+      },
+    };
+
+    object.insert("event".to_string(), Json::String(kind.to_string()));
+    Json::Object(object)
+  }
+}
+
+impl fmt::Display for Event {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Event::SceneActivated { ref scene } => {
+        write!(f, "SceneActivated(device: {}, scene: {}, key: {:?}, at: {})",
+          scene.device_id, scene.scene, scene.key_attribute, scene.timestamp)
+      },
+      Event::ValueInvalidated { ref value } => {
+        write!(f, "ValueInvalidated(device: {}, path: {}, at: {})",
+          value.device_id, value.path, value.timestamp)
+      },
+      Event::DataChanged { ref change } => {
+        write!(f, "DataChanged(subscription: {}, path: {}, value: {}, at: {})",
+          change.subscription, change.path, change.data.get_value(),
+          change.timestamp)
+      },
+      Event::Resynced { ref resync } => {
+        write!(f, "Resynced(reason: {}, at: {})", resync.reason,
+          resync.timestamp)
+      },
+    }
+  }
+}
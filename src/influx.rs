@@ -0,0 +1,397 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! InfluxDB line protocol for device readings.
+//!
+//! `InfluxExporter` turns each reading in a client's state into a `Point`,
+//! one measurement per command class (eg. "sensor_multilevel"), tagged with
+//! the device, instance and device name. Points are stamped with the
+//! gateway's `updateTime` for the reading rather than local time, so writing
+//! the same state twice, or again after a restart, overwrites rather than
+//! duplicates. Points go to any `Write`, eg. a file or stdout, through
+//! `LineWriter`, or to a server's write endpoint through `HttpWriter`.
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use data_holder::DataValue;
+use error::RazberryError;
+use network_state::NetworkState;
+use readings::get_string;
+use readings::readings;
+use std::collections::HashMap;
+use std::io::Write;
+use transport::HttpRequest;
+use transport::HyperTransport;
+use transport::Transport;
+
+/**
+ * The value of a point's "value" field.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldValue {
+  /// Numbers are always written as floats, so a series never changes type
+  /// when Z-Way reports a whole number.
+  Float(f64),
+  Boolean(bool),
+}
+
+/**
+ * A line of InfluxDB line protocol.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Point {
+  /// The measurement, eg. "sensor_binary".
+  pub measurement: String,
+
+  /// Tag keys and values, sorted by key.
+  pub tags: Vec<(String, String)>,
+
+  pub value: FieldValue,
+
+  /// When the gateway last updated the value.
+  pub timestamp: DateTime<UTC>,
+}
+
+/**
+ * Produces points for the readings that changed since the last call.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct InfluxExporter {
+  /// The timestamp last exported for each series, by measurement and tags.
+  exported: HashMap<String, i64>,
+}
+
+/**
+ * Somewhere to write points.
+ */
+pub trait PointWriter {
+  /// Write the points, in order.
+  fn write_points(&mut self, points: &[Point]) -> Result<(), RazberryError>;
+}
+
+/**
+ * Writes points as lines to a file, stdout or any other writer.
+ */
+pub struct LineWriter<W: Write> {
+  writer: W,
+}
+
+/**
+ * Posts points to an InfluxDB write endpoint,
+ * eg. "http://localhost:8086/write?db=home".
+ */
+pub struct HttpWriter {
+  url: String,
+  transport: Box<Transport + Send>,
+}
+
+impl Point {
+  /// Format as a line, without the newline. The timestamp is in
+  /// nanoseconds, InfluxDB's default precision.
+  pub fn to_line(&self) -> String {
+    let value = match self.value {
+      FieldValue::Float(value) => format!("{}", value),
+      FieldValue::Boolean(value) => format!("{}", value),
+    };
+
+    format!("{} value={} {}", self.get_series(), value,
+      self.timestamp.timestamp() * 1_000_000_000)
+  }
+
+  /// The escaped measurement and tags, which identify the series.
+  fn get_series(&self) -> String {
+    let mut series = escape(&self.measurement, ", ");
+
+    for &(ref key, ref value) in self.tags.iter() {
+      series.push_str(&format!(",{}={}", escape(key, ",= "), escape(value, ",= ")));
+    }
+
+    series
+  }
+}
+
+impl InfluxExporter {
+  /// Construct with nothing exported.
+  pub fn new() -> InfluxExporter {
+    InfluxExporter::default()
+  }
+
+  /// Forget what was exported, so the next call returns every reading.
+  pub fn reset(&mut self) {
+    self.exported.clear();
+  }
+
+  /// Get a point for every reading whose `updateTime` changed since the
+  /// last call, including readings that were re-reported unchanged. The
+  /// first call returns every current reading.
+  pub fn updates(&mut self, state: &NetworkState) -> Vec<Point> {
+    let mut points = Vec::new();
+
+    for point in InfluxExporter::points(state) {
+      let series = point.get_series();
+      let timestamp = point.timestamp.timestamp();
+
+      if self.exported.get(&series) == Some(&timestamp) {
+        continue;
+      }
+
+      self.exported.insert(series, timestamp);
+      points.push(point);
+    }
+
+    points
+  }
+
+  /// Get a point for every current reading in the state. Readings the
+  /// gateway never timestamped take the time of the last poll.
+  pub fn points(state: &NetworkState) -> Vec<Point> {
+    let mut points = Vec::new();
+
+    for reading in readings(state) {
+      let value = match *reading.value.get_value() {
+        DataValue::Bool(value) => FieldValue::Boolean(value),
+        DataValue::Int(value) => FieldValue::Float(value as f64),
+        DataValue::Float(value) if value.is_finite() => FieldValue::Float(value),
+        _ => continue,
+      };
+
+      let name = state.get_device(reading.device_id)
+          .map(|d| d.name.to_string())
+          .unwrap_or(String::new());
+
+      let mut tags = vec![
+        ("device", reading.device_id.to_string()),
+        ("instance", reading.instance.to_string()),
+        ("name", name),
+      ];
+
+      if reading.sensor != "level" {
+        tags.push(("sensor", reading.sensor.to_string()));
+      }
+
+      if let Some(sensor_type) = get_string(&reading, "sensorTypeString") {
+        tags.push(("type", sensor_type.to_string()));
+      }
+
+      if let Some(scale) = get_string(&reading, "scaleString") {
+        tags.push(("scale", scale.to_string()));
+      }
+
+      // InfluxDB rejects empty tag values, and prefers tags sorted by key.
+      let mut tags : Vec<(String, String)> = tags.into_iter()
+          .filter(|&(_, ref value)| !value.is_empty())
+          .map(|(key, value)| (key.to_string(), value))
+          .collect();
+      tags.sort();
+
+      points.push(Point {
+        measurement: measurement(reading.command_class.get_name().unwrap_or("Unknown")),
+        tags: tags,
+        value: value,
+        timestamp: reading.value.get_update_time()
+            .unwrap_or(state.get_update_time()),
+      });
+    }
+
+    points
+  }
+}
+
+impl <W: Write> LineWriter<W> {
+  /// Construct around a writer, eg. `io::stdout()` or an opened file.
+  pub fn new(writer: W) -> LineWriter<W> {
+    LineWriter { writer: writer }
+  }
+}
+
+impl <W: Write> PointWriter for LineWriter<W> {
+  fn write_points(&mut self, points: &[Point]) -> Result<(), RazberryError> {
+    for point in points {
+      writeln!(self.writer, "{}", point.to_line())
+          .map_err(|_| RazberryError::ClientError)?;
+    }
+
+    self.writer.flush().map_err(|_| RazberryError::ClientError)
+  }
+}
+
+impl HttpWriter {
+  /// Construct for a write endpoint URL, including the database,
+  /// eg. "http://localhost:8086/write?db=home". The default transport has
+  /// no TLS support, so "https://" URLs fail to write.
+  pub fn new(url: &str) -> HttpWriter {
+    HttpWriter {
+      url: url.to_string(),
+      transport: Box::new(HyperTransport::new()),
+    }
+  }
+
+  /// Replace the HTTP transport, eg. with a fake for testing.
+  pub fn set_transport(&mut self, transport: Box<Transport + Send>) {
+    self.transport = transport;
+  }
+}
+
+impl PointWriter for HttpWriter {
+  fn write_points(&mut self, points: &[Point]) -> Result<(), RazberryError> {
+    if points.is_empty() {
+      return Ok(());
+    }
+
+    let body = points.iter()
+        .map(|p| p.to_line())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let response = self.transport.send(&HttpRequest {
+      method: "POST".to_string(),
+      url: self.url.clone(),
+      headers: vec![("Content-Type".to_string(), "text/plain".to_string())],
+      body: Some(body),
+    })?;
+
+    match response.status {
+      status if status >= 200 && status < 300 => Ok(()),
+      401 | 403 => Err(RazberryError::BadCredentials),
+      status if status >= 400 && status < 500 => Err(RazberryError::BadRequest),
+      _ => Err(RazberryError::ServerError),
+    }
+  }
+}
+
+/// Convert a command class name to a measurement, eg. "SensorBinary" to
+/// "sensor_binary".
+fn measurement(name: &str) -> String {
+  let mut measurement = String::new();
+
+  for c in name.chars() {
+    if c.is_uppercase() && !measurement.is_empty() {
+      measurement.push('_');
+    }
+    measurement.extend(c.to_lowercase());
+  }
+
+  measurement
+}
+
+/// Backslash-escape the given characters.
+fn escape(value: &str, special: &str) -> String {
+  let mut escaped = String::new();
+
+  for c in value.chars() {
+    if special.contains(c) {
+      escaped.push('\\');
+    }
+    escaped.push(c);
+  }
+
+  escaped
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use simulator::Change;
+  use simulator::Simulator;
+  use simulator::VirtualClass;
+  use std::sync::Arc;
+  use std::sync::Mutex;
+  use transport::HttpResponse;
+
+  /// Keeps every request, and answers with a fixed status.
+  struct FakeInflux {
+    requests: Arc<Mutex<Vec<HttpRequest>>>,
+    status: u16,
+  }
+
+  impl Transport for FakeInflux {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, RazberryError> {
+      self.requests.lock().unwrap().push(request.clone());
+      Ok(HttpResponse { status: self.status, headers: Vec::new(), body: String::new() })
+    }
+  }
+
+  fn state(simulator: &Simulator) -> NetworkState {
+    NetworkState::from_json(&simulator.full_json()).unwrap()
+  }
+
+  #[test]
+  fn test_updates() {
+    let mut simulator = Simulator::new(1500000000);
+    simulator.add_node("2", "Hall motion", &[
+      VirtualClass::SensorBinary,
+      VirtualClass::SensorMultilevel { sensor_type: 1, scale: "°C".to_string() },
+      VirtualClass::Battery,
+    ]).unwrap();
+    simulator.add_node("3", "Lamp", &[VirtualClass::SwitchBinary]).unwrap();
+
+    let mut exporter = InfluxExporter::new();
+    let lines = exporter.updates(&state(&simulator)).iter()
+        .map(|p| p.to_line())
+        .collect::<Vec<_>>();
+
+    assert_eq!(4, lines.len());
+    assert!(lines.contains(&"sensor_binary,device=2,instance=0,name=Hall\\ motion,\
+      sensor=1,type=General\\ purpose value=false 1500000000000000000".to_string()));
+    assert!(lines.contains(&"sensor_multilevel,device=2,instance=0,name=Hall\\ motion,\
+      scale=°C,sensor=1,type=Temperature value=0 1500000000000000000".to_string()));
+    assert!(lines.contains(&"battery,device=2,instance=0,name=Hall\\ motion \
+      value=100 1500000000000000000".to_string()));
+    assert!(lines.contains(&"switch_binary,device=3,instance=0,name=Lamp \
+      value=false 1500000000000000000".to_string()));
+
+    // Only readings the gateway updated since are exported again, stamped
+    // with the gateway's time.
+    simulator.schedule(15, "2", Change::SensorMultilevel { sensor_type: 1, value: 21.5 });
+    simulator.advance(20).unwrap();
+
+    let points = exporter.updates(&state(&simulator));
+    assert_eq!(1, points.len());
+    assert_eq!(1500000015, points[0].timestamp.timestamp());
+    assert_eq!(FieldValue::Float(21.5), points[0].value);
+
+    assert!(exporter.updates(&state(&simulator)).is_empty());
+
+    exporter.reset();
+    assert_eq!(4, exporter.updates(&state(&simulator)).len());
+  }
+
+  #[test]
+  fn test_http_writer() {
+    let mut simulator = Simulator::new(1500000000);
+    simulator.add_node("3", "Lamp", &[VirtualClass::SwitchMultilevel]).unwrap();
+    let points = InfluxExporter::points(&state(&simulator));
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let mut writer = HttpWriter::new("http://localhost:8086/write?db=home");
+    writer.set_transport(Box::new(FakeInflux { requests: requests.clone(), status: 204 }));
+
+    writer.write_points(&points).unwrap();
+    writer.write_points(&[]).unwrap();
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(1, requests.len());
+    assert_eq!("POST", requests[0].method);
+    assert_eq!("http://localhost:8086/write?db=home", requests[0].url);
+    assert_eq!(Some("switch_multilevel,device=3,instance=0,name=Lamp \
+      value=0 1500000000000000000".to_string()), requests[0].body);
+
+    writer.set_transport(Box::new(FakeInflux {
+      requests: Arc::new(Mutex::new(Vec::new())),
+      status: 404,
+    }));
+    assert!(writer.write_points(&points).is_err());
+  }
+
+  #[test]
+  fn test_escape() {
+    let point = Point {
+      measurement: "a b,c".to_string(),
+      tags: vec![("name".to_string(), "x=1, y".to_string())],
+      value: FieldValue::Boolean(true),
+      timestamp: DateTime::<UTC>::from_utc(
+        ::chrono::NaiveDateTime::from_timestamp(1, 0), UTC),
+    };
+
+    assert_eq!("a\\ b\\,c,name=x\\=1\\,\\ y value=true 1000000000", point.to_line());
+  }
+}
@@ -4,7 +4,9 @@ extern crate chrono;
 extern crate hyper;
 extern crate log;
 extern crate rustc_serialize;
+extern crate toml;
 extern crate url;
+extern crate yaml_rust;
 
 pub use client::RazberryClient;
 pub use url::Url;
@@ -12,14 +14,55 @@ pub use url::Url;
 // FIXME: Don't dump everything into public namespace.
 mod client;
 mod command_classes;
+mod config_file;
+mod data_holder;
 mod device;
+mod device_identity;
 mod device_update;
 mod error;
+mod event;
+mod network_state;
+mod poll_stats;
+mod readings;
+mod subscription;
+mod tracked_value;
+mod transport;
+pub mod api;
 pub mod command_class;
+pub mod influx;
+pub mod metrics;
+pub mod mock;
+pub mod mqtt;
 pub mod response;
+pub mod rules;
+pub mod scheduler;
 pub mod sensors;
+pub mod simulator;
 
 pub use command_class::CommandClass;
 pub use command_classes::CommandClasses;
+pub use data_holder::DataHolder;
+pub use data_holder::DataValue;
 pub use device::Device;
+pub use device_identity::DeviceIdentity;
+pub use device_identity::ProductKey;
 pub use error::RazberryError;
+pub use event::Event;
+pub use event::InvalidationEvent;
+pub use event::ResyncEvent;
+pub use event::ResyncReason;
+pub use event::SceneEvent;
+pub use network_state::NetworkState;
+pub use poll_stats::PollStats;
+pub use subscription::DataChange;
+pub use subscription::PathPattern;
+pub use subscription::SubscriptionId;
+pub use tracked_value::Freshness;
+pub use tracked_value::TrackedValue;
+pub use transport::Exchange;
+pub use transport::HttpRequest;
+pub use transport::HttpResponse;
+pub use transport::HyperTransport;
+pub use transport::RecordingTransport;
+pub use transport::ReplayTransport;
+pub use transport::Transport;
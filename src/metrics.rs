@@ -0,0 +1,322 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Prometheus metrics for the gateway's devices and the client's polling.
+//!
+//! `Metrics` holds a copy of a client's state and poll counters, taken with
+//! `update` after each poll, and renders them in the Prometheus text format.
+//! `MetricsHandler` serves them at '/metrics' from a hyper server, so the
+//! scrape never waits on a poll. The `razberry_exporter` binary runs both.
+
+use client::RazberryClient;
+use command_classes::CommandClasses;
+use data_holder::DataValue;
+use hyper::header::ContentType;
+use hyper::server::Handler;
+use hyper::server::Request;
+use hyper::server::Response;
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+use network_state::NetworkState;
+use poll_stats::PollStats;
+use readings::get_string;
+use readings::readings;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+/// Content type of the Prometheus text format.
+pub const CONTENT_TYPE : &'static str = "text/plain; version=0.0.4";
+
+/**
+ * A copy of the values to export.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+  /// The gateway state, or None until devices are loaded.
+  state: Option<NetworkState>,
+
+  /// The client's poll counters.
+  stats: PollStats,
+}
+
+/**
+ * One metric and its samples.
+ */
+struct Family {
+  name: &'static str,
+  help: &'static str,
+  kind: &'static str,
+  samples: Vec<(Vec<(&'static str, String)>, f64)>,
+}
+
+impl Family {
+  fn new(name: &'static str, kind: &'static str, help: &'static str) -> Family {
+    Family { name: name, help: help, kind: kind, samples: Vec::new() }
+  }
+
+  fn add(&mut self, labels: Vec<(&'static str, String)>, value: f64) {
+    self.samples.push((labels, value));
+  }
+
+  fn render(&self, out: &mut String) {
+    let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+    let _ = writeln!(out, "# TYPE {} {}", self.name, self.kind);
+
+    for &(ref labels, value) in &self.samples {
+      let labels = labels.iter()
+          .map(|&(name, ref value)| format!("{}=\"{}\"", name, escape(value)))
+          .collect::<Vec<String>>();
+
+      if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", self.name, value);
+      } else {
+        let _ = writeln!(out, "{}{{{}}} {}", self.name, labels.join(","), value);
+      }
+    }
+  }
+}
+
+impl Metrics {
+  /// Construct with nothing loaded.
+  pub fn new() -> Metrics {
+    Metrics::default()
+  }
+
+  /// Copy the client's current state and poll counters.
+  pub fn update(&mut self, client: &RazberryClient) {
+    self.state = client.get_state().cloned();
+    self.stats = client.get_poll_stats().clone();
+  }
+
+  /// Render in the Prometheus text format. Device values are labelled by
+  /// "device_id", "device_name" and "instance". Ages are measured against
+  /// the gateway clock, as of the last poll.
+  pub fn render(&self) -> String {
+    let mut sensor_binary = Family::new("razberry_sensor_binary", "gauge",
+      "Binary sensor state (0x30), 1 when triggered.");
+    let mut sensor_multilevel = Family::new("razberry_sensor_multilevel", "gauge",
+      "Multilevel sensor reading (0x31), in the reported scale.");
+    let mut battery = Family::new("razberry_battery_percent", "gauge",
+      "Battery level (0x80), in percent.");
+    let mut switch_binary = Family::new("razberry_switch_binary", "gauge",
+      "Switch state (0x25), 1 when on.");
+    let mut switch_multilevel = Family::new("razberry_switch_multilevel", "gauge",
+      "Dimmer level (0x26), from 0 to 99.");
+    let mut meter = Family::new("razberry_meter", "gauge",
+      "Meter reading (0x32), in the reported scale.");
+    let mut last_contacted = Family::new("razberry_last_contacted_seconds", "gauge",
+      "Seconds since the device was last heard from.");
+
+    if let Some(ref state) = self.state {
+      let now = state.get_update_time();
+      let mut devices = state.get_devices();
+      devices.sort_by_key(|d| device_order(&d.id));
+
+      for device in devices {
+        let labels = vec![
+          ("device_id", device.id.to_string()),
+          ("device_name", device.name.to_string()),
+        ];
+
+        let age = now.timestamp() - device.last_contacted.timestamp();
+        last_contacted.add(labels, age as f64);
+      }
+
+      let mut current = readings(state);
+      current.sort_by_key(|r| device_order(r.device_id));
+
+      for reading in current {
+        let device = match state.get_device(reading.device_id) {
+          None => continue,
+          Some(device) => device,
+        };
+
+        let value = match sample_value(reading.value.get_value()) {
+          None => continue,
+          Some(value) => value,
+        };
+
+        let mut labels = vec![
+          ("device_id", device.id.to_string()),
+          ("device_name", device.name.to_string()),
+          ("instance", reading.instance.to_string()),
+        ];
+
+        let sensor_type = get_string(&reading, "sensorTypeString");
+        let scale = get_string(&reading, "scaleString");
+
+        let family = match reading.command_class {
+          CommandClasses::SensorBinary => {
+            labels.push(("sensor_type", sensor_type.unwrap_or(reading.sensor).to_string()));
+            &mut sensor_binary
+          },
+          CommandClasses::SensorMultilevel => {
+            labels.push(("sensor_type", sensor_type.unwrap_or(reading.sensor).to_string()));
+            labels.push(("scale", scale.unwrap_or("").to_string()));
+            &mut sensor_multilevel
+          },
+          CommandClasses::Meter => {
+            labels.push(("sensor_type", sensor_type.unwrap_or("").to_string()));
+            labels.push(("scale", scale.unwrap_or(reading.sensor).to_string()));
+            &mut meter
+          },
+          CommandClasses::Battery => &mut battery,
+          CommandClasses::SwitchBinary => &mut switch_binary,
+          CommandClasses::SwitchMultilevel => &mut switch_multilevel,
+          _ => continue,
+        };
+
+        family.add(labels, value);
+      }
+    }
+
+    let mut polls = Family::new("razberry_polls_total", "counter",
+      "Polls of the gateway for updates.");
+    polls.add(Vec::new(), self.stats.polls as f64);
+
+    let mut errors = Family::new("razberry_poll_errors_total", "counter",
+      "Polls that failed.");
+    errors.add(Vec::new(), self.stats.errors as f64);
+
+    let mut resyncs = Family::new("razberry_resyncs_total", "counter",
+      "Polls that reloaded the full state instead of applying a delta.");
+    resyncs.add(Vec::new(), self.stats.resyncs as f64);
+
+    let mut duration = Family::new("razberry_poll_duration_seconds_total", "counter",
+      "Time spent polling.");
+    duration.add(Vec::new(), seconds(self.stats.total_duration));
+
+    let mut last_duration = Family::new("razberry_last_poll_duration_seconds", "gauge",
+      "Time spent in the most recent poll.");
+    if let Some(last) = self.stats.last_duration {
+      last_duration.add(Vec::new(), seconds(last));
+    }
+
+    let mut out = String::new();
+
+    for family in &[sensor_binary, sensor_multilevel, battery, switch_binary,
+                    switch_multilevel, meter, last_contacted, polls, errors,
+                    resyncs, duration, last_duration] {
+      family.render(&mut out);
+    }
+
+    out
+  }
+}
+
+/**
+ * Serves '/metrics' from shared `Metrics`, eg. with
+ * `hyper::server::Server::http("0.0.0.0:9479")?.handle(handler)`.
+ */
+pub struct MetricsHandler {
+  metrics: Arc<Mutex<Metrics>>,
+}
+
+impl MetricsHandler {
+  /// Serve the metrics.
+  pub fn new(metrics: Arc<Mutex<Metrics>>) -> MetricsHandler {
+    MetricsHandler { metrics: metrics }
+  }
+}
+
+impl Handler for MetricsHandler {
+  fn handle(&self, request: Request, mut response: Response) {
+    let path = match request.uri {
+      RequestUri::AbsolutePath(ref path) => path.split("?").next().unwrap_or("").to_string(),
+      _ => String::new(),
+    };
+
+    if path != "/metrics" {
+      *response.status_mut() = StatusCode::NotFound;
+      let _ = response.send(b"Not Found");
+      return;
+    }
+
+    let body = match self.metrics.lock() {
+      Ok(metrics) => metrics.render(),
+      Err(_) => {
+        *response.status_mut() = StatusCode::InternalServerError;
+        let _ = response.send(b"Internal Server Error");
+        return;
+      },
+    };
+
+    response.headers_mut().set(ContentType(CONTENT_TYPE.parse().unwrap()));
+    let _ = response.send(body.as_bytes());
+  }
+}
+
+/// Order devices by numeric ID.
+fn device_order(device_id: &str) -> (u32, String) {
+  (device_id.parse::<u32>().unwrap_or(u32::max_value()), device_id.to_string())
+}
+
+/// The sample for a numeric or boolean value, 1 for true.
+fn sample_value(value: &DataValue) -> Option<f64> {
+  match *value {
+    DataValue::Bool(b) => Some(if b { 1.0 } else { 0.0 }),
+    ref value => value.as_float(),
+  }
+}
+
+fn seconds(duration: ::std::time::Duration) -> f64 {
+  duration.as_secs() as f64 + duration.subsec_nanos() as f64 / 1e9
+}
+
+fn escape(value: &str) -> String {
+  value.replace("\\", "\\\\").replace("\"", "\\\"").replace("\n", "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use mock::Fault;
+  use mock::MockGateway;
+  use mock::test_client;
+  use simulator::Change;
+  use simulator::Simulator;
+  use simulator::VirtualClass;
+
+  #[test]
+  fn test_render() {
+    let mut simulator = Simulator::new(1500000000);
+    simulator.add_node("2", "Hall \"motion\"", &[
+      VirtualClass::SensorBinary,
+      VirtualClass::SensorMultilevel { sensor_type: 1, scale: "°C".to_string() },
+      VirtualClass::Battery,
+    ]).unwrap();
+    simulator.add_node("3", "Lamp", &[VirtualClass::SwitchBinary]).unwrap();
+    simulator.schedule(10, "2", Change::SensorBinary(true));
+    simulator.schedule(10, "2", Change::SensorMultilevel { sensor_type: 1, value: 21.5 });
+
+    let gateway = Arc::new(Mutex::new(MockGateway::from_simulator(simulator)));
+    let mut client = test_client(&gateway);
+
+    gateway.lock().unwrap().advance(30).unwrap();
+    client.poll_updates().unwrap();
+
+    gateway.lock().unwrap().inject_fault(Fault::ServerError);
+    assert!(client.poll_updates().is_err());
+
+    let mut metrics = Metrics::new();
+    metrics.update(&client);
+    let text = metrics.render();
+
+    let labels = "device_id=\"2\",device_name=\"Hall \\\"motion\\\"\",instance=\"0\"";
+
+    assert!(text.contains("# TYPE razberry_sensor_binary gauge\n"));
+    assert!(text.contains(&format!(
+      "razberry_sensor_binary{{{},sensor_type=\"General purpose\"}} 1\n", labels)));
+    assert!(text.contains(&format!(
+      "razberry_sensor_multilevel{{{},sensor_type=\"Temperature\",scale=\"°C\"}} 21.5\n",
+      labels)));
+    assert!(text.contains(&format!("razberry_battery_percent{{{}}} 100\n", labels)));
+    assert!(text.contains(
+      "razberry_switch_binary{device_id=\"3\",device_name=\"Lamp\",instance=\"0\"} 0\n"));
+    assert!(text.contains(
+      "razberry_last_contacted_seconds{device_id=\"2\",device_name=\"Hall \\\"motion\\\"\"} 20\n"));
+    assert!(text.contains("razberry_polls_total 2\n"));
+    assert!(text.contains("razberry_poll_errors_total 1\n"));
+    assert!(text.contains("razberry_resyncs_total 0\n"));
+  }
+}
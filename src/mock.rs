@@ -0,0 +1,725 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! A mock Z-Way gateway for exercising `RazberryClient` without a Razberry.
+//!
+//! `MockGateway` models the gateway: sessions, and a `Simulator` holding the
+//! data tree and clock, seeded from a '/ZWaveAPI/Data' payload such as
+//! `sample_data/data.json` or built from virtual nodes. It answers requests
+//! in-process through `MockTransport`, or over HTTP through `MockServer`.
+
+use data_holder::DataHolder;
+use data_holder::DataValue;
+use error::RazberryError;
+use hyper::method::Method;
+use hyper::server::Handler;
+use hyper::server::Listening;
+use hyper::server::Request;
+use hyper::server::Response;
+use hyper::server::Server;
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+use rustc_serialize::json::Json;
+use simulator::Simulator;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+use transport::HttpRequest;
+use transport::HttpResponse;
+use transport::Transport;
+use url::Url;
+use url::percent_encoding::percent_decode;
+
+const SESSION_COOKIE_NAME : &'static str = "ZWAYSession";
+
+/**
+ * A failure to inject into the next response.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Fault {
+  /// Answer 401, as for an expired session.
+  Unauthorized,
+
+  /// Answer 500.
+  ServerError,
+
+  /// Answer 200 with a body that is not valid JSON.
+  MalformedJson,
+
+  /// Wait before answering normally, eg. to trip the client's read timeout.
+  Delay(Duration),
+}
+
+/**
+ * The state of a mock gateway.
+ */
+pub struct MockGateway {
+  /// The network served by '/ZWaveAPI/Data'.
+  simulator: Simulator,
+
+  /// Accepted login.
+  username: String,
+  password: String,
+
+  /// Session tokens issued by login.
+  sessions: HashSet<String>,
+
+  /// Faults to inject, one per request.
+  faults: VecDeque<Fault>,
+}
+
+impl MockGateway {
+  /// Construct a gateway serving the data of a '/ZWaveAPI/Data' payload.
+  /// The default login is "admin" / "admin".
+  pub fn new(seed: &Json) -> Result<MockGateway, RazberryError> {
+    Ok(MockGateway::from_simulator(Simulator::from_json(seed)?))
+  }
+
+  /// Construct a gateway serving a simulated network.
+  pub fn from_simulator(simulator: Simulator) -> MockGateway {
+    MockGateway {
+      simulator: simulator,
+      username: "admin".to_string(),
+      password: "admin".to_string(),
+      sessions: HashSet::new(),
+      faults: VecDeque::new(),
+    }
+  }
+
+  /// Get the simulated network.
+  pub fn get_simulator(&self) -> &Simulator {
+    &self.simulator
+  }
+
+  /// Get the simulated network, eg. to script changes.
+  pub fn get_simulator_mut(&mut self) -> &mut Simulator {
+    &mut self.simulator
+  }
+
+  /// Set the accepted login.
+  pub fn set_credentials(&mut self, username: &str, password: &str) {
+    self.username = username.to_string();
+    self.password = password.to_string();
+  }
+
+  /// Get the data tree.
+  pub fn get_data(&self) -> &DataHolder {
+    self.simulator.get_data()
+  }
+
+  /// Get the gateway time.
+  pub fn get_time(&self) -> i64 {
+    self.simulator.get_time()
+  }
+
+  /// Pin the gateway clock, or None to follow the system clock.
+  pub fn set_time(&mut self, time: Option<i64>) {
+    self.simulator.set_time(time);
+  }
+
+  /// Move the gateway clock, pinning it, and apply any scripted changes
+  /// that fall due.
+  pub fn advance(&mut self, seconds: i64) -> Result<(), RazberryError> {
+    self.simulator.advance(seconds)
+  }
+
+  /// Queue a fault for the next request. Faults are used one per request,
+  /// in order.
+  pub fn inject_fault(&mut self, fault: Fault) {
+    self.faults.push_back(fault);
+  }
+
+  /// Change a value as a device report would, eg.
+  /// "devices.4.instances.0.commandClasses.48.data.1.level".
+  pub fn set_value(&mut self, path: &str, value: DataValue)
+      -> Result<(), RazberryError> {
+    self.simulator.set_value(path, value)
+  }
+
+  /// Mark a value stale, as when the gateway asks a device to refresh it.
+  pub fn invalidate(&mut self, path: &str) -> Result<(), RazberryError> {
+    self.simulator.invalidate(path)
+  }
+
+  /// Remove a queued delay, if it is next. Servers sleep for it without
+  /// holding the gateway.
+  pub fn take_delay(&mut self) -> Option<Duration> {
+    match self.faults.front() {
+      Some(&Fault::Delay(delay)) => {
+        self.faults.pop_front();
+        Some(delay)
+      },
+      _ => None,
+    }
+  }
+
+  /// Answer a request. The URL may be absolute or a path.
+  pub fn handle(&mut self, request: &HttpRequest) -> HttpResponse {
+    match self.faults.pop_front() {
+      Some(Fault::Unauthorized) => return respond(401, "Unauthorized"),
+      Some(Fault::ServerError) => return respond(500, "Internal Server Error"),
+      Some(Fault::MalformedJson) => return respond(200, "{\"updateTime\": "),
+      Some(Fault::Delay(_)) | None => {},
+    }
+
+    let path = match Url::parse("http://localhost").and_then(|b| b.join(&request.url)) {
+      Err(_) => return respond(400, "Bad Request"),
+      Ok(url) => percent_decode(url.path().as_bytes()).decode_utf8_lossy().into_owned(),
+    };
+
+    if path == "/ZAutomation/api/v1/login" && request.method == "POST" {
+      return self.login(request);
+    }
+
+    if !self.is_authenticated(request) {
+      return respond(401, "Unauthorized");
+    }
+
+    if path == "/ZWaveAPI/Data" {
+      self.full_data()
+    } else if path.starts_with("/ZWaveAPI/Data/") {
+      match path["/ZWaveAPI/Data/".len()..].parse::<i64>() {
+        Err(_) => respond(400, "Bad Request"),
+        Ok(since) => self.delta_data(since),
+      }
+    } else if path.starts_with("/ZWaveAPI/Run/") {
+      let command = path["/ZWaveAPI/Run/".len()..].to_string();
+      self.run(&command)
+    } else {
+      respond(404, "Not Found")
+    }
+  }
+
+  fn login(&mut self, request: &HttpRequest) -> HttpResponse {
+    let json = request.body.as_ref().and_then(|b| Json::from_str(b).ok());
+
+    let login = json.as_ref().and_then(|j| j.find("login")).and_then(|l| l.as_string());
+    let password = json.as_ref().and_then(|j| j.find("password"))
+        .and_then(|p| p.as_string());
+
+    if login != Some(&self.username) || password != Some(&self.password) {
+      return respond(401, "Unauthorized");
+    }
+
+    let token = format!("mock-session-{}", self.sessions.len() + 1);
+    self.sessions.insert(token.clone());
+
+    let mut response = respond(200, "{\"data\": {}}");
+    response.headers.push(("Set-Cookie".to_string(),
+      format!("{}={}; Path=/", SESSION_COOKIE_NAME, token)));
+    response
+  }
+
+  fn is_authenticated(&self, request: &HttpRequest) -> bool {
+    request.headers.iter()
+        .filter(|&&(ref name, _)| name.eq_ignore_ascii_case("Cookie"))
+        .flat_map(|&(_, ref value)| value.split(";"))
+        .filter_map(|cookie| {
+          let mut parts = cookie.trim().splitn(2, "=");
+          match (parts.next(), parts.next()) {
+            (Some(SESSION_COOKIE_NAME), Some(token)) => Some(token),
+            _ => None,
+          }
+        })
+        .any(|token| self.sessions.contains(token))
+  }
+
+  fn full_data(&self) -> HttpResponse {
+    respond(200, &self.simulator.full_json().to_string())
+  }
+
+  fn delta_data(&self, since: i64) -> HttpResponse {
+    respond(200, &self.simulator.delta_json(since).to_string())
+  }
+
+  /// Run a command class method or evaluate a data expression.
+  fn run(&mut self, command: &str) -> HttpResponse {
+    let open = match command.find("(") {
+      None => return self.evaluate(command),
+      Some(open) => open,
+    };
+
+    let dot = match command[..open].rfind(".") {
+      None => return respond(500, "Unknown command"),
+      Some(dot) => dot,
+    };
+
+    // Eg. "devices[4].instances[0].commandClasses[98]" and "Set".
+    let target = command[..dot].replace("[", ".").replace("]", "").replace("\"", "");
+    let method = &command[dot + 1..open];
+    let args = command[open + 1..].trim_right_matches(")")
+        .split(",")
+        .map(|a| a.trim().trim_matches('"').to_string())
+        .collect::<Vec<String>>();
+
+    let command_class = target.rsplit(".").next().unwrap_or("").to_string();
+
+    if self.simulator.get_data().find_dotted(&target).is_none() {
+      return respond(500, "Unknown command class");
+    }
+
+    let result = match (command_class.as_str(), method) {
+      (_, "Get") => Ok(()),
+      ("50", "Reset") => self.reset_meter(&target),
+      ("37", "Set") => self.set_arg(&target, "data.level", &args, 0, |a| {
+        a.parse::<i64>().ok().map(|v| DataValue::Bool(v != 0))
+      }),
+      ("38", "Set") | ("64", "Set") | ("98", "Set") => {
+        let key = if command_class == "38" { "data.level" } else { "data.mode" };
+        self.set_arg(&target, key, &args, 0, |a| a.parse().ok().map(DataValue::Int))
+      },
+      ("67", "Set") => {
+        let key = format!("data.{}.setVal", args.get(0).map(|a| a.as_str()).unwrap_or(""));
+        self.set_arg(&target, &key, &args, 1, |a| a.parse().ok().map(DataValue::Float))
+      },
+      ("99", "Set") => self.set_user_code(&target, &args),
+      _ => return respond(500, "Unknown command"),
+    };
+
+    match result {
+      Ok(_) => respond(200, "null"),
+      Err(_) => respond(500, "Bad arguments"),
+    }
+  }
+
+  fn evaluate(&self, expression: &str) -> HttpResponse {
+    match self.simulator.get_data().query(expression) {
+      Ok(Some(holder)) => {
+        if expression.ends_with(".value") {
+          respond(200, &holder.get_value().to_json().to_string())
+        } else {
+          respond(200, &holder.to_json().to_string())
+        }
+      },
+      Ok(None) => respond(200, "null"),
+      Err(_) => respond(500, "Bad expression"),
+    }
+  }
+
+  fn set_arg<F>(&mut self, target: &str, key: &str, args: &[String], index: usize,
+                parse: F) -> Result<(), RazberryError>
+      where F: Fn(&str) -> Option<DataValue> {
+    let value = args.get(index)
+        .and_then(|a| parse(a))
+        .ok_or(RazberryError::InvalidArgument)?;
+    self.set_value(&format!("{}.{}", target, key), value)
+  }
+
+  fn reset_meter(&mut self, target: &str) -> Result<(), RazberryError> {
+    let scales = self.simulator.get_data().find_dotted(&format!("{}.data", target))
+        .map(|data| {
+          data.get_children().iter()
+              .filter(|&(_, scale)| scale.get_child("val").is_some())
+              .map(|(key, _)| key.to_string())
+              .collect::<Vec<String>>()
+        })
+        .unwrap_or(Vec::new());
+
+    for scale in scales {
+      self.set_value(&format!("{}.data.{}.val", target, scale), DataValue::Float(0.0))?;
+    }
+
+    Ok(())
+  }
+
+  fn set_user_code(&mut self, target: &str, args: &[String])
+      -> Result<(), RazberryError> {
+    let user_id = args.get(0).ok_or(RazberryError::InvalidArgument)?;
+    let code = args.get(1).ok_or(RazberryError::InvalidArgument)?;
+    let status = args.get(2).and_then(|s| s.parse::<i64>().ok())
+        .ok_or(RazberryError::InvalidArgument)?;
+
+    self.set_value(&format!("{}.data.{}.code", target, user_id),
+      DataValue::Binary(code.as_bytes().to_vec()))?;
+    self.set_value(&format!("{}.data.{}.status", target, user_id),
+      DataValue::Int(status))
+  }
+}
+
+fn respond(status: u16, body: &str) -> HttpResponse {
+  HttpResponse {
+    status: status,
+    headers: Vec::new(),
+    body: body.to_string(),
+  }
+}
+
+/// Answer a request, sleeping for any injected delay without holding the
+/// gateway. A delay longer than the read timeout fails after the timeout
+/// with `ClientError`, as `HyperTransport` does. The gateway still handles
+/// the request, as a slow gateway would, but the answer is lost.
+fn handle_shared(gateway: &Mutex<MockGateway>, request: &HttpRequest,
+                 read_timeout: Option<Duration>)
+    -> Result<HttpResponse, RazberryError> {
+  let delay = gateway.lock().map_err(|_| RazberryError::ServerError)?.take_delay();
+
+  let timed_out = match (delay, read_timeout) {
+    (Some(delay), Some(timeout)) if delay > timeout => {
+      thread::sleep(timeout);
+      true
+    },
+    (Some(delay), _) => {
+      thread::sleep(delay);
+      false
+    },
+    (None, _) => false,
+  };
+
+  let mut gateway = gateway.lock().map_err(|_| RazberryError::ServerError)?;
+  let response = gateway.handle(request);
+
+  if timed_out {
+    return Err(RazberryError::ClientError);
+  }
+
+  Ok(response)
+}
+
+/**
+ * Answers the client's requests from a mock gateway in-process, without
+ * opening a socket. The read timeout is honoured for injected delays.
+ */
+pub struct MockTransport {
+  gateway: Arc<Mutex<MockGateway>>,
+  read_timeout: Option<Duration>,
+}
+
+impl MockTransport {
+  /// Answer requests from the gateway.
+  pub fn new(gateway: Arc<Mutex<MockGateway>>) -> MockTransport {
+    MockTransport {
+      gateway: gateway,
+      read_timeout: None,
+    }
+  }
+}
+
+impl Transport for MockTransport {
+  fn send(&self, request: &HttpRequest) -> Result<HttpResponse, RazberryError> {
+    handle_shared(&self.gateway, request, self.read_timeout)
+  }
+
+  fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+    self.read_timeout = timeout;
+  }
+}
+
+/// A client of a mock gateway over a `MockTransport`, logged in with the
+/// default login and with its devices loaded.
+#[cfg(test)]
+pub fn test_client(gateway: &Arc<Mutex<MockGateway>>) -> ::client::RazberryClient {
+  let mut client = ::client::RazberryClient::for_hostname("localhost").unwrap();
+  client.set_transport(Box::new(MockTransport::new(gateway.clone())));
+  client.login("admin", "admin").unwrap();
+  client.load_devices().unwrap();
+  client
+}
+
+/**
+ * Serves a mock gateway over HTTP.
+ */
+pub struct MockServer {
+  listening: Listening,
+}
+
+impl MockServer {
+  /// Listen on an address, eg. "127.0.0.1:8083". Port 0 picks a free port.
+  pub fn start(address: &str, gateway: Arc<Mutex<MockGateway>>)
+      -> Result<MockServer, RazberryError> {
+    let mut server = Server::http(address).map_err(|_| RazberryError::ServerError)?;
+
+    // Idle keep-alive connections hold a worker thread each, which would
+    // block other clients, eg. a CLI run during a watch.
+    server.keep_alive(None);
+    let listening = server.handle(MockHandler { gateway: gateway })
+        .map_err(|_| RazberryError::ServerError)?;
+
+    Ok(MockServer { listening: listening })
+  }
+
+  /// Get the address the server is listening on.
+  pub fn get_address(&self) -> SocketAddr {
+    self.listening.socket
+  }
+
+  /// Stop serving.
+  pub fn close(mut self) -> Result<(), RazberryError> {
+    self.listening.close().map_err(|_| RazberryError::ServerError)
+  }
+}
+
+struct MockHandler {
+  gateway: Arc<Mutex<MockGateway>>,
+}
+
+impl Handler for MockHandler {
+  fn handle(&self, mut request: Request, mut response: Response) {
+    let url = match request.uri {
+      RequestUri::AbsolutePath(ref path) => path.to_string(),
+      RequestUri::AbsoluteUri(ref url) => url.to_string(),
+      _ => "/".to_string(),
+    };
+
+    let method = match request.method {
+      Method::Post => "POST",
+      _ => "GET",
+    };
+
+    let mut headers = Vec::new();
+    for header in request.headers.iter() {
+      headers.push((header.name().to_string(), header.value_string()));
+    }
+
+    let mut body = String::new();
+    let body = match request.read_to_string(&mut body) {
+      Ok(0) | Err(_) => None,
+      Ok(_) => Some(body),
+    };
+
+    // The client's own socket timeouts apply to delays.
+    let answer = handle_shared(&self.gateway, &HttpRequest {
+      method: method.to_string(),
+      url: url,
+      headers: headers,
+      body: body,
+    }, None).unwrap_or_else(|_| respond(500, "Internal Server Error"));
+
+    *response.status_mut() = StatusCode::from_u16(answer.status);
+    for (name, value) in answer.headers {
+      response.headers_mut().append_raw(name, value.into_bytes());
+    }
+
+    let _ = response.send(answer.body.as_bytes());
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use client::RazberryClient;
+  use event::Event;
+  use event::ResyncReason;
+  use simulator::Change;
+  use simulator::VirtualClass;
+
+  fn gateway() -> Arc<Mutex<MockGateway>> {
+    let json = Json::from_str(include_str!("../sample_data/data.json")).unwrap();
+    let mut gateway = MockGateway::new(&json).unwrap();
+    gateway.set_time(Some(1456036600));
+    Arc::new(Mutex::new(gateway))
+  }
+
+  #[test]
+  fn test_login() {
+    let gateway = gateway();
+    let mut client = RazberryClient::for_hostname("localhost").unwrap();
+    client.set_transport(Box::new(MockTransport::new(gateway.clone())));
+
+    assert!(client.load_devices().is_err()); // No session yet.
+
+    match client.login("admin", "wrong") {
+      Err(RazberryError::BadCredentials) => {},
+      other => panic!("Unexpected result: {:?}", other),
+    }
+
+    client.login("admin", "admin").unwrap();
+    assert!(client.get_session_token().is_some());
+    client.load_devices().unwrap();
+  }
+
+  #[test]
+  fn test_load_and_poll() {
+    let gateway = gateway();
+    let mut client = test_client(&gateway);
+    assert!(!client.get_devices().is_empty());
+
+    // Nothing has changed.
+    assert!(client.poll_updates().unwrap().is_empty());
+
+    {
+      let mut gateway = gateway.lock().unwrap();
+      gateway.advance(5).unwrap();
+      gateway.invalidate("devices.4.instances.0.commandClasses.48.data.1.level")
+          .unwrap();
+    }
+
+    let events = client.poll_updates().unwrap();
+    assert_eq!(1, events.len());
+    match events[0] {
+      Event::ValueInvalidated { ref value } => {
+        assert_eq!("4", value.device_id);
+        assert_eq!("instances.0.commandClasses.48.data.1.level", value.path);
+      },
+      ref other => panic!("Unexpected event: {}", other),
+    }
+
+    // The same second is polled again; the invalidation is not repeated.
+    assert!(client.poll_updates().unwrap().is_empty());
+  }
+
+  #[test]
+  fn test_run_mutates_state() {
+    let gateway = gateway();
+    let client = test_client(&gateway);
+
+    client.run_command("devices[4].instances[0].commandClasses[48].Get()").unwrap();
+
+    let level = client.run_command(
+        "devices[4].instances[0].commandClasses[48].data[1].level.value").unwrap();
+    assert_eq!(Json::Boolean(false), level);
+
+    gateway.lock().unwrap().set_value(
+        "devices.4.instances.0.commandClasses.48.data.1.level",
+        DataValue::Bool(true)).unwrap();
+
+    let level = client.run_command(
+        "devices[4].instances[0].commandClasses[48].data[1].level.value").unwrap();
+    assert_eq!(Json::Boolean(true), level);
+
+    assert!(client.run_command("devices[99].instances[0].commandClasses[98].Set(255)")
+        .is_err());
+  }
+
+  #[test]
+  fn test_faults() {
+    let gateway = gateway();
+    let mut client = test_client(&gateway);
+
+    gateway.lock().unwrap().inject_fault(Fault::Unauthorized);
+    match client.poll_updates() {
+      Err(RazberryError::BadCredentials) => {},
+      other => panic!("Unexpected result: {:?}", other),
+    }
+
+    gateway.lock().unwrap().inject_fault(Fault::MalformedJson);
+    assert!(client.poll_updates().is_err());
+
+    gateway.lock().unwrap().inject_fault(Fault::ServerError);
+    assert!(client.poll_updates().is_err());
+
+    // The clock goes backwards, as after a restart.
+    gateway.lock().unwrap().advance(-60).unwrap();
+    let events = client.poll_updates().unwrap();
+    match events[0] {
+      Event::Resynced { ref resync } => {
+        assert_eq!(ResyncReason::ClockRegression, resync.reason);
+      },
+      ref other => panic!("Unexpected event: {}", other),
+    }
+
+    gateway.lock().unwrap().inject_fault(Fault::Delay(Duration::from_millis(10)));
+    assert!(client.poll_updates().is_ok());
+  }
+
+  /// Answers polls with a delta whose device update cannot be applied.
+  struct BadDeltaTransport(MockTransport);
+
+  impl Transport for BadDeltaTransport {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, RazberryError> {
+      let mut response = self.0.send(request)?;
+      if request.url.contains("/ZWaveAPI/Data/") {
+        response.body = r#"{"devices.4.data.lastReceived": {"value": 0, "type": "int"},
+          "updateTime": 1456036605}"#.to_string();
+      }
+      Ok(response)
+    }
+  }
+
+  #[test]
+  fn test_bad_delta_resyncs() {
+    let gateway = gateway();
+    let mut client = RazberryClient::for_hostname("localhost").unwrap();
+    client.set_transport(Box::new(BadDeltaTransport(MockTransport::new(gateway.clone()))));
+    client.login("admin", "admin").unwrap();
+    client.load_devices().unwrap();
+
+    let events = client.poll_updates().unwrap();
+    assert_eq!(1, events.len());
+    match events[0] {
+      Event::Resynced { ref resync } => {
+        assert_eq!(ResyncReason::SuspiciousResponse, resync.reason);
+      },
+      ref other => panic!("Unexpected event: {}", other),
+    }
+  }
+
+  #[test]
+  fn test_read_timeout() {
+    let gateway = gateway();
+    let mut client = test_client(&gateway);
+    client.set_read_timeout(Some(Duration::from_millis(20)));
+
+    gateway.lock().unwrap().inject_fault(Fault::Delay(Duration::from_secs(5)));
+    let started = ::std::time::Instant::now();
+    assert!(client.poll_updates().is_err());
+    assert!(started.elapsed() < Duration::from_secs(1));
+
+    gateway.lock().unwrap().inject_fault(Fault::Delay(Duration::from_millis(10)));
+    assert!(client.poll_updates().is_ok());
+  }
+
+  #[test]
+  fn test_simulated_motion() {
+    let mut simulator = Simulator::new(1500000000);
+    simulator.add_node("2", "Hallway motion", &[VirtualClass::SensorBinary]).unwrap();
+    simulator.schedule(10, "2", Change::SensorBinary(true));
+
+    let gateway = Arc::new(Mutex::new(MockGateway::from_simulator(simulator)));
+    let mut client = test_client(&gateway);
+
+    let id = client.subscribe("devices.2.instances.0.commandClasses.48.data.1.level")
+        .unwrap();
+
+    gateway.lock().unwrap().advance(5).unwrap();
+    assert!(client.poll_updates().unwrap().is_empty());
+
+    gateway.lock().unwrap().advance(10).unwrap();
+    let events = client.poll_updates().unwrap();
+    assert_eq!(1, events.len());
+    match events[0] {
+      Event::DataChanged { ref change } => {
+        assert_eq!(id, change.subscription);
+        assert_eq!(Some(true), change.data.get_value().as_bool());
+      },
+      ref other => panic!("Unexpected event: {}", other),
+    }
+  }
+
+  #[test]
+  fn test_server() {
+    let gateway = gateway();
+    let server = MockServer::start("127.0.0.1:0", gateway.clone()).unwrap();
+
+    let port = server.get_address().port() as u32;
+    let mut client = RazberryClient::new("127.0.0.1", port).unwrap();
+
+    client.login("admin", "admin").unwrap();
+    client.load_devices().unwrap();
+    assert!(!client.get_devices().is_empty());
+
+    server.close().unwrap();
+  }
+
+  #[test]
+  fn test_server_read_timeout() {
+    let gateway = gateway();
+    let server = MockServer::start("127.0.0.1:0", gateway.clone()).unwrap();
+
+    let port = server.get_address().port() as u32;
+    let mut client = RazberryClient::new("127.0.0.1", port).unwrap();
+    client.login("admin", "admin").unwrap();
+    client.load_devices().unwrap();
+    client.set_read_timeout(Some(Duration::from_millis(100)));
+
+    gateway.lock().unwrap().inject_fault(Fault::Delay(Duration::from_secs(2)));
+    let started = ::std::time::Instant::now();
+    assert!(client.poll_updates().is_err());
+    assert!(started.elapsed() < Duration::from_secs(2));
+
+    server.close().unwrap();
+  }
+}
@@ -0,0 +1,331 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Home Assistant MQTT discovery configs for the bridge's channels.
+
+use command_classes::CommandClasses;
+use device::Device;
+use network_state::NetworkState;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use super::Channel;
+use super::MqttBridge;
+
+/// Build a retained discovery config, as (topic, payload), for each channel.
+/// Entities are grouped into one Home Assistant device per Z-Wave node.
+pub fn configs(bridge: &MqttBridge, discovery_prefix: &str, state: &NetworkState,
+               channels: &[Channel]) -> Vec<(String, String)> {
+  let home_id = state.get_data().find_dotted("controller.data.homeId")
+      .and_then(|h| h.get_value().as_int())
+      .map(|h| format!("_{:08x}", h as u32))
+      .unwrap_or(String::new());
+
+  let mut configs = Vec::new();
+
+  for channel in channels {
+    let node_id = format!("{}{}_{}", sanitize(&bridge.prefix), home_id, channel.device);
+    let object_id = sanitize(&format!("{}_{}_{}", channel.instance,
+      channel.command_class.get_name().unwrap_or("unknown"), channel.sensor));
+
+    let device = state.get_device(channel.device);
+    let state_topic = bridge.get_state_topic(channel);
+    let mut config = BTreeMap::new();
+
+    let (component, label) = match channel.command_class {
+      CommandClasses::SensorBinary => {
+        insert(&mut config, "payload_on", "true");
+        insert(&mut config, "payload_off", "false");
+        if let Some(class) = binary_device_class(channel.sensor) {
+          insert(&mut config, "device_class", class);
+        }
+        ("binary_sensor", sensor_type(channel).unwrap_or("Sensor".to_string()))
+      },
+      CommandClasses::SensorMultilevel => {
+        let unit = unit(channel);
+        if let Some(class) = multilevel_device_class(channel.sensor, &unit) {
+          insert(&mut config, "device_class", class);
+        }
+        if let Some(unit) = unit {
+          insert(&mut config, "unit_of_measurement", &unit);
+        }
+        insert(&mut config, "state_class", "measurement");
+        ("sensor", sensor_type(channel).unwrap_or("Sensor".to_string()))
+      },
+      CommandClasses::Battery => {
+        insert(&mut config, "device_class", "battery");
+        insert(&mut config, "unit_of_measurement", "%");
+        insert(&mut config, "state_class", "measurement");
+        ("sensor", "Battery".to_string())
+      },
+      CommandClasses::Meter => {
+        let unit = unit(channel);
+        let class = unit.as_ref().and_then(|u| meter_device_class(u));
+        if let Some(class) = class {
+          insert(&mut config, "device_class", class);
+        }
+        insert(&mut config, "state_class",
+          if class == Some("energy") { "total_increasing" } else { "measurement" });
+        let label = match unit {
+          Some(ref unit) => format!("Meter {}", unit),
+          None => "Meter".to_string(),
+        };
+        if let Some(unit) = unit {
+          insert(&mut config, "unit_of_measurement", &unit);
+        }
+        ("sensor", label)
+      },
+      CommandClasses::SwitchBinary => {
+        insert(&mut config, "command_topic", &format!("{}/set", state_topic));
+        insert(&mut config, "payload_on", "on");
+        insert(&mut config, "payload_off", "off");
+        insert(&mut config, "state_on", "true");
+        insert(&mut config, "state_off", "false");
+        ("switch", "Switch".to_string())
+      },
+      CommandClasses::SwitchMultilevel => {
+        insert(&mut config, "command_topic", &format!("{}/set", state_topic));
+        insert(&mut config, "payload_on", "on");
+        insert(&mut config, "payload_off", "off");
+        insert(&mut config, "state_value_template",
+          "{% if value | int > 0 %}on{% else %}off{% endif %}");
+        insert(&mut config, "brightness_state_topic", &state_topic);
+        insert(&mut config, "brightness_command_topic", &format!("{}/set", state_topic));
+        insert(&mut config, "on_command_type", "brightness");
+        config.insert("brightness_scale".to_string(), Json::U64(99));
+        ("light", "Light".to_string())
+      },
+      _ => continue,
+    };
+
+    let label = if channel.instance == "0" {
+      label
+    } else {
+      format!("{} {}", label, channel.instance)
+    };
+
+    insert(&mut config, "name", &format!("{} {}", device_name(channel.device, device), label));
+    insert(&mut config, "unique_id", &format!("{}_{}", node_id, object_id));
+    insert(&mut config, "state_topic", &state_topic);
+    insert(&mut config, "availability_mode", "all");
+    config.insert("availability".to_string(), Json::Array(vec![
+      topic_object(&bridge.get_availability_topic()),
+      topic_object(&bridge.get_device_availability_topic(channel.device)),
+    ]));
+    config.insert("device".to_string(), device_json(&node_id, channel.device, device));
+
+    configs.push((
+      format!("{}/{}/{}/{}/config", discovery_prefix, component, node_id, object_id),
+      Json::Object(config).to_string(),
+    ));
+  }
+
+  configs
+}
+
+/// Home Assistant device registry info, from the ManufacturerSpecific and
+/// Version command classes.
+fn device_json(node_id: &str, device_id: &str, device: Option<&Device>) -> Json {
+  let mut object = BTreeMap::new();
+  object.insert("identifiers".to_string(), Json::Array(vec![Json::String(node_id.to_string())]));
+  insert(&mut object, "name", &device_name(device_id, device));
+
+  if let Some(identity) = device.map(|d| d.get_identity()) {
+    if let Some(ref manufacturer) = identity.manufacturer {
+      insert(&mut object, "manufacturer", manufacturer);
+    }
+    if let Some(product) = identity.get_product_key() {
+      insert(&mut object, "model", &product.to_string());
+    }
+    if let Some(version) = identity.application_version {
+      insert(&mut object, "sw_version", &version.to_string());
+    }
+    if let Some(version) = identity.hardware_version {
+      insert(&mut object, "hw_version", &version.to_string());
+    }
+  }
+
+  Json::Object(object)
+}
+
+fn device_name(device_id: &str, device: Option<&Device>) -> String {
+  match device {
+    Some(device) if !device.name.is_empty() => device.name.to_string(),
+    _ => format!("Z-Wave device {}", device_id),
+  }
+}
+
+fn sensor_type(channel: &Channel) -> Option<String> {
+  channel.reading.get_child("sensorTypeString")
+      .and_then(|t| t.get_value().as_str())
+      .map(|t| t.to_string())
+}
+
+/// The reading's unit, in Home Assistant's spelling.
+fn unit(channel: &Channel) -> Option<String> {
+  let unit = channel.reading.get_child("scaleString")
+      .and_then(|s| s.get_value().as_str())?;
+
+  Some(match unit {
+    "Lux" => "lx".to_string(),
+    unit => unit.to_string(),
+  })
+}
+
+/// Device classes for the SensorBinary sensor types.
+fn binary_device_class(sensor_type: &str) -> Option<&'static str> {
+  match sensor_type {
+    "2" => Some("smoke"),
+    "3" => Some("carbon_monoxide"),
+    "4" => Some("gas"),
+    "5" => Some("heat"),
+    "6" => Some("moisture"),
+    "7" => Some("cold"),
+    "8" => Some("tamper"),
+    "10" => Some("door"),
+    "12" => Some("motion"),
+    _ => None, // Eg. 1, "General purpose".
+  }
+}
+
+/// Device classes for the SensorMultilevel sensor types, where the unit
+/// agrees.
+fn multilevel_device_class(sensor_type: &str, unit: &Option<String>)
+    -> Option<&'static str> {
+  let unit = unit.as_ref().map(|u| u.as_str());
+
+  match (sensor_type, unit) {
+    ("1", _) => Some("temperature"),
+    ("3", Some("lx")) => Some("illuminance"),
+    ("4", Some("W")) => Some("power"),
+    ("5", Some("%")) => Some("humidity"),
+    ("8", _) | ("9", _) => Some("pressure"),
+    ("15", Some("V")) => Some("voltage"),
+    ("16", Some("A")) => Some("current"),
+    ("17", Some("ppm")) => Some("carbon_dioxide"),
+    _ => None,
+  }
+}
+
+fn meter_device_class(unit: &str) -> Option<&'static str> {
+  match unit {
+    "kWh" => Some("energy"),
+    "W" => Some("power"),
+    "V" => Some("voltage"),
+    "A" => Some("current"),
+    _ => None,
+  }
+}
+
+fn sanitize(value: &str) -> String {
+  value.chars()
+      .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() } else { '_' })
+      .collect()
+}
+
+fn insert(object: &mut BTreeMap<String, Json>, key: &str, value: &str) {
+  object.insert(key.to_string(), Json::String(value.to_string()));
+}
+
+fn topic_object(topic: &str) -> Json {
+  let mut object = BTreeMap::new();
+  insert(&mut object, "topic", topic);
+  Json::Object(object)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::super::MqttBridge;
+  use network_state::NetworkState;
+  use rustc_serialize::json::Json;
+
+  fn configs() -> Vec<(String, Json)> {
+    let json = Json::from_str(include_str!("../../sample_data/data.json")).unwrap();
+    let state = NetworkState::from_json(&json).unwrap();
+
+    let mut bridge = MqttBridge::new("razberry");
+    bridge.set_discovery_prefix(Some("homeassistant"));
+
+    bridge.updates(&state).into_iter()
+        .filter(|m| m.topic.starts_with("homeassistant/"))
+        .map(|m| (m.topic, Json::from_str(&m.payload).unwrap()))
+        .collect()
+  }
+
+  fn find<'a>(configs: &'a [(String, Json)], topic: &str) -> &'a Json {
+    &configs.iter().find(|&&(ref t, _)| t == topic).expect(topic).1
+  }
+
+  #[test]
+  fn test_multisensor() {
+    let configs = configs();
+
+    let motion = find(&configs,
+      "homeassistant/binary_sensor/razberry_cf9fe200_4/0_sensorbinary_1/config");
+    assert_eq!(Some("Multisensor General purpose"),
+      motion.find("name").and_then(|n| n.as_string()));
+    assert_eq!(Some("razberry/4/0/SensorBinary/1"),
+      motion.find("state_topic").and_then(|t| t.as_string()));
+    assert!(motion.find("device_class").is_none());
+
+    let temperature = find(&configs,
+      "homeassistant/sensor/razberry_cf9fe200_4/0_sensormultilevel_1/config");
+    assert_eq!(Some("temperature"),
+      temperature.find("device_class").and_then(|c| c.as_string()));
+    assert_eq!(Some("°C"),
+      temperature.find("unit_of_measurement").and_then(|u| u.as_string()));
+
+    let light = find(&configs,
+      "homeassistant/sensor/razberry_cf9fe200_4/0_sensormultilevel_3/config");
+    assert_eq!(Some("illuminance"), light.find("device_class").and_then(|c| c.as_string()));
+    assert_eq!(Some("lx"), light.find("unit_of_measurement").and_then(|u| u.as_string()));
+
+    let battery = find(&configs,
+      "homeassistant/sensor/razberry_cf9fe200_4/0_battery_level/config");
+    assert_eq!(Some("battery"), battery.find("device_class").and_then(|c| c.as_string()));
+
+    let device = battery.find("device").unwrap();
+    assert_eq!(Some("razberry_cf9fe200_4"),
+      device.find("identifiers").and_then(|i| i.as_array())
+          .and_then(|i| i[0].as_string()));
+    assert_eq!(Some("Multisensor"), device.find("name").and_then(|n| n.as_string()));
+    assert!(device.find("manufacturer").is_some());
+    assert!(device.find("model").is_some());
+
+    // Only channels with values are announced.
+    assert_eq!(5, configs.len());
+  }
+
+  #[test]
+  fn test_switches() {
+    use simulator::Simulator;
+    use simulator::VirtualClass;
+
+    let mut simulator = Simulator::new(1500000000);
+    simulator.add_node("7", "Porch", &[VirtualClass::SwitchBinary]).unwrap();
+    simulator.add_node("8", "Lamp", &[VirtualClass::SwitchMultilevel]).unwrap();
+
+    let state = NetworkState::from_json(&simulator.full_json()).unwrap();
+    let mut bridge = MqttBridge::new("zwave");
+    bridge.set_discovery_prefix(Some("homeassistant"));
+
+    let messages = bridge.updates(&state);
+
+    // Configs come before states.
+    assert!(messages[0].topic.starts_with("homeassistant/"));
+
+    let switch = messages.iter()
+        .find(|m| m.topic == "homeassistant/switch/zwave_7/0_switchbinary_level/config")
+        .map(|m| Json::from_str(&m.payload).unwrap())
+        .unwrap();
+    assert_eq!(Some("zwave/7/0/SwitchBinary/level/set"),
+      switch.find("command_topic").and_then(|t| t.as_string()));
+    assert_eq!(Some("Porch Switch"), switch.find("name").and_then(|n| n.as_string()));
+
+    let light = messages.iter()
+        .find(|m| m.topic == "homeassistant/light/zwave_8/0_switchmultilevel_level/config")
+        .map(|m| Json::from_str(&m.payload).unwrap())
+        .unwrap();
+    assert_eq!(Some(99), light.find("brightness_scale").and_then(|s| s.as_u64()));
+    assert_eq!(Some("zwave/8/0/SwitchMultilevel/level"),
+      light.find("brightness_state_topic").and_then(|t| t.as_string()));
+  }
+}
@@ -0,0 +1,844 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! A bridge between the gateway and an MQTT broker.
+//!
+//! `MqttBridge` turns the client's state into retained messages on topics
+//! like "razberry/4/0/SensorMultilevel/1", with an availability topic per
+//! device, and turns messages on ".../set" topics into '/ZWaveAPI/Run'
+//! commands. It can also announce each device to Home Assistant through MQTT
+//! discovery. `MqttClient` is a minimal MQTT 3.1.1 client, at QoS 0, to carry
+//! them. The `razberry_mqtt` binary runs both.
+
+mod home_assistant;
+
+use client::RazberryClient;
+use command_classes::CommandClasses;
+use data_holder::DataHolder;
+use error::RazberryError;
+use network_state::NetworkState;
+use readings::readings;
+use rustc_serialize::json::Json;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::io::Read;
+use std::io::Write;
+use std::io;
+use std::net::Shutdown;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::mpsc::Receiver;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::mpsc::Sender;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+const CONNECT_TIMEOUT_SECS : u64 = 10;
+
+/**
+ * An MQTT application message.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+  /// The topic, eg. "razberry/4/0/SensorBinary/1".
+  pub topic: String,
+
+  /// The payload; values are JSON, eg. "true" or "19.1".
+  pub payload: String,
+
+  /// Whether the broker keeps the message for new subscribers.
+  pub retain: bool,
+}
+
+impl Message {
+  /// Construct a message.
+  pub fn new(topic: &str, payload: &str, retain: bool) -> Message {
+    Message {
+      topic: topic.to_string(),
+      payload: payload.to_string(),
+      retain: retain,
+    }
+  }
+}
+
+/**
+ * The MQTT 3.1.1 control packets used by the bridge.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Packet {
+  /// Opens a session.
+  Connect {
+    client_id: String,
+    username: Option<String>,
+    password: Option<String>,
+    keep_alive: u16,
+    will: Option<Message>,
+  },
+
+  /// Accepts a session if the return code is 0.
+  ConnAck { return_code: u8 },
+
+  /// Carries a message.
+  Publish { message: Message },
+
+  /// Asks for messages on topics matching the filters.
+  Subscribe { packet_id: u16, filters: Vec<String> },
+
+  /// Accepts a subscription.
+  SubAck { packet_id: u16 },
+
+  /// Keeps an idle connection open.
+  PingReq,
+  PingResp,
+
+  /// Closes a session cleanly.
+  Disconnect,
+}
+
+impl Packet {
+  /// Encode for the wire.
+  pub fn encode(&self) -> Vec<u8> {
+    let mut body = Vec::new();
+
+    let header = match *self {
+      Packet::Connect { ref client_id, ref username, ref password, keep_alive, ref will } => {
+        write_string(&mut body, "MQTT");
+        body.push(4); // Protocol level 3.1.1.
+
+        let mut flags = 0x02; // Clean session.
+        if let Some(ref will) = *will {
+          flags |= 0x04;
+          if will.retain {
+            flags |= 0x20;
+          }
+        }
+        if username.is_some() {
+          flags |= 0x80;
+        }
+        if password.is_some() {
+          flags |= 0x40;
+        }
+
+        body.push(flags);
+        body.push((keep_alive >> 8) as u8);
+        body.push(keep_alive as u8);
+
+        write_string(&mut body, client_id);
+        if let Some(ref will) = *will {
+          write_string(&mut body, &will.topic);
+          write_string(&mut body, &will.payload);
+        }
+        if let Some(ref username) = *username {
+          write_string(&mut body, username);
+        }
+        if let Some(ref password) = *password {
+          write_string(&mut body, password);
+        }
+        0x10
+      },
+      Packet::ConnAck { return_code } => {
+        body.push(0);
+        body.push(return_code);
+        0x20
+      },
+      Packet::Publish { ref message } => {
+        write_string(&mut body, &message.topic);
+        body.extend_from_slice(message.payload.as_bytes());
+        if message.retain { 0x31 } else { 0x30 }
+      },
+      Packet::Subscribe { packet_id, ref filters } => {
+        body.push((packet_id >> 8) as u8);
+        body.push(packet_id as u8);
+        for filter in filters {
+          write_string(&mut body, filter);
+          body.push(0); // QoS 0.
+        }
+        0x82
+      },
+      Packet::SubAck { packet_id } => {
+        body.push((packet_id >> 8) as u8);
+        body.push(packet_id as u8);
+        body.push(0);
+        0x90
+      },
+      Packet::PingReq => 0xC0,
+      Packet::PingResp => 0xD0,
+      Packet::Disconnect => 0xE0,
+    };
+
+    let mut packet = vec![header];
+    let mut length = body.len();
+
+    loop {
+      let mut byte = (length % 128) as u8;
+      length /= 128;
+      if length > 0 {
+        byte |= 0x80;
+      }
+      packet.push(byte);
+      if length == 0 {
+        break;
+      }
+    }
+
+    packet.extend(body);
+    packet
+  }
+
+  /// Read a packet sent by a broker. Packets that only a client sends are
+  /// rejected as invalid data.
+  pub fn read<R: Read>(reader: &mut R) -> io::Result<Packet> {
+    let mut header = [0u8; 1];
+    reader.read_exact(&mut header)?;
+
+    let mut length = 0usize;
+    let mut multiplier = 1usize;
+
+    for _ in 0..4 {
+      let mut byte = [0u8; 1];
+      reader.read_exact(&mut byte)?;
+      length += (byte[0] & 0x7F) as usize * multiplier;
+      multiplier *= 128;
+      if byte[0] & 0x80 == 0 {
+        break;
+      }
+    }
+
+    let mut body = vec![0u8; length];
+    reader.read_exact(&mut body)?;
+
+    let invalid = || io::Error::new(ErrorKind::InvalidData, "Malformed MQTT packet");
+
+    match header[0] >> 4 {
+      2 if body.len() == 2 => Ok(Packet::ConnAck { return_code: body[1] }),
+      3 => {
+        let qos = (header[0] >> 1) & 0x03;
+        let (topic, mut rest) = read_string(&body).ok_or_else(&invalid)?;
+        if qos > 0 {
+          rest = rest.get(2..).ok_or_else(&invalid)?; // Packet identifier.
+        }
+        let payload = String::from_utf8(rest.to_vec()).map_err(|_| invalid())?;
+        Ok(Packet::Publish {
+          message: Message {
+            topic: topic,
+            payload: payload,
+            retain: header[0] & 0x01 != 0,
+          },
+        })
+      },
+      9 if body.len() >= 2 => {
+        Ok(Packet::SubAck { packet_id: ((body[0] as u16) << 8) | body[1] as u16 })
+      },
+      12 => Ok(Packet::PingReq),
+      13 => Ok(Packet::PingResp),
+      14 => Ok(Packet::Disconnect),
+      _ => Err(invalid()),
+    }
+  }
+}
+
+fn write_string(buffer: &mut Vec<u8>, value: &str) {
+  buffer.push((value.len() >> 8) as u8);
+  buffer.push(value.len() as u8);
+  buffer.extend_from_slice(value.as_bytes());
+}
+
+fn read_string(buffer: &[u8]) -> Option<(String, &[u8])> {
+  if buffer.len() < 2 {
+    return None;
+  }
+
+  let length = ((buffer[0] as usize) << 8) | buffer[1] as usize;
+  let value = buffer.get(2..2 + length)?;
+  let value = String::from_utf8(value.to_vec()).ok()?;
+
+  Some((value, &buffer[2 + length..]))
+}
+
+/**
+ * Options for connecting to a broker.
+ */
+#[derive(Clone, Debug)]
+pub struct MqttOptions {
+  /// Identifies the client to the broker.
+  pub client_id: String,
+
+  /// Login, if the broker requires one.
+  pub username: Option<String>,
+  pub password: Option<String>,
+
+  /// Longest silence before the broker considers the client gone.
+  pub keep_alive: Duration,
+
+  /// Published by the broker if the client goes away without disconnecting.
+  pub will: Option<Message>,
+}
+
+impl MqttOptions {
+  /// Construct options with no login, a minute's keep-alive and no will.
+  pub fn new(client_id: &str) -> MqttOptions {
+    MqttOptions {
+      client_id: client_id.to_string(),
+      username: None,
+      password: None,
+      keep_alive: Duration::from_secs(60),
+      will: None,
+    }
+  }
+}
+
+/**
+ * A minimal MQTT 3.1.1 client. Messages are published and received at
+ * QoS 0; a background thread reads from the broker, and another sends
+ * PINGREQ every half keep-alive, so the connection stays open however long
+ * the caller goes between calls, eg. during a slow poll of the gateway.
+ */
+pub struct MqttClient {
+  stream: Arc<Mutex<TcpStream>>,
+  incoming: Receiver<Packet>,
+  next_packet_id: u16,
+
+  /// Dropped with the client, which stops the keep-alive thread.
+  _keep_alive: Sender<()>,
+}
+
+impl MqttClient {
+  /// Connect to a broker, eg. "127.0.0.1:1883".
+  pub fn connect(address: &str, options: &MqttOptions)
+      -> Result<MqttClient, RazberryError> {
+    let mut stream = TcpStream::connect(address)
+        .map_err(|_| RazberryError::BrokerError)?;
+
+    let connect = Packet::Connect {
+      client_id: options.client_id.clone(),
+      username: options.username.clone(),
+      password: options.password.clone(),
+      keep_alive: options.keep_alive.as_secs() as u16,
+      will: options.will.clone(),
+    };
+
+    stream.set_read_timeout(Some(Duration::from_secs(CONNECT_TIMEOUT_SECS)))
+        .and_then(|_| stream.write_all(&connect.encode()))
+        .map_err(|_| RazberryError::BrokerError)?;
+
+    match Packet::read(&mut stream) {
+      Ok(Packet::ConnAck { return_code: 0 }) => {},
+      _ => return Err(RazberryError::BrokerError),
+    }
+
+    let mut reader = stream.try_clone()
+        .and_then(|r| r.set_read_timeout(None).map(|_| r))
+        .map_err(|_| RazberryError::BrokerError)?;
+
+    let (sender, receiver) = mpsc::channel();
+
+    thread::spawn(move || {
+      while let Ok(packet) = Packet::read(&mut reader) {
+        if sender.send(packet).is_err() {
+          break;
+        }
+      }
+    });
+
+    let stream = Arc::new(Mutex::new(stream));
+    let (keep_alive, stopped) = mpsc::channel();
+
+    // A keep-alive of zero turns the mechanism off.
+    if options.keep_alive > Duration::from_secs(0) {
+      let pinger = stream.clone();
+      let interval = options.keep_alive / 2;
+
+      thread::spawn(move || {
+        while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+          let sent = match pinger.lock() {
+            Ok(mut stream) => stream.write_all(&Packet::PingReq.encode()),
+            Err(_) => break,
+          };
+          if sent.is_err() {
+            break;
+          }
+        }
+      });
+    }
+
+    Ok(MqttClient {
+      stream: stream,
+      incoming: receiver,
+      next_packet_id: 1,
+      _keep_alive: keep_alive,
+    })
+  }
+
+  /// Publish a message.
+  pub fn publish(&mut self, message: &Message) -> Result<(), RazberryError> {
+    self.send(&Packet::Publish { message: message.clone() })
+  }
+
+  /// Subscribe to a topic filter, eg. "razberry/+/+/+/set".
+  pub fn subscribe(&mut self, filter: &str) -> Result<(), RazberryError> {
+    let packet_id = self.next_packet_id;
+    self.next_packet_id = self.next_packet_id.wrapping_add(1).max(1);
+
+    self.send(&Packet::Subscribe {
+      packet_id: packet_id,
+      filters: vec![filter.to_string()],
+    })
+  }
+
+  /// Wait up to the timeout for a message on a subscribed topic.
+  pub fn recv_timeout(&mut self, timeout: Duration)
+      -> Result<Option<Message>, RazberryError> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+      let now = Instant::now();
+      if now >= deadline {
+        return Ok(None);
+      }
+
+      match self.incoming.recv_timeout(deadline - now) {
+        Ok(Packet::Publish { message }) => return Ok(Some(message)),
+        Ok(_) => continue, // Acknowledgements.
+        Err(RecvTimeoutError::Timeout) => return Ok(None),
+        Err(RecvTimeoutError::Disconnected) => return Err(RazberryError::BrokerError),
+      }
+    }
+  }
+
+  /// Disconnect cleanly, so the broker does not publish the will.
+  pub fn disconnect(self) -> Result<(), RazberryError> {
+    self.send(&Packet::Disconnect)?;
+    let stream = self.stream.lock().map_err(|_| RazberryError::BrokerError)?;
+    stream.shutdown(Shutdown::Both).map_err(|_| RazberryError::BrokerError)
+  }
+
+  fn send(&self, packet: &Packet) -> Result<(), RazberryError> {
+    let mut stream = self.stream.lock().map_err(|_| RazberryError::BrokerError)?;
+    stream.write_all(&packet.encode()).map_err(|_| RazberryError::BrokerError)
+  }
+}
+
+/**
+ * Maps the gateway state to MQTT messages, and MQTT commands to the gateway.
+ *
+ * Topics are "{prefix}/{device}/{instance}/{command class}/{sensor}", where
+ * the command class is its Z-Way name and the sensor is the sensor or meter
+ * number, or "level" for classes with a single value. Each device also has a
+ * "{prefix}/{device}/availability" topic, "online" unless Z-Way has marked
+ * it failed.
+ */
+#[derive(Clone, Debug)]
+pub struct MqttBridge {
+  prefix: String,
+
+  /// Where to announce devices to Home Assistant, or None not to.
+  discovery_prefix: Option<String>,
+
+  /// The last payload published to each topic.
+  published: HashMap<String, String>,
+}
+
+/**
+ * A value the bridge publishes.
+ */
+struct Channel<'a> {
+  device: &'a str,
+  instance: &'a str,
+  command_class: CommandClasses,
+
+  /// The sensor or meter number, or "level".
+  sensor: &'a str,
+
+  /// The holder of the value, eg. "data.1" of a binary sensor.
+  reading: &'a DataHolder,
+
+  payload: String,
+}
+
+impl MqttBridge {
+  /// Construct a bridge publishing beneath a topic prefix, eg. "razberry".
+  pub fn new(prefix: &str) -> MqttBridge {
+    MqttBridge {
+      prefix: prefix.trim_right_matches("/").to_string(),
+      discovery_prefix: None,
+      published: HashMap::new(),
+    }
+  }
+
+  /// The bridge's own availability topic. Publish "online" to it after
+  /// connecting, and set "offline" as the will.
+  pub fn get_availability_topic(&self) -> String {
+    format!("{}/bridge/availability", self.prefix)
+  }
+
+  /// The topic filters on which commands arrive.
+  pub fn get_command_filters(&self) -> Vec<String> {
+    vec![
+      format!("{}/+/+/+/set", self.prefix),
+      format!("{}/+/+/+/+/set", self.prefix),
+    ]
+  }
+
+  /// Announce devices to Home Assistant beneath a discovery prefix, usually
+  /// "homeassistant", or None not to.
+  pub fn set_discovery_prefix(&mut self, discovery_prefix: Option<&str>) {
+    self.discovery_prefix = discovery_prefix.map(|p| p.trim_right_matches("/").to_string());
+  }
+
+  /// Forget what was published, so the next `updates` repeats everything,
+  /// eg. after reconnecting to a broker that does not persist messages.
+  pub fn reset(&mut self) {
+    self.published.clear();
+  }
+
+  /// Retained messages for every value, availability and discovery config
+  /// that changed since the last call. Configs come first, so Home Assistant
+  /// knows each entity before its state arrives.
+  pub fn updates(&mut self, state: &NetworkState) -> Vec<Message> {
+    let channels = MqttBridge::channels(state);
+
+    let mut configs = match self.discovery_prefix {
+      None => Vec::new(),
+      Some(ref discovery_prefix) => {
+        home_assistant::configs(self, discovery_prefix, state, &channels)
+      },
+    };
+
+    let mut topics = MqttBridge::devices(state).into_iter()
+        .map(|(device, holder)| {
+          let failed = holder.find_dotted("data.isFailed")
+              .and_then(|f| f.get_value().as_bool())
+              .unwrap_or(false);
+          (self.get_device_availability_topic(device),
+            if failed { "offline" } else { "online" }.to_string())
+        })
+        .chain(channels.iter().map(|c| (self.get_state_topic(c), c.payload.clone())))
+        .collect::<Vec<(String, String)>>();
+
+    configs.sort();
+    topics.sort();
+
+    let mut messages = Vec::new();
+
+    for (topic, payload) in configs.into_iter().chain(topics) {
+      if self.published.get(&topic) != Some(&payload) {
+        messages.push(Message::new(&topic, &payload, true));
+        self.published.insert(topic, payload);
+      }
+    }
+
+    messages
+  }
+
+  /// Translate a message on a command topic into a '/ZWaveAPI/Run'
+  /// expression.
+  ///
+  /// On "{prefix}/{device}/{instance}/{command class}/set" the payload is a
+  /// method call, eg. "Set(255)" or "Get()", whose arguments may only be
+  /// numbers, booleans and plain quoted strings. On ".../SwitchBinary/level/
+  /// set" and ".../SwitchMultilevel/level/set" the payload is a level, or
+  /// "on" or "off".
+  pub fn command(&self, topic: &str, payload: &str) -> Result<String, RazberryError> {
+    if !topic.starts_with(&self.prefix) {
+      return Err(RazberryError::InvalidArgument);
+    }
+
+    let rest = &topic[self.prefix.len()..];
+    if !rest.starts_with("/") || !rest.ends_with("/set") {
+      return Err(RazberryError::InvalidArgument);
+    }
+
+    let parts = rest[1..rest.len() - "/set".len()].split("/").collect::<Vec<&str>>();
+
+    if parts.len() < 3 || parts.len() > 4 {
+      return Err(RazberryError::InvalidArgument);
+    }
+
+    let device = parts[0].parse::<u8>().map_err(|_| RazberryError::InvalidArgument)?;
+    let instance = parts[1].parse::<u8>().map_err(|_| RazberryError::InvalidArgument)?;
+    let command_class = parts[2].parse::<CommandClasses>()?;
+    let payload = payload.trim();
+
+    let method = match parts.get(3) {
+      None => parse_method(payload)?,
+      Some(&"level") => {
+        let level = match payload.to_lowercase().as_str() {
+          "on" | "true" => 255,
+          "off" | "false" => 0,
+          level => level.parse::<u8>().map_err(|_| RazberryError::InvalidArgument)?,
+        };
+
+        match command_class {
+          CommandClasses::SwitchBinary => format!("Set({})", if level > 0 { 255 } else { 0 }),
+          CommandClasses::SwitchMultilevel if level <= 99 || level == 255 => {
+            format!("Set({})", level)
+          },
+          _ => return Err(RazberryError::InvalidArgument),
+        }
+      },
+      Some(_) => return Err(RazberryError::InvalidArgument),
+    };
+
+    Ok(format!("devices[{}].instances[{}].commandClasses[{}].{}", device, instance,
+      command_class.to_byte(), method))
+  }
+
+  fn get_device_availability_topic(&self, device: &str) -> String {
+    format!("{}/{}/availability", self.prefix, device)
+  }
+
+  fn get_state_topic(&self, channel: &Channel) -> String {
+    format!("{}/{}/{}/{}/{}", self.prefix, channel.device, channel.instance,
+      channel.command_class.get_name().unwrap_or("Unknown"), channel.sensor)
+  }
+
+  fn devices(state: &NetworkState) -> Vec<(&str, &DataHolder)> {
+    state.get_data().find_dotted("devices")
+        .map(|d| d.get_children().iter().map(|(k, v)| (k.as_str(), v)).collect())
+        .unwrap_or(Vec::new())
+  }
+
+  fn channels<'a>(state: &'a NetworkState) -> Vec<Channel<'a>> {
+    readings(state).into_iter().map(|reading| {
+      Channel {
+        device: reading.device_id,
+        instance: reading.instance,
+        command_class: reading.command_class,
+        sensor: reading.sensor,
+        reading: reading.holder,
+        payload: reading.value.get_value().to_json().to_string(),
+      }
+    }).collect()
+  }
+}
+
+/// Parse a method call, eg. "Set(1,\"1234\",1)", reading each argument as
+/// JSON, and check it with `RazberryClient::format_method`.
+fn parse_method(method: &str) -> Result<String, RazberryError> {
+  let open = method.find("(").ok_or(RazberryError::InvalidArgument)?;
+
+  if !method.ends_with(")") {
+    return Err(RazberryError::InvalidArgument);
+  }
+
+  let args = method[open + 1..method.len() - 1].trim();
+
+  let args = if args.is_empty() {
+    Vec::new()
+  } else {
+    args.split(",")
+        .map(|arg| Json::from_str(arg.trim()).map_err(|_| RazberryError::InvalidArgument))
+        .collect::<Result<Vec<Json>, RazberryError>>()?
+  };
+
+  RazberryClient::format_method(&method[..open], &args)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use data_holder::DataValue;
+  use simulator::Change;
+  use simulator::Simulator;
+  use simulator::VirtualClass;
+
+  #[test]
+  fn test_encode_connect() {
+    let packet = Packet::Connect {
+      client_id: "rz".to_string(),
+      username: None,
+      password: None,
+      keep_alive: 60,
+      will: Some(Message::new("a", "off", true)),
+    };
+
+    assert_eq!(vec![0x10, 22, 0, 4, b'M', b'Q', b'T', b'T', 4, 0x26, 0, 60,
+      0, 2, b'r', b'z', 0, 1, b'a', 0, 3, b'o', b'f', b'f'], packet.encode());
+  }
+
+  #[test]
+  fn test_publish_round_trip() {
+    let payload = "x".repeat(200); // Two length bytes.
+    let packet = Packet::Publish {
+      message: Message::new("razberry/4/0/SensorBinary/1", &payload, true),
+    };
+
+    let encoded = packet.encode();
+    assert_eq!(vec![0x31, 101 | 0x80, 1], encoded[..3].to_vec());
+
+    assert_eq!(packet, Packet::read(&mut &encoded[..]).unwrap());
+  }
+
+  /// Read a packet sent by a client as raw bytes, for comparison with
+  /// `Packet::encode`.
+  fn read_raw(stream: &mut TcpStream) -> Vec<u8> {
+    let mut packet = vec![0u8; 2];
+    stream.read_exact(&mut packet).unwrap();
+    assert!(packet[1] & 0x80 == 0); // Short packets only.
+
+    let mut body = vec![0u8; packet[1] as usize];
+    stream.read_exact(&mut body).unwrap();
+    packet.extend(body);
+    packet
+  }
+
+  /// Accept one client on a local port, and run a fake broker on it.
+  fn fake_broker<F>(broker: F) -> (String, thread::JoinHandle<()>)
+      where F: FnOnce(TcpStream) + Send + 'static {
+    let listener = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+
+    let handle = thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+      broker(stream);
+    });
+
+    (address, handle)
+  }
+
+  #[test]
+  fn test_connection_refused() {
+    let (address, broker) = fake_broker(|mut stream| {
+      read_raw(&mut stream);
+      stream.write_all(&Packet::ConnAck { return_code: 5 }.encode()).unwrap();
+    });
+
+    match MqttClient::connect(&address, &MqttOptions::new("rz")) {
+      Err(RazberryError::BrokerError) => {},
+      other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+    }
+
+    broker.join().unwrap();
+  }
+
+  #[test]
+  fn test_client_session() {
+    let mut options = MqttOptions::new("rz");
+    options.username = Some("user".to_string());
+    options.password = Some("pass".to_string());
+    options.keep_alive = Duration::from_secs(1);
+
+    let expected_connect = Packet::Connect {
+      client_id: "rz".to_string(),
+      username: Some("user".to_string()),
+      password: Some("pass".to_string()),
+      keep_alive: 1,
+      will: None,
+    };
+
+    let (address, broker) = fake_broker(move |mut stream| {
+      assert_eq!(expected_connect.encode(), read_raw(&mut stream));
+      stream.write_all(&Packet::ConnAck { return_code: 0 }.encode()).unwrap();
+
+      let subscribe = Packet::Subscribe {
+        packet_id: 1,
+        filters: vec!["razberry/+/+/+/set".to_string()],
+      };
+      assert_eq!(subscribe.encode(), read_raw(&mut stream));
+      stream.write_all(&Packet::SubAck { packet_id: 1 }.encode()).unwrap();
+
+      let message = Message::new("razberry/2/0/SwitchBinary/set", "true", false);
+      stream.write_all(&Packet::Publish { message: message }.encode()).unwrap();
+
+      // The client pings without being called.
+      assert_eq!(Packet::PingReq, Packet::read(&mut stream).unwrap());
+      stream.write_all(&Packet::PingResp.encode()).unwrap();
+
+      loop {
+        match Packet::read(&mut stream).unwrap() {
+          Packet::PingReq => continue, // Another interval passed.
+          packet => {
+            assert_eq!(Packet::Disconnect, packet);
+            break;
+          },
+        }
+      }
+    });
+
+    let mut client = MqttClient::connect(&address, &options).unwrap();
+    client.subscribe("razberry/+/+/+/set").unwrap();
+
+    let message = client.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(Some(Message::new("razberry/2/0/SwitchBinary/set", "true", false)), message);
+
+    // Busy elsewhere for longer than half the keep-alive.
+    thread::sleep(Duration::from_millis(800));
+
+    client.disconnect().unwrap();
+    broker.join().unwrap();
+  }
+
+  #[test]
+  fn test_updates() {
+    let mut simulator = Simulator::new(1500000000);
+    simulator.add_node("2", "Hall", &[
+      VirtualClass::SensorBinary,
+      VirtualClass::SensorMultilevel { sensor_type: 1, scale: "°C".to_string() },
+    ]).unwrap();
+    simulator.add_node("3", "Lamp", &[VirtualClass::SwitchMultilevel]).unwrap();
+
+    let mut state = NetworkState::from_json(&simulator.full_json()).unwrap();
+    let mut bridge = MqttBridge::new("razberry/");
+
+    let topics = bridge.updates(&state).into_iter()
+        .map(|m| (m.topic, m.payload))
+        .collect::<Vec<(String, String)>>();
+
+    assert_eq!(vec![
+      ("razberry/2/0/SensorBinary/1".to_string(), "false".to_string()),
+      ("razberry/2/0/SensorMultilevel/1".to_string(), "0.0".to_string()),
+      ("razberry/2/availability".to_string(), "online".to_string()),
+      ("razberry/3/0/SwitchMultilevel/level".to_string(), "0".to_string()),
+      ("razberry/3/availability".to_string(), "online".to_string()),
+    ], topics);
+
+    // Only changes are published again.
+    assert!(bridge.updates(&state).is_empty());
+
+    simulator.schedule(5, "2", Change::SensorBinary(true));
+    simulator.advance(10).unwrap();
+    simulator.set_value("devices.3.data.isFailed", DataValue::Bool(true)).unwrap();
+
+    let since = state.get_update_time();
+    state.apply_delta(&simulator.delta_json(since.timestamp()), since).unwrap();
+
+    assert_eq!(vec![
+      Message::new("razberry/2/0/SensorBinary/1", "true", true),
+      Message::new("razberry/3/availability", "offline", true),
+    ], bridge.updates(&state));
+
+    bridge.reset();
+    assert_eq!(5, bridge.updates(&state).len());
+  }
+
+  #[test]
+  fn test_command() {
+    let bridge = MqttBridge::new("razberry");
+
+    assert_eq!("devices[3].instances[0].commandClasses[38].Set(40)",
+      bridge.command("razberry/3/0/SwitchMultilevel/level/set", "40").unwrap());
+    assert_eq!("devices[5].instances[0].commandClasses[37].Set(255)",
+      bridge.command("razberry/5/0/SwitchBinary/level/set", "ON").unwrap());
+    assert_eq!("devices[7].instances[1].commandClasses[99].Set(1,\"1234\",1)",
+      bridge.command("razberry/7/1/99/set", "Set(1,\"1234\",1)").unwrap());
+
+    for &(topic, payload) in &[
+      ("razberry/3/0/SwitchMultilevel/level/set", "100"),
+      ("razberry/3/0/SensorBinary/level/set", "on"),
+      ("razberry/3/0/Nonsense/set", "Get()"),
+      ("razberry/x/0/SwitchBinary/set", "Get()"),
+      ("other/3/0/SwitchBinary/set", "Get()"),
+      ("razberry/3/0/SwitchBinary/set", "Set(require(\"fs\"))"),
+      ("razberry/3/0/SwitchBinary/set", "Set(\"a\"+\"b\")"),
+      ("razberry/3/0/SwitchBinary/set", "x.Set(1)"),
+      ("razberry/3/0/SwitchBinary/set", "Set(NaN)"),
+      ("razberry/3/0/SwitchBinary/set", "Set(inf)"),
+      ("razberry/3/0/SwitchBinary/set", "Set(1e999)"),
+    ] {
+      assert!(bridge.command(topic, payload).is_err(), "{} {}", topic, payload);
+    }
+  }
+}
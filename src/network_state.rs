@@ -0,0 +1,599 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::NaiveDateTime;
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use command_class::user_code::remove_codes;
+use data_holder::DataHolder;
+use device::Device;
+use device_update::DeviceUpdate;
+use error::RazberryError;
+use event::Event;
+use event::ResyncReason;
+use rustc_serialize::json::Json;
+use sensors::BurglarAlarmData;
+use sensors::GeneralPurposeBinaryData;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+/// Version of the snapshot layout written by `to_snapshot`.
+const SNAPSHOT_VERSION : i64 = 1i64;
+
+/**
+ * The state of a Z-Way network: the raw data tree and the typed devices
+ * derived from it. Both are built from the same '/ZWaveAPI/Data' payload and
+ * advanced together by each '/ZWaveAPI/Data/{timestamp}' delta, so they never
+ * disagree about how current they are.
+ */
+#[derive(Clone, Debug)]
+pub struct NetworkState {
+  /// The raw data tree.
+  data: DataHolder,
+
+  /// Devices keyed by device ID.
+  devices: HashMap<String, Device>,
+
+  /// Gateway time of the last payload applied ("updateTime").
+  update_time: DateTime<UTC>,
+}
+
+impl NetworkState {
+  /// Build from a full '/ZWaveAPI/Data' payload.
+  pub fn from_json(json: &Json) -> Result<NetworkState, RazberryError> {
+    let devices_json = json.find("devices")
+        .and_then(|d| d.as_object())
+        .ok_or(RazberryError::BadResponse)?;
+
+    let mut devices = HashMap::new();
+
+    for (device_id, device_json) in devices_json {
+      let device = Device::initialize_from_json(device_id, &device_json)?;
+      devices.insert(device_id.to_string(), device);
+    }
+
+    Ok(NetworkState {
+      data: DataHolder::from_json(json)?,
+      devices: devices,
+      update_time: parse_update_time(json)?,
+    })
+  }
+
+  /// Build from a full '/ZWaveAPI/Data' payload, tolerating what `from_json`
+  /// rejects. A missing "devices" object is treated as empty, and devices
+  /// that cannot be typed are left out of `get_devices`, though their data
+  /// stays in the tree. Only "updateTime" is required.
+  pub fn from_json_lenient(json: &Json) -> Result<NetworkState, RazberryError> {
+    let mut devices = HashMap::new();
+
+    if let Some(devices_json) = json.find("devices").and_then(|d| d.as_object()) {
+      for (device_id, device_json) in devices_json {
+        if let Ok(device) = Device::initialize_from_json(device_id, &device_json) {
+          devices.insert(device_id.to_string(), device);
+        }
+      }
+    }
+
+    Ok(NetworkState {
+      data: DataHolder::from_json(json)?,
+      devices: devices,
+      update_time: parse_update_time(json)?,
+    })
+  }
+
+  /// Restore from a snapshot written by `to_snapshot`. Returns the state and
+  /// the time the snapshot was saved.
+  pub fn from_snapshot(json: &Json)
+      -> Result<(NetworkState, DateTime<UTC>), RazberryError> {
+    let version = json.find("version").and_then(|v| v.as_i64());
+
+    if version != Some(SNAPSHOT_VERSION) {
+      return Err(RazberryError::BadSnapshot);
+    }
+
+    let saved_at = parse_time(json, "savedAt")
+        .map_err(|_| RazberryError::BadSnapshot)?;
+    let update_time = parse_time(json, "updateTime")
+        .map_err(|_| RazberryError::BadSnapshot)?;
+
+    let data = json.find("data").ok_or(RazberryError::BadSnapshot)?;
+    let mut state = NetworkState::from_json(data)
+        .map_err(|_| RazberryError::BadSnapshot)?;
+
+    state.update_time = update_time;
+
+    Ok((state, saved_at))
+  }
+
+  /// Save as a versioned JSON snapshot. The devices, their command classes
+  /// and the controller are all derived from the data tree, so the snapshot
+  /// holds the tree and they are rebuilt from it on restore.
+  ///
+  /// User codes (see `user_code::remove_codes`) are left out, so a restored
+  /// state has none until the gateway reports them again.
+  pub fn to_snapshot(&self, saved_at: DateTime<UTC>) -> Json {
+    let mut data = self.data.to_json();
+    remove_codes(&mut data, &[]);
+
+    let mut object = BTreeMap::new();
+
+    object.insert("version".to_string(), Json::I64(SNAPSHOT_VERSION));
+    object.insert("savedAt".to_string(), Json::I64(saved_at.timestamp()));
+    object.insert("updateTime".to_string(),
+      Json::I64(self.update_time.timestamp()));
+    object.insert("data".to_string(), data);
+
+    Json::Object(object)
+  }
+
+  /// Check that a '/ZWaveAPI/Data/{since}' delta payload can be trusted to
+  /// carry every update since the state was last advanced. Returns why the
+  /// state should instead be reloaded in full, if it should.
+  pub fn check_delta(&self, json: &Json, since: DateTime<UTC>)
+      -> Option<ResyncReason> {
+    if since > self.update_time {
+      return Some(ResyncReason::Gap);
+    }
+
+    let object = match json.as_object() {
+      None => return Some(ResyncReason::SuspiciousResponse),
+      Some(o) => o,
+    };
+
+    let update_time = match parse_update_time(json) {
+      Err(_) => return Some(ResyncReason::SuspiciousResponse),
+      Ok(t) => t,
+    };
+
+    if update_time < self.update_time {
+      return Some(ResyncReason::ClockRegression);
+    }
+
+    if object.contains_key("devices") {
+      return Some(ResyncReason::SuspiciousResponse); // A full data dump.
+    }
+
+    for (key, value) in object {
+      if key == "updateTime" {
+        continue;
+      }
+
+      if !value.is_object() {
+        return Some(ResyncReason::SuspiciousResponse);
+      }
+
+      let mut path = key.split(".");
+      if path.next() == Some("devices") {
+        match path.next() {
+          Some(id) if self.devices.contains_key(id) => {},
+          Some(id) => {
+            return Some(ResyncReason::UnknownDevice { device_id: id.to_string() });
+          },
+          None => return Some(ResyncReason::SuspiciousResponse),
+        }
+      }
+    }
+
+    None
+  }
+
+  /// Remove the entries of a '/ZWaveAPI/Data/{since}' delta payload that the
+  /// state has already applied.
+  ///
+  /// "updateTime" has a resolution of one second, and each poll asks for
+  /// updates from the second of the last, so updates landing in that second
+  /// are delivered again. An entry whose holder already matches the tree,
+  /// times included, is such a redelivery. A genuinely new update in the
+  /// same second changes the value or a time, and is kept.
+  pub fn dedupe_delta(&self, json: &Json) -> Result<Json, RazberryError> {
+    let object = json.as_object().ok_or(RazberryError::BadResponse)?;
+
+    let mut deduped = BTreeMap::new();
+
+    for (key, value) in object {
+      if value.is_object() {
+        let holder = DataHolder::from_json(value)?;
+        let redelivered = self.data.find_dotted(key)
+            .map_or(false, |existing| existing.includes(&holder));
+
+        if redelivered {
+          continue;
+        }
+      }
+      deduped.insert(key.to_string(), value.clone());
+    }
+
+    Ok(Json::Object(deduped))
+  }
+
+  /// Apply a '/ZWaveAPI/Data/{since}' delta payload to both the data tree
+  /// and the devices, returning the discrete events it contained. Entries
+  /// already applied are skipped (see `dedupe_delta`), so applying the same
+  /// delta twice reports its events once.
+  ///
+  /// A delta requested from after the state's update time could have
+  /// skipped updates, and is rejected with `PossibleMissingEvents`. A delta
+  /// older than the state is ignored. A delta that cannot be applied in full
+  /// changes nothing.
+  pub fn apply_delta(&mut self, json: &Json, since: DateTime<UTC>)
+      -> Result<Vec<Event>, RazberryError> {
+    if since > self.update_time {
+      return Err(RazberryError::PossibleMissingEvents);
+    }
+
+    let update_time = parse_update_time(json)?;
+
+    if update_time < self.update_time {
+      return Ok(Vec::new()); // No new updates to pick up.
+    }
+
+    let json = self.dedupe_delta(json)?;
+    let updates = DeviceUpdate::parse_updates(&json)?;
+
+    let mut events = Vec::new();
+    let mut updated = Vec::new();
+
+    // Update copies, so a bad update leaves every device as it was.
+    for (device_id, updates) in updates {
+      let mut device = match self.devices.get(&device_id) {
+        None => continue, // Perhaps a new device was added. We must ignore.
+        Some(device) => device.clone(),
+      };
+
+      let previous = self.data.find(&["devices", &device_id]);
+      events.extend(device.process_updates(updates, previous)?);
+      updated.push((device_id, device));
+    }
+
+    // Every holder was parsed by `dedupe_delta`, so this cannot fail.
+    self.data.apply_delta(&json)?;
+    self.devices.extend(updated);
+    self.update_time = update_time;
+
+    Ok(events)
+  }
+
+  /// Get the raw data tree.
+  pub fn get_data(&self) -> &DataHolder {
+    &self.data
+  }
+
+  /// Get the devices.
+  pub fn get_devices(&self) -> Vec<&Device> {
+    self.devices.values().collect()
+  }
+
+  /// Get a device by ID.
+  pub fn get_device(&self, device_id: &str) -> Option<&Device> {
+    self.devices.get(device_id)
+  }
+
+  /// Get the gateway time of the last payload applied.
+  pub fn get_update_time(&self) -> DateTime<UTC> {
+    self.update_time
+  }
+
+  /// Get "burglar alarm" sensor data (0x71, payload 7), if present.
+  pub fn get_burglar_alarm(&self, device: u8, instance: u8)
+      -> Option<BurglarAlarmData> {
+    let path = format!("devices.{}.instances.{}.commandClasses.113.data.7",
+      device, instance);
+
+    self.data.find_dotted(&path)
+        .map(|holder| BurglarAlarmData::new(&holder.to_json()))
+  }
+
+  /// Get the "general purpose" (0x01) binary sensor (0x30) data, if present.
+  pub fn get_general_purpose_binary(&self, device: u8, instance: u8)
+      -> Option<GeneralPurposeBinaryData> {
+    let path = format!("devices.{}.instances.{}.commandClasses.48.data.1",
+      device, instance);
+
+    self.data.find_dotted(&path)
+        .map(|holder| GeneralPurposeBinaryData::new(&holder.to_json()))
+  }
+}
+
+/// Parse the "updateTime" of either payload.
+fn parse_update_time(json: &Json) -> Result<DateTime<UTC>, RazberryError> {
+  parse_time(json, "updateTime")
+}
+
+fn parse_time(json: &Json, key: &str) -> Result<DateTime<UTC>, RazberryError> {
+  let timestamp = json.find(key)
+      .and_then(|t| t.as_i64())
+      .ok_or(RazberryError::BadResponse)?;
+
+  Ok(DateTime::from_utc(NaiveDateTime::from_timestamp(timestamp, 0), UTC))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn at(timestamp: i64) -> DateTime<UTC> {
+    DateTime::from_utc(NaiveDateTime::from_timestamp(timestamp, 0), UTC)
+  }
+
+  fn load() -> NetworkState {
+    let json = Json::from_str(include_str!("../sample_data/data.json")).unwrap();
+    NetworkState::from_json(&json).unwrap()
+  }
+
+  #[test]
+  fn test_from_json() {
+    let state = load();
+
+    assert_eq!(1456036521, state.get_update_time().timestamp());
+    assert!(!state.get_devices().is_empty());
+
+    // Every typed device has its raw subtree.
+    for device in state.get_devices() {
+      assert!(state.get_data().find(&["devices", &device.id]).is_some());
+    }
+  }
+
+  #[test]
+  fn test_apply_delta() {
+    let mut state = load();
+    let since = state.get_update_time();
+
+    let json = Json::from_str(include_str!("../sample_data/data-device-updates.json"))
+        .unwrap();
+    let update_time = json.find("updateTime").unwrap().as_i64().unwrap();
+
+    state.apply_delta(&json, since).unwrap();
+    assert_eq!(update_time, state.get_update_time().timestamp());
+
+    // Replaying the same delta changes nothing.
+    assert!(state.apply_delta(&json, since).unwrap().is_empty());
+    assert_eq!(update_time, state.get_update_time().timestamp());
+  }
+
+  #[test]
+  fn test_dedupe_delta() {
+    let mut state = load();
+    let since = state.get_update_time();
+
+    // The sample delta was requested from before the full payload was taken,
+    // so the full payload already holds every entry.
+    let json = Json::from_str(include_str!("../sample_data/data-device-updates.json"))
+        .unwrap();
+    let deduped = state.dedupe_delta(&json).unwrap();
+    assert_eq!(vec!["updateTime"],
+      deduped.as_object().unwrap().keys().collect::<Vec<_>>());
+
+    let data = state.get_data().find(&["devices"]).cloned();
+    assert!(state.apply_delta(&json, since).unwrap().is_empty());
+    assert_eq!(data.as_ref(), state.get_data().find(&["devices"]));
+  }
+
+  #[test]
+  fn test_redelivered_updates_are_not_reported_again() {
+    let mut state = load();
+    let since = state.get_update_time();
+
+    // The sample delta, with the invalidation of a sensor level in place of
+    // the sensor's entry.
+    let mut json = Json::from_str(include_str!("../sample_data/data-device-updates.json"))
+        .unwrap();
+    json.as_object_mut().unwrap()
+        .remove("devices.4.instances.0.commandClasses.48.data.1");
+    json.as_object_mut().unwrap().insert(
+      "devices.4.instances.0.commandClasses.48.data.1.level".to_string(),
+      Json::from_str(r#"
+        {
+          "value": true,
+          "type": "bool",
+          "invalidateTime": 1456036634,
+          "updateTime": 1456036226
+        }
+      "#).unwrap());
+
+    assert_eq!(1, state.apply_delta(&json, since).unwrap().len());
+    assert_eq!(1456036634, state.get_update_time().timestamp());
+
+    // The next poll asks for updates from the same second, and the gateway
+    // delivers the invalidation again along with a new update in that second.
+    json.as_object_mut().unwrap().insert(
+      "updateTime".to_string(), Json::I64(1456036635));
+    json.as_object_mut().unwrap().insert(
+      "devices.4.data.isAwake".to_string(),
+      Json::from_str(r#"{ "value": true, "type": "bool", "updateTime": 1456036634 }"#)
+          .unwrap());
+
+    let deduped = state.dedupe_delta(&json).unwrap();
+    assert_eq!(vec!["devices.4.data.isAwake", "updateTime"],
+      deduped.as_object().unwrap().keys().collect::<Vec<_>>());
+
+    assert!(state.apply_delta(&json, at(1456036634)).unwrap().is_empty());
+    assert_eq!(1456036635, state.get_update_time().timestamp());
+    assert_eq!(Some(true),
+      state.get_data().find_value(&["devices", "4", "data", "isAwake"])
+          .and_then(|v| v.as_bool()));
+  }
+
+  #[test]
+  fn test_stale_value_is_reported_once() {
+    let mut state = load();
+    let since = state.get_update_time();
+
+    let sensor = |level_update_time: i64| {
+      Json::from_str(&format!(r#"
+        {{
+          "value": null,
+          "type": "empty",
+          "updateTime": 1456036000,
+          "level": {{
+            "value": true,
+            "type": "bool",
+            "invalidateTime": 1456036600,
+            "updateTime": 1456036226
+          }},
+          "icon": {{
+            "value": "motion",
+            "type": "string",
+            "updateTime": {}
+          }}
+        }}
+      "#, level_update_time)).unwrap()
+    };
+
+    let delta = |update_time: i64, level_update_time: i64| {
+      let mut object = ::std::collections::BTreeMap::new();
+      object.insert("updateTime".to_string(), Json::I64(update_time));
+      object.insert("devices.4.instances.0.commandClasses.48.data.1".to_string(),
+        sensor(level_update_time));
+      Json::Object(object)
+    };
+
+    let events = state.apply_delta(&delta(1456036700, 1456036650), since).unwrap();
+    assert_eq!(1, events.len());
+
+    // The parent subtree is delivered again because a sibling changed. The
+    // level is still invalid, but it did not become so in this delta.
+    let events = state.apply_delta(&delta(1456036800, 1456036750), at(1456036700))
+        .unwrap();
+    assert!(events.is_empty());
+  }
+
+  #[test]
+  fn test_bad_delta_changes_nothing() {
+    // Devices are updated in no set order, so break either one.
+    for &(good, bad) in &[("1", "4"), ("4", "1")] {
+      let mut state = load();
+      let since = state.get_update_time();
+      let before = state.clone();
+
+      // One device is heard from, but the other's update has no "updateTime".
+      let delta = Json::from_str(&format!(r#"
+        {{
+          "devices.{}.data.lastReceived": {{
+            "value": 0, "type": "int", "updateTime": 1456036700
+          }},
+          "devices.{}.data.lastReceived": {{"value": 0, "type": "int"}},
+          "updateTime": 1456036700
+        }}
+      "#, good, bad)).unwrap();
+
+      assert!(state.apply_delta(&delta, since).is_err());
+      assert_eq!(before.get_update_time(), state.get_update_time());
+      assert_eq!(before.get_data(), state.get_data());
+      assert_eq!(before.get_device(good).map(|d| d.last_contacted),
+        state.get_device(good).map(|d| d.last_contacted));
+    }
+  }
+
+  #[test]
+  fn test_snapshot() {
+    let mut state = load();
+    let since = state.get_update_time();
+
+    let json = Json::from_str(include_str!("../sample_data/data-device-updates.json"))
+        .unwrap();
+    state.apply_delta(&json, since).unwrap();
+
+    let snapshot = state.to_snapshot(at(1456040000)).to_string();
+    let (restored, saved_at) =
+        NetworkState::from_snapshot(&Json::from_str(&snapshot).unwrap()).unwrap();
+
+    assert_eq!(1456040000, saved_at.timestamp());
+    assert_eq!(state.get_update_time(), restored.get_update_time());
+    assert_eq!(state.get_data(), restored.get_data());
+
+    let mut ids = state.get_devices().iter()
+        .map(|d| (d.id.clone(), d.name.clone(), d.supported_command_classes.clone()))
+        .collect::<Vec<_>>();
+    let mut restored_ids = restored.get_devices().iter()
+        .map(|d| (d.id.clone(), d.name.clone(), d.supported_command_classes.clone()))
+        .collect::<Vec<_>>();
+    ids.sort_by(|a, b| a.0.cmp(&b.0));
+    restored_ids.sort_by(|a, b| a.0.cmp(&b.0));
+    assert_eq!(ids, restored_ids);
+  }
+
+  #[test]
+  fn test_snapshot_omits_user_codes() {
+    let mut state = load();
+    let since = state.get_update_time();
+
+    let json = Json::from_str(r#"
+      {
+        "updateTime": 1456036600,
+        "devices.4.instances.0.commandClasses.99.data.1": {
+          "value": null,
+          "type": "empty",
+          "code": { "value": "1234", "type": "string", "updateTime": 1456036600 },
+          "status": { "value": 1, "type": "int", "updateTime": 1456036600 }
+        }
+      }
+    "#).unwrap();
+    state.apply_delta(&json, since).unwrap();
+
+    let snapshot = state.to_snapshot(at(1456040000)).to_string();
+    assert!(!snapshot.contains("1234"));
+
+    let (restored, _) =
+        NetworkState::from_snapshot(&Json::from_str(&snapshot).unwrap()).unwrap();
+    let slot = ["devices", "4", "instances", "0", "commandClasses", "99", "data", "1"];
+    assert!(restored.get_data().find(&slot).unwrap().get_child("code").is_none());
+    assert!(restored.get_data().find(&slot).unwrap().get_child("status").is_some());
+  }
+
+  #[test]
+  fn test_snapshot_version() {
+    let state = load();
+
+    let mut json = state.to_snapshot(at(1456040000));
+    json.as_object_mut().unwrap().insert("version".to_string(), Json::I64(2));
+
+    match NetworkState::from_snapshot(&json) {
+      Err(RazberryError::BadSnapshot) => {},
+      other => panic!("Unexpected result: {:?}", other),
+    }
+  }
+
+  #[test]
+  fn test_check_delta() {
+    let state = load();
+    let since = state.get_update_time();
+
+    let json = Json::from_str(include_str!("../sample_data/data-device-updates.json"))
+        .unwrap();
+    assert_eq!(None, state.check_delta(&json, since));
+
+    assert_eq!(Some(ResyncReason::Gap),
+      state.check_delta(&json, at(since.timestamp() + 1)));
+
+    let json = Json::from_str(r#"{ "updateTime": 1456030000 }"#).unwrap();
+    assert_eq!(Some(ResyncReason::ClockRegression), state.check_delta(&json, since));
+
+    let full = Json::from_str(include_str!("../sample_data/data.json")).unwrap();
+    assert_eq!(Some(ResyncReason::SuspiciousResponse),
+      state.check_delta(&full, since));
+
+    let json = Json::from_str(r#"{ "devices.4.data.isAwake": 1, "updateTime": 1456036600 }"#).unwrap();
+    assert_eq!(Some(ResyncReason::SuspiciousResponse), state.check_delta(&json, since));
+
+    let json = Json::from_str(r#"
+      {
+        "devices.99.data.isAwake": { "value": true, "type": "bool" },
+        "updateTime": 1456036600
+      }
+    "#).unwrap();
+    assert_eq!(Some(ResyncReason::UnknownDevice { device_id: "99".to_string() }),
+      state.check_delta(&json, since));
+  }
+
+  #[test]
+  fn test_apply_delta_gap() {
+    let mut state = load();
+    let later = at(state.get_update_time().timestamp() + 60);
+
+    let json = Json::from_str(r#"{ "updateTime": 2000000000 }"#).unwrap();
+
+    match state.apply_delta(&json, later) {
+      Err(RazberryError::PossibleMissingEvents) => {},
+      other => panic!("Unexpected result: {:?}", other),
+    }
+  }
+}
@@ -0,0 +1,50 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use error::RazberryError;
+use event::Event;
+use std::time::Duration;
+
+/**
+ * Counters describing the health of a client's polling, for monitoring.
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PollStats {
+  /// Calls to `poll_updates`.
+  pub polls: u64,
+
+  /// Polls that returned an error.
+  pub errors: u64,
+
+  /// Polls that reloaded the state in full.
+  pub resyncs: u64,
+
+  /// Time spent in every poll, including failed ones.
+  pub total_duration: Duration,
+
+  /// Time spent in the most recent poll.
+  pub last_duration: Option<Duration>,
+}
+
+impl PollStats {
+  /// Count a poll and its outcome.
+  pub fn record(&mut self, duration: Duration,
+                result: &Result<Vec<Event>, RazberryError>) {
+    self.polls += 1;
+    self.total_duration += duration;
+    self.last_duration = Some(duration);
+
+    match *result {
+      Err(_) => self.errors += 1,
+      Ok(ref events) => {
+        let resynced = events.iter().any(|e| match *e {
+          Event::Resynced { .. } => true,
+          _ => false,
+        });
+
+        if resynced {
+          self.resyncs += 1;
+        }
+      },
+    }
+  }
+}
@@ -0,0 +1,96 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use command_classes::CommandClasses;
+use data_holder::DataHolder;
+use network_state::NetworkState;
+
+/// Command classes with readings, and the value key of each.
+const READING_CLASSES : &'static [(CommandClasses, &'static str)] = &[
+  (CommandClasses::SwitchBinary, "level"),
+  (CommandClasses::SwitchMultilevel, "level"),
+  (CommandClasses::SensorBinary, "level"),
+  (CommandClasses::SensorMultilevel, "val"),
+  (CommandClasses::Meter, "val"),
+  (CommandClasses::Battery, "last"),
+];
+
+/**
+ * A current value reported by a device, for exporting.
+ */
+pub struct Reading<'a> {
+  pub device_id: &'a str,
+  pub instance: &'a str,
+  pub command_class: CommandClasses,
+
+  /// The sensor or meter number, or "level" for classes with one value.
+  pub sensor: &'a str,
+
+  /// The holder of the reading, eg. "data.1" of a binary sensor, which also
+  /// holds "sensorTypeString" and "scaleString".
+  pub holder: &'a DataHolder,
+
+  /// The holder of the value, eg. "data.1.level".
+  pub value: &'a DataHolder,
+}
+
+/// Get every sensor, meter, battery and switch value in the state, ordered
+/// by path. Values not yet reported are left out.
+pub fn readings<'a>(state: &'a NetworkState) -> Vec<Reading<'a>> {
+  let mut readings = Vec::new();
+
+  let devices = match state.get_data().find_dotted("devices") {
+    None => return readings,
+    Some(devices) => devices,
+  };
+
+  for (device_id, device) in devices.get_children() {
+    let instances = match device.get_child("instances") {
+      None => continue,
+      Some(instances) => instances,
+    };
+
+    for (instance, instance_holder) in instances.get_children() {
+      for &(command_class, key) in READING_CLASSES {
+        let path = format!("commandClasses.{}.data", command_class.to_byte());
+        let data = match instance_holder.find_dotted(&path) {
+          None => continue,
+          Some(data) => data,
+        };
+
+        let holders = match command_class {
+          CommandClasses::SensorBinary | CommandClasses::SensorMultilevel
+              | CommandClasses::Meter => {
+            data.get_children().iter()
+                .filter(|&(sensor, _)| sensor.parse::<u32>().is_ok())
+                .map(|(sensor, holder)| (sensor.as_str(), holder))
+                .collect()
+          },
+          _ => vec![("level", data)],
+        };
+
+        for (sensor, holder) in holders {
+          let value = match holder.get_child(key) {
+            Some(value) if !value.get_value().is_empty() => value,
+            _ => continue,
+          };
+
+          readings.push(Reading {
+            device_id: device_id,
+            instance: instance,
+            command_class: command_class,
+            sensor: sensor,
+            holder: holder,
+            value: value,
+          });
+        }
+      }
+    }
+  }
+
+  readings
+}
+
+/// Get a string child of a reading, eg. "sensorTypeString" or "scaleString".
+pub fn get_string<'a>(reading: &Reading<'a>, key: &str) -> Option<&'a str> {
+  reading.holder.get_child(key).and_then(|c| c.get_value().as_str())
+}
@@ -1,8 +1,14 @@
 // Copyright (c) 2016 Brandon Thomas <bt@brand.io>
 
+#![allow(deprecated)]
+
+use chrono::NaiveDateTime;
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use error::RazberryError;
+use network_state::NetworkState;
 use rustc_serialize::json::BuilderError;
 use rustc_serialize::json::Json;
-use rustc_serialize::json::Object;
 use sensors::BurglarAlarmData;
 use sensors::GeneralPurposeBinaryData;
 
@@ -12,9 +18,16 @@ pub type ParseError = BuilderError;
 /// A response from Razberry's /ZWaveAPI/Data endpoint.
 /// These responses contain the entire state of the gateway at the time
 /// of the request.
+///
+/// XXX: DEPRECATED. A wrapper over the `NetworkState` built from the
+/// payload. Use `RazberryClient::get_state`.
+#[deprecated]
 #[derive(Clone)]
 pub struct GatewayState {
-  /// Internal data.
+  /// The data tree and the typed devices, built leniently from the payload.
+  state: NetworkState,
+
+  /// The tree of `state` in the payload layout, rebuilt after each merge.
   json: Json,
 
   /// The end of the state change range (gateway server time when the
@@ -25,6 +38,9 @@ pub struct GatewayState {
 /// A response from Razberry's /ZWaveAPI/Data/{timestamp} endpoint.
 /// These responses contain only updates that ocurred after the
 /// requested timestamp.
+///
+/// XXX: DEPRECATED. Deltas are applied by `NetworkState::apply_delta`.
+#[deprecated]
 #[derive(Clone)]
 pub struct PartialGatewayState {
   /// Internal data.
@@ -58,7 +74,9 @@ pub enum ResponseError {
 }
 
 impl GatewayState {
-  /// Build from a raw JSON string.
+  /// Build from a raw JSON string. Only "updateTime" is required; devices
+  /// that cannot be typed are left out of `get_devices`, but stay in the
+  /// data tree.
   pub fn build(raw_json: &str) -> Result<GatewayState, ResponseError> {
     let json = try!(parse_json(raw_json));
 
@@ -66,13 +84,22 @@ impl GatewayState {
                          .and_then(|t| t.as_i64())
                          .ok_or(ResponseError::MissingTimestamp));
 
+    let state = NetworkState::from_json_lenient(&json)
+        .map_err(|_| ResponseError::MalformedResponse)?;
+
     Ok(GatewayState {
-      json: json,
+      json: to_payload(&state, timestamp),
+      state: state,
       end_timestamp: timestamp,
     })
   }
 
-  /// Get a reference to the underlying Json.
+  /// Get the underlying network state.
+  pub fn get_state(&self) -> &NetworkState {
+    &self.state
+  }
+
+  /// Get the data tree as a '/ZWaveAPI/Data' payload.
   pub fn get_json(&self) -> &Json {
     &self.json
   }
@@ -82,7 +109,8 @@ impl GatewayState {
     self.end_timestamp
   }
 
-  /// TODO DOC
+  /// Merge a partial state into this one with `NetworkState::apply_delta`.
+  /// Fails if updates between the two could have been missed.
   pub fn merge(&mut self, partial_state: &PartialGatewayState)
       -> Result<(), ResponseError> {
     if partial_state.get_start_timestamp() > self.end_timestamp {
@@ -93,119 +121,35 @@ impl GatewayState {
       return Ok(());
     }
 
-    let updated_values_obj = match partial_state.get_json().as_object() {
-      None => { return Err(ResponseError::MalformedResponse); },
-      Some(j) => j,
-    };
+    let since = DateTime::from_utc(
+        NaiveDateTime::from_timestamp(partial_state.get_start_timestamp(), 0),
+        UTC);
 
-    for (key, updated_json_val) in updated_values_obj.iter() {
-      if key != "updateTime" {
-        self.merge_updated_values(key, updated_json_val);
-      }
-    }
+    self.state.apply_delta(partial_state.get_json(), since)
+        .map_err(|e| match e {
+          RazberryError::PossibleMissingEvents =>
+              ResponseError::PossibleMissingEvents,
+          _ => ResponseError::MalformedResponse,
+        })?;
 
     self.end_timestamp = partial_state.get_end_timestamp();
+    self.json = to_payload(&self.state, self.end_timestamp);
 
     Ok(())
   }
 
-  // TODO: This seriously needs testing.
-  /// Merge updates into the JSON tree.
-  ///
-  /// The 'device_key' encodes a tree traversal path of the form,
-  ///
-  ///   "devices.1.instances.0.commandClasses.32.data.level"
-  ///
-  /// The 'updated_json' contains updated keys (not a full leaf
-  /// replacement) at that subtree, eg.
-  ///
-  ///   { "value": 255, "updateTime": 1456647251 }
-  ///
-  fn merge_updated_values(&mut self, device_key: &str, updated_json: &Json) {
-    let updated_object = match updated_json.as_object() {
-      None => { return; }, // Malformed update payload.
-      Some(j) => j,
-    };
-
-    let split = device_key.split(".");
-    let search_path = split.collect::<Vec<&str>>();
-
-    let root_object = self.json.as_object_mut();
-    let maybe_subtree = GatewayState::get_subtree_mut(root_object, &search_path);
-
-    let mut subtree = match maybe_subtree {
-      None => {
-        // We received a reference to a part of the tree that doesn't
-        // exist, which probably means there was a new device added.
-        // TODO: Synthesize new nodes in the device tree instead of
-        // ignoring new devices outright.
-        return;
-      },
-      Some(stree) => stree,
-    };
-
-    for (updated_key, updated_val) in updated_object.iter() {
-      subtree.insert(updated_key.to_string(), updated_val.clone());
-    }
-  }
-
-  // TODO: This seriously needs testing.
-  /// Search the JSON tree for the subtree we want to update, and return
-  /// a mutable reference. This is a hack since there is nothing along
-  /// the lines of 'Json::find_path_mut(path)'.
-  ///
-  /// 'maybe_json_object' is the tree node we want to search.
-  /// 'path' is a slice containing the key traversal, in DFS order.
-  fn get_subtree_mut<'a>(maybe_json_object: Option<&'a mut Object>, path: &[&str])
-      -> Option<&'a mut Object> {
-    let object = match maybe_json_object {
-      None => { return None; }, // Couldn't recursively find the node we wanted.
-      Some(o) => o,
-    };
-
-    match path.split_first() {
-      None => {
-        return Some(object); // Our search is done, pop off the stack.
-      },
-      Some((first, remaining_path)) => {
-        // Recursively search...
-        let child : Option<&mut Json> = object.get_mut(&(first.to_string()));
-        let child_json = match child {
-          None => { return None; }, // Couldn't find child.
-          Some(j) => j,
-        };
-        GatewayState::get_subtree_mut(child_json.as_object_mut(), remaining_path)
-      },
-    }
-  }
-
   // TODO: Fix this API. It's really obtuse.
   /// Get "burglar alarm" sensor data from the results, if present.
   pub fn get_burglar_alarm(&self, device: u8, instance: u8) ->
       Option<BurglarAlarmData> {
-    let name = format!(
-        "devices.{}.instances.{}.commandClasses.113.data.7",
-        device, instance);
-
-    let path = DataResponse::path_query_parts(&name);
-    let alarm_data = self.json.find_path(&path);
-
-    alarm_data.and_then(|data| Some(BurglarAlarmData::new(&data)))
+    self.state.get_burglar_alarm(device, instance)
   }
 
   // TODO: Fix this API. It's really obtuse.
   /// Get the "general purpose" (0x01) binary sensor (0x30) data, if present.
   pub fn get_general_purpose_binary(&self, device: u8, instance: u8) ->
     Option<GeneralPurposeBinaryData> {
-    let name = format!(
-        "devices.{}.instances.{}.commandClasses.48.data.1",
-        device, instance);
-
-    let path = DataResponse::path_query_parts(&name);
-    let sensor_data = self.json.find_path(&path);
-
-    sensor_data.and_then(
-        |data| Some(GeneralPurposeBinaryData::new(&data)))
+    self.state.get_general_purpose_binary(device, instance)
   }
 }
 
@@ -242,6 +186,15 @@ impl PartialGatewayState {
   }
 }
 
+/// Encode a state's data tree as a full payload, stamped with the time.
+fn to_payload(state: &NetworkState, timestamp: Timestamp) -> Json {
+  let mut json = state.get_data().to_json();
+  if let Some(root) = json.as_object_mut() {
+    root.insert("updateTime".to_string(), Json::I64(timestamp));
+  }
+  json
+}
+
 /// Parse a raw string into JSON.
 fn parse_json(raw_string: &str) -> Result<Json, ResponseError> {
   return Json::from_str(raw_string).map_err(|_| ResponseError::ParseError)
@@ -251,6 +204,7 @@ fn parse_json(raw_string: &str) -> Result<Json, ResponseError> {
 
 /// XXX: DEPRECATED.
 /// A response from Razberry's /ZWaveAPI/Data endpoint.
+#[deprecated]
 pub struct DataResponse {
   json: Json,
 }
@@ -351,6 +305,61 @@ mod tests {
     assert!(response.get_timestamp().is_none());
   }
 
+  #[test]
+  fn gateway_state_merge() {
+    let mut state = GatewayState::build(include_str!("../sample_data/data.json"))
+        .unwrap();
+    assert_eq!(1456036521, state.get_end_timestamp());
+
+    let partial = PartialGatewayState::build(
+        include_str!("../sample_data/data-device-updates.json"), 1456036521)
+        .unwrap();
+    state.merge(&partial).unwrap();
+
+    assert_eq!(1456036634, state.get_end_timestamp());
+    assert_eq!(Some(1456036634), state.get_json().find("updateTime")
+        .and_then(|t| t.as_i64()));
+
+    // The payload is the merged tree.
+    let awake = ["devices", "4", "data", "isAwake"];
+    assert_eq!(Some(&Json::Boolean(false)), state.get_json().find_path(&awake)
+        .and_then(|a| a.find("value")));
+    assert_eq!(Some(&Json::I64(1456036466)), state.get_json().find_path(&awake)
+        .and_then(|a| a.find("updateTime")));
+  }
+
+  #[test]
+  fn gateway_state_lenient_build() {
+    let state = GatewayState::build("{ \"updateTime\": 1 }").unwrap();
+    assert_eq!(1, state.get_end_timestamp());
+    assert!(state.get_state().get_devices().is_empty());
+
+    // A device without "givenName" is kept in the JSON as received.
+    let raw_json = "{ \"updateTime\": 1, \"devices\": { \"2\": \
+      { \"data\": { \"custom\": { \"value\": 1.5, \"type\": \"float\" } } } } }";
+    let state = GatewayState::build(raw_json).unwrap();
+    assert!(state.get_state().get_device("2").is_none());
+    assert_eq!(Json::from_str(raw_json).unwrap().to_string(), state.get_json().to_string());
+
+    assert!(GatewayState::build("{}").is_err());
+  }
+
+  #[test]
+  fn gateway_state_merge_gap() {
+    let mut state = GatewayState::build(include_str!("../sample_data/data.json"))
+        .unwrap();
+
+    let partial = PartialGatewayState::build(
+        include_str!("../sample_data/data-device-updates.json"), 1456036600)
+        .unwrap();
+
+    match state.merge(&partial) {
+      Err(ResponseError::PossibleMissingEvents) => {},
+      other => panic!("Unexpected result: {:?}", other),
+    }
+    assert_eq!(1456036521, state.get_end_timestamp());
+  }
+
   #[test]
   fn path_query_parts() {
     let expected = vec!["devices", "1", "instances"];
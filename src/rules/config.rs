@@ -0,0 +1,288 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Parsing rules from TOML, YAML and JSON.
+
+use config_file::parse_time_of_day;
+use config_file::parse_toml;
+use config_file::parse_utc_offset;
+use config_file::parse_yaml;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use super::Action;
+use super::Condition;
+use super::Rule;
+use super::RuleEngine;
+use super::Trigger;
+
+pub fn from_toml(text: &str) -> Result<RuleEngine, RazberryError> {
+  from_json(&parse_toml(text).ok_or(RazberryError::BadRule)?)
+}
+
+pub fn from_yaml(text: &str) -> Result<RuleEngine, RazberryError> {
+  from_json(&parse_yaml(text).ok_or(RazberryError::BadRule)?)
+}
+
+pub fn from_json(json: &Json) -> Result<RuleEngine, RazberryError> {
+  let rules = match json.find("rules") {
+    None => Vec::new(),
+    Some(&Json::Array(ref rules)) => rules.iter()
+        .map(rule)
+        .collect::<Result<Vec<Rule>, RazberryError>>()?,
+    Some(_) => return Err(RazberryError::BadRule),
+  };
+
+  let mut engine = RuleEngine::new(rules);
+
+  if let Some(offset) = json.find("utc_offset") {
+    let offset = offset.as_string().and_then(parse_utc_offset);
+    engine.set_utc_offset(offset.ok_or(RazberryError::BadRule)?);
+  }
+
+  Ok(engine)
+}
+
+fn rule(json: &Json) -> Result<Rule, RazberryError> {
+  let rule = Rule {
+    name: get_string(json, "name")?,
+    triggers: get_list(json, "triggers", trigger)?,
+    conditions: get_list(json, "conditions", condition)?,
+    actions: get_list(json, "actions", Action::from_json)?,
+  };
+
+  if rule.triggers.is_empty() || rule.actions.is_empty() {
+    return Err(RazberryError::BadRule);
+  }
+
+  Ok(rule)
+}
+
+/// Eg. {"changed": PATH}, {"above": PATH, "threshold": 25}, {"below": ...},
+/// {"dead": DEVICE, "after": SECONDS} or {"at": "18:30"}.
+fn trigger(json: &Json) -> Result<Trigger, RazberryError> {
+  if let Some(path) = json.find("changed") {
+    Ok(Trigger::Changed { path: as_string(path)? })
+  } else if let Some(path) = json.find("above") {
+    Ok(Trigger::Above { path: as_string(path)?, threshold: get_number(json, "threshold")? })
+  } else if let Some(path) = json.find("below") {
+    Ok(Trigger::Below { path: as_string(path)?, threshold: get_number(json, "threshold")? })
+  } else if let Some(device_id) = json.find("dead") {
+    Ok(Trigger::DeviceDead {
+      device_id: as_string(device_id)?,
+      after_secs: get_number(json, "after")? as i64,
+    })
+  } else if let Some(time) = json.find("at") {
+    Ok(Trigger::TimeOfDay { minute: time_of_day(&as_string(time)?)? })
+  } else {
+    Err(RazberryError::BadRule)
+  }
+}
+
+/// Eg. {"path": PATH, "equals": true}, {"path": PATH, "above": 25},
+/// {"path": PATH, "below": 10} or {"between": ["22:00", "06:00"]}.
+fn condition(json: &Json) -> Result<Condition, RazberryError> {
+  if let Some(window) = json.find("between") {
+    let window = window.as_array()
+        .filter(|w| w.len() == 2)
+        .ok_or(RazberryError::BadRule)?;
+
+    return Ok(Condition::Between {
+      start: time_of_day(&as_string(&window[0])?)?,
+      end: time_of_day(&as_string(&window[1])?)?,
+    });
+  }
+
+  let path = get_string(json, "path")?;
+
+  if let Some(value) = json.find("equals") {
+    Ok(Condition::Equals { path: path, value: value.clone() })
+  } else if json.find("above").is_some() {
+    Ok(Condition::Above { path: path, threshold: get_number(json, "above")? })
+  } else if json.find("below").is_some() {
+    Ok(Condition::Below { path: path, threshold: get_number(json, "below")? })
+  } else {
+    Err(RazberryError::BadRule)
+  }
+}
+
+impl Action {
+  /// Parse an action. Eg. {"switch": DEVICE, "instance": 0, "on": true},
+  /// {"dim": DEVICE, "level": 40}, {"run": EXPRESSION}, {"delay": SECONDS} or
+  /// {"notify": MESSAGE}. The instance defaults to 0.
+  pub fn from_json(json: &Json) -> Result<Action, RazberryError> {
+    let instance = match json.find("instance") {
+      None => 0,
+      Some(instance) => instance.as_u64()
+          .filter(|i| *i <= 255)
+          .ok_or(RazberryError::BadRule)? as u8,
+    };
+
+    if let Some(device_id) = json.find("switch") {
+      Ok(Action::Switch {
+        device_id: as_string(device_id)?,
+        instance: instance,
+        on: json.find("on").and_then(|o| o.as_boolean()).ok_or(RazberryError::BadRule)?,
+      })
+    } else if let Some(device_id) = json.find("dim") {
+      let level = get_number(json, "level")?;
+      if level < 0.0 || level > 99.0 {
+        return Err(RazberryError::BadRule);
+      }
+
+      Ok(Action::Dim { device_id: as_string(device_id)?, instance: instance, level: level as u8 })
+    } else if let Some(command) = json.find("run") {
+      Ok(Action::Run { command: as_string(command)? })
+    } else if json.find("delay").is_some() {
+      Ok(Action::Delay { secs: get_number(json, "delay")? as i64 })
+    } else if let Some(message) = json.find("notify") {
+      Ok(Action::Notify { message: as_string(message)? })
+    } else {
+      Err(RazberryError::BadRule)
+    }
+  }
+}
+
+fn get_list<T, F>(json: &Json, key: &str, parse: F) -> Result<Vec<T>, RazberryError>
+    where F: Fn(&Json) -> Result<T, RazberryError> {
+  match json.find(key) {
+    None => Ok(Vec::new()),
+    Some(&Json::Array(ref items)) => items.iter().map(parse).collect(),
+    Some(_) => Err(RazberryError::BadRule),
+  }
+}
+
+fn get_string(json: &Json, key: &str) -> Result<String, RazberryError> {
+  json.find(key).ok_or(RazberryError::BadRule).and_then(as_string)
+}
+
+/// A string, or a number such as a device ID written without quotes.
+fn as_string(json: &Json) -> Result<String, RazberryError> {
+  match *json {
+    Json::String(ref s) => Ok(s.to_string()),
+    Json::I64(n) => Ok(n.to_string()),
+    Json::U64(n) => Ok(n.to_string()),
+    _ => Err(RazberryError::BadRule),
+  }
+}
+
+fn get_number(json: &Json, key: &str) -> Result<f64, RazberryError> {
+  json.find(key).and_then(|n| n.as_f64()).ok_or(RazberryError::BadRule)
+}
+
+fn time_of_day(time: &str) -> Result<u32, RazberryError> {
+  parse_time_of_day(time).ok_or(RazberryError::BadRule)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_from_toml() {
+    let engine = from_toml(r#"
+      utc_offset = "-05:30"
+
+      [[rules]]
+      name = "Hall light"
+      triggers = [{ changed = "devices.2.instances.0.commandClasses.48.data.1.level" }]
+      conditions = [
+        { path = "devices.2.instances.0.commandClasses.48.data.1.level", equals = true },
+        { between = ["18:00", "06:00"] },
+      ]
+      actions = [{ switch = 3, on = true }, { delay = 300 }, { switch = "3", on = false }]
+
+      [[rules]]
+      name = "Checks"
+      triggers = [
+        { above = "devices.2.instances.0.commandClasses.49.data.1.val", threshold = 25 },
+        { dead = "2", after = 3600 },
+        { at = "07:15" },
+      ]
+      actions = [{ dim = "4", instance = 1, level = 40 }, { notify = "Check the hall" }]
+    "#).unwrap();
+
+    assert_eq!(-(5 * 3600 + 30 * 60), engine.utc_offset);
+
+    let rules = engine.get_rules();
+    assert_eq!(2, rules.len());
+    assert_eq!("Hall light", rules[0].name);
+    assert_eq!(Condition::Between { start: 18 * 60, end: 6 * 60 }, rules[0].conditions[1]);
+    assert_eq!(Action::Switch { device_id: "3".to_string(), instance: 0, on: true },
+      rules[0].actions[0]);
+    assert_eq!(Action::Delay { secs: 300 }, rules[0].actions[1]);
+
+    assert_eq!(Trigger::Above {
+      path: "devices.2.instances.0.commandClasses.49.data.1.val".to_string(),
+      threshold: 25.0,
+    }, rules[1].triggers[0]);
+    assert_eq!(Trigger::DeviceDead { device_id: "2".to_string(), after_secs: 3600 },
+      rules[1].triggers[1]);
+    assert_eq!(Trigger::TimeOfDay { minute: 7 * 60 + 15 }, rules[1].triggers[2]);
+    assert_eq!(Action::Dim { device_id: "4".to_string(), instance: 1, level: 40 },
+      rules[1].actions[0]);
+  }
+
+  #[test]
+  fn test_from_yaml() {
+    let engine = from_yaml(r#"
+utc_offset: "+01:00"
+rules:
+  - name: Hall light
+    triggers:
+      - changed: devices.2.instances.0.commandClasses.48.data.1.level
+    conditions:
+      - path: devices.2.instances.0.commandClasses.48.data.1.level
+        equals: true
+      - between: ["18:00", "06:00"]
+    actions:
+      - switch: 3
+        on: true
+      - delay: 300
+      - switch: 3
+        on: false
+  - name: Hot
+    triggers:
+      - above: devices.2.instances.0.commandClasses.49.data.1.val
+        threshold: 25.5
+    actions:
+      - notify: Too hot
+"#).unwrap();
+
+    assert_eq!(3600, engine.utc_offset);
+
+    let rules = engine.get_rules();
+    assert_eq!(2, rules.len());
+    assert_eq!(Condition::Equals {
+      path: "devices.2.instances.0.commandClasses.48.data.1.level".to_string(),
+      value: Json::Boolean(true),
+    }, rules[0].conditions[0]);
+    assert_eq!(Condition::Between { start: 18 * 60, end: 6 * 60 }, rules[0].conditions[1]);
+    assert_eq!(Action::Switch { device_id: "3".to_string(), instance: 0, on: false },
+      rules[0].actions[2]);
+    assert_eq!(Trigger::Above {
+      path: "devices.2.instances.0.commandClasses.49.data.1.val".to_string(),
+      threshold: 25.5,
+    }, rules[1].triggers[0]);
+
+    for text in &["rules: 1", "rules: [", "? [1]\n: 2"] {
+      assert!(from_yaml(text).is_err(), "{}", text);
+    }
+  }
+
+  #[test]
+  fn test_invalid() {
+    for text in &[
+      "rules = 1",
+      "[[rules]]\nname = \"x\"\nactions = [{ notify = \"y\" }]",
+      "[[rules]]\nname = \"x\"\ntriggers = [{ at = \"25:00\" }]\nactions = [{ notify = \"y\" }]",
+      "[[rules]]\nname = \"x\"\ntriggers = [{ at = \"7:00\" }]\nactions = [{ dim = 1, level = 100 }]",
+      "[[rules]]\nname = \"x\"\ntriggers = [{ bogus = 1 }]\nactions = [{ notify = \"y\" }]",
+      "not toml",
+    ] {
+      assert!(from_toml(text).is_err(), "{}", text);
+    }
+
+    let json = Json::from_str(r#"{"rules": [{"name": "x", "triggers": [{"at": "7:00"}],
+      "actions": [{"run": "controller.data.homeId.value"}]}]}"#).unwrap();
+    assert_eq!(1, from_json(&json).unwrap().get_rules().len());
+  }
+}
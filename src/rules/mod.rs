@@ -0,0 +1,625 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Automation rules evaluated against the client's device state.
+//!
+//! A `Rule` fires when any of its triggers does and all of its conditions
+//! hold, then runs its actions in order. Rules are built in code or loaded
+//! with `RuleEngine::from_toml`, `from_yaml` or `from_json`, which share
+//! one set of keys:
+//!
+//!   [[rules]]
+//!   name = "Hall light"
+//!   triggers = [{ changed = "devices.2.instances.0.commandClasses.48.data.1.level" }]
+//!   conditions = [{ between = ["18:00", "06:00"] }]
+//!   actions = [{ switch = "3", on = true }, { delay = 300 }, { switch = "3", on = false }]
+//!
+//! The engine has no clock of its own. `evaluate` takes the time, and `run`
+//! uses the gateway time of the client's last poll, so rules behave the same
+//! against a simulated gateway. With dry run set, `run` reports the actions
+//! it would take without sending any commands.
+
+mod config;
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use client::RazberryClient;
+use data_holder::DataHolder;
+use data_holder::DataValue;
+use error::RazberryError;
+use network_state::NetworkState;
+use rustc_serialize::json::Json;
+use std::collections::HashMap;
+use std::fmt;
+
+const SECONDS_PER_DAY : i64 = 86400;
+
+/**
+ * Something that makes a rule fire.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Trigger {
+  /// The value at a data path was reported, eg.
+  /// "devices.2.instances.0.commandClasses.48.data.1.level". Fires when the
+  /// value changes or its "updateTime" advances, so a motion sensor that
+  /// reports true again, or goes false and back between polls, fires too.
+  Changed { path: String },
+
+  /// The number at a data path rose above the threshold.
+  Above { path: String, threshold: f64 },
+
+  /// The number at a data path fell below the threshold.
+  Below { path: String, threshold: f64 },
+
+  /// The device was marked failed, or not heard from for the given seconds.
+  DeviceDead { device_id: String, after_secs: i64 },
+
+  /// The time of day, in minutes after midnight, was reached.
+  TimeOfDay { minute: u32 },
+}
+
+/**
+ * Something that must hold for a rule to fire.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+  /// The value at a data path equals the JSON value. Numbers are compared
+  /// by value, so 1 equals 1.0.
+  Equals { path: String, value: Json },
+
+  /// The number at a data path is above the threshold.
+  Above { path: String, threshold: f64 },
+
+  /// The number at a data path is below the threshold.
+  Below { path: String, threshold: f64 },
+
+  /// The time of day, in minutes after midnight, is in [start, end). The
+  /// window may wrap past midnight, eg. from 22:00 to 06:00.
+  Between { start: u32, end: u32 },
+}
+
+/**
+ * Something a rule does.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+  /// Turn a binary switch (0x25) on or off.
+  Switch { device_id: String, instance: u8, on: bool },
+
+  /// Set a dimmer (0x26) level, from 0 to 99.
+  Dim { device_id: String, instance: u8, level: u8 },
+
+  /// Run a '/ZWaveAPI/Run' expression.
+  Run { command: String },
+
+  /// Wait before the following actions. If the rule fires again first,
+  /// the wait starts over.
+  Delay { secs: i64 },
+
+  /// Report a message to the engine's caller.
+  Notify { message: String },
+}
+
+/**
+ * A named automation.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+  pub name: String,
+
+  /// The rule fires when any trigger does.
+  pub triggers: Vec<Trigger>,
+
+  /// And only if every condition holds.
+  pub conditions: Vec<Condition>,
+
+  pub actions: Vec<Action>,
+}
+
+/**
+ * An action due to be taken. Never a delay.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Activation {
+  /// The name of the rule.
+  pub rule: String,
+
+  pub action: Action,
+
+  /// When the action became due.
+  pub time: DateTime<UTC>,
+}
+
+/**
+ * Evaluates rules against successive states.
+ */
+#[derive(Clone, Debug)]
+pub struct RuleEngine {
+  rules: Vec<Rule>,
+
+  /// Report actions without sending commands.
+  dry_run: bool,
+
+  /// Offset of local time from UTC, in seconds, for times of day.
+  utc_offset: i64,
+
+  /// What each trigger last saw, by rule and trigger index.
+  memory: HashMap<(usize, usize), TriggerMemory>,
+
+  /// Actions waiting out a delay, as (due time, rule index, actions).
+  pending: Vec<(i64, usize, Vec<Action>)>,
+
+  /// The time of the last evaluation.
+  last_time: Option<i64>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct TriggerMemory {
+  value: Option<DataValue>,
+  update_time: Option<DateTime<UTC>>,
+  dead: bool,
+}
+
+impl Action {
+  /// The '/ZWaveAPI/Run' expression for the action, if it sends one.
+  pub fn get_command(&self) -> Option<String> {
+    match *self {
+      Action::Switch { ref device_id, instance, on } => {
+        Some(format!("devices[{}].instances[{}].commandClasses[37].Set({})",
+          device_id, instance, if on { 255 } else { 0 }))
+      },
+      Action::Dim { ref device_id, instance, level } => {
+        Some(format!("devices[{}].instances[{}].commandClasses[38].Set({})",
+          device_id, instance, level))
+      },
+      Action::Run { ref command } => Some(command.to_string()),
+      Action::Delay { .. } | Action::Notify { .. } => None,
+    }
+  }
+}
+
+impl fmt::Display for Action {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Action::Switch { ref device_id, instance, on } => {
+        write!(f, "switch {}.{} {}", device_id, instance, if on { "on" } else { "off" })
+      },
+      Action::Dim { ref device_id, instance, level } => {
+        write!(f, "dim {}.{} to {}", device_id, instance, level)
+      },
+      Action::Run { ref command } => write!(f, "run {}", command),
+      Action::Delay { secs } => write!(f, "wait {}s", secs),
+      Action::Notify { ref message } => write!(f, "notify: {}", message),
+    }
+  }
+}
+
+impl RuleEngine {
+  /// Construct with rules, evaluated in order.
+  pub fn new(rules: Vec<Rule>) -> RuleEngine {
+    RuleEngine {
+      rules: rules,
+      dry_run: false,
+      utc_offset: 0,
+      memory: HashMap::new(),
+      pending: Vec::new(),
+      last_time: None,
+    }
+  }
+
+  /// Parse rules from JSON, as {"utc_offset": "+01:00", "rules": [...]}.
+  pub fn from_json(json: &Json) -> Result<RuleEngine, RazberryError> {
+    config::from_json(json)
+  }
+
+  /// Parse rules from TOML, with the same keys as `from_json`.
+  pub fn from_toml(text: &str) -> Result<RuleEngine, RazberryError> {
+    config::from_toml(text)
+  }
+
+  /// Parse rules from YAML, with the same keys as `from_json`.
+  pub fn from_yaml(text: &str) -> Result<RuleEngine, RazberryError> {
+    config::from_yaml(text)
+  }
+
+  pub fn get_rules(&self) -> &[Rule] {
+    &self.rules
+  }
+
+  /// Whether `run` only reports actions, without sending commands.
+  pub fn is_dry_run(&self) -> bool {
+    self.dry_run
+  }
+
+  pub fn set_dry_run(&mut self, dry_run: bool) {
+    self.dry_run = dry_run;
+  }
+
+  /// Set the offset of local time from UTC, in seconds, for times of day.
+  pub fn set_utc_offset(&mut self, secs: i64) {
+    self.utc_offset = secs;
+  }
+
+  /// Evaluate the rules against the state at a time, and return the actions
+  /// now due, including delayed ones. Change, threshold and time triggers
+  /// compare against the last call, so the first call only records where
+  /// they start; a device already dead fires at once.
+  pub fn evaluate(&mut self, state: &NetworkState, now: DateTime<UTC>) -> Vec<Activation> {
+    let now_secs = now.timestamp();
+    let mut activations = Vec::new();
+
+    // Delayed actions first, in the order they fall due.
+    self.pending.sort_by_key(|&(due, _, _)| due);
+    while !self.pending.is_empty() && self.pending[0].0 <= now_secs {
+      let (due, index, actions) = self.pending.remove(0);
+      self.start(index, actions, due, now, &mut activations);
+    }
+
+    for index in 0..self.rules.len() {
+      let mut fired = false;
+
+      for trigger_index in 0..self.rules[index].triggers.len() {
+        // Every trigger is checked, so each keeps its memory current.
+        if self.check_trigger(index, trigger_index, state, now_secs) {
+          fired = true;
+        }
+      }
+
+      let conditions_hold = self.rules[index].conditions.iter()
+          .all(|c| self.check_condition(c, state, now_secs));
+
+      if fired && conditions_hold {
+        self.pending.retain(|&(_, i, _)| i != index);
+        let actions = self.rules[index].actions.clone();
+        self.start(index, actions, now_secs, now, &mut activations);
+      }
+    }
+
+    self.last_time = Some(now_secs);
+    activations
+  }
+
+  /// Evaluate the rules against the client's state, at the gateway time of
+  /// its last poll, and send the commands now due unless in dry run. Returns
+  /// each action with the result of sending it.
+  pub fn run(&mut self, client: &RazberryClient)
+      -> Vec<(Activation, Result<(), RazberryError>)> {
+    let state = match client.get_state() {
+      None => return Vec::new(),
+      Some(state) => state,
+    };
+
+    let activations = self.evaluate(state, state.get_update_time());
+
+    activations.into_iter().map(|activation| {
+      let result = match activation.action.get_command() {
+        Some(ref command) if !self.dry_run => client.run_command(command).map(|_| ()),
+        _ => Ok(()),
+      };
+      (activation, result)
+    }).collect()
+  }
+
+  /// Take actions in order until a delay, which holds the rest back.
+  fn start(&mut self, index: usize, actions: Vec<Action>, from: i64, now: DateTime<UTC>,
+           activations: &mut Vec<Activation>) {
+    let mut actions = actions.into_iter();
+
+    while let Some(action) = actions.next() {
+      if let Action::Delay { secs } = action {
+        self.pending.push((from + secs, index, actions.collect()));
+        return;
+      }
+
+      activations.push(Activation {
+        rule: self.rules[index].name.to_string(),
+        action: action,
+        time: now,
+      });
+    }
+  }
+
+  fn check_trigger(&mut self, index: usize, trigger_index: usize, state: &NetworkState,
+                   now: i64) -> bool {
+    let last_time = self.last_time;
+    let utc_offset = self.utc_offset;
+    let memory = self.memory.entry((index, trigger_index)).or_insert(TriggerMemory::default());
+
+    match self.rules[index].triggers[trigger_index] {
+      Trigger::Changed { ref path } => {
+        let holder = match find_holder(state, path) {
+          None => return false,
+          Some(holder) => holder,
+        };
+
+        let value = Some(holder.get_value().clone());
+        let update_time = holder.get_update_time();
+        let fired = memory.value.is_some()
+            && (value != memory.value || update_time > memory.update_time);
+
+        memory.value = value;
+        memory.update_time = update_time;
+        fired
+      },
+      Trigger::Above { ref path, threshold } | Trigger::Below { ref path, threshold } => {
+        let above = match self.rules[index].triggers[trigger_index] {
+          Trigger::Above { .. } => true,
+          _ => false,
+        };
+        let value = find_value(state, path);
+        let crossed = |v: &Option<DataValue>| v.as_ref()
+            .and_then(|v| v.as_float())
+            .map(|v| if above { v > threshold } else { v < threshold });
+
+        let fired = crossed(&memory.value) == Some(false) && crossed(&value) == Some(true);
+        if value.is_some() {
+          memory.value = value;
+        }
+        fired
+      },
+      Trigger::DeviceDead { ref device_id, after_secs } => {
+        let failed = state.get_data()
+            .find_dotted(&format!("devices.{}.data.isFailed", device_id))
+            .and_then(|f| f.get_value().as_bool())
+            .unwrap_or(false);
+        let silent = state.get_device(device_id)
+            .map(|d| now - d.last_contacted.timestamp() >= after_secs)
+            .unwrap_or(false);
+
+        let dead = failed || silent;
+        let fired = dead && !memory.dead;
+        memory.dead = dead;
+        fired
+      },
+      Trigger::TimeOfDay { minute } => {
+        let last = match last_time {
+          None => return false,
+          Some(last) => last + utc_offset,
+        };
+
+        // The first occurrence of the minute after the last evaluation.
+        let time = minute as i64 * 60;
+        let next = ((last - time).div_euclid(SECONDS_PER_DAY) + 1) * SECONDS_PER_DAY + time;
+        next <= now + utc_offset
+      },
+    }
+  }
+
+  fn check_condition(&self, condition: &Condition, state: &NetworkState, now: i64) -> bool {
+    match *condition {
+      Condition::Equals { ref path, ref value } => {
+        let actual = match find_value(state, path) {
+          None => return false,
+          Some(actual) => actual.to_json(),
+        };
+
+        match (actual.as_f64(), value.as_f64()) {
+          (Some(a), Some(b)) => a == b,
+          _ => actual == *value,
+        }
+      },
+      Condition::Above { ref path, threshold } => {
+        find_value(state, path).and_then(|v| v.as_float()).map(|v| v > threshold)
+            .unwrap_or(false)
+      },
+      Condition::Below { ref path, threshold } => {
+        find_value(state, path).and_then(|v| v.as_float()).map(|v| v < threshold)
+            .unwrap_or(false)
+      },
+      Condition::Between { start, end } => {
+        let minute = ((now + self.utc_offset).rem_euclid(SECONDS_PER_DAY) / 60) as u32;
+        if start <= end {
+          minute >= start && minute < end
+        } else {
+          minute >= start || minute < end
+        }
+      },
+    }
+  }
+}
+
+/// The data holder at a path, if it has a value.
+fn find_holder<'a>(state: &'a NetworkState, path: &str) -> Option<&'a DataHolder> {
+  state.get_data().find_dotted(path).filter(|h| !h.get_value().is_empty())
+}
+
+/// The reported value at a data path, if any.
+fn find_value(state: &NetworkState, path: &str) -> Option<DataValue> {
+  find_holder(state, path).map(|h| h.get_value().clone())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::NaiveDateTime;
+  use mock::MockGateway;
+  use mock::test_client;
+  use simulator::Change;
+  use simulator::Simulator;
+  use simulator::VirtualClass;
+  use std::sync::Arc;
+  use std::sync::Mutex;
+
+  const MOTION : &'static str = "devices.2.instances.0.commandClasses.48.data.1.level";
+  const TEMPERATURE : &'static str = "devices.2.instances.0.commandClasses.49.data.1.val";
+
+  /// 2017-07-14 02:40:00 UTC.
+  const START : i64 = 1500000000;
+
+  fn simulator() -> Simulator {
+    let mut simulator = Simulator::new(START);
+    simulator.add_node("2", "Hall", &[
+      VirtualClass::SensorBinary,
+      VirtualClass::SensorMultilevel { sensor_type: 1, scale: "°C".to_string() },
+    ]).unwrap();
+    simulator.add_node("3", "Lamp", &[VirtualClass::SwitchBinary]).unwrap();
+    simulator
+  }
+
+  fn state(simulator: &Simulator) -> NetworkState {
+    NetworkState::from_json(&simulator.full_json()).unwrap()
+  }
+
+  fn time(secs: i64) -> DateTime<UTC> {
+    DateTime::<UTC>::from_utc(NaiveDateTime::from_timestamp(secs, 0), UTC)
+  }
+
+  fn actions(activations: &[Activation]) -> Vec<String> {
+    activations.iter().map(|a| format!("{}: {}", a.rule, a.action)).collect()
+  }
+
+  fn switch(on: bool) -> Action {
+    Action::Switch { device_id: "3".to_string(), instance: 0, on: on }
+  }
+
+  #[test]
+  fn test_changed_with_delay() {
+    let mut engine = RuleEngine::new(vec![Rule {
+      name: "hall".to_string(),
+      triggers: vec![Trigger::Changed { path: MOTION.to_string() }],
+      conditions: vec![Condition::Equals { path: MOTION.to_string(), value: Json::Boolean(true) }],
+      actions: vec![switch(true), Action::Delay { secs: 60 }, switch(false)],
+    }]);
+
+    let mut simulator = simulator();
+    assert!(engine.evaluate(&state(&simulator), time(START)).is_empty());
+
+    simulator.apply("2", &Change::SensorBinary(true)).unwrap();
+    let activations = engine.evaluate(&state(&simulator), time(START + 1));
+    assert_eq!(vec!["hall: switch 3.0 on"], actions(&activations));
+    assert_eq!(Some("devices[3].instances[0].commandClasses[37].Set(255)".to_string()),
+      activations[0].action.get_command());
+
+    // Motion again restarts the delay.
+    simulator.apply("2", &Change::SensorBinary(false)).unwrap();
+    assert!(engine.evaluate(&state(&simulator), time(START + 30)).is_empty());
+    simulator.apply("2", &Change::SensorBinary(true)).unwrap();
+    assert_eq!(vec!["hall: switch 3.0 on"],
+      actions(&engine.evaluate(&state(&simulator), time(START + 40))));
+
+    assert!(engine.evaluate(&state(&simulator), time(START + 99)).is_empty());
+    let activations = engine.evaluate(&state(&simulator), time(START + 100));
+    assert_eq!(vec!["hall: switch 3.0 off"], actions(&activations));
+    assert_eq!(time(START + 100), activations[0].time);
+    assert!(engine.evaluate(&state(&simulator), time(START + 200)).is_empty());
+  }
+
+  #[test]
+  fn test_changed_on_report() {
+    let mut engine = RuleEngine::new(vec![Rule {
+      name: "motion".to_string(),
+      triggers: vec![Trigger::Changed { path: MOTION.to_string() }],
+      conditions: Vec::new(),
+      actions: vec![Action::Notify { message: "Motion".to_string() }],
+    }]);
+
+    let mut simulator = simulator();
+    simulator.apply("2", &Change::SensorBinary(true)).unwrap();
+    assert!(engine.evaluate(&state(&simulator), time(START)).is_empty());
+
+    // The same value, reported again.
+    simulator.advance(10).unwrap();
+    simulator.apply("2", &Change::SensorBinary(true)).unwrap();
+    assert_eq!(1, engine.evaluate(&state(&simulator), time(START + 10)).len());
+    assert!(engine.evaluate(&state(&simulator), time(START + 11)).is_empty());
+
+    // Cleared and detected again between evaluations.
+    simulator.advance(10).unwrap();
+    simulator.apply("2", &Change::SensorBinary(false)).unwrap();
+    simulator.apply("2", &Change::SensorBinary(true)).unwrap();
+    assert_eq!(1, engine.evaluate(&state(&simulator), time(START + 20)).len());
+  }
+
+  #[test]
+  fn test_threshold_and_dead() {
+    let mut engine = RuleEngine::new(vec![
+      Rule {
+        name: "hot".to_string(),
+        triggers: vec![Trigger::Above { path: TEMPERATURE.to_string(), threshold: 25.0 }],
+        conditions: Vec::new(),
+        actions: vec![Action::Notify { message: "Too hot".to_string() }],
+      },
+      Rule {
+        name: "dead".to_string(),
+        triggers: vec![Trigger::DeviceDead { device_id: "2".to_string(), after_secs: 3600 }],
+        conditions: Vec::new(),
+        actions: vec![Action::Notify { message: "Hall is silent".to_string() }],
+      },
+    ]);
+
+    let mut simulator = simulator();
+    assert!(engine.evaluate(&state(&simulator), time(START)).is_empty());
+
+    for &(value, fires) in &[(24.0, false), (26.0, true), (27.0, false), (20.0, false),
+                             (25.5, true)] {
+      simulator.apply("2", &Change::SensorMultilevel { sensor_type: 1, value: value }).unwrap();
+      assert_eq!(fires, !engine.evaluate(&state(&simulator), time(START + 10)).is_empty(),
+        "{}", value);
+    }
+
+    assert!(engine.evaluate(&state(&simulator), time(START + 3000)).is_empty());
+    assert_eq!(vec!["dead: notify: Hall is silent"],
+      actions(&engine.evaluate(&state(&simulator), time(START + 3610))));
+    assert!(engine.evaluate(&state(&simulator), time(START + 7200)).is_empty());
+  }
+
+  #[test]
+  fn test_time_of_day() {
+    let mut engine = RuleEngine::new(vec![Rule {
+      name: "evening".to_string(),
+      triggers: vec![Trigger::TimeOfDay { minute: 18 * 60 }],
+      conditions: vec![Condition::Between { start: 17 * 60, end: 6 * 60 }],
+      actions: vec![switch(true)],
+    }]);
+    engine.set_utc_offset(2 * 3600);
+
+    let simulator = simulator();
+    let state = state(&simulator);
+
+    // START is 04:40 local, so 18:00 local is 13h20m later.
+    let six_pm = START + 13 * 3600 + 20 * 60;
+    assert!(engine.evaluate(&state, time(START)).is_empty());
+    assert!(engine.evaluate(&state, time(six_pm - 1)).is_empty());
+    assert_eq!(1, engine.evaluate(&state, time(six_pm + 5)).len());
+    assert!(engine.evaluate(&state, time(six_pm + 60)).is_empty());
+    assert_eq!(1, engine.evaluate(&state, time(six_pm + SECONDS_PER_DAY)).len());
+  }
+
+  #[test]
+  fn test_run_and_dry_run() {
+    let rule = Rule {
+      name: "lamp".to_string(),
+      triggers: vec![Trigger::Changed { path: MOTION.to_string() }],
+      conditions: Vec::new(),
+      actions: vec![switch(true)],
+    };
+
+    let gateway = Arc::new(Mutex::new(MockGateway::from_simulator(simulator())));
+    let mut client = test_client(&gateway);
+
+    let lamp = "devices.3.instances.0.commandClasses.37.data.level";
+    let lamp_on = |gateway: &Arc<Mutex<MockGateway>>| {
+      gateway.lock().unwrap().get_simulator().get_data().find_dotted(lamp)
+          .and_then(|l| l.get_value().as_bool())
+    };
+
+    for &dry_run in &[true, false] {
+      let mut engine = RuleEngine::new(vec![rule.clone()]);
+      engine.set_dry_run(dry_run);
+      assert!(engine.run(&client).is_empty());
+
+      {
+        let mut gateway = gateway.lock().unwrap();
+        let motion = gateway.get_simulator().get_data().find_dotted(MOTION)
+            .and_then(|m| m.get_value().as_bool()).unwrap();
+        gateway.get_simulator_mut().schedule(1, "2", Change::SensorBinary(!motion));
+        gateway.advance(2).unwrap();
+      }
+      client.poll_updates().unwrap();
+
+      let results = engine.run(&client);
+      assert_eq!(1, results.len());
+      assert!(results[0].1.is_ok());
+      assert_eq!(Some(!dry_run), lamp_on(&gateway));
+    }
+  }
+}
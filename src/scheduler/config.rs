@@ -0,0 +1,198 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Parsing schedules from TOML and JSON.
+
+use config_file::parse_toml;
+use config_file::parse_utc_offset;
+use error::RazberryError;
+use rules::Action;
+use rustc_serialize::json::Json;
+use super::Job;
+use super::MissedRuns;
+use super::Schedule;
+use super::Scheduler;
+use super::cron::CronSchedule;
+use super::sun::SunEvent;
+
+pub fn from_toml(text: &str) -> Result<Scheduler, RazberryError> {
+  from_json(&parse_toml(text).ok_or(RazberryError::BadSchedule)?)
+}
+
+pub fn from_json(json: &Json) -> Result<Scheduler, RazberryError> {
+  let missed_runs = match json.find("missed_runs") {
+    None => MissedRuns::Skip,
+    Some(policy) => missed_runs(policy)?,
+  };
+
+  let jobs = match json.find("jobs") {
+    None => Vec::new(),
+    Some(&Json::Array(ref jobs)) => jobs.iter()
+        .map(|j| job(j, missed_runs))
+        .collect::<Result<Vec<Job>, RazberryError>>()?,
+    Some(_) => return Err(RazberryError::BadSchedule),
+  };
+
+  for (index, job) in jobs.iter().enumerate() {
+    // Next runs are saved by name.
+    if jobs[..index].iter().any(|j| j.name == job.name) {
+      return Err(RazberryError::BadSchedule);
+    }
+  }
+
+  let location = match (json.find("latitude"), json.find("longitude")) {
+    (None, None) => None,
+    (Some(latitude), Some(longitude)) => {
+      match (latitude.as_f64(), longitude.as_f64()) {
+        (Some(lat), Some(lon)) if lat.abs() <= 90.0 && lon.abs() <= 180.0 => Some((lat, lon)),
+        _ => return Err(RazberryError::BadSchedule),
+      }
+    },
+    _ => return Err(RazberryError::BadSchedule),
+  };
+
+  let needs_location = jobs.iter().any(|j| match j.schedule {
+    Schedule::Sun { .. } => true,
+    _ => false,
+  });
+
+  if needs_location && location.is_none() {
+    return Err(RazberryError::BadSchedule);
+  }
+
+  let mut scheduler = Scheduler::new(jobs);
+
+  if let Some((latitude, longitude)) = location {
+    scheduler.set_location(latitude, longitude);
+  }
+
+  if let Some(offset) = json.find("utc_offset") {
+    let offset = offset.as_string().and_then(parse_utc_offset);
+    scheduler.set_utc_offset(offset.ok_or(RazberryError::BadSchedule)?);
+  }
+
+  if let Some(grace) = json.find("grace") {
+    scheduler.set_grace(grace.as_i64().filter(|g| *g >= 0).ok_or(RazberryError::BadSchedule)?);
+  }
+
+  Ok(scheduler)
+}
+
+fn job(json: &Json, default_missed_runs: MissedRuns) -> Result<Job, RazberryError> {
+  let name = json.find("name").and_then(|n| n.as_string())
+      .ok_or(RazberryError::BadSchedule)?;
+
+  let schedule = match (json.find("cron"), json.find("sun")) {
+    (Some(expression), None) => {
+      let expression = expression.as_string().ok_or(RazberryError::BadSchedule)?;
+      Schedule::Cron(CronSchedule::parse(expression)?)
+    },
+    (None, Some(event)) => {
+      let event = match event.as_string() {
+        Some("sunrise") => SunEvent::Sunrise,
+        Some("sunset") => SunEvent::Sunset,
+        _ => return Err(RazberryError::BadSchedule),
+      };
+      let offset_minutes = match json.find("offset") {
+        None => 0,
+        Some(offset) => offset.as_i64().ok_or(RazberryError::BadSchedule)?,
+      };
+      Schedule::Sun { event: event, offset_minutes: offset_minutes }
+    },
+    _ => return Err(RazberryError::BadSchedule),
+  };
+
+  let actions = match json.find("actions") {
+    Some(&Json::Array(ref actions)) => actions.iter()
+        .map(|a| Action::from_json(a).map_err(|_| RazberryError::BadSchedule))
+        .collect::<Result<Vec<Action>, RazberryError>>()?,
+    _ => return Err(RazberryError::BadSchedule),
+  };
+
+  let has_delay = actions.iter().any(|a| match *a {
+    Action::Delay { .. } => true,
+    _ => false,
+  });
+
+  if actions.is_empty() || has_delay {
+    return Err(RazberryError::BadSchedule);
+  }
+
+  let missed_runs = match json.find("missed_runs") {
+    None => default_missed_runs,
+    Some(policy) => missed_runs(policy)?,
+  };
+
+  Ok(Job {
+    name: name.to_string(),
+    schedule: schedule,
+    actions: actions,
+    missed_runs: missed_runs,
+  })
+}
+
+fn missed_runs(json: &Json) -> Result<MissedRuns, RazberryError> {
+  match json.as_string() {
+    Some("skip") => Ok(MissedRuns::Skip),
+    Some("run_once") => Ok(MissedRuns::RunOnce),
+    _ => Err(RazberryError::BadSchedule),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn test_from_toml() {
+    let scheduler = from_toml(r#"
+      latitude = 51.5
+      longitude = -0.13
+      utc_offset = "+01:00"
+      missed_runs = "run_once"
+      grace = 300
+
+      [[jobs]]
+      name = "Porch light"
+      sun = "sunset"
+      offset = -30
+      actions = [{ switch = 5, on = true }]
+
+      [[jobs]]
+      name = "Porch light off"
+      cron = "30 23 * * *"
+      missed_runs = "skip"
+      actions = [{ switch = 5, on = false }, { notify = "Porch light off" }]
+    "#).unwrap();
+
+    assert_eq!(Some((51.5, -0.13)), scheduler.location);
+    assert_eq!(3600, scheduler.utc_offset);
+    assert_eq!(300, scheduler.grace_secs);
+
+    let jobs = scheduler.get_jobs();
+    assert_eq!(2, jobs.len());
+    assert_eq!(Schedule::Sun { event: SunEvent::Sunset, offset_minutes: -30 }, jobs[0].schedule);
+    assert_eq!(MissedRuns::RunOnce, jobs[0].missed_runs);
+    assert_eq!("30 23 * * *", jobs[1].schedule.to_string());
+    assert_eq!(MissedRuns::Skip, jobs[1].missed_runs);
+    assert_eq!(2, jobs[1].actions.len());
+  }
+
+  #[test]
+  fn test_invalid() {
+    let job = "name = \"x\"\nactions = [{ notify = \"y\" }]";
+
+    for text in &[
+      format!("[[jobs]]\n{}", job),
+      format!("[[jobs]]\n{}\ncron = \"61 * * * *\"", job),
+      format!("[[jobs]]\n{}\ncron = \"* * * * *\"\nsun = \"sunset\"", job),
+      format!("[[jobs]]\n{}\nsun = \"sunset\"", job),
+      format!("latitude = 91\nlongitude = 0\n[[jobs]]\n{}\nsun = \"sunset\"", job),
+      format!("[[jobs]]\n{}\ncron = \"@daily\"\n[[jobs]]\n{}\ncron = \"@hourly\"", job, job),
+      format!("[[jobs]]\n{}\ncron = \"@daily\"\nmissed_runs = \"always\"", job),
+      "[[jobs]]\nname = \"x\"\ncron = \"@daily\"\nactions = [{ delay = 5 }]".to_string(),
+      "[[jobs]]\nname = \"x\"\ncron = \"@daily\"\nactions = []".to_string(),
+    ] {
+      assert!(from_toml(text).is_err(), "{}", text);
+    }
+  }
+}
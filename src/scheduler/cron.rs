@@ -0,0 +1,217 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Cron expressions: "minute hour day-of-month month day-of-week".
+
+use chrono::Datelike;
+use chrono::NaiveDateTime;
+use error::RazberryError;
+use std::fmt;
+
+const SECONDS_PER_DAY : i64 = 86400;
+
+/// Days searched for the next match. Covers "0 0 29 2 *" across leap years.
+const SEARCH_DAYS : i64 = 366 * 8;
+
+const MONTHS : &'static [&'static str] = &["jan", "feb", "mar", "apr", "may", "jun",
+  "jul", "aug", "sep", "oct", "nov", "dec"];
+
+const WEEKDAYS : &'static [&'static str] = &["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/**
+ * A parsed cron expression. Fields take "*", numbers, names ("mon",
+ * "jan"), ranges ("1-5"), lists ("1,15"), and steps over "*" or a range,
+ * eg. "0-30/10".
+ * Sunday is 0 or 7. As in cron, when both the day of the month and the day
+ * of the week are restricted, either may match. "@hourly", "@daily",
+ * "@weekly", "@monthly" and "@yearly" are also accepted.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct CronSchedule {
+  expression: String,
+  minutes: u64,
+  hours: u64,
+  days: u64,
+  months: u64,
+  weekdays: u64,
+  any_day: bool,
+  any_weekday: bool,
+}
+
+impl CronSchedule {
+  pub fn parse(expression: &str) -> Result<CronSchedule, RazberryError> {
+    let expanded = match expression.trim() {
+      "@hourly" => "0 * * * *",
+      "@daily" | "@midnight" => "0 0 * * *",
+      "@weekly" => "0 0 * * 0",
+      "@monthly" => "0 0 1 * *",
+      "@yearly" | "@annually" => "0 0 1 1 *",
+      other => other,
+    };
+
+    let fields = expanded.split_whitespace().collect::<Vec<&str>>();
+    if fields.len() != 5 {
+      return Err(RazberryError::BadSchedule);
+    }
+
+    let weekdays = parse_field(fields[4], 0, 7, WEEKDAYS)?;
+
+    Ok(CronSchedule {
+      expression: expression.trim().to_string(),
+      minutes: parse_field(fields[0], 0, 59, &[])?,
+      hours: parse_field(fields[1], 0, 23, &[])?,
+      days: parse_field(fields[2], 1, 31, &[])?,
+      months: parse_field(fields[3], 1, 12, MONTHS)?,
+      // Fold Sunday as 7 into 0.
+      weekdays: (weekdays | (weekdays >> 7)) & 0x7f,
+      any_day: fields[2] == "*",
+      any_weekday: fields[4] == "*",
+    })
+  }
+
+  /// The first matching minute after a time, both in local seconds since
+  /// the epoch, or None if it never matches, eg. "0 0 31 2 *".
+  pub fn next_after(&self, time: i64) -> Option<i64> {
+    let start = (time.div_euclid(60) + 1) * 60;
+    let first_day = start.div_euclid(SECONDS_PER_DAY);
+    let mut first_minute = start.rem_euclid(SECONDS_PER_DAY) / 60;
+
+    for day in first_day..first_day + SEARCH_DAYS {
+      if self.matches_day(day) {
+        for minute in first_minute..1440 {
+          if bit(self.hours, minute / 60) && bit(self.minutes, minute % 60) {
+            return Some(day * SECONDS_PER_DAY + minute * 60);
+          }
+        }
+      }
+      first_minute = 0;
+    }
+
+    None
+  }
+
+  fn matches_day(&self, day: i64) -> bool {
+    let date = NaiveDateTime::from_timestamp(day * SECONDS_PER_DAY, 0).date();
+
+    if !bit(self.months, date.month() as i64) {
+      return false;
+    }
+
+    let day_matches = bit(self.days, date.day() as i64);
+    let weekday_matches = bit(self.weekdays, date.weekday().num_days_from_sunday() as i64);
+
+    match (self.any_day, self.any_weekday) {
+      (true, true) => true,
+      (true, false) => weekday_matches,
+      (false, true) => day_matches,
+      (false, false) => day_matches || weekday_matches,
+    }
+  }
+}
+
+impl fmt::Display for CronSchedule {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.expression)
+  }
+}
+
+fn bit(set: u64, n: i64) -> bool {
+  set & (1 << n) != 0
+}
+
+/// Parse a field as a bit set of the values it matches.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> Result<u64, RazberryError> {
+  let mut set = 0;
+
+  for part in field.split(",") {
+    let mut pieces = part.splitn(2, "/");
+    let range = pieces.next().unwrap_or("");
+    let step = match pieces.next() {
+      None => 1,
+      Some(step) => step.parse::<u32>().ok()
+          .filter(|s| *s > 0 && *s <= max - min + 1)
+          .ok_or(RazberryError::BadSchedule)?,
+    };
+
+    let (start, end) = if range == "*" {
+      (min, max)
+    } else {
+      let mut bounds = range.splitn(2, "-");
+      let start = parse_value(bounds.next().unwrap_or(""), min, names)?;
+      let end = match bounds.next() {
+        Some(end) => parse_value(end, min, names)?,
+        None if step > 1 => max, // "5/10" runs from 5 to the end.
+        None => start,
+      };
+      (start, end)
+    };
+
+    if start < min || end > max || start > end {
+      return Err(RazberryError::BadSchedule);
+    }
+
+    let mut value = start;
+    while value <= end {
+      set |= 1 << value;
+      value += step;
+    }
+  }
+
+  Ok(set)
+}
+
+fn parse_value(value: &str, min: u32, names: &[&str]) -> Result<u32, RazberryError> {
+  let lower = value.to_lowercase();
+
+  if let Some(index) = names.iter().position(|n| *n == lower) {
+    return Ok(index as u32 + min);
+  }
+
+  value.parse().map_err(|_| RazberryError::BadSchedule)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Local seconds for a date and time.
+  fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> i64 {
+    ::chrono::NaiveDate::from_ymd(year, month, day).and_hms(hour, minute, 0).timestamp()
+  }
+
+  fn next(expression: &str, time: i64) -> Option<i64> {
+    CronSchedule::parse(expression).unwrap().next_after(time)
+  }
+
+  #[test]
+  fn test_next_after() {
+    // 2017-07-14 was a Friday.
+    let friday = at(2017, 7, 14, 6, 45);
+
+    assert_eq!(Some(at(2017, 7, 14, 6, 46)), next("* * * * *", friday));
+    assert_eq!(Some(at(2017, 7, 14, 7, 30)), next("30 7 * * *", friday));
+    assert_eq!(Some(at(2017, 7, 17, 6, 30)), next("30 6 * * mon-fri", friday));
+    assert_eq!(Some(at(2017, 7, 14, 7, 0)), next("*/15 7-9 * * *", friday));
+    assert_eq!(Some(at(2017, 7, 14, 7, 0)), next("@hourly", friday));
+    assert_eq!(Some(at(2017, 7, 16, 0, 0)), next("@weekly", friday));
+    assert_eq!(Some(at(2017, 7, 16, 0, 0)), next("0 0 * * 7", friday));
+    assert_eq!(Some(at(2017, 12, 25, 8, 0)), next("0 8 25 DEC *", friday));
+    assert_eq!(Some(at(2020, 2, 29, 0, 0)), next("0 0 29 2 *", friday));
+
+    // Day of the month or day of the week.
+    assert_eq!(Some(at(2017, 7, 15, 0, 0)), next("0 0 1 * sat", friday));
+    assert_eq!(Some(at(2017, 8, 1, 0, 0)), next("0 0 1 * *", friday));
+
+    // Strictly after.
+    assert_eq!(Some(at(2017, 7, 15, 6, 45)), next("45 6 * * *", friday));
+    assert_eq!(None, next("0 0 31 2 *", friday));
+  }
+
+  #[test]
+  fn test_invalid() {
+    for expression in &["", "* * * *", "60 * * * *", "* 24 * * *", "* * 0 * *",
+                        "* * * 13 *", "* * * * 8", "*/0 * * * *", "5-1 * * * *", "x * * * *",
+                        "59/4294967295 * * * *", "0 0 * * 7/4294967290", "*/61 * * * *"] {
+      assert!(CronSchedule::parse(expression).is_err(), "{}", expression);
+    }
+  }
+}
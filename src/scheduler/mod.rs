@@ -0,0 +1,463 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Commands issued on a timetable: cron expressions, or sunrise and sunset
+//! at a location, with an offset.
+//!
+//!   latitude = 51.5
+//!   longitude = -0.13
+//!   utc_offset = "+01:00"
+//!
+//!   [[jobs]]
+//!   name = "Porch light"
+//!   sun = "sunset"
+//!   offset = -30
+//!   actions = [{ switch = 5, on = true }]
+//!
+//!   [[jobs]]
+//!   name = "Porch light off"
+//!   cron = "30 23 * * *"
+//!   missed_runs = "run_once"
+//!   actions = [{ switch = 5, on = false }]
+//!
+//! Jobs take the same actions as `rules`, other than delays. Cron times are
+//! in local time, at a fixed offset from UTC; daylight saving is not
+//! followed. Sun times are computed from the location, with no network.
+//!
+//! The next run of each job can be saved with `to_json` and loaded with
+//! `restore`. A restored run that fell due while the scheduler was down is
+//! skipped or run once, by the job's `MissedRuns` policy.
+
+mod config;
+pub mod cron;
+pub mod sun;
+
+use chrono::NaiveDateTime;
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use client::RazberryClient;
+use error::RazberryError;
+use rules::Action;
+use rustc_serialize::json::Json;
+use self::cron::CronSchedule;
+use self::sun::SunEvent;
+use self::sun::sun_event;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Version of the saved next runs.
+const STATE_VERSION : i64 = 1;
+
+/// Runs this late are still on time, eg. when a poll was slow.
+const DEFAULT_GRACE_SECS : i64 = 60;
+
+const SECONDS_PER_DAY : i64 = 86400;
+
+/**
+ * When a job runs.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Schedule {
+  /// On a cron expression, in local time.
+  Cron(CronSchedule),
+
+  /// At sunrise or sunset, offset by minutes, eg. -30 for half an hour
+  /// before. Needs the scheduler's location.
+  Sun { event: SunEvent, offset_minutes: i64 },
+}
+
+/**
+ * What to do with runs that fell due while the scheduler was not running.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MissedRuns {
+  /// Wait for the next run.
+  Skip,
+
+  /// Run once straight away, however many runs were missed.
+  RunOnce,
+}
+
+/**
+ * Actions run on a schedule.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Job {
+  /// A unique name, under which the next run is saved.
+  pub name: String,
+
+  pub schedule: Schedule,
+
+  /// Run in order. Delays are not supported, and are skipped.
+  pub actions: Vec<Action>,
+
+  pub missed_runs: MissedRuns,
+}
+
+/**
+ * An action due to be taken.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct JobRun {
+  /// The name of the job.
+  pub job: String,
+
+  pub action: Action,
+
+  /// When the run was due.
+  pub scheduled: DateTime<UTC>,
+
+  /// When the run was found due.
+  pub time: DateTime<UTC>,
+}
+
+/**
+ * Finds the jobs due to run.
+ */
+#[derive(Clone, Debug)]
+pub struct Scheduler {
+  jobs: Vec<Job>,
+
+  /// The next run of each job, in Unix seconds, or None until the first
+  /// call to `due`.
+  next_runs: Vec<Option<i64>>,
+
+  /// Latitude and longitude, for sun schedules.
+  location: Option<(f64, f64)>,
+
+  /// Offset of local time from UTC, in seconds, for cron schedules.
+  utc_offset: i64,
+
+  /// How late a run may be and still count as on time.
+  grace_secs: i64,
+}
+
+impl Schedule {
+  /// The first run after a time, in Unix seconds, or None if there is none
+  /// within the search window, eg. a sun schedule during polar night.
+  pub fn next_after(&self, time: i64, utc_offset: i64, location: Option<(f64, f64)>)
+      -> Option<i64> {
+    match *self {
+      Schedule::Cron(ref cron) => {
+        cron.next_after(time + utc_offset).map(|local| local - utc_offset)
+      },
+      Schedule::Sun { event, offset_minutes } => {
+        let (latitude, longitude) = location?;
+        let today = time.div_euclid(SECONDS_PER_DAY);
+
+        (today - 1..today + 367)
+            .filter_map(|day| sun_event(event, day, latitude, longitude))
+            .map(|t| t + offset_minutes * 60)
+            .find(|t| *t > time)
+      },
+    }
+  }
+}
+
+impl fmt::Display for Schedule {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      Schedule::Cron(ref cron) => write!(f, "{}", cron),
+      Schedule::Sun { event, offset_minutes } => {
+        let name = match event {
+          SunEvent::Sunrise => "sunrise",
+          SunEvent::Sunset => "sunset",
+        };
+
+        if offset_minutes == 0 {
+          write!(f, "{}", name)
+        } else {
+          write!(f, "{}{:+}m", name, offset_minutes)
+        }
+      },
+    }
+  }
+}
+
+impl Scheduler {
+  /// Construct with jobs, at UTC and with no location.
+  pub fn new(jobs: Vec<Job>) -> Scheduler {
+    Scheduler {
+      next_runs: vec![None; jobs.len()],
+      jobs: jobs,
+      location: None,
+      utc_offset: 0,
+      grace_secs: DEFAULT_GRACE_SECS,
+    }
+  }
+
+  /// Parse jobs from JSON, with the same keys as `from_toml`.
+  pub fn from_json(json: &Json) -> Result<Scheduler, RazberryError> {
+    config::from_json(json)
+  }
+
+  /// Parse jobs from TOML, as in the module example. Each job has a "cron"
+  /// expression, or a "sun" event of "sunrise" or "sunset" with an optional
+  /// "offset" in minutes. "missed_runs" is "skip" (the default) or
+  /// "run_once", and may be set for all jobs at the top level.
+  pub fn from_toml(text: &str) -> Result<Scheduler, RazberryError> {
+    config::from_toml(text)
+  }
+
+  pub fn get_jobs(&self) -> &[Job] {
+    &self.jobs
+  }
+
+  /// Set the location for sun schedules, in degrees, east positive.
+  pub fn set_location(&mut self, latitude: f64, longitude: f64) {
+    self.location = Some((latitude, longitude));
+  }
+
+  /// Set the offset of local time from UTC, in seconds, for cron schedules.
+  pub fn set_utc_offset(&mut self, secs: i64) {
+    self.utc_offset = secs;
+  }
+
+  /// Set how late a run may be found, in seconds, and still count as on
+  /// time rather than missed. Defaults to a minute.
+  pub fn set_grace(&mut self, secs: i64) {
+    self.grace_secs = secs;
+  }
+
+  /// The next run of a job, once known.
+  pub fn get_next_run(&self, name: &str) -> Option<DateTime<UTC>> {
+    self.jobs.iter().position(|j| j.name == name)
+        .and_then(|i| self.next_runs[i])
+        .map(|t| DateTime::<UTC>::from_utc(NaiveDateTime::from_timestamp(t, 0), UTC))
+  }
+
+  /// Get the actions due at a time, and schedule each job's next run. Jobs
+  /// without a restored next run are scheduled from the first call.
+  pub fn due(&mut self, now: DateTime<UTC>) -> Vec<JobRun> {
+    let now_secs = now.timestamp();
+    let mut runs = Vec::new();
+
+    for (index, job) in self.jobs.iter().enumerate() {
+      let next_run = match self.next_runs[index] {
+        Some(next_run) if next_run > now_secs => continue,
+        Some(next_run) => next_run,
+        None => {
+          self.next_runs[index] = job.schedule.next_after(now_secs, self.utc_offset,
+            self.location);
+          continue;
+        },
+      };
+
+      let on_time = now_secs - next_run <= self.grace_secs;
+
+      if on_time || job.missed_runs == MissedRuns::RunOnce {
+        let scheduled = DateTime::<UTC>::from_utc(NaiveDateTime::from_timestamp(next_run, 0), UTC);
+
+        for action in job.actions.iter() {
+          if let Action::Delay { .. } = *action {
+            continue;
+          }
+
+          runs.push(JobRun {
+            job: job.name.to_string(),
+            action: action.clone(),
+            scheduled: scheduled,
+            time: now,
+          });
+        }
+      }
+
+      self.next_runs[index] = job.schedule.next_after(now_secs, self.utc_offset, self.location);
+    }
+
+    runs
+  }
+
+  /// Get the actions due at the gateway time of the client's last poll, and
+  /// send their commands. Returns each action with the result of sending it.
+  pub fn run(&mut self, client: &RazberryClient) -> Vec<(JobRun, Result<(), RazberryError>)> {
+    let now = match client.get_state() {
+      None => return Vec::new(),
+      Some(state) => state.get_update_time(),
+    };
+
+    self.due(now).into_iter().map(|run| {
+      let result = match run.action.get_command() {
+        Some(ref command) => client.run_command(command).map(|_| ()),
+        None => Ok(()),
+      };
+      (run, result)
+    }).collect()
+  }
+
+  /// Save the next runs, by job name and schedule.
+  pub fn to_json(&self) -> Json {
+    let mut jobs = BTreeMap::new();
+
+    for (job, next_run) in self.jobs.iter().zip(self.next_runs.iter()) {
+      if let Some(next_run) = *next_run {
+        let mut object = BTreeMap::new();
+        object.insert("schedule".to_string(), Json::String(job.schedule.to_string()));
+        object.insert("nextRun".to_string(), Json::I64(next_run));
+        jobs.insert(job.name.to_string(), Json::Object(object));
+      }
+    }
+
+    let mut object = BTreeMap::new();
+    object.insert("version".to_string(), Json::I64(STATE_VERSION));
+    object.insert("jobs".to_string(), Json::Object(jobs));
+    Json::Object(object)
+  }
+
+  /// Restore next runs saved by `to_json`. Runs saved for a job whose
+  /// schedule has since changed are ignored.
+  pub fn restore(&mut self, json: &Json) -> Result<(), RazberryError> {
+    if json.find("version").and_then(|v| v.as_i64()) != Some(STATE_VERSION) {
+      return Err(RazberryError::BadSchedule);
+    }
+
+    let saved = json.find("jobs").and_then(|j| j.as_object())
+        .ok_or(RazberryError::BadSchedule)?;
+
+    for (index, job) in self.jobs.iter().enumerate() {
+      let saved = match saved.get(&job.name) {
+        None => continue,
+        Some(saved) => saved,
+      };
+
+      if saved.find("schedule").and_then(|s| s.as_string()) != Some(&job.schedule.to_string()) {
+        continue;
+      }
+
+      if let Some(next_run) = saved.find("nextRun").and_then(|n| n.as_i64()) {
+        self.next_runs[index] = Some(next_run);
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::NaiveDate;
+  use mock::MockGateway;
+  use mock::test_client;
+  use simulator::Simulator;
+  use simulator::VirtualClass;
+  use std::sync::Arc;
+  use std::sync::Mutex;
+
+  fn time(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<UTC> {
+    DateTime::<UTC>::from_utc(NaiveDate::from_ymd(year, month, day).and_hms(hour, minute, 0), UTC)
+  }
+
+  fn job(name: &str, schedule: Schedule, missed_runs: MissedRuns) -> Job {
+    Job {
+      name: name.to_string(),
+      schedule: schedule,
+      actions: vec![Action::Switch { device_id: "3".to_string(), instance: 0, on: true }],
+      missed_runs: missed_runs,
+    }
+  }
+
+  fn cron(expression: &str) -> Schedule {
+    Schedule::Cron(CronSchedule::parse(expression).unwrap())
+  }
+
+  #[test]
+  fn test_due() {
+    let mut scheduler = Scheduler::new(vec![job("morning", cron("30 7 * * *"), MissedRuns::Skip)]);
+    scheduler.set_utc_offset(3600);
+
+    assert!(scheduler.due(time(2017, 7, 14, 5, 0)).is_empty());
+    assert_eq!(Some(time(2017, 7, 14, 6, 30)), scheduler.get_next_run("morning"));
+
+    assert!(scheduler.due(time(2017, 7, 14, 6, 29)).is_empty());
+
+    let runs = scheduler.due(time(2017, 7, 14, 6, 30));
+    assert_eq!(1, runs.len());
+    assert_eq!("morning", runs[0].job);
+    assert_eq!(time(2017, 7, 14, 6, 30), runs[0].scheduled);
+    assert_eq!(Some(time(2017, 7, 15, 6, 30)), scheduler.get_next_run("morning"));
+
+    assert!(scheduler.due(time(2017, 7, 14, 6, 31)).is_empty());
+  }
+
+  #[test]
+  fn test_missed_runs() {
+    let jobs = vec![
+      job("skip", cron("0 7 * * *"), MissedRuns::Skip),
+      job("once", cron("0 7 * * *"), MissedRuns::RunOnce),
+    ];
+
+    let mut scheduler = Scheduler::new(jobs.clone());
+    scheduler.due(time(2017, 7, 14, 5, 0));
+    let saved = scheduler.to_json();
+
+    // Down from before 07:00 on the 14th until the 16th.
+    let mut scheduler = Scheduler::new(jobs.clone());
+    scheduler.restore(&saved).unwrap();
+    assert_eq!(Some(time(2017, 7, 14, 7, 0)), scheduler.get_next_run("skip"));
+
+    let runs = scheduler.due(time(2017, 7, 16, 6, 0));
+    assert_eq!(vec!["once"], runs.iter().map(|r| r.job.as_str()).collect::<Vec<&str>>());
+    assert_eq!(time(2017, 7, 14, 7, 0), runs[0].scheduled);
+    assert_eq!(Some(time(2017, 7, 16, 7, 0)), scheduler.get_next_run("skip"));
+    assert_eq!(Some(time(2017, 7, 16, 7, 0)), scheduler.get_next_run("once"));
+
+    // Late within the grace period is on time.
+    let mut scheduler = Scheduler::new(jobs.clone());
+    scheduler.restore(&saved).unwrap();
+    assert_eq!(2, scheduler.due(time(2017, 7, 14, 7, 1)).len());
+
+    // A changed schedule drops the saved run.
+    let mut scheduler = Scheduler::new(vec![job("skip", cron("0 8 * * *"), MissedRuns::Skip)]);
+    scheduler.restore(&saved).unwrap();
+    assert_eq!(None, scheduler.get_next_run("skip"));
+
+    assert!(scheduler.restore(&Json::from_str("{\"version\": 0}").unwrap()).is_err());
+  }
+
+  #[test]
+  fn test_sun_schedule() {
+    let mut scheduler = Scheduler::new(vec![job("dusk",
+      Schedule::Sun { event: SunEvent::Sunset, offset_minutes: -30 }, MissedRuns::Skip)]);
+    assert_eq!("sunset-30m", scheduler.get_jobs()[0].schedule.to_string());
+
+    // No location, no runs.
+    scheduler.due(time(2017, 6, 21, 12, 0));
+    assert_eq!(None, scheduler.get_next_run("dusk"));
+
+    // London sunset on the solstice is about 20:21 UTC.
+    let mut scheduler = Scheduler::new(scheduler.get_jobs().to_vec());
+    scheduler.set_location(51.5074, -0.1278);
+    scheduler.due(time(2017, 6, 21, 12, 0));
+    let next = scheduler.get_next_run("dusk").unwrap().timestamp();
+    assert!((next - time(2017, 6, 21, 19, 51).timestamp()).abs() <= 120);
+
+    // After today's run, tomorrow's.
+    scheduler.due(time(2017, 6, 21, 20, 0));
+    let next = scheduler.get_next_run("dusk").unwrap().timestamp();
+    assert!((next - time(2017, 6, 22, 19, 51).timestamp()).abs() <= 120);
+  }
+
+  #[test]
+  fn test_run() {
+    let mut simulator = Simulator::new(time(2017, 7, 14, 6, 59).timestamp());
+    simulator.add_node("3", "Lamp", &[VirtualClass::SwitchBinary]).unwrap();
+
+    let gateway = Arc::new(Mutex::new(MockGateway::from_simulator(simulator)));
+    let mut client = test_client(&gateway);
+
+    let mut scheduler = Scheduler::new(vec![job("lamp", cron("0 7 * * *"), MissedRuns::Skip)]);
+    assert!(scheduler.run(&client).is_empty());
+
+    gateway.lock().unwrap().advance(60).unwrap();
+    client.poll_updates().unwrap();
+
+    let results = scheduler.run(&client);
+    assert_eq!(1, results.len());
+    assert!(results[0].1.is_ok());
+
+    let lamp = gateway.lock().unwrap().get_simulator().get_data()
+        .find_dotted("devices.3.instances.0.commandClasses.37.data.level")
+        .and_then(|l| l.get_value().as_bool());
+    assert_eq!(Some(true), lamp);
+  }
+}
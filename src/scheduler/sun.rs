@@ -0,0 +1,102 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+//! Sunrise and sunset times, from the sunrise equation. Accurate to within
+//! a minute or two away from the poles, which is plenty for lights.
+
+use std::f64::consts::PI;
+
+const SECONDS_PER_DAY : f64 = 86400.0;
+
+/// Julian date of the Unix epoch.
+const UNIX_EPOCH_JD : f64 = 2440587.5;
+
+/// Julian date of J2000.0.
+const J2000 : f64 = 2451545.0;
+
+/// Altitude of the sun's centre at sunrise and sunset, in degrees,
+/// allowing for refraction and the sun's radius.
+const HORIZON : f64 = -0.833;
+
+/**
+ * A daily astronomical event.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SunEvent {
+  Sunrise,
+  Sunset,
+}
+
+/// The time of the event on a UTC day, counted from the epoch, in Unix
+/// seconds, at a latitude and longitude (east positive). None while the sun
+/// stays up or down all day.
+pub fn sun_event(event: SunEvent, day: i64, latitude: f64, longitude: f64) -> Option<i64> {
+  let n = (day as f64 + UNIX_EPOCH_JD + 0.5 - J2000).round();
+
+  // Mean solar noon, the sun's mean anomaly and the equation of the centre.
+  let mean_noon = n - longitude / 360.0;
+  let anomaly = (357.5291 + 0.98560028 * mean_noon).rem_euclid(360.0);
+  let centre = 1.9148 * sin(anomaly) + 0.02 * sin(2.0 * anomaly) + 0.0003 * sin(3.0 * anomaly);
+
+  let ecliptic_longitude = (anomaly + centre + 180.0 + 102.9372).rem_euclid(360.0);
+  let transit = J2000 + mean_noon + 0.0053 * sin(anomaly) - 0.0069 * sin(2.0 * ecliptic_longitude);
+
+  let declination = (sin(ecliptic_longitude) * sin(23.4397)).asin();
+  let cos_hour_angle = (sin(HORIZON) - sin(latitude) * declination.sin())
+      / (cos(latitude) * declination.cos());
+
+  if cos_hour_angle < -1.0 || cos_hour_angle > 1.0 {
+    return None;
+  }
+
+  let hour_angle = cos_hour_angle.acos() * 180.0 / PI;
+  let julian = match event {
+    SunEvent::Sunrise => transit - hour_angle / 360.0,
+    SunEvent::Sunset => transit + hour_angle / 360.0,
+  };
+
+  Some(((julian - UNIX_EPOCH_JD) * SECONDS_PER_DAY).round() as i64)
+}
+
+fn sin(degrees: f64) -> f64 {
+  (degrees * PI / 180.0).sin()
+}
+
+fn cos(degrees: f64) -> f64 {
+  (degrees * PI / 180.0).cos()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::NaiveDate;
+
+  fn day(year: i32, month: u32, day: u32) -> i64 {
+    NaiveDate::from_ymd(year, month, day).and_hms(0, 0, 0).timestamp() / 86400
+  }
+
+  fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> i64 {
+    NaiveDate::from_ymd(year, month, day).and_hms(hour, minute, 0).timestamp()
+  }
+
+  fn assert_near(expected: i64, actual: Option<i64>) {
+    let actual = actual.unwrap();
+    assert!((expected - actual).abs() <= 120, "expected {}, got {}", expected, actual);
+  }
+
+  #[test]
+  fn test_sun_event() {
+    // London, at the summer solstice: 04:43 and 21:21 BST.
+    let solstice = day(2017, 6, 21);
+    assert_near(utc(2017, 6, 21, 3, 43), sun_event(SunEvent::Sunrise, solstice, 51.5074, -0.1278));
+    assert_near(utc(2017, 6, 21, 20, 21), sun_event(SunEvent::Sunset, solstice, 51.5074, -0.1278));
+
+    // Sydney, in winter: 07:00 and 16:54 AEST.
+    let winter = day(2017, 6, 21);
+    assert_near(utc(2017, 6, 20, 21, 0), sun_event(SunEvent::Sunrise, winter, -33.8688, 151.2093));
+    assert_near(utc(2017, 6, 21, 6, 54), sun_event(SunEvent::Sunset, winter, -33.8688, 151.2093));
+
+    // Tromsø has midnight sun in June and polar night in December.
+    assert_eq!(None, sun_event(SunEvent::Sunrise, solstice, 69.6492, 18.9553));
+    assert_eq!(None, sun_event(SunEvent::Sunset, day(2017, 12, 21), 69.6492, 18.9553));
+  }
+}
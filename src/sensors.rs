@@ -177,6 +177,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn get_status_absent() {
       let response = DataResponse::from_str("{}").unwrap();
       let result = response.get_burglar_alarm(4, 1);
@@ -0,0 +1,447 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::UTC;
+use command_classes::CommandClasses;
+use data_holder::DataHolder;
+use data_holder::DataValue;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+
+/**
+ * A command class that a virtual node can implement.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum VirtualClass {
+  /// Binary sensor (0x30), eg. motion. Reports "data.1.level".
+  SensorBinary,
+
+  /// Multilevel sensor (0x31). Reports "data.{sensor_type}.val".
+  SensorMultilevel { sensor_type: u8, scale: String },
+
+  /// On/off switch (0x25). Reports "data.level".
+  SwitchBinary,
+
+  /// Dimmer (0x26), from 0 to 99. Reports "data.level".
+  SwitchMultilevel,
+
+  /// Battery (0x80), in percent. Reports "data.last".
+  Battery,
+}
+
+impl VirtualClass {
+  /// Get the command class.
+  pub fn get_command_class(&self) -> CommandClasses {
+    match *self {
+      VirtualClass::SensorBinary => CommandClasses::SensorBinary,
+      VirtualClass::SensorMultilevel { .. } => CommandClasses::SensorMultilevel,
+      VirtualClass::SwitchBinary => CommandClasses::SwitchBinary,
+      VirtualClass::SwitchMultilevel => CommandClasses::SwitchMultilevel,
+      VirtualClass::Battery => CommandClasses::Battery,
+    }
+  }
+}
+
+/**
+ * A change a virtual node reports.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+  /// The binary sensor tripped or cleared.
+  SensorBinary(bool),
+
+  /// A multilevel sensor of the type read a value.
+  SensorMultilevel { sensor_type: u8, value: f64 },
+
+  /// The switch turned on or off.
+  SwitchBinary(bool),
+
+  /// The dimmer moved to a level.
+  SwitchMultilevel(u8),
+
+  /// The battery level changed.
+  Battery(u8),
+}
+
+impl Change {
+  /// Get the path of the changed value beneath the node's command classes,
+  /// and the value.
+  fn get_path_and_value(&self) -> (String, DataValue) {
+    match *self {
+      Change::SensorBinary(level) => ("48.data.1.level".to_string(), DataValue::Bool(level)),
+      Change::SensorMultilevel { sensor_type, value } => {
+        (format!("49.data.{}.val", sensor_type), DataValue::Float(value))
+      },
+      Change::SwitchBinary(level) => ("37.data.level".to_string(), DataValue::Bool(level)),
+      Change::SwitchMultilevel(level) => {
+        ("38.data.level".to_string(), DataValue::Int(level as i64))
+      },
+      Change::Battery(level) => ("128.data.last".to_string(), DataValue::Int(level as i64)),
+    }
+  }
+
+  /// Get the path of the holder Z-Way reports the change on, if not the
+  /// value itself. Sensors report the whole reading, eg. "48.data.1".
+  fn get_reported_path(&self) -> Option<String> {
+    match *self {
+      Change::SensorBinary(_) => Some("48.data.1".to_string()),
+      Change::SensorMultilevel { sensor_type, .. } => {
+        Some(format!("49.data.{}", sensor_type))
+      },
+      _ => None,
+    }
+  }
+}
+
+/**
+ * An in-process model of a Z-Way network, for testing without hardware.
+ *
+ * Declare virtual nodes with `add_node`, change them directly with `apply`
+ * or script changes with `schedule`, and move simulated time forward with
+ * `advance`. `full_json` and `delta_json` produce the payloads Z-Way would
+ * serve from '/ZWaveAPI/Data' and '/ZWaveAPI/Data/{timestamp}'. A
+ * `MockGateway` built on the simulator serves them to a client.
+ */
+#[derive(Clone, Debug)]
+pub struct Simulator {
+  /// The data tree.
+  data: DataHolder,
+
+  /// Simulated time, or None to follow the system clock.
+  time: Option<i64>,
+
+  /// Scripted changes as (time, node, change), in the order scheduled.
+  script: Vec<(i64, String, Change)>,
+}
+
+impl Simulator {
+  /// Construct an empty network with the clock pinned at a time.
+  pub fn new(time: i64) -> Simulator {
+    let mut data = DataHolder::empty();
+    data.merge(&["devices"], DataHolder::empty());
+
+    Simulator {
+      data: data,
+      time: Some(time),
+      script: Vec::new(),
+    }
+  }
+
+  /// Construct from a '/ZWaveAPI/Data' payload, eg. `sample_data/data.json`.
+  /// The clock follows the system clock.
+  pub fn from_json(json: &Json) -> Result<Simulator, RazberryError> {
+    Ok(Simulator {
+      data: DataHolder::from_json(json)?,
+      time: None,
+      script: Vec::new(),
+    })
+  }
+
+  /// Get the data tree.
+  pub fn get_data(&self) -> &DataHolder {
+    &self.data
+  }
+
+  /// Get the simulated time.
+  pub fn get_time(&self) -> i64 {
+    self.time.unwrap_or_else(|| UTC::now().timestamp())
+  }
+
+  /// Pin the clock, or None to follow the system clock. Scripted changes
+  /// are only applied by `advance`.
+  pub fn set_time(&mut self, time: Option<i64>) {
+    self.time = time;
+  }
+
+  /// Move the clock, pinning it, and apply the scripted changes that fall
+  /// due, each at its own time. The clock may move backwards, as after a
+  /// gateway restart.
+  pub fn advance(&mut self, seconds: i64) -> Result<(), RazberryError> {
+    let target = self.get_time() + seconds;
+
+    let mut due = Vec::new();
+    let mut pending = Vec::new();
+
+    for entry in self.script.drain(..) {
+      if entry.0 <= target {
+        due.push(entry);
+      } else {
+        pending.push(entry);
+      }
+    }
+
+    self.script = pending;
+    due.sort_by_key(|entry| entry.0); // Stable, so same-time changes keep order.
+
+    for (time, node, change) in due {
+      self.time = Some(time);
+      self.apply(&node, &change)?;
+    }
+
+    self.time = Some(target);
+    Ok(())
+  }
+
+  /// Add a virtual node. Its values start at rest: sensors clear, switches
+  /// off and the battery full.
+  pub fn add_node(&mut self, id: &str, name: &str, classes: &[VirtualClass])
+      -> Result<(), RazberryError> {
+    let device = format!("devices.{}", id);
+    let time = self.get_time();
+
+    self.set(&format!("{}.data.givenName", device), DataValue::String(name.to_string()), time)?;
+    self.set(&format!("{}.data.lastReceived", device), DataValue::Int(0), time)?;
+    self.set(&format!("{}.data.isAwake", device), DataValue::Bool(true), time)?;
+    self.set(&format!("{}.data.isFailed", device), DataValue::Bool(false), time)?;
+
+    for class in classes {
+      let command_class = format!("{}.instances.0.commandClasses.{}", device,
+        class.get_command_class().to_byte());
+
+      self.set(&format!("{}.data.supported", command_class), DataValue::Bool(true), time)?;
+      self.set(&format!("{}.data.version", command_class), DataValue::Int(1), time)?;
+
+      let initial = match *class {
+        VirtualClass::SensorBinary => {
+          self.set(&format!("{}.data.1.sensorTypeString", command_class),
+            DataValue::String("General purpose".to_string()), time)?;
+          Change::SensorBinary(false)
+        },
+        VirtualClass::SensorMultilevel { sensor_type, ref scale } => {
+          self.set(&format!("{}.data.{}.sensorTypeString", command_class, sensor_type),
+            DataValue::String(sensor_type_string(sensor_type).to_string()), time)?;
+          self.set(&format!("{}.data.{}.scaleString", command_class, sensor_type),
+            DataValue::String(scale.to_string()), time)?;
+          Change::SensorMultilevel { sensor_type: sensor_type, value: 0.0 }
+        },
+        VirtualClass::SwitchBinary => Change::SwitchBinary(false),
+        VirtualClass::SwitchMultilevel => Change::SwitchMultilevel(0),
+        VirtualClass::Battery => Change::Battery(100),
+      };
+
+      self.report(id, &initial, time)?;
+    }
+
+    Ok(())
+  }
+
+  /// Report a change from a node now. The node is also marked as heard from.
+  pub fn apply(&mut self, node: &str, change: &Change) -> Result<(), RazberryError> {
+    let device = format!("devices.{}", node);
+
+    if self.data.find_dotted(&device).is_none() {
+      return Err(RazberryError::InvalidArgument);
+    }
+
+    let time = self.get_time();
+
+    self.report(node, change, time)?;
+    self.set(&format!("{}.data.lastReceived", device), DataValue::Int(0), time)
+  }
+
+  /// Script a change from a node, the given number of seconds from now.
+  pub fn schedule(&mut self, delay: i64, node: &str, change: Change) {
+    let time = self.get_time() + delay;
+    self.script.push((time, node.to_string(), change));
+  }
+
+  /// Change any value now, eg.
+  /// "devices.4.instances.0.commandClasses.48.data.1.level". Its
+  /// invalidation time is kept.
+  pub fn set_value(&mut self, path: &str, value: DataValue)
+      -> Result<(), RazberryError> {
+    let time = self.get_time();
+    self.set(path, value, time)
+  }
+
+  /// Mark a value stale, as when the gateway asks a device to refresh it.
+  pub fn invalidate(&mut self, path: &str) -> Result<(), RazberryError> {
+    let mut object = BTreeMap::new();
+
+    if let Some(holder) = self.data.find_dotted(path) {
+      object.insert("value".to_string(), holder.get_value().to_json());
+      object.insert("type".to_string(),
+        Json::String(holder.get_value().get_type().to_string()));
+      if let Some(time) = holder.get_update_time() {
+        object.insert("updateTime".to_string(), Json::I64(time.timestamp()));
+      }
+    }
+
+    object.insert("invalidateTime".to_string(), Json::I64(self.get_time()));
+
+    self.merge(path, &Json::Object(object))
+  }
+
+  /// Produce the '/ZWaveAPI/Data' payload.
+  pub fn full_json(&self) -> Json {
+    let mut json = self.data.to_json();
+
+    if let Some(object) = json.as_object_mut() {
+      object.insert("updateTime".to_string(), Json::I64(self.get_time()));
+    }
+
+    json
+  }
+
+  /// Produce the '/ZWaveAPI/Data/{since}' payload: each holder updated or
+  /// invalidated since the time, keyed by its dotted path.
+  pub fn delta_json(&self, since: i64) -> Json {
+    let mut object = BTreeMap::new();
+
+    for (key, child) in self.data.get_children() {
+      collect_changed(key, child, since, &mut object);
+    }
+
+    object.insert("updateTime".to_string(), Json::I64(self.get_time()));
+
+    Json::Object(object)
+  }
+
+  fn report(&mut self, node: &str, change: &Change, time: i64)
+      -> Result<(), RazberryError> {
+    let command_classes = format!("devices.{}.instances.0.commandClasses", node);
+    let (path, value) = change.get_path_and_value();
+
+    self.set(&format!("{}.{}", command_classes, path), value, time)?;
+
+    match change.get_reported_path() {
+      Some(reported) => {
+        let reported = format!("{}.{}", command_classes, reported);
+        let value = self.data.find_dotted(&reported)
+            .map(|h| h.get_value().clone())
+            .unwrap_or(DataValue::Empty);
+        self.set(&reported, value, time)
+      },
+      None => Ok(()),
+    }
+  }
+
+  fn set(&mut self, path: &str, value: DataValue, time: i64)
+      -> Result<(), RazberryError> {
+    let invalidate_time = self.data.find_dotted(path)
+        .and_then(|h| h.get_invalidate_time());
+
+    let mut object = BTreeMap::new();
+    object.insert("value".to_string(), value.to_json());
+    object.insert("type".to_string(), Json::String(value.get_type().to_string()));
+    object.insert("updateTime".to_string(), Json::I64(time));
+
+    if let Some(invalidated) = invalidate_time {
+      object.insert("invalidateTime".to_string(), Json::I64(invalidated.timestamp()));
+    }
+
+    self.merge(path, &Json::Object(object))
+  }
+
+  fn merge(&mut self, path: &str, json: &Json) -> Result<(), RazberryError> {
+    let holder = DataHolder::from_json(json)?;
+    let path = path.split(".").collect::<Vec<&str>>();
+    self.data.merge(&path, holder);
+    Ok(())
+  }
+}
+
+fn sensor_type_string(sensor_type: u8) -> &'static str {
+  match sensor_type {
+    1 => "Temperature",
+    3 => "Luminance",
+    4 => "Power",
+    5 => "Humidity",
+    _ => "General purpose",
+  }
+}
+
+fn collect_changed(path: &str, holder: &DataHolder, since: i64,
+                   object: &mut BTreeMap<String, Json>) {
+  let changed = holder.get_update_time().map_or(false, |t| t.timestamp() >= since)
+      || holder.get_invalidate_time().map_or(false, |t| t.timestamp() >= since);
+
+  if changed {
+    object.insert(path.to_string(), holder.to_json());
+    return;
+  }
+
+  for (key, child) in holder.get_children() {
+    collect_changed(&format!("{}.{}", path, key), child, since, object);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::NaiveDateTime;
+  use chrono::datetime::DateTime;
+  use command_class::CommandClass;
+  use network_state::NetworkState;
+
+  fn simulator() -> Simulator {
+    let mut simulator = Simulator::new(1500000000);
+    simulator.add_node("2", "Hallway motion", &[
+      VirtualClass::SensorBinary,
+      VirtualClass::SensorMultilevel { sensor_type: 1, scale: "°C".to_string() },
+      VirtualClass::Battery,
+    ]).unwrap();
+    simulator.add_node("3", "Hallway light", &[VirtualClass::SwitchMultilevel])
+        .unwrap();
+    simulator
+  }
+
+  fn motion(state: &NetworkState) -> bool {
+    match state.get_device("2").unwrap()
+        .command_classes.get(&CommandClasses::SensorBinary) {
+      Some(&CommandClass::SensorBinary { ref inner }) => inner.get_level(),
+      _ => panic!("No binary sensor"),
+    }
+  }
+
+  #[test]
+  fn test_full_json() {
+    let state = NetworkState::from_json(&simulator().full_json()).unwrap();
+
+    assert_eq!(2, state.get_devices().len());
+    assert_eq!("Hallway motion", state.get_device("2").unwrap().name);
+    assert_eq!(vec![CommandClasses::SensorBinary, CommandClasses::SensorMultilevel,
+      CommandClasses::Battery],
+      state.get_device("2").unwrap().supported_command_classes);
+    assert!(!motion(&state));
+  }
+
+  #[test]
+  fn test_scripted_changes() {
+    let mut simulator = simulator();
+    let mut state = NetworkState::from_json(&simulator.full_json()).unwrap();
+
+    simulator.schedule(10, "2", Change::SensorBinary(true));
+    simulator.schedule(12, "3", Change::SwitchMultilevel(99));
+    simulator.schedule(60, "2", Change::SensorBinary(false));
+
+    // Only the first two fall due.
+    simulator.advance(30).unwrap();
+
+    let delta = simulator.delta_json(1500000000 + 1);
+    assert_eq!(Some(1500000030), delta.find("updateTime").and_then(|t| t.as_i64()));
+    assert_eq!(Some(1500000010),
+      delta.find("devices.2.instances.0.commandClasses.48.data.1")
+          .and_then(|l| l.find_path(&["level", "updateTime"]))
+          .and_then(|t| t.as_i64()));
+    assert_eq!(Some(99),
+      delta.find("devices.3.instances.0.commandClasses.38.data.level")
+          .and_then(|l| l.find("value"))
+          .and_then(|v| v.as_i64()));
+
+    let since = DateTime::from_utc(NaiveDateTime::from_timestamp(1500000000, 0), UTC);
+    state.apply_delta(&delta, since).unwrap();
+    assert!(motion(&state));
+
+    simulator.advance(30).unwrap();
+    let since = state.get_update_time();
+    state.apply_delta(&simulator.delta_json(since.timestamp()), since).unwrap();
+    assert!(!motion(&state));
+  }
+
+  #[test]
+  fn test_apply_unknown_node() {
+    let mut simulator = simulator();
+    assert!(simulator.apply("9", &Change::SwitchBinary(true)).is_err());
+  }
+}
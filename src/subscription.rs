@@ -0,0 +1,306 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use data_holder::DataHolder;
+use error::RazberryError;
+use rustc_serialize::json::Json;
+use std::fmt;
+
+/**
+ * Identifies a subscription registered with the client.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SubscriptionId(pub usize);
+
+impl fmt::Display for SubscriptionId {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+/**
+ * A dotted data path pattern, eg. "devices.*.data.lastReceived".
+ *
+ * `*` matches exactly one path component and `**` matches any number of
+ * components, including none. Every other component must match exactly.
+ */
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PathPattern {
+  segments: Vec<String>,
+}
+
+impl PathPattern {
+  /// Parse a pattern.
+  pub fn parse(pattern: &str) -> Result<PathPattern, RazberryError> {
+    let segments = pattern.trim().split(".")
+        .map(|s| s.to_string())
+        .collect::<Vec<String>>();
+
+    let invalid = segments.iter().any(|s| {
+      s.is_empty() || (s.contains("*") && s != "*" && s != "**")
+    });
+
+    if invalid {
+      return Err(RazberryError::InvalidArgument);
+    }
+
+    Ok(PathPattern { segments: segments })
+  }
+
+  /// Match a full path. On success, returns the components matched by each
+  /// wildcard, in order. A `**` capture is its components joined by dots.
+  pub fn matches(&self, path: &[&str]) -> Option<Vec<String>> {
+    let mut captures = Vec::new();
+    if match_segments(&self.segments, path, &mut captures, false) {
+      Some(captures)
+    } else {
+      None
+    }
+  }
+
+  /// Whether some descendant of the path could match.
+  fn matches_below(&self, path: &[&str]) -> bool {
+    match_segments(&self.segments, path, &mut Vec::new(), true)
+  }
+}
+
+impl fmt::Display for PathPattern {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.segments.join("."))
+  }
+}
+
+fn match_segments(pattern: &[String], path: &[&str],
+                  captures: &mut Vec<String>, prefix: bool) -> bool {
+  let (first, rest) = match pattern.split_first() {
+    None => return path.is_empty(),
+    Some(split) => split,
+  };
+
+  if path.is_empty() && prefix {
+    return true; // The rest of the pattern may match deeper in the tree.
+  }
+
+  match first.as_str() {
+    "**" => {
+      for taken in 0..(path.len() + 1) {
+        let mut attempt = captures.clone();
+        attempt.push(path[..taken].join("."));
+        if match_segments(rest, &path[taken..], &mut attempt, prefix) {
+          *captures = attempt;
+          return true;
+        }
+      }
+      false
+    },
+    "*" => match path.split_first() {
+      None => false,
+      Some((component, remaining)) => {
+        captures.push(component.to_string());
+        match_segments(rest, remaining, captures, prefix)
+      },
+    },
+    literal => match path.split_first() {
+      Some((component, remaining)) if *component == literal => {
+        match_segments(rest, remaining, captures, prefix)
+      },
+      _ => false,
+    },
+  }
+}
+
+/**
+ * A change to a subscribed data path, observed while polling.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct DataChange {
+  /// The subscription that matched.
+  pub subscription: SubscriptionId,
+
+  /// The full dotted path of the changed data holder, eg.
+  /// "devices.9.instances.0.commandClasses.48.data.1".
+  pub path: String,
+
+  /// The components matched by the pattern's wildcards, in order.
+  pub captures: Vec<String>,
+
+  /// The new data holder, including its value and children.
+  pub data: DataHolder,
+
+  /// Gateway time of the change: the holder's "updateTime", or that of the
+  /// poll if the holder has none.
+  pub timestamp: DateTime<UTC>,
+}
+
+/**
+ * The patterns registered with a client.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct Subscriptions {
+  next_id: usize,
+  patterns: Vec<(SubscriptionId, PathPattern)>,
+}
+
+impl Subscriptions {
+  /// Register a pattern.
+  pub fn subscribe(&mut self, pattern: &str)
+      -> Result<SubscriptionId, RazberryError> {
+    let pattern = PathPattern::parse(pattern)?;
+    let id = SubscriptionId(self.next_id);
+    self.next_id += 1;
+    self.patterns.push((id, pattern));
+    Ok(id)
+  }
+
+  /// Remove a pattern. Returns whether it was registered.
+  pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+    let before = self.patterns.len();
+    self.patterns.retain(|&(ref existing, _)| *existing != id);
+    self.patterns.len() != before
+  }
+
+  /// Match every key of a '/ZWaveAPI/Data/{timestamp}' delta payload,
+  /// including controller keys, against the registered patterns. Each
+  /// subscription reports the shallowest matching holder beneath each key.
+  pub fn collect_changes(&self, json: &Json, poll_time: DateTime<UTC>)
+      -> Result<Vec<DataChange>, RazberryError> {
+    let object = json.as_object().ok_or(RazberryError::BadResponse)?;
+
+    let mut changes = Vec::new();
+
+    if self.patterns.is_empty() {
+      return Ok(changes);
+    }
+
+    for (key, value) in object {
+      if !value.is_object() {
+        continue; // Eg. the top-level "updateTime".
+      }
+
+      let holder = DataHolder::from_json(value)?;
+      let path = key.split(".").collect::<Vec<&str>>();
+
+      for &(id, ref pattern) in self.patterns.iter() {
+        collect(id, pattern, &mut path.clone(), &holder, poll_time,
+          &mut changes);
+      }
+    }
+
+    Ok(changes)
+  }
+}
+
+fn collect<'a>(id: SubscriptionId, pattern: &PathPattern,
+               path: &mut Vec<&'a str>, holder: &'a DataHolder,
+               poll_time: DateTime<UTC>, changes: &mut Vec<DataChange>) {
+  if let Some(captures) = pattern.matches(path) {
+    changes.push(DataChange {
+      subscription: id,
+      path: path.join("."),
+      captures: captures,
+      data: holder.clone(),
+      timestamp: holder.get_update_time().unwrap_or(poll_time),
+    });
+    return;
+  }
+
+  if !pattern.matches_below(path) {
+    return;
+  }
+
+  for (key, child) in holder.get_children() {
+    path.push(key);
+    collect(id, pattern, path, child, poll_time, changes);
+    path.pop();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::NaiveDateTime;
+
+  fn matches(pattern: &str, path: &str) -> Option<Vec<String>> {
+    let path = path.split(".").collect::<Vec<&str>>();
+    PathPattern::parse(pattern).unwrap().matches(&path)
+  }
+
+  #[test]
+  fn test_parse() {
+    assert!(PathPattern::parse("devices.*.data.lastReceived").is_ok());
+    assert!(PathPattern::parse("devices.**").is_ok());
+    assert!(PathPattern::parse("").is_err());
+    assert!(PathPattern::parse("devices..data").is_err());
+    assert!(PathPattern::parse("devices.4*").is_err());
+  }
+
+  #[test]
+  fn test_matches() {
+    assert_eq!(Some(vec!["9".to_string()]),
+      matches("devices.*.data.lastReceived", "devices.9.data.lastReceived"));
+    assert_eq!(None,
+      matches("devices.*.data.lastReceived", "devices.9.data.isAwake"));
+    assert_eq!(None, matches("devices.*", "devices.9.data"));
+
+    assert_eq!(Some(vec!["0".to_string(), "data.1.val".to_string()]),
+      matches("devices.4.instances.*.commandClasses.49.**",
+        "devices.4.instances.0.commandClasses.49.data.1.val"));
+    assert_eq!(Some(vec!["".to_string()]), matches("controller.**", "controller"));
+    assert_eq!(Some(vec!["devices.4".to_string()]),
+      matches("**.data", "devices.4.data"));
+  }
+
+  #[test]
+  fn test_collect_changes() {
+    let json = Json::from_str(r#"
+      {
+        "devices.9.data.lastReceived": {
+          "value": 0, "type": "int", "updateTime": 1492409902
+        },
+        "devices.9.instances.0.commandClasses.48.data.1": {
+          "value": null,
+          "type": "empty",
+          "level": { "value": true, "type": "bool", "updateTime": 1492409903 },
+          "updateTime": 1492409903
+        },
+        "controller.data.lastIncludedDevice": {
+          "value": 12, "type": "int", "updateTime": 1492409904
+        },
+        "updateTime": 1492409924
+      }
+    "#).unwrap();
+
+    let mut subscriptions = Subscriptions::default();
+    let received = subscriptions.subscribe("devices.*.data.lastReceived").unwrap();
+    let level = subscriptions
+        .subscribe("devices.*.instances.*.commandClasses.48.data.*.level")
+        .unwrap();
+    let controller = subscriptions.subscribe("controller.**").unwrap();
+
+    let poll_time = DateTime::from_utc(
+        NaiveDateTime::from_timestamp(1492409924, 0), UTC);
+    let mut changes = subscriptions.collect_changes(&json, poll_time).unwrap();
+    changes.sort_by_key(|c| c.subscription);
+
+    assert_eq!(3, changes.len());
+
+    assert_eq!(received, changes[0].subscription);
+    assert_eq!(vec!["9"], changes[0].captures);
+
+    // Found beneath the delta key.
+    assert_eq!(level, changes[1].subscription);
+    assert_eq!("devices.9.instances.0.commandClasses.48.data.1.level",
+      changes[1].path);
+    assert_eq!(vec!["9", "0", "1"], changes[1].captures);
+    assert_eq!(Some(true), changes[1].data.get_value().as_bool());
+    assert_eq!(1492409903, changes[1].timestamp.timestamp());
+
+    assert_eq!(controller, changes[2].subscription);
+    assert_eq!("controller.data.lastIncludedDevice", changes[2].path);
+
+    assert!(subscriptions.unsubscribe(controller));
+    assert!(!subscriptions.unsubscribe(controller));
+    assert_eq!(2, subscriptions.collect_changes(&json, poll_time).unwrap().len());
+  }
+}
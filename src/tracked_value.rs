@@ -0,0 +1,127 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use data_holder::DataHolder;
+use data_holder::DataValue;
+
+/**
+ * Whether a value can be trusted as current.
+ *
+ * Z-Way sets "invalidateTime" when it asks a device to refresh a value. Until
+ * the device answers and "updateTime" moves past it, the value is stale. This
+ * is typical of sleeping battery devices.
+ */
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Freshness {
+  /// The value answers the most recent refresh request.
+  Valid,
+  /// A refresh was requested and has not yet been answered.
+  Invalid,
+  /// Neither timestamp was reported.
+  Unknown,
+}
+
+impl Freshness {
+  /// Compare a value's update and invalidation times.
+  pub fn from_times(update_time: Option<DateTime<UTC>>,
+                    invalidate_time: Option<DateTime<UTC>>) -> Freshness {
+    match (update_time, invalidate_time) {
+      (None, None) => Freshness::Unknown,
+      (None, Some(_)) => Freshness::Invalid,
+      (Some(_), None) => Freshness::Valid,
+      (Some(updated), Some(invalidated)) => {
+        if invalidated > updated {
+          Freshness::Invalid
+        } else {
+          Freshness::Valid
+        }
+      },
+    }
+  }
+}
+
+/**
+ * A value along with the time it was last updated and last invalidated.
+ */
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TrackedValue<T> {
+  value: T,
+  update_time: Option<DateTime<UTC>>,
+  invalidate_time: Option<DateTime<UTC>>,
+}
+
+impl <T> TrackedValue<T> {
+  /// Construct a tracked value.
+  pub fn new(value: T,
+             update_time: Option<DateTime<UTC>>,
+             invalidate_time: Option<DateTime<UTC>>) -> TrackedValue<T> {
+    TrackedValue {
+      value: value,
+      update_time: update_time,
+      invalidate_time: invalidate_time,
+    }
+  }
+
+  /// Construct a tracked value from a data holder, converting its value.
+  pub fn from_holder<F>(holder: &DataHolder, convert: F) -> TrackedValue<T>
+      where F: FnOnce(&DataValue) -> T {
+    TrackedValue {
+      value: convert(holder.get_value()),
+      update_time: holder.get_update_time(),
+      invalidate_time: holder.get_invalidate_time(),
+    }
+  }
+
+  /// Get the value.
+  pub fn get_value(&self) -> &T {
+    &self.value
+  }
+
+  /// Get when the value was last updated.
+  pub fn get_update_time(&self) -> Option<DateTime<UTC>> {
+    self.update_time
+  }
+
+  /// Get when the value was last invalidated.
+  pub fn get_invalidate_time(&self) -> Option<DateTime<UTC>> {
+    self.invalidate_time
+  }
+
+  /// Get whether the value can be trusted as current.
+  pub fn get_freshness(&self) -> Freshness {
+    Freshness::from_times(self.update_time, self.invalidate_time)
+  }
+
+  /// Whether the value answers the most recent refresh request.
+  pub fn is_valid(&self) -> bool {
+    self.get_freshness() == Freshness::Valid
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use chrono::NaiveDateTime;
+
+  fn at(timestamp: i64) -> Option<DateTime<UTC>> {
+    Some(DateTime::from_utc(NaiveDateTime::from_timestamp(timestamp, 0), UTC))
+  }
+
+  #[test]
+  fn test_freshness() {
+    assert_eq!(Freshness::Unknown, Freshness::from_times(None, None));
+    assert_eq!(Freshness::Valid, Freshness::from_times(at(10), None));
+    assert_eq!(Freshness::Valid, Freshness::from_times(at(10), at(5)));
+    assert_eq!(Freshness::Valid, Freshness::from_times(at(10), at(10)));
+    assert_eq!(Freshness::Invalid, Freshness::from_times(at(10), at(20)));
+    assert_eq!(Freshness::Invalid, Freshness::from_times(None, at(20)));
+  }
+
+  #[test]
+  fn test_is_valid() {
+    assert!(TrackedValue::new(true, at(10), at(5)).is_valid());
+    assert!(!TrackedValue::new(true, at(10), at(20)).is_valid());
+    assert!(!TrackedValue::new(true, None, None).is_valid());
+  }
+}
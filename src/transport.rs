@@ -0,0 +1,683 @@
+// Copyright (c) 2017 Brandon Thomas <bt@brand.io, echelon@gmail.com>
+
+use chrono::NaiveDateTime;
+use chrono::UTC;
+use chrono::datetime::DateTime;
+use command_class::user_code::is_in_code;
+use command_class::user_code::remove_codes;
+use error::RazberryError;
+use hyper::client::Client;
+use hyper::header::Headers;
+use rustc_serialize::json::Json;
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use url::Url;
+
+/// Placeholder for secrets left out of recordings.
+const REDACTED : &'static str = "<redacted>";
+
+/// The command whose arguments carry a PIN (see `RazberryClient::set_user_code`).
+const USER_CODE_SET : &'static str = "commandClasses[99].Set(";
+
+/// The session cookie, as it appears in "Cookie" and "Set-Cookie" headers.
+const SESSION_COOKIE_PREFIX : &'static str = "ZWAYSession=";
+
+/// The login endpoint, whose response carries the session ID as "data.sid".
+const LOGIN_PATH : &'static str = "/ZAutomation/api/v1/login";
+
+/// The full data endpoint. Deltas are under it, eg. "/ZWaveAPI/Data/{since}".
+const DATA_PATH : &'static str = "/ZWaveAPI/Data";
+
+/**
+ * An HTTP request made by the client.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpRequest {
+  /// "GET" or "POST".
+  pub method: String,
+
+  /// The full URL.
+  pub url: String,
+
+  /// Header names and values, one pair per header line.
+  pub headers: Vec<(String, String)>,
+
+  /// The request body, if any.
+  pub body: Option<String>,
+}
+
+/**
+ * An HTTP response received by the client.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct HttpResponse {
+  /// The status code, eg. 200.
+  pub status: u16,
+
+  /// Header names and values, one pair per header line.
+  pub headers: Vec<(String, String)>,
+
+  /// The response body.
+  pub body: String,
+}
+
+impl HttpResponse {
+  /// Get the values of every header line with the name, ignoring case.
+  pub fn get_headers(&self, name: &str) -> Vec<&str> {
+    self.headers.iter()
+        .filter(|&&(ref n, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, ref v)| v.as_str())
+        .collect()
+  }
+}
+
+/**
+ * Sends the client's requests to the gateway. Replacing the transport lets
+ * sessions be recorded and replayed without a gateway.
+ */
+pub trait Transport {
+  /// Send a request. Fails only if no response was received.
+  fn send(&self, request: &HttpRequest) -> Result<HttpResponse, RazberryError>;
+
+  /// Set the read timeout, where applicable.
+  fn set_read_timeout(&mut self, _timeout: Option<Duration>) {}
+
+  /// Set the write timeout, where applicable.
+  fn set_write_timeout(&mut self, _timeout: Option<Duration>) {}
+}
+
+/**
+ * Sends requests over HTTP. This is the client's default transport.
+ */
+pub struct HyperTransport {
+  client: Client,
+}
+
+impl HyperTransport {
+  /// Construct the transport.
+  pub fn new() -> HyperTransport {
+    HyperTransport { client: Client::new() }
+  }
+}
+
+impl Transport for HyperTransport {
+  fn send(&self, request: &HttpRequest) -> Result<HttpResponse, RazberryError> {
+    let mut headers = Headers::new();
+    for &(ref name, ref value) in request.headers.iter() {
+      headers.append_raw(name.to_string(), value.as_bytes().to_vec());
+    }
+
+    let builder = match request.method.as_str() {
+      "GET" => self.client.get(&request.url),
+      "POST" => self.client.post(&request.url),
+      _ => return Err(RazberryError::InvalidArgument),
+    };
+
+    let builder = builder.headers(headers);
+    let builder = match request.body {
+      None => builder,
+      Some(ref body) => builder.body(body.as_str()),
+    };
+
+    let mut result = builder.send()
+        .map_err(|_| RazberryError::ClientError)?;
+
+    let mut response_headers = Vec::new();
+    for header in result.headers.iter() {
+      if let Some(lines) = result.headers.get_raw(header.name()) {
+        for line in lines {
+          response_headers.push((header.name().to_string(),
+            String::from_utf8_lossy(line).into_owned()));
+        }
+      }
+    }
+
+    let mut body = String::new();
+    result.read_to_string(&mut body)
+        .map_err(|_| RazberryError::ServerError)?;
+
+    Ok(HttpResponse {
+      status: result.status.to_u16(),
+      headers: response_headers,
+      body: body,
+    })
+  }
+
+  fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+    self.client.set_read_timeout(timeout)
+  }
+
+  fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+    self.client.set_write_timeout(timeout)
+  }
+}
+
+/**
+ * A request and the response it received, as written by `RecordingTransport`.
+ */
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exchange {
+  /// The request.
+  pub request: HttpRequest,
+
+  /// The response, or None if the transport failed.
+  pub response: Option<HttpResponse>,
+
+  /// When the request was sent.
+  pub timestamp: DateTime<UTC>,
+
+  /// How long the response took, in milliseconds.
+  pub elapsed_ms: u64,
+}
+
+impl Exchange {
+  /// Decode from a line of a recording.
+  pub fn from_json(json: &Json) -> Result<Exchange, RazberryError> {
+    let request = HttpRequest {
+      method: get_string(json, "method")?,
+      url: get_string(json, "url")?,
+      headers: get_headers(json, "requestHeaders")?,
+      body: json.find("requestBody").and_then(|b| b.as_string())
+          .map(|b| b.to_string()),
+    };
+
+    let response = match json.find("status").and_then(|s| s.as_u64()) {
+      None => None, // The transport failed.
+      Some(status) => Some(HttpResponse {
+        status: status as u16,
+        headers: get_headers(json, "headers")?,
+        body: get_string(json, "body")?,
+      }),
+    };
+
+    let timestamp = json.find("timestamp").and_then(|t| t.as_i64())
+        .ok_or(RazberryError::BadResponse)?;
+
+    Ok(Exchange {
+      request: request,
+      response: response,
+      timestamp: DateTime::from_utc(
+        NaiveDateTime::from_timestamp(timestamp / 1000,
+          ((timestamp % 1000) * 1000000) as u32), UTC),
+      elapsed_ms: json.find("elapsedMs").and_then(|e| e.as_u64()).unwrap_or(0),
+    })
+  }
+
+  /// Encode as a line of a recording.
+  pub fn to_json(&self) -> Json {
+    let mut object = BTreeMap::new();
+
+    object.insert("method".to_string(), Json::String(self.request.method.clone()));
+    object.insert("url".to_string(), Json::String(self.request.url.clone()));
+    object.insert("requestHeaders".to_string(),
+      headers_to_json(&self.request.headers));
+
+    if let Some(ref body) = self.request.body {
+      object.insert("requestBody".to_string(), Json::String(body.clone()));
+    }
+
+    if let Some(ref response) = self.response {
+      object.insert("status".to_string(), Json::U64(response.status as u64));
+      object.insert("headers".to_string(), headers_to_json(&response.headers));
+      object.insert("body".to_string(), Json::String(response.body.clone()));
+    }
+
+    object.insert("timestamp".to_string(),
+      Json::I64(self.timestamp.timestamp() * 1000
+        + self.timestamp.timestamp_subsec_millis() as i64));
+    object.insert("elapsedMs".to_string(), Json::U64(self.elapsed_ms));
+
+    Json::Object(object)
+  }
+}
+
+/**
+ * Wraps another transport and writes every exchange to a JSONL recording,
+ * one `Exchange` per line, for `ReplayTransport` to serve back.
+ *
+ * Secrets are kept out of the recording: request bodies are not written,
+ * since the login request carries the password, the arguments of UserCode
+ * (0x63) "Set" commands are redacted from URLs, since they carry PINs, and
+ * the session token is redacted from "Cookie" and "Set-Cookie" headers.
+ * Stored codes are removed from data payloads, and the session ID from the
+ * login response.
+ */
+pub struct RecordingTransport<T: Transport> {
+  inner: T,
+  writer: Mutex<Box<Write + Send>>,
+}
+
+impl <T: Transport> RecordingTransport<T> {
+  /// Record the exchanges of a transport to a writer, eg. a `File`.
+  pub fn new(inner: T, writer: Box<Write + Send>) -> RecordingTransport<T> {
+    RecordingTransport {
+      inner: inner,
+      writer: Mutex::new(writer),
+    }
+  }
+}
+
+impl <T: Transport> Transport for RecordingTransport<T> {
+  fn send(&self, request: &HttpRequest) -> Result<HttpResponse, RazberryError> {
+    let timestamp = UTC::now();
+    let started = Instant::now();
+
+    let result = self.inner.send(request);
+
+    let elapsed = started.elapsed();
+
+    let exchange = Exchange {
+      request: HttpRequest {
+        method: request.method.clone(),
+        url: redact_url(&request.url),
+        headers: redact_headers(&request.headers),
+        body: None,
+      },
+      response: result.as_ref().ok().map(|response| HttpResponse {
+        status: response.status,
+        headers: redact_headers(&response.headers),
+        body: redact_body(&request.url, &response.body),
+      }),
+      timestamp: timestamp,
+      elapsed_ms: elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1000000) as u64,
+    };
+
+    let mut writer = self.writer.lock()
+        .map_err(|_| RazberryError::ClientError)?;
+
+    writeln!(writer, "{}", exchange.to_json())
+        .and_then(|_| writer.flush())
+        .map_err(|_| RazberryError::ClientError)?;
+
+    result
+  }
+
+  fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+    self.inner.set_read_timeout(timeout)
+  }
+
+  fn set_write_timeout(&mut self, timeout: Option<Duration>) {
+    self.inner.set_write_timeout(timeout)
+  }
+}
+
+/**
+ * Serves the exchanges of a recording back in order, without a gateway.
+ * Each request must match the method and URL of the next exchange, once
+ * redacted as `RecordingTransport` does.
+ */
+pub struct ReplayTransport {
+  exchanges: Mutex<VecDeque<Exchange>>,
+}
+
+impl ReplayTransport {
+  /// Serve the exchanges in order.
+  pub fn new(exchanges: Vec<Exchange>) -> ReplayTransport {
+    ReplayTransport {
+      exchanges: Mutex::new(exchanges.into_iter().collect()),
+    }
+  }
+
+  /// Read a recording written by `RecordingTransport`.
+  pub fn from_reader<R: BufRead>(reader: R)
+      -> Result<ReplayTransport, RazberryError> {
+    let mut exchanges = Vec::new();
+
+    for line in reader.lines() {
+      let line = line.map_err(|_| RazberryError::ClientError)?;
+      if line.trim().is_empty() {
+        continue;
+      }
+      exchanges.push(Exchange::from_json(&Json::from_str(&line)?)?);
+    }
+
+    Ok(ReplayTransport::new(exchanges))
+  }
+
+  /// Get the number of exchanges not yet served.
+  pub fn remaining(&self) -> usize {
+    self.exchanges.lock().map(|e| e.len()).unwrap_or(0)
+  }
+}
+
+impl Transport for ReplayTransport {
+  fn send(&self, request: &HttpRequest) -> Result<HttpResponse, RazberryError> {
+    let mut exchanges = self.exchanges.lock()
+        .map_err(|_| RazberryError::ClientError)?;
+
+    let exchange = exchanges.pop_front().ok_or(RazberryError::ClientError)?;
+
+    if exchange.request.method != request.method
+        || exchange.request.url != redact_url(&request.url) {
+      return Err(RazberryError::ClientError); // Diverged from the recording.
+    }
+
+    exchange.response.ok_or(RazberryError::ClientError)
+  }
+}
+
+/// Replace the arguments of UserCode "Set" commands in a URL, eg.
+/// "commandClasses[99].Set(1,%221234%22,1)".
+fn redact_url(url: &str) -> String {
+  let mut redacted = String::new();
+  let mut rest = url;
+
+  while let Some(start) = rest.find(USER_CODE_SET) {
+    let (before, after) = rest.split_at(start + USER_CODE_SET.len());
+    redacted.push_str(before);
+    redacted.push_str(REDACTED);
+
+    rest = match after.find(")") {
+      Some(end) => &after[end..],
+      None => "",
+    };
+  }
+
+  redacted.push_str(rest);
+  redacted
+}
+
+/// Remove stored user codes from a data payload, and the session ID from a
+/// login response. Other bodies, and bodies that are not JSON, are kept.
+fn redact_body(url: &str, body: &str) -> String {
+  let path = match Url::parse(url) {
+    Ok(url) => url.path().to_string(),
+    Err(_) => return body.to_string(),
+  };
+
+  let is_delta = path.starts_with(&format!("{}/", DATA_PATH));
+  if path != DATA_PATH && !is_delta && path != LOGIN_PATH {
+    return body.to_string();
+  }
+
+  let mut json = match Json::from_str(body) {
+    Ok(json) => json,
+    Err(_) => return body.to_string(),
+  };
+
+  if path == LOGIN_PATH {
+    let data = json.as_object_mut()
+        .and_then(|o| o.get_mut("data"))
+        .and_then(|d| d.as_object_mut());
+
+    if let Some(data) = data {
+      data.remove("sid");
+    }
+  } else if path == DATA_PATH {
+    remove_codes(&mut json, &[]);
+  } else if let Some(object) = json.as_object_mut() {
+    // A delta is keyed by dotted path, eg. "devices.5.instances.0.commandClasses.99.data.1".
+    let keys = object.keys().cloned().collect::<Vec<String>>();
+
+    for key in keys {
+      let path = key.split(".").collect::<Vec<&str>>();
+      if is_in_code(&path) {
+        object.remove(&key);
+      } else if let Some(value) = object.get_mut(&key) {
+        remove_codes(value, &path);
+      }
+    }
+  }
+
+  json.to_string()
+}
+
+/// Replace the session token in "Cookie" and "Set-Cookie" headers.
+fn redact_headers(headers: &[(String, String)]) -> Vec<(String, String)> {
+  headers.iter().map(|&(ref name, ref value)| {
+    let is_cookie = name.eq_ignore_ascii_case("Cookie")
+        || name.eq_ignore_ascii_case("Set-Cookie");
+
+    if !is_cookie {
+      return (name.clone(), value.clone());
+    }
+
+    let cookies = value.split(";").map(|cookie| {
+      match cookie.find(SESSION_COOKIE_PREFIX) {
+        Some(start) if cookie[..start].trim().is_empty() => {
+          format!("{}{}", &cookie[..start + SESSION_COOKIE_PREFIX.len()], REDACTED)
+        },
+        _ => cookie.to_string(),
+      }
+    }).collect::<Vec<String>>();
+
+    (name.clone(), cookies.join(";"))
+  }).collect()
+}
+
+fn get_string(json: &Json, key: &str) -> Result<String, RazberryError> {
+  json.find(key)
+      .and_then(|v| v.as_string())
+      .map(|v| v.to_string())
+      .ok_or(RazberryError::BadResponse)
+}
+
+fn get_headers(json: &Json, key: &str)
+    -> Result<Vec<(String, String)>, RazberryError> {
+  let pairs = match json.find(key).and_then(|h| h.as_array()) {
+    None => return Ok(Vec::new()),
+    Some(pairs) => pairs,
+  };
+
+  pairs.iter().map(|pair| {
+    let pair = pair.as_array().ok_or(RazberryError::BadResponse)?;
+    match (pair.get(0).and_then(|n| n.as_string()),
+           pair.get(1).and_then(|v| v.as_string())) {
+      (Some(name), Some(value)) => Ok((name.to_string(), value.to_string())),
+      _ => Err(RazberryError::BadResponse),
+    }
+  }).collect()
+}
+
+fn headers_to_json(headers: &[(String, String)]) -> Json {
+  Json::Array(headers.iter()
+      .map(|&(ref name, ref value)| {
+        Json::Array(vec![Json::String(name.clone()), Json::String(value.clone())])
+      })
+      .collect())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::io::Cursor;
+  use std::sync::Arc;
+
+  /// Answers every request with its own URL.
+  struct EchoTransport;
+
+  impl Transport for EchoTransport {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, RazberryError> {
+      Ok(HttpResponse {
+        status: 200,
+        headers: vec![("Set-Cookie".to_string(), "ZWAYSession=abc; Path=/".to_string())],
+        body: request.url.clone(),
+      })
+    }
+  }
+
+  /// Answers every request by starting a session.
+  struct LoginTransport;
+
+  impl Transport for LoginTransport {
+    fn send(&self, _request: &HttpRequest) -> Result<HttpResponse, RazberryError> {
+      Ok(HttpResponse {
+        status: 200,
+        headers: vec![("Set-Cookie".to_string(), "ZWAYSession=4567cdef; Path=/".to_string())],
+        body: "null".to_string(),
+      })
+    }
+  }
+
+  /// Answers logins with a session ID, and data requests with user codes.
+  struct SecretTransport;
+
+  impl Transport for SecretTransport {
+    fn send(&self, request: &HttpRequest) -> Result<HttpResponse, RazberryError> {
+      let slot = r#"{"value": null, "type": "empty",
+        "status": {"value": 1, "type": "int"},
+        "code": {"value": "CODE", "type": "string"}}"#;
+
+      let body = if request.url.ends_with("/login") {
+        r#"{"data": {"sid": "89abfedc01", "id": 1}}"#.to_string()
+      } else if request.url.ends_with("/Data") {
+        r#"{"devices": {"5": {"instances": {"0": {"commandClasses": {"99":
+          {"data": {"1": SLOT}}}}}}}, "updateTime": 1}"#
+            .replace("SLOT", &slot.replace("CODE", "2468013579"))
+      } else {
+        r#"{"devices.5.instances.0.commandClasses.99.data.1": SLOT,
+          "devices.5.instances.0.commandClasses.99.data.2.code":
+            {"value": "9753108642", "type": "string"},
+          "updateTime": 2}"#
+            .replace("SLOT", &slot.replace("CODE", "1357924680"))
+      };
+
+      Ok(HttpResponse { status: 200, headers: Vec::new(), body: body })
+    }
+  }
+
+  /// A writer whose contents can be read after it is handed off.
+  #[derive(Clone, Default)]
+  struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+  impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> ::std::io::Result<usize> {
+      self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> ::std::io::Result<()> {
+      Ok(())
+    }
+  }
+
+  fn get(url: &str) -> HttpRequest {
+    HttpRequest {
+      method: "GET".to_string(),
+      url: url.to_string(),
+      headers: vec![("Cookie".to_string(), "ZWAYSession=abc".to_string())],
+      body: None,
+    }
+  }
+
+  #[test]
+  fn test_record_and_replay() {
+    let buffer = SharedBuffer::default();
+    let recorder = RecordingTransport::new(EchoTransport, Box::new(buffer.clone()));
+
+    let first = recorder.send(&get("http://localhost:8083/ZWaveAPI/Data")).unwrap();
+    let second = recorder.send(&get("http://localhost:8083/ZWaveAPI/Data/1")).unwrap();
+
+    let recording = buffer.0.lock().unwrap().clone();
+    assert_eq!(2, String::from_utf8(recording.clone()).unwrap().lines().count());
+
+    let replay = ReplayTransport::from_reader(Cursor::new(recording)).unwrap();
+    assert_eq!(2, replay.remaining());
+
+    let replayed = replay.send(&get("http://localhost:8083/ZWaveAPI/Data")).unwrap();
+    assert_eq!((first.status, &first.body), (replayed.status, &replayed.body));
+
+    let replayed = replay.send(&get("http://localhost:8083/ZWaveAPI/Data/1")).unwrap();
+    assert_eq!((second.status, &second.body), (replayed.status, &replayed.body));
+    assert_eq!(vec!["ZWAYSession=abc; Path=/"], second.get_headers("set-cookie"));
+    assert_eq!(vec!["ZWAYSession=<redacted>; Path=/"], replayed.get_headers("set-cookie"));
+
+    // Exhausted.
+    assert!(replay.send(&get("http://localhost:8083/ZWaveAPI/Data/1")).is_err());
+  }
+
+  #[test]
+  fn test_replay_diverged() {
+    let exchange = Exchange {
+      request: get("http://localhost:8083/ZWaveAPI/Data"),
+      response: None,
+      timestamp: UTC::now(),
+      elapsed_ms: 0,
+    };
+
+    let replay = ReplayTransport::new(vec![exchange]);
+    assert!(replay.send(&get("http://localhost:8083/ZWaveAPI/Data/1")).is_err());
+  }
+
+  #[test]
+  fn test_recording_omits_request_body() {
+    let buffer = SharedBuffer::default();
+    let recorder = RecordingTransport::new(EchoTransport, Box::new(buffer.clone()));
+
+    let request = HttpRequest {
+      method: "POST".to_string(),
+      url: "http://localhost:8083/ZAutomation/api/v1/login".to_string(),
+      headers: Vec::new(),
+      body: Some("{\"password\":\"secret\"}".to_string()),
+    };
+    recorder.send(&request).unwrap();
+
+    let recording = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    assert!(!recording.contains("secret"));
+  }
+
+  #[test]
+  fn test_recording_redacts_response_bodies() {
+    let buffer = SharedBuffer::default();
+    let recorder = RecordingTransport::new(SecretTransport, Box::new(buffer.clone()));
+
+    let mut responses = Vec::new();
+    for url in &["http://localhost:8083/ZAutomation/api/v1/login",
+                 "http://localhost:8083/ZWaveAPI/Data",
+                 "http://localhost:8083/ZWaveAPI/Data/1"] {
+      responses.push(recorder.send(&get(url)).unwrap().body);
+    }
+
+    // The client still sees every secret.
+    assert!(responses[0].contains("89abfedc01"));
+    assert!(responses[1].contains("2468013579"));
+    assert!(responses[2].contains("1357924680") && responses[2].contains("9753108642"));
+
+    let recording = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    for secret in &["89abfedc01", "2468013579", "1357924680", "9753108642"] {
+      assert!(!recording.contains(secret), "{}", secret);
+    }
+
+    // Everything else is kept.
+    let bodies = recording.lines()
+        .map(|line| Json::from_str(line).unwrap())
+        .map(|exchange| Json::from_str(exchange.find("body").unwrap().as_string().unwrap())
+            .unwrap())
+        .collect::<Vec<Json>>();
+    assert_eq!(Some(1), bodies[0].find_path(&["data", "id"]).and_then(|i| i.as_u64()));
+    assert!(bodies[1].find_path(&["devices", "5", "instances", "0", "commandClasses", "99",
+      "data", "1", "status"]).is_some());
+    assert!(bodies[2].find("devices.5.instances.0.commandClasses.99.data.1")
+        .and_then(|s| s.find("status")).is_some());
+  }
+
+  #[test]
+  fn test_recording_redacts_codes_and_sessions() {
+    let buffer = SharedBuffer::default();
+    let recorder = RecordingTransport::new(LoginTransport, Box::new(buffer.clone()));
+
+    let url = "http://localhost:8083/ZWaveAPI/Run/\
+      devices[5].instances[0].commandClasses[99].Set(1,%225678%22,1)";
+    let mut request = get(url);
+    request.headers = vec![
+      ("Cookie".to_string(), "theme=dark; ZWAYSession=0123abcd".to_string()),
+    ];
+    recorder.send(&request).unwrap();
+
+    let recording = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+    assert!(!recording.contains("5678"));
+    assert!(!recording.contains("0123abcd"));
+    assert!(!recording.contains("4567cdef"));
+    assert!(recording.contains("commandClasses[99].Set(<redacted>)"));
+    assert!(recording.contains("theme=dark; ZWAYSession=<redacted>"));
+
+    // Replay matches on the redacted URL.
+    let replay = ReplayTransport::from_reader(Cursor::new(recording.into_bytes())).unwrap();
+    assert!(replay.send(&get(url)).is_ok());
+  }
+}